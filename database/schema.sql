@@ -56,7 +56,7 @@ CREATE TABLE spreadsheet_columns (
     id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
     spreadsheet_id UUID NOT NULL REFERENCES spreadsheets(id) ON DELETE CASCADE,
     name VARCHAR(255) NOT NULL,
-    column_type VARCHAR(50) NOT NULL DEFAULT 'text' CHECK (column_type IN ('text', 'number', 'date', 'boolean', 'select', 'currency')),
+    column_type VARCHAR(50) NOT NULL DEFAULT 'text' CHECK (column_type IN ('text', 'number', 'date', 'boolean', 'select', 'currency', 'multi_select', 'email', 'url', 'percent', 'person', 'checkbox')),
     position INTEGER NOT NULL,
     is_required BOOLEAN DEFAULT false,
     default_value TEXT,
@@ -101,7 +101,7 @@ export const Dashboard: React.FC = () => {
         { name: 'Account', column_type: 'text', position: 1, is_required: true },
         { name: 'Type', column_type: 'select', position: 2, is_required: false },
         { name: 'Primary Contact', column_type: 'text', position: 3, is_required: true },
-        { name: 'Deal Value', column_type: 'number', position: 4 }
+        { name: 'Deal Value', column_type: 'number', position: 4, display_options: { indexed: true } }
       ]
     },
     {
@@ -119,14 +119,14 @@ export const Dashboard: React.FC = () => {
         { name: 'Phone', column_type: 'text', position: 5 },
         
         // Deal Basics
-        { name: 'Deal Value', column_type: 'number', position: 6 },
+        { name: 'Deal Value', column_type: 'number', position: 6, display_options: { indexed: true } },
         { name: 'Close Date', column_type: 'date', position: 7 },
         { name: 'Lead Source', column_type: 'select', position: 8,
           validation_rules: { options: ['Inbound', 'Outbound', 'Referral', 'Website', 'Marketing'] }
         },
         
         // Quick SE Process
-        { name: 'Stage', column_type: 'select', position: 9, is_required: true,
+        { name: 'Stage', column_type: 'select', position: 9, is_required: true, display_options: { indexed: true },
           validation_rules: {
             options: ['Qualified', 'Discovery Call', 'Demo Scheduled', 'Demo Done', 'Proposal', 'Negotiation', 'Won', 'Lost']
           }
@@ -167,7 +167,7 @@ export const Dashboard: React.FC = () => {
         { name: 'Customer Phone', column_type: 'text', position: 8 },
         
         // Deal Details
-        { name: 'Deal Value', column_type: 'number', position: 9 },
+        { name: 'Deal Value', column_type: 'number', position: 9, display_options: { indexed: true } },
         { name: 'Partner Commission %', column_type: 'number', position: 10 },
         { name: 'Expected Close Date', column_type: 'date', position: 11 },
         { name: 'Lead Source', column_type: 'select', position: 12,
@@ -175,7 +175,7 @@ export const Dashboard: React.FC = () => {
         },
         
         // Sales Process
-        { name: 'Stage', column_type: 'select', position: 13, is_required: true,
+        { name: 'Stage', column_type: 'select', position: 13, is_required: true, display_options: { indexed: true },
           validation_rules: {
             options: ['Partner Qualified', 'Partner Introduction', 'Discovery Call', 'Technical Review', 'Proposal', 'Partner Negotiation', 'Won', 'Lost']
           }
@@ -535,19 +535,16 @@ export function SpreadsheetView() {
         for (const [index, opp] of result.opportunities.entries()) {
           console.log(`🔄 Processing opportunity ${index + 1}/${result.opportunities.length}:`, opp);
           
-          // Create row data from opportunity fields
-          const rowData: any = {};
-          
-          // Map all opportunity fields to the row, using exact field names from backend
-          Object.keys(opp).forEach(key => {
-            rowData[key] = opp[key];
-          });
-          
-          // Also map to legacy column names for backward compatibility
-          if (opp['Account']) rowData['Account'] = opp['Account'];
-          if (opp['Type']) rowData['Type'] = opp['Type'];
-          if (opp['Owner']) rowData['Primary Contact'] = opp['Owner'];
-          if (opp['Amount'] !== undefined) rowData['Deal Value'] = opp['Amount'];
+          // The backend resolves each field to this sheet's column ids;
+          // older backends only send name-keyed opportunities
+          let rowData: any = result.rows?.[index];
+          if (!rowData) {
+            rowData = { ...opp };
+
+            // Also map to legacy column names for backward compatibility
+            if (opp['Owner']) rowData['Primary Contact'] = opp['Owner'];
+            if (opp['Amount'] !== undefined) rowData['Deal Value'] = opp['Amount'];
+          }
           
           try {
             // Save row to database
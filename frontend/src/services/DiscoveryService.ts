@@ -5,6 +5,8 @@
  * Handles all communication with the backend discovery API
  */
 
+import { PaginatedResponse } from '../types';
+
 const API_BASE_URL = process.env.REACT_APP_API_URL || 'http://localhost:8003/api';
 
 // Type definitions matching backend models
@@ -263,22 +265,35 @@ export const discoveryService = {
    */
   async getSessionsByAccount(accountId: string): Promise<DiscoverySession[]> {
     console.log('🔗 [API] GET /discovery/accounts/' + accountId);
-    const response = await fetch(
-      `${API_BASE_URL}/discovery/accounts/${accountId}`,
-      {
-        method: 'GET',
-        headers: getHeaders(),
+    const sessions: DiscoverySession[] = [];
+    let cursor: string | null | undefined;
+
+    // The list is paginated; follow next_cursor until we have every session
+    do {
+      const query = new URLSearchParams({ limit: '100' });
+      if (cursor) {
+        query.set('cursor', cursor);
+      }
+      const response = await fetch(
+        `${API_BASE_URL}/discovery/accounts/${accountId}?${query}`,
+        {
+          method: 'GET',
+          headers: getHeaders(),
+        }
+      );
+
+      if (!response.ok) {
+        console.error('❌ [API] GET /discovery/accounts/' + accountId + ' - Status:', response.status);
+        await handleError(response);
       }
-    );
 
-    if (!response.ok) {
-      console.error('❌ [API] GET /discovery/accounts/' + accountId + ' - Status:', response.status);
-      await handleError(response);
-    }
+      const page: PaginatedResponse<DiscoverySession> = await response.json();
+      sessions.push(...page.data);
+      cursor = page.next_cursor;
+    } while (cursor);
 
-    const data = await response.json();
-    console.log('✅ [API] GET /discovery/accounts/' + accountId + ' - Response:', data);
-    return data;
+    console.log('✅ [API] GET /discovery/accounts/' + accountId + ' - Response:', sessions);
+    return sessions;
   },
 
   /**
@@ -1,5 +1,5 @@
 import axios, { AxiosInstance, AxiosResponse } from 'axios';
-import { AuthResponse, LoginRequest, RegisterRequest, User } from '../types';
+import { AuthResponse, LoginRequest, PaginatedResponse, RegisterRequest, User } from '../types';
 
 class ApiService {
   private api: AxiosInstance;
@@ -109,6 +109,18 @@ class ApiService {
     return response.data.data;
   }
 
+  // Follow next_cursor through a paginated list and return every item
+  async getAll<T>(url: string, params?: any): Promise<T[]> {
+    const items: T[] = [];
+    let cursor: string | undefined;
+    do {
+      const page = await this.get<PaginatedResponse<T>>(url, { ...params, cursor });
+      items.push(...page.data);
+      cursor = page.next_cursor ?? undefined;
+    } while (cursor);
+    return items;
+  }
+
   async post<T>(url: string, data?: any): Promise<T> {
     const response: AxiosResponse<{ data: T }> = await this.api.post(url, data);
     return response.data.data;
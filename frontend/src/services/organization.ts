@@ -0,0 +1,96 @@
+import { apiService } from './api';
+import {
+  Organization,
+  OrganizationInvitation,
+  OrganizationMember,
+  OrganizationMembership,
+  OrganizationRole,
+  Spreadsheet,
+  Team,
+  TeamMember,
+} from '../types';
+
+export class OrganizationService {
+  /** The current user's organization and their role in it */
+  async getOrganization(): Promise<OrganizationMembership> {
+    return apiService.get('/api/organization');
+  }
+
+  async updateOrganization(name: string): Promise<Organization> {
+    return apiService.put('/api/organization', { name });
+  }
+
+  async getMembers(): Promise<OrganizationMember[]> {
+    return apiService.get('/api/organization/members');
+  }
+
+  /** Invite someone by email; they join once they accept */
+  async inviteMember(email: string, role?: OrganizationRole): Promise<OrganizationInvitation> {
+    return apiService.post('/api/organization/invitations', { email, role });
+  }
+
+  async getInvitations(): Promise<OrganizationInvitation[]> {
+    return apiService.get('/api/organization/invitations');
+  }
+
+  async revokeInvitation(id: string): Promise<void> {
+    return apiService.delete(`/api/organization/invitations/${id}`);
+  }
+
+  /** Invitations other organizations have sent the current user */
+  async getReceivedInvitations(): Promise<OrganizationInvitation[]> {
+    return apiService.get('/api/organization/invitations/received');
+  }
+
+  /** Join the inviting organization; bringData moves what the user has in their current one along */
+  async acceptInvitation(id: string, bringData = false): Promise<OrganizationMember> {
+    return apiService.post(`/api/organization/invitations/${id}/accept`, { bring_data: bringData });
+  }
+
+  async declineInvitation(id: string): Promise<void> {
+    return apiService.post(`/api/organization/invitations/${id}/decline`);
+  }
+
+  async updateMemberRole(userId: string, role: OrganizationRole): Promise<OrganizationMember> {
+    return apiService.put(`/api/organization/members/${userId}`, { role });
+  }
+
+  async removeMember(userId: string): Promise<void> {
+    return apiService.delete(`/api/organization/members/${userId}`);
+  }
+
+  async getTeams(): Promise<Team[]> {
+    return apiService.get('/api/teams');
+  }
+
+  async createTeam(name: string, description?: string): Promise<Team> {
+    return apiService.post('/api/teams', { name, description });
+  }
+
+  async updateTeam(id: string, data: { name?: string; description?: string }): Promise<Team> {
+    return apiService.put(`/api/teams/${id}`, data);
+  }
+
+  async deleteTeam(id: string): Promise<void> {
+    return apiService.delete(`/api/teams/${id}`);
+  }
+
+  async getTeamMembers(teamId: string): Promise<TeamMember[]> {
+    return apiService.get(`/api/teams/${teamId}/members`);
+  }
+
+  async addTeamMember(teamId: string, email: string, role?: TeamMember['role']): Promise<TeamMember> {
+    return apiService.post(`/api/teams/${teamId}/members`, { email, role });
+  }
+
+  async removeTeamMember(teamId: string, userId: string): Promise<void> {
+    return apiService.delete(`/api/teams/${teamId}/members/${userId}`);
+  }
+
+  /** Give a spreadsheet to a team, or pass null to take it away from its team */
+  async assignSpreadsheetTeam(spreadsheetId: string, teamId: string | null): Promise<Spreadsheet> {
+    return apiService.put(`/api/spreadsheets/${spreadsheetId}/team`, { team_id: teamId });
+  }
+}
+
+export const organizationService = new OrganizationService();
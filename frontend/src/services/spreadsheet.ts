@@ -9,8 +9,25 @@ import {
   CreateRowRequest,
   UpdateRowRequest,
   CollaboratorInfo,
+  SocketResume,
+  SocketSessionMessage,
 } from '../types';
 
+// Call `onResync` if a socket or stream message is a ResyncRequired, or the
+// error a connection is closed with after messages to it were dropped
+function notifyResync(data: string, onResync: (seq?: number) => void): void {
+  try {
+    const message = JSON.parse(data) as SocketSessionMessage;
+    if (message.type === 'ResyncRequired') {
+      onResync(message.seq);
+    } else if (message.type === 'Error' && message.code === 'RESYNC_REQUIRED') {
+      onResync();
+    }
+  } catch {
+    // Not JSON; nothing to do with resyncing
+  }
+}
+
 export class SpreadsheetService {
   async getSpreadsheets(params?: PaginationParams): Promise<PaginatedResponse<Spreadsheet>> {
     return apiService.get('/api/spreadsheets', params);
@@ -36,11 +53,23 @@ export class SpreadsheetService {
     return apiService.get(`/api/spreadsheets/${spreadsheetId}/columns`);
   }
 
+  async updateColumn(spreadsheetId: string, columnId: string, data: Record<string, any>): Promise<any> {
+    return apiService.put(`/api/spreadsheets/${spreadsheetId}/columns/${columnId}`, data);
+  }
+
+  async deleteColumn(spreadsheetId: string, columnId: string): Promise<void> {
+    return apiService.delete(`/api/spreadsheets/${spreadsheetId}/columns/${columnId}`);
+  }
+
   async syncSalesforceColumns(spreadsheetId: string): Promise<{ added_columns: any[]; total_columns: number }> {
     return apiService.post(`/api/spreadsheets/${spreadsheetId}/salesforce/columns`, {});
   }
 
   async getRows(spreadsheetId: string, params?: PaginationParams): Promise<SpreadsheetRow[]> {
+    return apiService.getAll(`/api/spreadsheets/${spreadsheetId}/rows`, params);
+  }
+
+  async getRowsPage(spreadsheetId: string, params?: PaginationParams): Promise<PaginatedResponse<SpreadsheetRow>> {
     return apiService.get(`/api/spreadsheets/${spreadsheetId}/rows`, params);
   }
 
@@ -176,7 +205,14 @@ export class SpreadsheetService {
   }
 
   // WebSocket connection for real-time updates
-  createWebSocketConnection(spreadsheetId: string): WebSocket | null {
+  // Pass the resume token from the last `Session` message and the last seq applied
+  // to have missed events replayed instead of reloading the sheet. `onResync` is
+  // called when the server says messages were missed and the sheet needs reloading.
+  createWebSocketConnection(
+    spreadsheetId: string,
+    resume?: SocketResume,
+    onResync?: (seq?: number) => void,
+  ): WebSocket | null {
     try {
       const wsUrl = apiService.getBaseURL().replace('http', 'ws');
       const token = localStorage.getItem('access_token');
@@ -186,9 +222,11 @@ export class SpreadsheetService {
         return null;
       }
 
-      // Note: This is a simplified WebSocket connection
-      // In production, you'd want proper authentication for WebSocket
-      const ws = new WebSocket(`${wsUrl}/ws/spreadsheet/${spreadsheetId}`);
+      // Browsers can't set headers on the upgrade, so the token rides along as a subprotocol
+      const query = resume
+        ? `?resume=${encodeURIComponent(resume.token)}&last_seq=${resume.lastSeq}`
+        : '';
+      const ws = new WebSocket(`${wsUrl}/ws/spreadsheet/${spreadsheetId}${query}`, ['contrivance.bearer', token]);
       
       ws.onopen = () => {
         console.log('WebSocket connected for spreadsheet:', spreadsheetId);
@@ -202,12 +240,99 @@ export class SpreadsheetService {
         console.log('WebSocket disconnected for spreadsheet:', spreadsheetId);
       };
 
+      if (onResync) {
+        ws.addEventListener('message', (event) => notifyResync(event.data, onResync));
+      }
+
       return ws;
     } catch (error) {
       console.error('Failed to create WebSocket connection:', error);
       return null;
     }
   }
+
+  // Server-Sent Events fallback for networks that block WebSocket upgrades. It
+  // carries the same messages but only receives; edits go over REST. The
+  // browser reconnects on its own and sends the last event id to resume.
+  createEventSource(
+    spreadsheetId: string,
+    lastSeq?: number,
+    onResync?: (seq?: number) => void,
+  ): EventSource | null {
+    const token = localStorage.getItem('access_token');
+    if (!token) {
+      console.error('No access token available for event stream');
+      return null;
+    }
+
+    // EventSource can't set headers either, so the token goes in the query
+    const query = `?token=${encodeURIComponent(token)}` + (lastSeq !== undefined ? `&last_seq=${lastSeq}` : '');
+    const source = new EventSource(`${apiService.getBaseURL()}/api/spreadsheets/${spreadsheetId}/events${query}`);
+    source.onerror = (error) => {
+      console.error('Event stream error:', error);
+    };
+    if (onResync) {
+      source.addEventListener('message', (event) => notifyResync(event.data, onResync));
+    }
+    return source;
+  }
+
+  /** Share the selected cell with everyone else viewing the spreadsheet; pass nulls to clear it */
+  sendSelection(ws: WebSocket, rowId: string | null, columnId: string | null): void {
+    if (ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: 'Select', row_id: rowId, column_id: columnId }));
+    }
+  }
+
+  /**
+   * Ask for the lock on a cell before editing it. Locks expire after 15s, so
+   * resend while the editor is open; peers see `CellLocked`/`CellUnlocked`.
+   */
+  sendLockCell(ws: WebSocket, rowId: string, columnId: string): void {
+    if (ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: 'LockCell', row_id: rowId, column_id: columnId }));
+    }
+  }
+
+  sendUnlockCell(ws: WebSocket, rowId: string, columnId: string): void {
+    if (ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: 'UnlockCell', row_id: rowId, column_id: columnId }));
+    }
+  }
+
+  /** Open a text cell for collaborative editing; the reply is a `TextSnapshot` */
+  sendOpenText(ws: WebSocket, rowId: string, columnId: string): void {
+    if (ws.readyState === WebSocket.OPEN) {
+      ws.send(JSON.stringify({ type: 'OpenText', row_id: rowId, column_id: columnId }));
+    }
+  }
+
+  /** Send base64 Automerge changes to an opened text cell, returning the op id */
+  sendEditText(ws: WebSocket, rowId: string, columnId: string, changes: string): string {
+    return this.sendOp(ws, { type: 'EditText', row_id: rowId, column_id: columnId, changes });
+  }
+
+  /**
+   * Row edits sent over the socket instead of REST. Each returns the op id the
+   * server echoes in its `OpAck` or `OpNack`.
+   */
+  sendCreateRow(ws: WebSocket, rowData: Record<string, any>, position?: number): string {
+    return this.sendOp(ws, { type: 'CreateRow', row_data: rowData, position: position ?? null });
+  }
+
+  sendUpdateRow(ws: WebSocket, rowId: string, rowData: Record<string, any>): string {
+    return this.sendOp(ws, { type: 'UpdateRow', row_id: rowId, row_data: rowData });
+  }
+
+  sendDeleteRow(ws: WebSocket, rowId: string): string {
+    return this.sendOp(ws, { type: 'DeleteRow', row_id: rowId });
+  }
+
+  private sendOp(ws: WebSocket, op: Record<string, any>): string {
+    const opId = crypto.randomUUID();
+    ws.send(JSON.stringify({ ...op, op_id: opId }));
+    return opId;
+  }
 }
 
 export const spreadsheetService = new SpreadsheetService();
\ No newline at end of file
@@ -14,6 +14,35 @@ export interface Todo {
   row_id?: string;
   user_id: string;
   assigned_to?: string;
+  // Recurring todos: occurrences share series_id and an RFC 5545 rule
+  series_id?: string;
+  recurrence?: string;
+  recurrence_timezone?: string;
+  series_start?: Date;
+  scheduled_at?: Date;
+  // Subtasks, checklists and dependencies
+  parent_id?: string;
+  blocked_by: string[];
+  blocked: boolean; // waiting on a todo that's still open
+  checklist: TodoChecklistItem[];
+}
+
+export interface TodoChecklistItem {
+  id: string;
+  title: string;
+  completed: boolean;
+  position: number;
+}
+
+export interface TodoProgress {
+  completed: number;
+  total: number;
+}
+
+// A todo with its subtasks, from getTodoTree
+export interface TodoNode extends Todo {
+  subtasks: TodoNode[];
+  progress: TodoProgress; // checklist items and subtasks, all the way down
 }
 
 export interface CreateTodoRequest {
@@ -25,8 +54,13 @@ export interface CreateTodoRequest {
   spreadsheet_id: string;
   row_id?: string;
   assigned_to?: string;
+  recurrence?: string; // e.g. 'FREQ=WEEKLY;BYDAY=MO', starting from due_date
+  recurrence_timezone?: string; // IANA name, UTC if not given
+  parent_id?: string; // on the same row
 }
 
+export type TodoEditScope = 'occurrence' | 'series';
+
 export interface UpdateTodoRequest {
   title?: string;
   description?: string;
@@ -35,6 +69,10 @@ export interface UpdateTodoRequest {
   due_date?: Date;
   supporting_artifact?: string;
   assigned_to?: string;
+  recurrence?: string; // series scope only; '' stops the series
+  recurrence_timezone?: string;
+  parent_id?: string; // occurrence scope only
+  scope?: TodoEditScope; // defaults to 'occurrence'
 }
 
 export interface TodoStats {
@@ -44,6 +82,7 @@ export interface TodoStats {
   high_priority: number;
   medium_priority: number;
   low_priority: number;
+  blocked: number;
 }
 
 export interface User {
@@ -61,12 +100,17 @@ class TodoService {
 
   // Get todos for a spreadsheet (pipeline-level)
   async getTodosBySpreadsheet(spreadsheetId: string): Promise<Todo[]> {
-    return await apiService.get<Todo[]>(`/api/spreadsheets/${spreadsheetId}/todos`);
+    return await apiService.getAll<Todo>(`/api/spreadsheets/${spreadsheetId}/todos`);
   }
 
   // Get todos for a specific row
   async getTodosByRow(spreadsheetId: string, rowId: string): Promise<Todo[]> {
-    return await apiService.get<Todo[]>(`/api/spreadsheets/${spreadsheetId}/rows/${rowId}/todos`);
+    return await apiService.getAll<Todo>(`/api/spreadsheets/${spreadsheetId}/rows/${rowId}/todos`);
+  }
+
+  // Get a row's todos arranged under their parents
+  async getTodoTree(spreadsheetId: string, rowId: string): Promise<TodoNode[]> {
+    return await apiService.get<TodoNode[]>(`/api/spreadsheets/${spreadsheetId}/rows/${rowId}/todos?tree=true`);
   }
 
   // Get todo statistics for a spreadsheet
@@ -99,6 +143,31 @@ class TodoService {
     return await apiService.put<Todo>(`/api/todos/${todoId}/uncomplete`, {});
   }
 
+  // Make a todo wait until another is completed
+  async addDependency(todoId: string, blockedBy: string): Promise<Todo> {
+    return await apiService.post<Todo>(`/api/todos/${todoId}/dependencies`, { blocked_by: blockedBy });
+  }
+
+  async removeDependency(todoId: string, blockedBy: string): Promise<Todo> {
+    return await apiService.delete<Todo>(`/api/todos/${todoId}/dependencies/${blockedBy}`);
+  }
+
+  async addChecklistItem(todoId: string, title: string, position?: number): Promise<Todo> {
+    return await apiService.post<Todo>(`/api/todos/${todoId}/checklist`, { title, position });
+  }
+
+  async updateChecklistItem(
+    todoId: string,
+    itemId: string,
+    changes: Partial<Pick<TodoChecklistItem, 'title' | 'completed' | 'position'>>
+  ): Promise<Todo> {
+    return await apiService.put<Todo>(`/api/todos/${todoId}/checklist/${itemId}`, changes);
+  }
+
+  async deleteChecklistItem(todoId: string, itemId: string): Promise<Todo> {
+    return await apiService.delete<Todo>(`/api/todos/${todoId}/checklist/${itemId}`);
+  }
+
   // Get users for assignment dropdown
   async getUsersForAssignment(): Promise<User[]> {
     try {
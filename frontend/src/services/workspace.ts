@@ -0,0 +1,97 @@
+import { apiService } from './api';
+import {
+  DefaultPermission,
+  Favorites,
+  Folder,
+  FolderListing,
+  PaginationParams,
+  Spreadsheet,
+  Workspace,
+  WorkspaceMember,
+} from '../types';
+
+export class WorkspaceService {
+  async getWorkspaces(): Promise<Workspace[]> {
+    return apiService.get('/api/workspaces');
+  }
+
+  async createWorkspace(name: string, defaultPermission?: DefaultPermission): Promise<Workspace> {
+    return apiService.post('/api/workspaces', { name, default_permission: defaultPermission });
+  }
+
+  async updateWorkspace(id: string, data: { name?: string; default_permission?: DefaultPermission }): Promise<Workspace> {
+    return apiService.put(`/api/workspaces/${id}`, data);
+  }
+
+  async deleteWorkspace(id: string): Promise<void> {
+    return apiService.delete(`/api/workspaces/${id}`);
+  }
+
+  async getMembers(workspaceId: string): Promise<WorkspaceMember[]> {
+    return apiService.get(`/api/workspaces/${workspaceId}/members`);
+  }
+
+  async addMember(workspaceId: string, email: string, role?: WorkspaceMember['role']): Promise<WorkspaceMember> {
+    return apiService.post(`/api/workspaces/${workspaceId}/members`, { email, role });
+  }
+
+  async removeMember(workspaceId: string, userId: string): Promise<void> {
+    return apiService.delete(`/api/workspaces/${workspaceId}/members/${userId}`);
+  }
+
+  /** Folders and spreadsheets at the workspace root */
+  async getContents(workspaceId: string, params?: PaginationParams): Promise<FolderListing> {
+    return apiService.get(`/api/workspaces/${workspaceId}/contents`, params);
+  }
+
+  async createFolder(
+    workspaceId: string,
+    name: string,
+    parentId?: string | null,
+    defaultPermission?: DefaultPermission,
+  ): Promise<Folder> {
+    return apiService.post(`/api/workspaces/${workspaceId}/folders`, {
+      name,
+      parent_id: parentId ?? undefined,
+      default_permission: defaultPermission,
+    });
+  }
+
+  /** A folder's subfolders and spreadsheets, with its path from the workspace root */
+  async getFolder(folderId: string, params?: PaginationParams): Promise<FolderListing> {
+    return apiService.get(`/api/folders/${folderId}`, params);
+  }
+
+  async updateFolder(folderId: string, data: { name?: string; default_permission?: DefaultPermission }): Promise<Folder> {
+    return apiService.put(`/api/folders/${folderId}`, data);
+  }
+
+  async moveFolder(folderId: string, parentId: string | null): Promise<Folder> {
+    return apiService.post(`/api/folders/${folderId}/move`, { parent_id: parentId });
+  }
+
+  async deleteFolder(folderId: string): Promise<void> {
+    return apiService.delete(`/api/folders/${folderId}`);
+  }
+
+  /** File a spreadsheet in a folder, at a workspace root, or (with neither) out of workspaces */
+  async moveSpreadsheet(spreadsheetId: string, target: { workspace_id?: string; folder_id?: string }): Promise<Spreadsheet> {
+    return apiService.post(`/api/spreadsheets/${spreadsheetId}/move`, target);
+  }
+
+  async getFavorites(): Promise<Favorites> {
+    return apiService.get('/api/favorites');
+  }
+
+  async setSpreadsheetFavorite(spreadsheetId: string, favorite: boolean): Promise<void> {
+    const url = `/api/spreadsheets/${spreadsheetId}/favorite`;
+    return favorite ? apiService.put(url) : apiService.delete(url);
+  }
+
+  async setFolderFavorite(folderId: string, favorite: boolean): Promise<void> {
+    const url = `/api/folders/${folderId}/favorite`;
+    return favorite ? apiService.put(url) : apiService.delete(url);
+  }
+}
+
+export const workspaceService = new WorkspaceService();
@@ -34,6 +34,10 @@ export interface Spreadsheet {
   updated_at: string;
   is_public: boolean;
   settings: Record<string, any>;
+  workspace_id?: string | null;
+  folder_id?: string | null;
+  organization_id: string;
+  team_id?: string | null;
 }
 
 export interface SpreadsheetColumn {
@@ -82,6 +86,8 @@ export interface CreateSpreadsheetRequest {
   is_public?: boolean;
   settings?: Record<string, any>;
   columns?: CreateColumnRequest[];
+  workspace_id?: string;
+  folder_id?: string;
 }
 
 export interface CreateColumnRequest {
@@ -105,6 +111,7 @@ export interface UpdateRowRequest {
 export interface PaginationParams {
   page?: number;
   limit?: number;
+  cursor?: string;
 }
 
 export interface PaginatedResponse<T> {
@@ -115,6 +122,106 @@ export interface PaginatedResponse<T> {
   total_pages: number;
   has_next: boolean;
   has_prev: boolean;
+  next_cursor?: string | null;
+  prev_cursor?: string | null;
+}
+
+export type DefaultPermission = 'inherit' | 'none' | 'view' | 'edit' | 'admin';
+
+export interface Workspace {
+  id: string;
+  name: string;
+  owner_id: string;
+  organization_id: string;
+  default_permission: Exclude<DefaultPermission, 'inherit'>;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface WorkspaceMember {
+  user_id: string;
+  name: string;
+  email: string;
+  role: 'admin' | 'member';
+  added_at: string;
+}
+
+export interface Folder {
+  id: string;
+  workspace_id: string;
+  parent_id: string | null;
+  name: string;
+  default_permission: DefaultPermission;
+  created_by: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface FolderSummary extends Folder {
+  folder_count: number;
+  spreadsheet_count: number;
+}
+
+export interface FolderListing {
+  workspace: Workspace;
+  folder: Folder | null;
+  path: Folder[];
+  permission: DefaultPermission | null;
+  folders: FolderSummary[];
+  spreadsheets: PaginatedResponse<Spreadsheet>;
+}
+
+export interface Favorites {
+  spreadsheets: Spreadsheet[];
+  folders: Folder[];
+}
+
+export type OrganizationRole = 'owner' | 'admin' | 'member';
+
+export interface Organization {
+  id: string;
+  name: string;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface OrganizationMembership extends Organization {
+  role: OrganizationRole;
+}
+
+export interface OrganizationMember {
+  user_id: string;
+  name: string;
+  email: string;
+  role: OrganizationRole;
+  joined_at: string;
+}
+
+export interface OrganizationInvitation {
+  id: string;
+  organization_id: string;
+  organization_name: string;
+  email: string;
+  role: OrganizationRole;
+  invited_by: string | null;
+  created_at: string;
+}
+
+export interface Team {
+  id: string;
+  organization_id: string;
+  name: string;
+  description: string | null;
+  created_at: string;
+  updated_at: string;
+}
+
+export interface TeamMember {
+  user_id: string;
+  name: string;
+  email: string;
+  role: 'lead' | 'member';
+  added_at: string;
 }
 
 export interface ApiResponse<T> {
@@ -124,6 +231,58 @@ export interface ApiResponse<T> {
   message?: string;
 }
 
+/** Someone viewing a spreadsheet, from a `PresenceSnapshot` */
+export interface PresenceUser {
+  user_id: string;
+  user_name: string;
+  color: string;
+  row_id: string | null;
+  column_id: string | null;
+}
+
+/** Reply to a row op sent over the spreadsheet WebSocket */
+export type RowOpResult =
+  | { type: 'OpAck'; op_id: string; row: SpreadsheetRow | null; seq: number | null }
+  | { type: 'OpNack'; op_id: string; message: string; code: string };
+
+/** An advisory lock on a cell, held while someone has its editor open */
+export interface CellLock {
+  row_id: string;
+  column_id: string;
+  user_id: string;
+  user_name: string;
+  expires_at: string;
+}
+
+export type CellLockMessage =
+  | { type: 'CellLocked'; spreadsheet_id: string; lock: CellLock }
+  | { type: 'CellUnlocked'; spreadsheet_id: string; row_id: string; column_id: string; user_id: string };
+
+/** Collaborative text editing messages; `state` and `changes` are base64 Automerge bytes */
+export type TextCellMessage =
+  | { type: 'TextSnapshot'; spreadsheet_id: string; row_id: string; column_id: string; state: string; text: string }
+  | {
+      type: 'TextEdited';
+      spreadsheet_id: string;
+      row_id: string;
+      column_id: string;
+      changes: string;
+      text: string;
+      edited_by: string;
+    };
+
+// Sent once a spreadsheet socket has caught up. ResyncRequired means reload instead;
+// it also comes when the server may have lost messages for the spreadsheet.
+export type SocketSessionMessage =
+  | { type: 'Session'; spreadsheet_id: string; resume_token: string; seq: number }
+  | { type: 'ResyncRequired'; spreadsheet_id: string; seq: number }
+  | { type: 'Error'; message: string; code?: string };
+
+export interface SocketResume {
+  token: string;
+  lastSeq: number;
+}
+
 export interface WebSocketMessage {
   type: 'spreadsheet_updated' | 'row_created' | 'row_updated' | 'row_deleted' | 'user_joined' | 'user_left';
   spreadsheet_id: string;
@@ -0,0 +1,14 @@
+-- Extend spreadsheet column types to match common::models::ColumnType
+-- Adds multi_select, email, url, percent, person and checkbox
+
+-- Drop the existing check constraint
+ALTER TABLE spreadsheet_columns
+DROP CONSTRAINT IF EXISTS spreadsheet_columns_column_type_check;
+
+-- Add new check constraint with the additional types
+ALTER TABLE spreadsheet_columns
+ADD CONSTRAINT spreadsheet_columns_column_type_check
+CHECK (column_type IN (
+    'text', 'number', 'date', 'boolean', 'select', 'currency',
+    'multi_select', 'email', 'url', 'percent', 'person', 'checkbox'
+));
@@ -0,0 +1,25 @@
+-- Exchange rates for multi-currency Currency columns
+-- A row means: 1 unit of base_currency = rate units of quote_currency on rate_date
+
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    base_currency VARCHAR(3) NOT NULL,
+    quote_currency VARCHAR(3) NOT NULL,
+    rate DOUBLE PRECISION NOT NULL CHECK (rate > 0),
+    rate_date DATE NOT NULL,
+    source VARCHAR(100),
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT unique_exchange_rate UNIQUE (base_currency, quote_currency, rate_date),
+    CONSTRAINT exchange_rate_distinct_currencies CHECK (base_currency <> quote_currency)
+);
+
+CREATE INDEX IF NOT EXISTS idx_exchange_rates_pair_date ON exchange_rates(base_currency, quote_currency, rate_date DESC);
+
+CREATE TRIGGER update_exchange_rates_updated_at BEFORE UPDATE ON exchange_rates
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Existing spreadsheets report in USD until configured otherwise
+UPDATE spreadsheets
+SET settings = COALESCE(settings, '{}'::jsonb) || '{"reporting_currency": "USD"}'::jsonb
+WHERE NOT (COALESCE(settings, '{}'::jsonb) ? 'reporting_currency');
@@ -0,0 +1,22 @@
+-- Per-spreadsheet change feed
+-- Every mutation appends an event with a sequence number that increases by one
+-- per spreadsheet, so clients can detect gaps and catch up with
+-- GET /spreadsheets/{id}/changes?since=<seq>
+
+CREATE TABLE IF NOT EXISTS spreadsheet_event_sequences (
+    spreadsheet_id UUID PRIMARY KEY REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    last_seq BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE TABLE IF NOT EXISTS spreadsheet_events (
+    id BIGSERIAL PRIMARY KEY,
+    spreadsheet_id UUID NOT NULL REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    seq BIGINT NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    actor_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT unique_spreadsheet_event_seq UNIQUE (spreadsheet_id, seq)
+);
+
+CREATE INDEX IF NOT EXISTS idx_spreadsheet_events_created_at ON spreadsheet_events(created_at);
@@ -0,0 +1,36 @@
+-- Per-spreadsheet "when X then Y" automation rules
+-- trigger and actions hold the JSON forms of AutomationTrigger and AutomationAction
+
+CREATE TABLE IF NOT EXISTS automation_rules (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    spreadsheet_id UUID NOT NULL REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    trigger JSONB NOT NULL,
+    actions JSONB NOT NULL DEFAULT '[]',
+    is_active BOOLEAN NOT NULL DEFAULT true,
+    created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_automation_rules_spreadsheet ON automation_rules(spreadsheet_id) WHERE is_active;
+CREATE INDEX IF NOT EXISTS idx_automation_rules_trigger_type ON automation_rules((trigger->>'type')) WHERE is_active;
+
+CREATE TRIGGER update_automation_rules_updated_at BEFORE UPDATE ON automation_rules
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+-- Run log; trigger_key makes scheduled triggers fire once per row/date or todo
+CREATE TABLE IF NOT EXISTS automation_runs (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    rule_id UUID NOT NULL REFERENCES automation_rules(id) ON DELETE CASCADE,
+    row_id UUID REFERENCES spreadsheet_rows(id) ON DELETE SET NULL,
+    todo_id UUID REFERENCES todos(id) ON DELETE SET NULL,
+    trigger_key VARCHAR(255),
+    status VARCHAR(20) NOT NULL DEFAULT 'running' CHECK (status IN ('running', 'succeeded', 'failed')),
+    results JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_automation_runs_rule ON automation_runs(rule_id, created_at DESC);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_automation_runs_trigger_key ON automation_runs(rule_id, trigger_key) WHERE trigger_key IS NOT NULL;
@@ -0,0 +1,9 @@
+-- Indexes matching the (sort key, id) order used by cursor pagination.
+-- Timestamps are nullable, so lists sort on COALESCE(ts, 'epoch') and the
+-- indexes use the same expression.
+
+CREATE INDEX IF NOT EXISTS idx_spreadsheet_rows_keyset ON spreadsheet_rows(spreadsheet_id, position, id);
+CREATE INDEX IF NOT EXISTS idx_spreadsheets_keyset ON spreadsheets((COALESCE(updated_at, 'epoch'::timestamptz)), id);
+CREATE INDEX IF NOT EXISTS idx_todos_spreadsheet_keyset ON todos(spreadsheet_id, (COALESCE(created_at, 'epoch'::timestamptz)), id);
+CREATE INDEX IF NOT EXISTS idx_users_keyset ON users((COALESCE(created_at, 'epoch'::timestamptz)), id) WHERE is_active = true;
+CREATE INDEX IF NOT EXISTS idx_discovery_sessions_keyset ON discovery_sessions(account_id, user_id, created_at, id);
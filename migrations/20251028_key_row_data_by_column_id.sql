@@ -0,0 +1,22 @@
+-- Store row cells under the column id instead of the column name, so that
+-- renaming a column keeps its cells and two columns can share a label.
+-- Keys that don't match any column of the row's spreadsheet are left as they are.
+
+UPDATE spreadsheet_rows r
+SET row_data = keyed.row_data
+FROM (
+    SELECT r2.id, jsonb_object_agg(COALESCE(c.id::text, cell.key), cell.value) AS row_data
+    FROM spreadsheet_rows r2
+    CROSS JOIN LATERAL jsonb_each(r2.row_data) AS cell
+    LEFT JOIN LATERAL (
+        -- If a name was somehow used twice, the cells go to the first column
+        SELECT id FROM spreadsheet_columns
+        WHERE spreadsheet_id = r2.spreadsheet_id AND name = cell.key
+        ORDER BY position, created_at
+        LIMIT 1
+    ) c ON true
+    WHERE jsonb_typeof(r2.row_data) = 'object'
+    GROUP BY r2.id
+) keyed
+WHERE r.id = keyed.id
+  AND r.row_data IS DISTINCT FROM keyed.row_data;
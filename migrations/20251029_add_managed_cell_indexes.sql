@@ -0,0 +1,86 @@
+-- Per-spreadsheet expression indexes on row cells, managed by contrivance-service.
+--
+-- The hard-coded indexes below key on cell names our templates never used
+-- (and, since cells moved to column ids, no longer match anything). Indexes
+-- are now created per column, partial on the spreadsheet, with
+-- CREATE INDEX CONCURRENTLY, either because the column sets
+-- display_options.indexed or because it is filtered on often.
+
+DROP INDEX IF EXISTS idx_row_data_status;
+DROP INDEX IF EXISTS idx_row_data_stage;
+DROP INDEX IF EXISTS idx_row_data_value;
+DROP INDEX IF EXISTS idx_row_data_company;
+DROP INDEX IF EXISTS idx_row_data_owner;
+
+-- Typed views of a cell for indexing and filtering. They return NULL rather
+-- than raising for values that don't parse, so a stray cell can never fail
+-- a write or an index build.
+
+-- Numbers, numeric strings ("1,500", "45%", "$20") and currency cells
+-- ({"amount": 1500, "currency": "EUR"}, compared by amount)
+CREATE OR REPLACE FUNCTION cell_numeric(cell jsonb) RETURNS numeric
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    value jsonb := CASE WHEN jsonb_typeof(cell) = 'object' THEN cell->'amount' ELSE cell END;
+    digits text;
+BEGIN
+    IF jsonb_typeof(value) = 'number' THEN
+        RETURN value::numeric;
+    ELSIF jsonb_typeof(value) = 'string' THEN
+        digits := regexp_replace(value #>> '{}', '[^0-9.+-]', '', 'g');
+        IF digits ~ '^[+-]?([0-9]+\.?[0-9]*|\.[0-9]+)$' THEN
+            RETURN digits::numeric;
+        END IF;
+    END IF;
+    RETURN NULL;
+EXCEPTION WHEN others THEN
+    RETURN NULL;
+END;
+$$;
+
+-- YYYY-MM-DD or RFC 3339 strings, by their calendar date
+CREATE OR REPLACE FUNCTION cell_date(cell jsonb) RETURNS date
+LANGUAGE plpgsql IMMUTABLE PARALLEL SAFE AS $$
+DECLARE
+    value text := cell #>> '{}';
+BEGIN
+    IF jsonb_typeof(cell) = 'string' AND value ~ '^\s*\d{4}-\d{2}-\d{2}' THEN
+        value := btrim(value);
+        RETURN make_date(substr(value, 1, 4)::int, substr(value, 6, 2)::int, substr(value, 9, 2)::int);
+    END IF;
+    RETURN NULL;
+EXCEPTION WHEN others THEN
+    RETURN NULL;
+END;
+$$;
+
+-- true/false and "yes"/"no"/"1"/"0" strings
+CREATE OR REPLACE FUNCTION cell_bool(cell jsonb) RETURNS boolean
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT CASE
+        WHEN jsonb_typeof(cell) = 'boolean' THEN cell::text::boolean
+        WHEN lower(btrim(cell #>> '{}')) IN ('true', 'yes', '1') THEN true
+        WHEN lower(btrim(cell #>> '{}')) IN ('false', 'no', '0') THEN false
+    END
+$$;
+
+-- How often each column has been filtered on
+CREATE TABLE IF NOT EXISTS spreadsheet_column_usage (
+    column_id UUID PRIMARY KEY REFERENCES spreadsheet_columns(id) ON DELETE CASCADE,
+    spreadsheet_id UUID NOT NULL REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    filter_count BIGINT NOT NULL DEFAULT 0,
+    last_filtered_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Indexes the service has built. Deliberately no foreign keys: the record
+-- has to outlive its column or spreadsheet so the index can be dropped.
+CREATE TABLE IF NOT EXISTS spreadsheet_cell_indexes (
+    column_id UUID PRIMARY KEY,
+    spreadsheet_id UUID NOT NULL,
+    index_name VARCHAR(63) NOT NULL UNIQUE,
+    definition TEXT NOT NULL,
+    reason VARCHAR(20) NOT NULL CHECK (reason IN ('declared', 'usage')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_spreadsheet_cell_indexes_spreadsheet ON spreadsheet_cell_indexes(spreadsheet_id);
@@ -0,0 +1,93 @@
+-- Workspaces and nested folders for organising spreadsheets, e.g.
+-- "FY26 / EMEA / Enterprise".
+--
+-- Workspace members get access to the spreadsheets inside according to the
+-- nearest folder (walking up to the workspace) with a default permission
+-- set; 'inherit' defers to the parent and 'none' grants nothing. Workspace
+-- admins, including the owner, get admin on everything in the workspace.
+-- Spreadsheet owners and collaborators keep their own access as before.
+
+CREATE TABLE IF NOT EXISTS workspaces (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    default_permission VARCHAR(20) NOT NULL DEFAULT 'none'
+        CHECK (default_permission IN ('none', 'view', 'edit', 'admin')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS workspace_members (
+    workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'member' CHECK (role IN ('admin', 'member')),
+    added_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (workspace_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_workspace_members_user ON workspace_members(user_id);
+
+CREATE TABLE IF NOT EXISTS folders (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    workspace_id UUID NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+    parent_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    default_permission VARCHAR(20) NOT NULL DEFAULT 'inherit'
+        CHECK (default_permission IN ('inherit', 'none', 'view', 'edit', 'admin')),
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Sibling folders can't share a name
+CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_sibling_name ON folders(
+    workspace_id,
+    COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'::uuid),
+    lower(name)
+);
+CREATE INDEX IF NOT EXISTS idx_folders_parent ON folders(parent_id);
+
+-- Removing a workspace leaves its spreadsheets unfiled rather than deleting them
+ALTER TABLE spreadsheets
+    ADD COLUMN IF NOT EXISTS workspace_id UUID REFERENCES workspaces(id) ON DELETE SET NULL,
+    ADD COLUMN IF NOT EXISTS folder_id UUID REFERENCES folders(id) ON DELETE SET NULL;
+
+CREATE INDEX IF NOT EXISTS idx_spreadsheets_workspace_folder ON spreadsheets(workspace_id, folder_id);
+CREATE INDEX IF NOT EXISTS idx_spreadsheets_folder ON spreadsheets(folder_id);
+
+-- Pinned spreadsheets and folders, per user
+CREATE TABLE IF NOT EXISTS favorites (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    spreadsheet_id UUID REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    folder_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((spreadsheet_id IS NULL) <> (folder_id IS NULL))
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_favorites_spreadsheet ON favorites(user_id, spreadsheet_id) WHERE spreadsheet_id IS NOT NULL;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_favorites_folder ON favorites(user_id, folder_id) WHERE folder_id IS NOT NULL;
+
+-- The permission a user gets on spreadsheets in a workspace folder (or at
+-- the workspace root when p_folder is NULL), or NULL for none
+CREATE OR REPLACE FUNCTION workspace_permission(p_user UUID, p_workspace UUID, p_folder UUID)
+RETURNS VARCHAR LANGUAGE sql STABLE AS $$
+    WITH RECURSIVE chain AS (
+        SELECT id, parent_id, default_permission, 0 AS depth
+        FROM folders WHERE id = p_folder AND workspace_id = p_workspace
+        UNION ALL
+        SELECT f.id, f.parent_id, f.default_permission, c.depth + 1
+        FROM folders f JOIN chain c ON f.id = c.parent_id
+        WHERE c.depth < 64
+    )
+    SELECT CASE
+        WHEN m.role = 'admin' OR w.owner_id = p_user THEN 'admin'
+        ELSE NULLIF(COALESCE(
+            (SELECT default_permission FROM chain WHERE default_permission <> 'inherit' ORDER BY depth LIMIT 1),
+            w.default_permission
+        ), 'none')
+    END
+    FROM workspaces w
+    JOIN workspace_members m ON m.workspace_id = w.id AND m.user_id = p_user
+    WHERE w.id = p_workspace
+$$;
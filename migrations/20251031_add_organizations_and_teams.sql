@@ -0,0 +1,127 @@
+-- Organizations are the tenant boundary: every user belongs to exactly one,
+-- and spreadsheets, workspaces and discovery sessions belong to the
+-- organization they were created in. Nothing is visible across organizations,
+-- public spreadsheets included.
+--
+-- Organization owners and admins get admin on every spreadsheet in the
+-- organization. Teams group members; a spreadsheet owned by a team gives its
+-- members edit and its leads admin.
+
+CREATE TABLE IF NOT EXISTS organizations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One row per user: the user's organization and their role in it
+CREATE TABLE IF NOT EXISTS organization_members (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_organization_members_org ON organization_members(organization_id);
+
+CREATE TABLE IF NOT EXISTS teams (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_teams_org_name ON teams(organization_id, lower(name));
+
+CREATE TABLE IF NOT EXISTS team_members (
+    team_id UUID NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL DEFAULT 'member' CHECK (role IN ('lead', 'member')),
+    added_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (team_id, user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_team_members_user ON team_members(user_id);
+
+-- The organization a user belongs to
+CREATE OR REPLACE FUNCTION user_organization(p_user UUID)
+RETURNS UUID LANGUAGE sql STABLE AS $$
+    SELECT organization_id FROM organization_members WHERE user_id = p_user
+$$;
+
+-- The access a user gets on a spreadsheet through its organization and team,
+-- or NULL for none. Doesn't check that the user is in the organization.
+CREATE OR REPLACE FUNCTION tenant_permission(p_user UUID, p_team UUID)
+RETURNS VARCHAR LANGUAGE sql STABLE AS $$
+    SELECT CASE
+        WHEN EXISTS (
+            SELECT 1 FROM organization_members
+            WHERE user_id = p_user AND role IN ('owner', 'admin')
+        ) THEN 'admin'
+        ELSE (
+            SELECT CASE tm.role WHEN 'lead' THEN 'admin' ELSE 'edit' END
+            FROM team_members tm
+            WHERE tm.team_id = p_team AND tm.user_id = p_user
+        )
+    END
+$$;
+
+-- Existing users all shared one space, so they start out in one organization.
+-- The first admin (or failing that, the first user) owns it and the other
+-- global admins become organization admins.
+DO $$
+DECLARE
+    v_org UUID;
+BEGIN
+    IF EXISTS (SELECT 1 FROM users) AND NOT EXISTS (SELECT 1 FROM organizations) THEN
+        INSERT INTO organizations (name) VALUES ('Default') RETURNING id INTO v_org;
+
+        INSERT INTO organization_members (user_id, organization_id, role)
+        SELECT id, v_org,
+            CASE
+                WHEN ROW_NUMBER() OVER (ORDER BY role <> 'admin', created_at, id) = 1 THEN 'owner'
+                WHEN role = 'admin' THEN 'admin'
+                ELSE 'member'
+            END
+        FROM users;
+    END IF;
+END $$;
+
+-- New users get an organization of their own, which they own, until an
+-- organization admin adds them to theirs
+CREATE OR REPLACE FUNCTION create_personal_organization()
+RETURNS TRIGGER LANGUAGE plpgsql AS $$
+DECLARE
+    v_org UUID;
+BEGIN
+    INSERT INTO organizations (name) VALUES (NEW.name) RETURNING id INTO v_org;
+    INSERT INTO organization_members (user_id, organization_id, role) VALUES (NEW.id, v_org, 'owner');
+    RETURN NEW;
+END $$;
+
+DROP TRIGGER IF EXISTS users_personal_organization ON users;
+CREATE TRIGGER users_personal_organization
+    AFTER INSERT ON users
+    FOR EACH ROW EXECUTE FUNCTION create_personal_organization();
+
+-- Tenant-owned data
+ALTER TABLE spreadsheets
+    ADD COLUMN IF NOT EXISTS organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    ADD COLUMN IF NOT EXISTS team_id UUID REFERENCES teams(id) ON DELETE SET NULL;
+ALTER TABLE workspaces ADD COLUMN IF NOT EXISTS organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE;
+ALTER TABLE discovery_sessions ADD COLUMN IF NOT EXISTS organization_id UUID REFERENCES organizations(id) ON DELETE CASCADE;
+
+UPDATE spreadsheets SET organization_id = user_organization(owner_id) WHERE organization_id IS NULL;
+UPDATE workspaces SET organization_id = user_organization(owner_id) WHERE organization_id IS NULL;
+UPDATE discovery_sessions SET organization_id = user_organization(user_id) WHERE organization_id IS NULL;
+
+ALTER TABLE spreadsheets ALTER COLUMN organization_id SET NOT NULL;
+ALTER TABLE workspaces ALTER COLUMN organization_id SET NOT NULL;
+ALTER TABLE discovery_sessions ALTER COLUMN organization_id SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_spreadsheets_organization ON spreadsheets(organization_id);
+CREATE INDEX IF NOT EXISTS idx_spreadsheets_team ON spreadsheets(team_id) WHERE team_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_workspaces_organization ON workspaces(organization_id);
+CREATE INDEX IF NOT EXISTS idx_discovery_sessions_organization ON discovery_sessions(organization_id);
@@ -0,0 +1,16 @@
+-- Short-lived, single-use tickets for opening a WebSocket.
+--
+-- Browsers can't set an Authorization header on a WebSocket upgrade, so a
+-- client trades its JWT for a ticket over REST and passes it as ?ticket= on
+-- the socket URL. Only the SHA-256 of the ticket is stored. The socket stays
+-- open until the JWT the ticket was issued for would have expired.
+
+CREATE TABLE websocket_tickets (
+    ticket_hash VARCHAR(64) PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    session_expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_websocket_tickets_expires_at ON websocket_tickets (expires_at);
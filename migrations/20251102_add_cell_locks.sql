@@ -0,0 +1,21 @@
+-- Advisory locks on cells open in someone's editor.
+--
+-- A client takes a lock over the WebSocket when it opens a cell editor and
+-- renews it while the editor stays open. Peers are shown who holds it, and
+-- writes to the cell by anyone else are refused until it's released, its
+-- socket closes or it lapses. Locks live here rather than in one instance's
+-- memory so every replica sees them.
+
+CREATE TABLE cell_locks (
+    row_id UUID NOT NULL REFERENCES spreadsheet_rows(id) ON DELETE CASCADE,
+    column_id UUID NOT NULL REFERENCES spreadsheet_columns(id) ON DELETE CASCADE,
+    spreadsheet_id UUID NOT NULL REFERENCES spreadsheets(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- The socket holding the lock, so it can be released when that socket closes
+    connection_id UUID NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (row_id, column_id)
+);
+
+CREATE INDEX idx_cell_locks_spreadsheet_id ON cell_locks (spreadsheet_id);
+CREATE INDEX idx_cell_locks_connection_id ON cell_locks (connection_id);
@@ -0,0 +1,16 @@
+-- Collaborative editing state for text cells.
+--
+-- A text cell that several people edit at once gets an Automerge document
+-- holding its text. Clients send their changes over the WebSocket and the
+-- server merges them here, so concurrent edits are combined rather than the
+-- last save winning. The merged text is also written to the cell in
+-- `spreadsheet_rows.row_data`, which stays the value everything else reads;
+-- `state` is the document compacted after each merge.
+
+CREATE TABLE cell_documents (
+    row_id UUID NOT NULL REFERENCES spreadsheet_rows(id) ON DELETE CASCADE,
+    column_id UUID NOT NULL REFERENCES spreadsheet_columns(id) ON DELETE CASCADE,
+    state BYTEA NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (row_id, column_id)
+);
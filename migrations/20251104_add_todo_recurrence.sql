@@ -0,0 +1,19 @@
+-- Recurring todos.
+--
+-- Each occurrence of a recurring todo is its own row, linked to the others by
+-- `series_id`. Every row of a series carries the RFC 5545 rule, the time zone
+-- it is read in and the series' first due date (its DTSTART), so completing
+-- the open occurrence can create the next one from the row alone.
+-- `scheduled_at` is when the rule put an occurrence; its `due_date` can be
+-- moved without shifting the rest of the series.
+
+ALTER TABLE todos
+    ADD COLUMN series_id UUID,
+    ADD COLUMN recurrence TEXT,
+    ADD COLUMN recurrence_timezone TEXT,
+    ADD COLUMN series_start TIMESTAMPTZ,
+    ADD COLUMN scheduled_at TIMESTAMPTZ;
+
+-- Also stops an occurrence being created twice when it's completed twice at once
+CREATE UNIQUE INDEX idx_todos_series_occurrence ON todos (series_id, scheduled_at)
+    WHERE series_id IS NOT NULL;
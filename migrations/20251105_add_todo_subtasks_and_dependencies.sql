@@ -0,0 +1,33 @@
+-- Subtasks, checklists and dependencies for todos.
+--
+-- A todo with `parent_id` is a subtask: deleting the parent deletes it, and
+-- the parent is completed once all its subtasks are. Checklist items are
+-- lightweight steps within one todo. A dependency makes a todo wait until the
+-- todo it's blocked by has been completed; the service refuses dependencies
+-- and parents that would make a todo wait on itself.
+
+ALTER TABLE todos ADD COLUMN parent_id UUID REFERENCES todos(id) ON DELETE CASCADE;
+
+CREATE INDEX idx_todos_parent_id ON todos (parent_id) WHERE parent_id IS NOT NULL;
+
+CREATE TABLE todo_checklist_items (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    todo_id UUID NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    title VARCHAR(500) NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT false,
+    position INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_todo_checklist_items_todo_id ON todo_checklist_items (todo_id, position);
+
+CREATE TABLE todo_dependencies (
+    todo_id UUID NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    blocked_by_id UUID NOT NULL REFERENCES todos(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (todo_id, blocked_by_id),
+    CHECK (todo_id <> blocked_by_id)
+);
+
+CREATE INDEX idx_todo_dependencies_blocked_by_id ON todo_dependencies (blocked_by_id);
@@ -0,0 +1,17 @@
+-- Joining an organization takes an invitation the user accepts. Invitations
+-- are addressed by email, so they can be sent before the user signs up and
+-- don't reveal whether an account exists.
+
+CREATE TABLE IF NOT EXISTS organization_invitations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role VARCHAR(20) NOT NULL DEFAULT 'member' CHECK (role IN ('owner', 'admin', 'member')),
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One pending invitation per address and organization; inviting again replaces it
+CREATE UNIQUE INDEX IF NOT EXISTS idx_organization_invitations_email
+    ON organization_invitations(organization_id, lower(email));
+CREATE INDEX IF NOT EXISTS idx_organization_invitations_recipient ON organization_invitations(lower(email));
@@ -0,0 +1,4 @@
+-- Email bodies are only needed until the email is sent or given up on; they
+-- can carry secrets such as password reset links, so they are cleared then.
+
+UPDATE email_outbox SET html_body = '', text_body = '' WHERE status <> 'pending';
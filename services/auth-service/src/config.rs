@@ -8,6 +8,9 @@ pub struct Config {
     pub jwt_expiration_hours: i64,
     pub refresh_expiration_days: i64,
     pub cors_origins: Vec<String>,
+    /// Frontend base URL, used for links in password reset emails
+    pub app_url: String,
+    pub password_reset_expiration_minutes: i64,
 }
 
 impl Config {
@@ -25,6 +28,10 @@ impl Config {
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .collect(),
+            app_url: EnvUtils::get_var("APP_URL", "http://localhost:3000")
+                .trim_end_matches('/')
+                .to_string(),
+            password_reset_expiration_minutes: EnvUtils::get_var_as_int("PASSWORD_RESET_EXPIRATION_MINUTES", 60).max(1) as i64,
         }
     }
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
 use actix_web::{web, HttpRequest, HttpResponse, Result};
-use common::{ApiResponse, CreateUserRequest, LoginRequest, HttpUtils};
+use common::{
+    ApiResponse, CreateUserRequest, LoginRequest, HttpUtils, PasswordResetRequest,
+    ConfirmPasswordResetRequest,
+};
 use crate::service::AuthService;
 use tracing::{info, warn, error};
 
@@ -165,6 +168,45 @@ pub async fn logout(
     }
 }
 
+/// Request a password reset email
+pub async fn request_password_reset(
+    auth_service: web::Data<AuthService>,
+    request: web::Json<PasswordResetRequest>,
+) -> Result<HttpResponse> {
+    match auth_service.request_password_reset(request.into_inner()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+            (),
+            "If that email has an account, a reset link is on its way".to_string(),
+        ))),
+        Err(err) => {
+            warn!("Password reset request failed: {}", err);
+            Ok(HttpResponse::build(actix_web::http::StatusCode::from_u16(err.status_code()).unwrap())
+                .json(ApiResponse::<()>::error(err.to_string())))
+        }
+    }
+}
+
+/// Set a new password with a reset token
+pub async fn confirm_password_reset(
+    auth_service: web::Data<AuthService>,
+    request: web::Json<ConfirmPasswordResetRequest>,
+) -> Result<HttpResponse> {
+    match auth_service.confirm_password_reset(request.into_inner()).await {
+        Ok(()) => {
+            info!("Password reset completed");
+            Ok(HttpResponse::Ok().json(ApiResponse::success_with_message(
+                (),
+                "Password updated".to_string(),
+            )))
+        }
+        Err(err) => {
+            warn!("Password reset failed: {}", err);
+            Ok(HttpResponse::build(actix_web::http::StatusCode::from_u16(err.status_code()).unwrap())
+                .json(ApiResponse::<()>::error(err.to_string())))
+        }
+    }
+}
+
 /// Health check endpoint
 pub async fn health_check(auth_service: web::Data<AuthService>) -> Result<HttpResponse> {
     match auth_service.health_check().await {
@@ -41,7 +41,8 @@ async fn main() -> std::io::Result<()> {
         Some(config.jwt_expiration_hours),
         Some(config.refresh_expiration_days),
     );
-    let auth_service = service::AuthService::new(repository, jwt_service);
+    let outbox = common::mailer::EmailOutbox::new(database.pool().clone());
+    let auth_service = service::AuthService::new(repository, jwt_service, outbox, &config);
 
     // Start HTTP server
     HttpServer::new(move || {
@@ -63,6 +64,8 @@ async fn main() -> std::io::Result<()> {
             .route("/refresh", web::post().to(handlers::refresh_token))
             .route("/validate", web::get().to(handlers::validate_token))
             .route("/logout", web::post().to(handlers::logout))
+            .route("/password-reset/request", web::post().to(handlers::request_password_reset))
+            .route("/password-reset/confirm", web::post().to(handlers::confirm_password_reset))
             .route("/health", web::get().to(handlers::health_check))
     })
     .bind(format!("0.0.0.0:{}", config.port))?
@@ -195,4 +195,62 @@ impl AuthRepository {
             row.active_users.unwrap_or(0)
         ))
     }
-}
\ No newline at end of file
+
+    /// Store a new password reset token, invalidating any the user hasn't used yet
+    pub async fn create_password_reset_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> ContrivanceResult<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE password_reset_tokens SET used_at = NOW() WHERE user_id = $1 AND used_at IS NULL")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO password_reset_tokens (user_id, token_hash, expires_at) VALUES ($1, $2, $3)"
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Use a reset token to set a new password. Returns the user's id, or
+    /// `None` if the token is unknown, used or expired.
+    pub async fn reset_password(&self, token_hash: &str, password_hash: &str) -> ContrivanceResult<Option<Uuid>> {
+        let mut tx = self.pool.begin().await?;
+
+        let user_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            UPDATE password_reset_tokens
+            SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING user_id
+            "#
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some(user_id) = user_id else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE users SET password_hash = $2 WHERE id = $1 AND is_active = true")
+            .bind(user_id)
+            .bind(password_hash)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(Some(user_id))
+    }
+}
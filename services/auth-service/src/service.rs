@@ -1,9 +1,11 @@
 use common::{
     ContrivanceError, ContrivanceResult, User, CreateUserRequest, LoginRequest,
-    LoginResponse, UserResponse, JwtService,
+    LoginResponse, UserResponse, JwtService, PasswordResetRequest, ConfirmPasswordResetRequest,
 };
 use common::auth::{PasswordService, SessionService};
-use crate::repository::AuthRepository;
+use common::mailer::{EmailOutbox, EmailTemplate};
+use crate::{config::Config, repository::AuthRepository};
+use rand::{distributions::Alphanumeric, Rng};
 use uuid::Uuid;
 use validator::Validate;
 
@@ -11,13 +13,19 @@ use validator::Validate;
 pub struct AuthService {
     repository: AuthRepository,
     jwt_service: JwtService,
+    outbox: EmailOutbox,
+    app_url: String,
+    password_reset_expiration_minutes: i64,
 }
 
 impl AuthService {
-    pub fn new(repository: AuthRepository, jwt_service: JwtService) -> Self {
+    pub fn new(repository: AuthRepository, jwt_service: JwtService, outbox: EmailOutbox, config: &Config) -> Self {
         Self {
             repository,
             jwt_service,
+            outbox,
+            app_url: config.app_url.clone(),
+            password_reset_expiration_minutes: config.password_reset_expiration_minutes,
         }
     }
 
@@ -137,6 +145,50 @@ impl AuthService {
         Ok(())
     }
 
+    /// Email a single-use password reset link. Unknown emails succeed silently
+    /// so the endpoint can't be used to find out who has an account.
+    pub async fn request_password_reset(&self, request: PasswordResetRequest) -> ContrivanceResult<()> {
+        request.validate()?;
+
+        let Some(user) = self.repository.find_user_by_email(&request.email).await? else {
+            return Ok(());
+        };
+
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(48)
+            .map(char::from)
+            .collect();
+        let expires_at = chrono::Utc::now() + chrono::Duration::minutes(self.password_reset_expiration_minutes);
+        self.repository
+            .create_password_reset_token(user.id, &SessionService::generate_session_hash(&token), expires_at)
+            .await?;
+
+        let email = EmailTemplate::PasswordReset {
+            reset_url: format!("{}/reset-password?token={}", self.app_url, token),
+            expires_minutes: self.password_reset_expiration_minutes,
+        };
+        self.outbox.enqueue(Some(user.id), &user.email, &email, None).await?;
+
+        Ok(())
+    }
+
+    /// Set a new password with a reset token and sign the user out everywhere
+    pub async fn confirm_password_reset(&self, request: ConfirmPasswordResetRequest) -> ContrivanceResult<()> {
+        request.validate()?;
+        PasswordService::validate_password_strength(&request.new_password)?;
+
+        let password_hash = PasswordService::hash_password(&request.new_password)?;
+        let user_id = self.repository
+            .reset_password(&SessionService::generate_session_hash(&request.token), &password_hash)
+            .await?
+            .ok_or_else(|| ContrivanceError::validation("Invalid or expired reset token"))?;
+
+        self.repository.revoke_all_user_sessions(user_id).await?;
+
+        Ok(())
+    }
+
     /// Get user by token
     pub async fn get_user_by_token(&self, token: &str) -> ContrivanceResult<UserResponse> {
         self.validate_token(token).await
@@ -0,0 +1,699 @@
+use chrono::{NaiveDate, Utc};
+use common::{
+    cells::parse_date,
+    webhooks::{sign_payload, EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    AutomationAction, AutomationRule, AutomationTrigger, ContrivanceResult, CreateTodoRequest,
+    JwtService, Money, SpreadsheetColumn, SpreadsheetRow, Todo, TodoPriority, TodoTemplate,
+    NotificationKind, WebSocketMessage,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    events::EventPublisher,
+    notifications::{NewNotification, Notifier},
+    repository::ContrivanceRepository,
+    webhooks::WebhookClient,
+};
+
+/// How often close-date and overdue-todo triggers are checked
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Built-in todo template sets usable as `{"type": "create_todos", "template": "..."}`
+pub fn builtin_template(name: &str) -> Option<Vec<TodoTemplate>> {
+    let todo = |title: &str, priority: TodoPriority, due_in_days: i64| TodoTemplate {
+        title: title.to_string(),
+        description: None,
+        priority,
+        due_in_days: Some(due_in_days),
+        assigned_to: None,
+        assign_from_column: Some("Owner".to_string()),
+    };
+
+    match name {
+        "poc" => Some(vec![
+            todo("Agree POC success criteria with {{Opportunity Name}}", TodoPriority::High, 3),
+            todo("Schedule POC kickoff call", TodoPriority::High, 5),
+            todo("Provision POC environment", TodoPriority::Medium, 7),
+            todo("Run mid-point POC review", TodoPriority::Medium, 14),
+            todo("Deliver POC readout and confirm technical win", TodoPriority::High, 30),
+        ]),
+        _ => None,
+    }
+}
+
+/// Why a rule is firing, and the row or todo it applies to
+struct Firing {
+    spreadsheet_id: Uuid,
+    row: Option<SpreadsheetRow>,
+    todo: Option<Todo>,
+    /// Makes scheduled triggers fire once per key
+    trigger_key: Option<String>,
+}
+
+/// Evaluates automation rules on row writes and on a schedule, and runs their actions.
+///
+/// Row changes made by actions are written straight to the repository, so they
+/// are broadcast like any other edit but never re-trigger rules.
+#[derive(Clone)]
+pub struct AutomationEngine {
+    repository: ContrivanceRepository,
+    events: EventPublisher,
+    notifier: Notifier,
+    /// For the Salesforce service
+    client: reqwest::Client,
+    /// For rules' own webhook URLs
+    webhook_client: WebhookClient,
+    jwt_service: JwtService,
+    salesforce_service_url: String,
+}
+
+impl AutomationEngine {
+    pub fn new(
+        repository: ContrivanceRepository,
+        events: EventPublisher,
+        notifier: Notifier,
+        jwt_service: JwtService,
+        config: &Config,
+    ) -> Self {
+        Self {
+            repository,
+            events,
+            notifier,
+            client: reqwest::Client::new(),
+            webhook_client: WebhookClient::new(config.webhook_allow_private_targets),
+            jwt_service,
+            salesforce_service_url: config.salesforce_service_url.clone(),
+        }
+    }
+
+    /// Run rules for a created (`previous` is `None`) or updated row
+    pub async fn on_row_written(&self, previous: Option<&SpreadsheetRow>, row: &SpreadsheetRow) {
+        let rules = match self.repository.get_active_automation_rules(Some(row.spreadsheet_id), None).await {
+            Ok(rules) => rules,
+            Err(e) => {
+                error!("Failed to load automation rules for spreadsheet {}: {}", row.spreadsheet_id, e);
+                return;
+            }
+        };
+        if rules.is_empty() {
+            return;
+        }
+
+        let columns = match self.repository.get_spreadsheet_columns(row.spreadsheet_id).await {
+            Ok(columns) => columns,
+            Err(e) => {
+                error!("Failed to load columns for spreadsheet {}: {}", row.spreadsheet_id, e);
+                return;
+            }
+        };
+
+        for rule in rules {
+            let previous_data = previous.map(|p| &p.row_data);
+            if row_trigger_matches(&rule.trigger, &columns, previous_data, &row.row_data) {
+                let firing = Firing {
+                    spreadsheet_id: row.spreadsheet_id,
+                    row: Some(row.clone()),
+                    todo: None,
+                    trigger_key: None,
+                };
+                self.fire(&rule, firing).await;
+            }
+        }
+    }
+
+    /// Check scheduled triggers every few minutes until the process exits
+    pub async fn run(self) {
+        info!("Automation scheduler started");
+        loop {
+            if let Err(e) = self.run_scheduled().await {
+                error!("Scheduled automation run failed: {}", e);
+            }
+            tokio::time::sleep(SCHEDULE_INTERVAL).await;
+        }
+    }
+
+    /// Fire close-date and overdue-todo rules that haven't fired yet
+    pub async fn run_scheduled(&self) -> ContrivanceResult<()> {
+        let today = Utc::now().date_naive();
+
+        for rule in self.repository.get_active_automation_rules(None, Some("close_date_approaching")).await? {
+            let AutomationTrigger::CloseDateApproaching { column, days_before } = &rule.trigger else {
+                continue;
+            };
+            let columns = self.repository.get_spreadsheet_columns(rule.spreadsheet_id).await?;
+            let Some(date_column) = find_column(&columns, column) else {
+                continue;
+            };
+
+            for row in self.repository.get_spreadsheet_rows(rule.spreadsheet_id).await? {
+                let due = date_column
+                    .cell(&row.row_data)
+                    .and_then(|cell| close_date_due(cell, *days_before, today));
+                if let Some(date) = due {
+                    let firing = Firing {
+                        spreadsheet_id: rule.spreadsheet_id,
+                        trigger_key: Some(format!("row:{}:{}", row.id, date)),
+                        row: Some(row),
+                        todo: None,
+                    };
+                    self.fire(&rule, firing).await;
+                }
+            }
+        }
+
+        for rule in self.repository.get_active_automation_rules(None, Some("todo_overdue")).await? {
+            for todo in self.repository.get_overdue_todos(rule.spreadsheet_id).await? {
+                let row = match todo.row_id {
+                    Some(row_id) => self.repository.get_row(row_id).await?,
+                    None => None,
+                };
+                let firing = Firing {
+                    spreadsheet_id: rule.spreadsheet_id,
+                    trigger_key: Some(format!("todo:{}", todo.id)),
+                    row,
+                    todo: Some(todo),
+                };
+                self.fire(&rule, firing).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run a rule's actions in order, recording the outcome of each. A rule
+    /// whose creator can no longer edit the spreadsheet is skipped.
+    async fn fire(&self, rule: &AutomationRule, mut firing: Firing) {
+        let row_id = firing.row.as_ref().map(|r| r.id);
+        let todo_id = firing.todo.as_ref().map(|t| t.id);
+
+        let run_id = match self.repository
+            .start_automation_run(rule.id, row_id, todo_id, firing.trigger_key.as_deref())
+            .await
+        {
+            Ok(Some(run_id)) => run_id,
+            // Already fired for this trigger key
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to start automation run for rule {}: {}", rule.id, e);
+                return;
+            }
+        };
+
+        // Actions run as the creator, so they need to still be able to edit
+        let skipped = match self.repository.can_user_edit_spreadsheet(rule.created_by, firing.spreadsheet_id).await {
+            Ok(true) => None,
+            Ok(false) => Some("The rule's creator can no longer edit this spreadsheet".to_string()),
+            Err(e) => Some(format!("Failed to check the rule creator's access: {}", e)),
+        };
+        if let Some(reason) = skipped {
+            warn!("Skipping automation rule {}: {}", rule.id, reason);
+            let results = json!([{ "ok": false, "error": reason }]);
+            if let Err(e) = self.repository.finish_automation_run(run_id, false, &results).await {
+                error!("Failed to record automation run {}: {}", run_id, e);
+            }
+            return;
+        }
+
+        let columns = match self.repository.get_spreadsheet_columns(firing.spreadsheet_id).await {
+            Ok(columns) => columns,
+            Err(e) => {
+                error!("Failed to load columns for spreadsheet {}: {}", firing.spreadsheet_id, e);
+                Vec::new()
+            }
+        };
+
+        let mut results = Vec::with_capacity(rule.actions.len());
+        let mut succeeded = true;
+        for action in &rule.actions {
+            let action_type = serde_json::to_value(action)
+                .ok()
+                .and_then(|v| v.get("type").cloned())
+                .unwrap_or(Value::Null);
+
+            match self.apply(rule, action, &mut firing, &columns).await {
+                Ok(detail) => results.push(json!({ "action": action_type, "ok": true, "detail": detail })),
+                Err(reason) => {
+                    warn!("Automation rule {} action {} failed: {}", rule.id, action_type, reason);
+                    succeeded = false;
+                    results.push(json!({ "action": action_type, "ok": false, "error": reason }));
+                }
+            }
+        }
+
+        if let Err(e) = self.repository.finish_automation_run(run_id, succeeded, &Value::Array(results)).await {
+            error!("Failed to record automation run {}: {}", run_id, e);
+        }
+    }
+
+    async fn apply(
+        &self,
+        rule: &AutomationRule,
+        action: &AutomationAction,
+        firing: &mut Firing,
+        columns: &[SpreadsheetColumn],
+    ) -> Result<Value, String> {
+        let empty = Value::Object(Default::default());
+        let row_data = firing.row.as_ref().map(|r| &r.row_data).unwrap_or(&empty);
+
+        match action {
+            AutomationAction::CreateTodos { template, todos } => {
+                let mut templates = match template {
+                    Some(name) => builtin_template(name).ok_or_else(|| format!("Unknown todo template '{}'", name))?,
+                    None => Vec::new(),
+                };
+                templates.extend(todos.iter().cloned());
+
+                let mut created = Vec::with_capacity(templates.len());
+                for template in templates {
+                    let assigned_to = template.assigned_to.or_else(|| {
+                        template
+                            .assign_from_column
+                            .as_deref()
+                            .and_then(|name| first_person(columns, row_data, name))
+                    });
+                    let request = CreateTodoRequest {
+                        title: render(&template.title, columns, row_data),
+                        description: template.description.as_deref().map(|d| render(d, columns, row_data)),
+                        priority: template.priority,
+                        due_date: template.due_in_days.map(|days| Utc::now() + chrono::Duration::days(days)),
+                        supporting_artifact: None,
+                        spreadsheet_id: firing.spreadsheet_id,
+                        row_id: firing.row.as_ref().map(|r| r.id),
+                        assigned_to,
+                        recurrence: None,
+                        recurrence_timezone: None,
+                        parent_id: None,
+                    };
+
+                    let todo = self.create_todo(&request, rule.created_by).await.map_err(|e| e.to_string())?;
+                    created.push(todo.id);
+                    self.notifier.todo_assigned(&todo, None, rule.created_by).await;
+                }
+
+                Ok(json!({ "todo_ids": created }))
+            }
+            AutomationAction::SetCell { column, value } => {
+                let value = match value {
+                    Value::String(s) => Value::String(render(s, columns, row_data)),
+                    other => other.clone(),
+                };
+                self.write_cell(rule, firing, columns, column, value).await
+            }
+            AutomationAction::AssignOwner { column, user_id } => {
+                self.write_cell(rule, firing, columns, column, json!([user_id])).await
+            }
+            AutomationAction::Notify { message, user_ids, people_column } => {
+                let mut recipients = user_ids.clone();
+                if let Some(name) = people_column {
+                    let people = find_column(columns, name)
+                        .and_then(|c| c.cell(row_data))
+                        .and_then(common::person_ids)
+                        .unwrap_or_default();
+                    recipients.extend(people);
+                }
+                if let Some(assignee) = firing.todo.as_ref().and_then(|t| t.assigned_to) {
+                    if user_ids.is_empty() && people_column.is_none() {
+                        recipients.push(assignee);
+                    }
+                }
+                recipients.sort();
+                recipients.dedup();
+                // People cells and fixed recipients can name users who can't see the spreadsheet
+                let mut allowed = Vec::with_capacity(recipients.len());
+                for recipient in recipients {
+                    let can_access = self.repository
+                        .can_user_access_spreadsheet(recipient, firing.spreadsheet_id)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    if can_access {
+                        allowed.push(recipient);
+                    }
+                }
+                let recipients = allowed;
+                if recipients.is_empty() {
+                    return Err("No recipients with access to the spreadsheet".to_string());
+                }
+
+                let text = render(message, columns, row_data);
+                let row_id = firing.row.as_ref().map(|r| r.id);
+                for recipient in &recipients {
+                    let notification = NewNotification::new(*recipient, NotificationKind::Automation, rule.name.clone())
+                        .body(text.clone())
+                        .spreadsheet(firing.spreadsheet_id)
+                        .row(row_id)
+                        .actor(rule.created_by);
+                    self.notifier.notify(notification).await;
+                }
+
+                let message = WebSocketMessage::AutomationNotification {
+                    spreadsheet_id: firing.spreadsheet_id,
+                    rule_id: rule.id,
+                    row_id,
+                    recipients: recipients.clone(),
+                    message: text,
+                };
+                if let Err(e) = self.events.publish(firing.spreadsheet_id, rule.created_by, message).await {
+                    error!("Failed to publish automation notification for rule {}: {}", rule.id, e);
+                }
+
+                Ok(json!({ "recipients": recipients }))
+            }
+            AutomationAction::CallWebhook { url, secret } => {
+                let body = serde_json::to_vec(&json!({
+                    "rule_id": rule.id,
+                    "rule_name": rule.name,
+                    "spreadsheet_id": firing.spreadsheet_id,
+                    "trigger": rule.trigger,
+                    "row": firing.row,
+                    "todo": firing.todo,
+                }))
+                .map_err(|e| e.to_string())?;
+
+                let timestamp = Utc::now().timestamp();
+                let mut request = self.webhook_client
+                    .post(url)?
+                    .timeout(HTTP_TIMEOUT)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .header(EVENT_HEADER, "automation");
+                if let Some(secret) = secret {
+                    request = request
+                        .header(TIMESTAMP_HEADER, timestamp.to_string())
+                        .header(SIGNATURE_HEADER, sign_payload(secret, timestamp, &body));
+                }
+
+                let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+                let status = response.status();
+                if !status.is_success() {
+                    return Err(format!("Webhook responded with HTTP {}", status.as_u16()));
+                }
+
+                Ok(json!({ "status": status.as_u16() }))
+            }
+            AutomationAction::PushToSalesforce { column, field, id_column } => {
+                let result = self.push_to_salesforce(rule, columns, row_data, column, field, id_column).await;
+                if let Err(reason) = &result {
+                    let notification = NewNotification::new(
+                        rule.created_by,
+                        NotificationKind::SalesforceSyncFailed,
+                        format!("Salesforce sync failed for rule '{}'", rule.name),
+                    )
+                    .body(reason.clone())
+                    .spreadsheet(firing.spreadsheet_id)
+                    .row(firing.row.as_ref().map(|r| r.id));
+                    self.notifier.notify(notification).await;
+                }
+                result
+            }
+        }
+    }
+
+    /// Send one cell of the firing row to Salesforce as an opportunity field
+    async fn push_to_salesforce(
+        &self,
+        rule: &AutomationRule,
+        columns: &[SpreadsheetColumn],
+        row_data: &Value,
+        column: &str,
+        field: &str,
+        id_column: &str,
+    ) -> Result<Value, String> {
+        let opportunity_id = find_column(columns, id_column)
+            .and_then(|c| c.cell(row_data))
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .ok_or_else(|| format!("Row has no '{}'", id_column))?
+            .to_string();
+        let source = find_column(columns, column).ok_or_else(|| format!("Unknown column '{}'", column))?;
+        let value = salesforce_value(source, source.cell(row_data).unwrap_or(&Value::Null));
+
+        let token = self.jwt_service
+            .generate_token(rule.created_by, Uuid::new_v4(), "user", 1)
+            .map_err(|e| e.to_string())?;
+        let response = self.client
+            .post(format!(
+                "{}/api/salesforce/update/opportunity/{}",
+                self.salesforce_service_url, opportunity_id
+            ))
+            .timeout(HTTP_TIMEOUT)
+            .bearer_auth(token)
+            .json(&json!({ "field_name": field, "value": value }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Salesforce update failed with HTTP {}: {}", status.as_u16(), body));
+        }
+
+        Ok(json!({ "opportunity_id": opportunity_id, "field": field }))
+    }
+
+    /// Create a todo for a rule and record it in the change feed
+    async fn create_todo(&self, request: &CreateTodoRequest, created_by: Uuid) -> ContrivanceResult<Todo> {
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository.create_todo(publication.tx(), request, created_by).await?;
+        let message = WebSocketMessage::TodoCreated {
+            spreadsheet_id: request.spreadsheet_id,
+            todo: todo.clone(),
+            created_by,
+        };
+        publication.record(request.spreadsheet_id, created_by, message).await?;
+        publication.commit().await?;
+        Ok(todo)
+    }
+
+    /// Set one cell for a rule and record the row update in the change feed
+    async fn update_cell(
+        &self,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column: &SpreadsheetColumn,
+        columns: &[SpreadsheetColumn],
+        value: Value,
+        updated_by: Uuid,
+    ) -> ContrivanceResult<SpreadsheetRow> {
+        let mut publication = self.events.begin().await?;
+        let updated = self.repository
+            .update_cell(publication.tx(), row_id, column, value, updated_by)
+            .await?;
+        let message = WebSocketMessage::RowUpdated {
+            spreadsheet_id,
+            row: updated.clone().keyed(columns, common::RowKeys::Name),
+            updated_by,
+        };
+        publication.record(spreadsheet_id, updated_by, message).await?;
+        publication.commit().await?;
+        Ok(updated)
+    }
+
+    /// Set one cell on the firing row and publish the change
+    async fn write_cell(
+        &self,
+        rule: &AutomationRule,
+        firing: &mut Firing,
+        columns: &[SpreadsheetColumn],
+        column_name: &str,
+        value: Value,
+    ) -> Result<Value, String> {
+        let row = firing.row.as_ref().ok_or("Trigger has no row")?;
+        let column = find_column(columns, column_name).ok_or_else(|| format!("Unknown column '{}'", column_name))?;
+
+        // Only this cell, so edits made since the rule fired aren't undone
+        let updated = self
+            .update_cell(firing.spreadsheet_id, row.id, column, columns, value.clone(), rule.created_by)
+            .await
+            .map_err(|e| e.to_string())?;
+        firing.row = Some(updated);
+
+        Ok(json!({ "column": column.name, "value": value }))
+    }
+}
+
+/// Rules may name a column or give its id; the id keeps working after a rename
+fn find_column<'c>(columns: &'c [SpreadsheetColumn], name: &str) -> Option<&'c SpreadsheetColumn> {
+    columns
+        .iter()
+        .find(|c| c.key() == name)
+        .or_else(|| columns.iter().find(|c| c.name == name))
+}
+
+/// Whether a row-write trigger fires. `previous` is `None` for a new row.
+pub fn row_trigger_matches(
+    trigger: &AutomationTrigger,
+    columns: &[SpreadsheetColumn],
+    previous: Option<&Value>,
+    current: &Value,
+) -> bool {
+    match trigger {
+        AutomationTrigger::RowCreated => previous.is_none(),
+        AutomationTrigger::CellChanged { column, to } => {
+            let Some(column) = find_column(columns, column) else {
+                return false;
+            };
+            let before = previous.and_then(|p| column.cell(p)).filter(|v| !v.is_null());
+            let after = column.cell(current).filter(|v| !v.is_null());
+            if before == after {
+                return false;
+            }
+
+            match to {
+                Some(expected) => {
+                    after.map(|v| cell_matches(v, expected)).unwrap_or(false)
+                        && !before.map(|v| cell_matches(v, expected)).unwrap_or(false)
+                }
+                None => true,
+            }
+        }
+        AutomationTrigger::CloseDateApproaching { .. } | AutomationTrigger::TodoOverdue => false,
+    }
+}
+
+/// A cell matches a value if it equals it or, for multi-value cells, contains it
+fn cell_matches(cell: &Value, expected: &Value) -> bool {
+    match (cell, expected) {
+        (Value::String(a), Value::String(b)) => a.trim() == b.trim(),
+        (Value::Array(items), expected) if !expected.is_array() => items.iter().any(|item| cell_matches(item, expected)),
+        (cell, expected) => cell == expected,
+    }
+}
+
+/// The close date if it falls within `days_before` days from `today`
+pub fn close_date_due(cell: &Value, days_before: i64, today: NaiveDate) -> Option<NaiveDate> {
+    let date = cell.as_str().and_then(parse_date)?;
+    let days_left = (date - today).num_days();
+    (0..=days_before).contains(&days_left).then_some(date)
+}
+
+/// Replace `{{Column Name}}` with the row's cell value as plain text
+pub fn render(text: &str, columns: &[SpreadsheetColumn], row_data: &Value) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+
+        let name = rest[start + 2..start + end].trim();
+        let value = match find_column(columns, name) {
+            Some(column) => column
+                .cell(row_data)
+                .map(|v| column.column_type.export_value(v, &HashMap::new()))
+                .unwrap_or_default(),
+            None => row_data.get(name).and_then(Value::as_str).unwrap_or_default().to_string(),
+        };
+        rendered.push_str(&value);
+        rest = &rest[start + end + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+fn first_person(columns: &[SpreadsheetColumn], row_data: &Value, column: &str) -> Option<Uuid> {
+    find_column(columns, column)
+        .and_then(|c| c.cell(row_data))
+        .and_then(common::person_ids)
+        .and_then(|ids| ids.into_iter().next())
+}
+
+/// Salesforce fields take plain values, so Currency cells send just the amount
+fn salesforce_value(column: &SpreadsheetColumn, value: &Value) -> Value {
+    match column.column_type {
+        common::ColumnType::Currency => Money::from_cell(value, "USD")
+            .map(|money| json!(money.amount))
+            .unwrap_or(Value::Null),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::ColumnType;
+
+    fn column(name: &str, column_type: ColumnType) -> SpreadsheetColumn {
+        SpreadsheetColumn {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::new_v4(),
+            name: name.to_string(),
+            column_type,
+            position: 0,
+            is_required: Some(false),
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_cell_changed_to_value() {
+        let columns = vec![column("SE Stage", ColumnType::Select)];
+        let trigger: AutomationTrigger = serde_json::from_value(json!({
+            "type": "cell_changed", "column": "SE Stage", "to": "POC/Pilot"
+        }))
+        .unwrap();
+
+        let discovery = json!({"SE Stage": "Discovery"});
+        let poc = json!({"SE Stage": "POC/Pilot", "Notes": "x"});
+
+        assert!(row_trigger_matches(&trigger, &columns, Some(&discovery), &poc));
+        assert!(row_trigger_matches(&trigger, &columns, None, &poc));
+        // Editing another cell while already in POC doesn't fire again
+        assert!(!row_trigger_matches(&trigger, &columns, Some(&json!({"SE Stage": "POC/Pilot"})), &poc));
+        assert!(!row_trigger_matches(&trigger, &columns, Some(&poc), &discovery));
+    }
+
+    #[test]
+    fn test_row_created_and_any_change() {
+        let columns = vec![column("Stage", ColumnType::Text)];
+        let any_change = AutomationTrigger::CellChanged { column: "Stage".to_string(), to: None };
+
+        assert!(row_trigger_matches(&AutomationTrigger::RowCreated, &columns, None, &json!({})));
+        assert!(!row_trigger_matches(&AutomationTrigger::RowCreated, &columns, Some(&json!({})), &json!({})));
+        assert!(row_trigger_matches(&any_change, &columns, Some(&json!({"Stage": "A"})), &json!({"Stage": "B"})));
+        assert!(!row_trigger_matches(&any_change, &columns, Some(&json!({"Stage": "A"})), &json!({"Stage": "A"})));
+    }
+
+    #[test]
+    fn test_close_date_window() {
+        let today = NaiveDate::from_ymd_opt(2025, 10, 1).unwrap();
+
+        assert_eq!(close_date_due(&json!("2025-10-08"), 7, today), NaiveDate::from_ymd_opt(2025, 10, 8));
+        assert_eq!(close_date_due(&json!("2025-10-01"), 7, today), Some(today));
+        assert_eq!(close_date_due(&json!("2025-10-09"), 7, today), None);
+        assert_eq!(close_date_due(&json!("2025-09-30"), 7, today), None);
+        assert_eq!(close_date_due(&json!("soon"), 7, today), None);
+    }
+
+    #[test]
+    fn test_render_placeholders() {
+        let columns = vec![column("Opportunity Name", ColumnType::Text), column("Amount", ColumnType::Currency)];
+        let row = json!({"Opportunity Name": "Acme", "Amount": {"amount": 1500, "currency": "EUR"}});
+
+        assert_eq!(
+            render("POC for {{Opportunity Name}} ({{ Amount }}){{Missing}}", &columns, &row),
+            "POC for Acme (1500 EUR)"
+        );
+        assert_eq!(render("no placeholders {{", &columns, &row), "no placeholders {{");
+    }
+
+    #[test]
+    fn test_builtin_poc_template() {
+        let todos = builtin_template("poc").unwrap();
+        assert_eq!(todos.len(), 5);
+        assert!(builtin_template("unknown").is_none());
+    }
+}
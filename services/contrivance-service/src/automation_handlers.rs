@@ -0,0 +1,357 @@
+use actix_web::{web, HttpResponse, HttpRequest};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+use crate::{
+    automation::builtin_template,
+    repository::ContrivanceRepository,
+    middleware::auth::get_user_from_request,
+};
+use common::{
+    cells::is_valid_url,
+    ApiResponse, AutomationAction, AutomationRule, AutomationTrigger, ColumnType, ContrivanceError,
+    CreateAutomationRuleRequest, SpreadsheetColumn, UpdateAutomationRuleRequest, REDACTED_SECRET,
+};
+
+pub struct AutomationHandlers {
+    repository: ContrivanceRepository,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RunsQuery {
+    pub limit: Option<i64>,
+}
+
+impl AutomationHandlers {
+    pub fn new(repository: ContrivanceRepository) -> Self {
+        Self { repository }
+    }
+
+    /// List a spreadsheet's automation rules
+    pub async fn list_rules(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        let rules: Vec<_> = self.repository
+            .list_automation_rules(spreadsheet_id)
+            .await?
+            .into_iter()
+            .map(AutomationRule::redacted)
+            .collect();
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(rules)))
+    }
+
+    /// Create an automation rule; its actions will run on behalf of the creator
+    pub async fn create_rule(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<CreateAutomationRuleRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+        payload.validate()?;
+
+        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        let mut payload = payload.into_inner();
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        validate_trigger(&mut payload.trigger, &columns)?;
+        self.validate_actions(spreadsheet_id, &mut payload.actions, &columns).await?;
+
+        let rule = self.repository
+            .create_automation_rule(spreadsheet_id, &payload, user.id)
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(rule.redacted())))
+    }
+
+    /// Get an automation rule
+    pub async fn get_rule(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, rule_id) = path.into_inner();
+
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        let rule = self.rule_in_spreadsheet(spreadsheet_id, rule_id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(rule.redacted())))
+    }
+
+    /// Update an automation rule. From now on its actions run on behalf of
+    /// the editor, who takes it over from whoever set it up.
+    pub async fn update_rule(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+        payload: web::Json<UpdateAutomationRuleRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, rule_id) = path.into_inner();
+        payload.validate()?;
+
+        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        let rule = self.rule_in_spreadsheet(spreadsheet_id, rule_id).await?;
+        let mut payload = payload.into_inner();
+
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        if let Some(trigger) = &mut payload.trigger {
+            validate_trigger(trigger, &columns)?;
+        }
+        if let Some(actions) = &mut payload.actions {
+            restore_secrets(actions, &rule.actions)?;
+            self.validate_actions(spreadsheet_id, actions, &columns).await?;
+        }
+
+        let rule = self.repository
+            .update_automation_rule(rule.id, &payload, user.id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Automation rule not found"))?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(rule.redacted())))
+    }
+
+    /// Delete an automation rule
+    pub async fn delete_rule(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, rule_id) = path.into_inner();
+
+        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        let rule = self.rule_in_spreadsheet(spreadsheet_id, rule_id).await?;
+        self.repository.delete_automation_rule(rule.id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Get a rule's run log, newest first
+    pub async fn list_runs(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+        query: web::Query<RunsQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, rule_id) = path.into_inner();
+
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        let rule = self.rule_in_spreadsheet(spreadsheet_id, rule_id).await?;
+        let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+        let runs = self.repository.list_automation_runs(rule.id, limit).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(runs)))
+    }
+
+    async fn rule_in_spreadsheet(&self, spreadsheet_id: Uuid, rule_id: Uuid) -> Result<AutomationRule, ContrivanceError> {
+        self.repository
+            .get_automation_rule(rule_id)
+            .await?
+            .filter(|rule| rule.spreadsheet_id == spreadsheet_id)
+            .ok_or_else(|| ContrivanceError::not_found("Automation rule not found"))
+    }
+
+    async fn validate_actions(
+        &self,
+        spreadsheet_id: Uuid,
+        actions: &mut [AutomationAction],
+        columns: &[SpreadsheetColumn],
+    ) -> Result<(), ContrivanceError> {
+        for action in actions {
+            match action {
+                AutomationAction::CreateTodos { template, todos } => {
+                    if let Some(name) = template {
+                        if builtin_template(name).is_none() {
+                            return Err(ContrivanceError::validation(format!("Unknown todo template '{}'", name)));
+                        }
+                    } else if todos.is_empty() {
+                        return Err(ContrivanceError::validation("create_todos needs a template or todos"));
+                    }
+                    for todo in todos.iter_mut() {
+                        if todo.title.trim().is_empty() {
+                            return Err(ContrivanceError::validation("Todo title is required"));
+                        }
+                        if let Some(name) = &mut todo.assign_from_column {
+                            *name = person_column(columns, name)?.key();
+                        }
+                    }
+                    let assignees: Vec<Uuid> = todos.iter().filter_map(|t| t.assigned_to).collect();
+                    self.repository.ensure_users_exist(spreadsheet_id, &assignees).await?;
+                }
+                AutomationAction::SetCell { column: name, value } => {
+                    let column = existing_column(columns, name)?;
+                    *name = column.key();
+                    // Placeholders are only known once the rule fires
+                    let templated = value.as_str().map(|s| s.contains("{{")).unwrap_or(false);
+                    if !templated {
+                        column.validate_cell(value)?;
+                    }
+                }
+                AutomationAction::AssignOwner { column, user_id } => {
+                    *column = person_column(columns, column)?.key();
+                    self.repository.ensure_users_exist(spreadsheet_id, &[*user_id]).await?;
+                }
+                AutomationAction::Notify { message, user_ids, people_column } => {
+                    if message.trim().is_empty() {
+                        return Err(ContrivanceError::validation("Notification message is required"));
+                    }
+                    if let Some(name) = people_column {
+                        *name = person_column(columns, name)?.key();
+                    }
+                    self.repository.ensure_users_exist(spreadsheet_id, user_ids).await?;
+                }
+                AutomationAction::CallWebhook { url, .. } => {
+                    if !is_valid_url(url) {
+                        return Err(ContrivanceError::validation("Webhook URL must be an http(s) URL"));
+                    }
+                }
+                AutomationAction::PushToSalesforce { column, field, id_column } => {
+                    *column = existing_column(columns, column)?.key();
+                    *id_column = existing_column(columns, id_column)?.key();
+                    if field.trim().is_empty() {
+                        return Err(ContrivanceError::validation("Salesforce field is required"));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Put back the secrets of webhook actions sent back redacted, from the
+/// rule's action with the same URL
+fn restore_secrets(actions: &mut [AutomationAction], existing: &[AutomationAction]) -> Result<(), ContrivanceError> {
+    for action in actions {
+        let AutomationAction::CallWebhook { url, secret: Some(secret) } = action else {
+            continue;
+        };
+        if secret != REDACTED_SECRET {
+            continue;
+        }
+        let previous = existing.iter().find_map(|existing| match existing {
+            AutomationAction::CallWebhook { url: existing_url, secret: Some(secret) } if existing_url == url => {
+                Some(secret.clone())
+            }
+            _ => None,
+        });
+        *secret = previous.ok_or_else(|| {
+            ContrivanceError::validation(format!("Set the secret again for the webhook to {}", url))
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Check the trigger's columns exist, and refer to them by id from now on so
+/// renaming a column doesn't break the rule
+fn validate_trigger(trigger: &mut AutomationTrigger, columns: &[SpreadsheetColumn]) -> Result<(), ContrivanceError> {
+    match trigger {
+        AutomationTrigger::CellChanged { column, .. } => {
+            *column = existing_column(columns, column)?.key();
+        }
+        AutomationTrigger::CloseDateApproaching { column: name, days_before } => {
+            let column = existing_column(columns, name)?;
+            *name = column.key();
+            if column.column_type != ColumnType::Date {
+                return Err(ContrivanceError::validation(format!("Column '{}' is not a Date column", column.name)));
+            }
+            if *days_before < 0 {
+                return Err(ContrivanceError::validation("days_before must not be negative"));
+            }
+        }
+        AutomationTrigger::RowCreated | AutomationTrigger::TodoOverdue => {}
+    }
+
+    Ok(())
+}
+
+fn existing_column<'c>(columns: &'c [SpreadsheetColumn], name: &str) -> Result<&'c SpreadsheetColumn, ContrivanceError> {
+    columns
+        .iter()
+        .find(|c| c.key() == name)
+        .or_else(|| columns.iter().find(|c| c.name == name))
+        .ok_or_else(|| ContrivanceError::validation(format!("Unknown column '{}'", name)))
+}
+
+fn person_column<'c>(columns: &'c [SpreadsheetColumn], name: &str) -> Result<&'c SpreadsheetColumn, ContrivanceError> {
+    let column = existing_column(columns, name)?;
+    if column.column_type != ColumnType::Person {
+        return Err(ContrivanceError::validation(format!("Column '{}' is not a Person column", column.name)));
+    }
+    Ok(column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, column_type: ColumnType) -> SpreadsheetColumn {
+        SpreadsheetColumn {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::new_v4(),
+            name: name.to_string(),
+            column_type,
+            position: 0,
+            is_required: Some(false),
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_trigger_columns_are_stored_by_id() {
+        let close = column("Close Date", ColumnType::Date);
+        let stage = column("Stage", ColumnType::Text);
+        let columns = vec![close.clone(), stage.clone()];
+
+        let mut trigger = AutomationTrigger::CellChanged { column: "Stage".to_string(), to: None };
+        validate_trigger(&mut trigger, &columns).unwrap();
+        assert!(matches!(&trigger, AutomationTrigger::CellChanged { column, .. } if *column == stage.key()));
+
+        // Already an id, e.g. a rule sent back unchanged
+        validate_trigger(&mut trigger, &columns).unwrap();
+        assert!(matches!(&trigger, AutomationTrigger::CellChanged { column, .. } if *column == stage.key()));
+
+        let mut trigger = AutomationTrigger::CloseDateApproaching { column: close.key(), days_before: 3 };
+        validate_trigger(&mut trigger, &columns).unwrap();
+        assert!(matches!(&trigger, AutomationTrigger::CloseDateApproaching { column, .. } if *column == close.key()));
+
+        let mut trigger = AutomationTrigger::CloseDateApproaching { column: "Stage".to_string(), days_before: 3 };
+        assert!(validate_trigger(&mut trigger, &columns).is_err());
+        let mut trigger = AutomationTrigger::CellChanged { column: "Amount".to_string(), to: None };
+        assert!(validate_trigger(&mut trigger, &columns).is_err());
+    }
+}
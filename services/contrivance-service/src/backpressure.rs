@@ -0,0 +1,336 @@
+//! Limits on what one WebSocket connection can cost the server.
+//!
+//! Inbound, each connection has a token bucket of messages; frames beyond it
+//! are dropped and the client is told it's being rate limited. Outbound,
+//! messages for a connection wait in its bounded `Outbox` rather than in the
+//! actor's mailbox, which has no bound. An actor only runs while its socket is
+//! being written, so a client that stops reading leaves its outbox filling
+//! up: a newer `RowUpdated` replaces a queued one for the same row. Once the
+//! outbox is full, the next message is dropped and the outbox takes no more,
+//! so nothing after the gap reaches the client; the connection is then closed
+//! with `RESYNC_REQUIRED`, as is one whose outbox stays full, and its client
+//! resumes from the change feed or reloads when it reconnects.
+
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Per-connection budgets
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SocketLimits {
+    /// Sustained rate of client messages allowed
+    pub messages_per_second: f64,
+    /// Messages a client may send at once before the rate applies
+    pub burst: u32,
+    /// Messages held for a connection that isn't keeping up
+    pub outbox_capacity: usize,
+    /// How long an outbox may stay full before the connection is closed
+    pub slow_consumer_timeout: Duration,
+}
+
+impl Default for SocketLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 20.0,
+            burst: 40,
+            outbox_capacity: 256,
+            slow_consumer_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Token bucket for a connection's inbound messages
+pub struct RateLimiter {
+    tokens: f64,
+    capacity: f64,
+    per_second: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(limits: &SocketLimits, now: Instant) -> Self {
+        let capacity = f64::from(limits.burst.max(1));
+        Self {
+            tokens: capacity,
+            capacity,
+            per_second: limits.messages_per_second,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token for a message; false if the client is over its budget
+    pub fn allow(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What happened to a message pushed into an outbox
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Queued {
+    /// Queued behind nothing; the actor needs waking to send it
+    Wake,
+    /// Queued behind messages the actor has already been woken for
+    Behind,
+    /// Replaced an older update to the same row
+    Coalesced,
+    /// The outbox is full, has dropped a message before, or is closed
+    Dropped,
+}
+
+struct Outgoing {
+    /// The row a `RowUpdated` is for; newer updates to it replace this one
+    row_id: Option<Uuid>,
+    json: String,
+}
+
+/// Messages waiting to be written to one connection
+pub struct Outbox {
+    queue: VecDeque<Outgoing>,
+    capacity: usize,
+    /// Whether the actor has been sent a wake-up it hasn't handled yet
+    woken: bool,
+    /// When the outbox last filled up, while it stays full
+    full_since: Option<Instant>,
+    /// Whether a message has been dropped, leaving the client out of date
+    overflowed: bool,
+    closed: bool,
+}
+
+impl Outbox {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            queue: VecDeque::new(),
+            capacity: capacity.max(1),
+            woken: false,
+            full_since: None,
+            overflowed: false,
+            closed: false,
+        }
+    }
+
+    /// Queue a message. An update to a row with an update already queued
+    /// takes that one's place at the back, so sequence numbers stay in order.
+    /// After a message is dropped nothing more is queued, not even updates
+    /// that would coalesce, so the client can't be handed a later sequence
+    /// number than one it missed.
+    pub fn push(&mut self, json: String, row_id: Option<Uuid>, now: Instant) -> Queued {
+        if self.closed || self.overflowed {
+            return Queued::Dropped;
+        }
+
+        let replaced = row_id.and_then(|row_id| self.queue.iter().position(|queued| queued.row_id == Some(row_id)));
+        if let Some(index) = replaced {
+            self.queue.remove(index);
+        } else if self.queue.len() >= self.capacity {
+            self.full_since.get_or_insert(now);
+            self.overflowed = true;
+            return Queued::Dropped;
+        }
+        self.queue.push_back(Outgoing { row_id, json });
+
+        if replaced.is_some() {
+            Queued::Coalesced
+        } else if std::mem::replace(&mut self.woken, true) {
+            Queued::Behind
+        } else {
+            Queued::Wake
+        }
+    }
+
+    /// Everything queued, for the actor to write
+    pub fn drain(&mut self) -> Vec<String> {
+        self.woken = false;
+        self.full_since = None;
+        self.queue.drain(..).map(|queued| queued.json).collect()
+    }
+
+    /// Whether the outbox has been full for longer than `timeout`
+    pub fn lagging(&self, now: Instant, timeout: Duration) -> bool {
+        self.full_since.is_some_and(|since| now.saturating_duration_since(since) > timeout)
+    }
+
+    /// Stop taking messages and free the ones waiting
+    pub fn close(&mut self) {
+        self.closed = true;
+        self.queue.clear();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Whether a message was dropped, so the connection has to be closed and
+    /// its client told to resync
+    pub fn needs_resync(&self) -> bool {
+        self.overflowed
+    }
+}
+
+/// The row a broadcast `RowUpdated` carries, which later updates to the same
+/// row supersede
+pub fn coalescing_key(payload: &Value) -> Option<Uuid> {
+    if payload.get("type")?.as_str()? != "RowUpdated" {
+        return None;
+    }
+    payload.get("row")?.get("id")?.as_str()?.parse().ok()
+}
+
+/// Something the limits did to a message or connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketEvent {
+    InboundDropped,
+    OutboundDropped,
+    Coalesced,
+    SlowConsumerClosed,
+}
+
+/// Counts of what the limits did, served at `/metrics`
+#[derive(Default)]
+pub struct SocketMetrics {
+    inbound_dropped: AtomicU64,
+    outbound_dropped: AtomicU64,
+    coalesced: AtomicU64,
+    slow_consumers_closed: AtomicU64,
+}
+
+impl SocketMetrics {
+    pub fn record(&self, event: SocketEvent) {
+        let counter = match event {
+            SocketEvent::InboundDropped => &self.inbound_dropped,
+            SocketEvent::OutboundDropped => &self.outbound_dropped,
+            SocketEvent::Coalesced => &self.coalesced,
+            SocketEvent::SlowConsumerClosed => &self.slow_consumers_closed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The counters in the Prometheus text format
+    pub fn render(&self, connections: usize) -> String {
+        let counters = [
+            ("contrivance_ws_inbound_dropped_total", "Client messages dropped by the rate limit", &self.inbound_dropped),
+            ("contrivance_ws_outbound_dropped_total", "Messages dropped for connections with a full outbox", &self.outbound_dropped),
+            ("contrivance_ws_outbound_coalesced_total", "Queued row updates replaced by a newer one", &self.coalesced),
+            ("contrivance_ws_slow_consumers_closed_total", "Connections closed for not keeping up", &self.slow_consumers_closed),
+        ];
+
+        let mut out = String::new();
+        for (name, help, counter) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}", counter.load(Ordering::Relaxed));
+        }
+        let _ = writeln!(
+            out,
+            "# HELP contrivance_ws_connections Open WebSocket connections\n# TYPE contrivance_ws_connections gauge\ncontrivance_ws_connections {connections}"
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rate_limiter_allows_a_burst_then_the_rate() {
+        let limits = SocketLimits { messages_per_second: 10.0, burst: 3, ..SocketLimits::default() };
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(&limits, start);
+
+        assert!((0..3).all(|_| limiter.allow(start)));
+        assert!(!limiter.allow(start));
+        // A tenth of a second buys one more message, and idling doesn't bank more than the burst
+        assert!(limiter.allow(start + Duration::from_millis(100)));
+        assert!(!limiter.allow(start + Duration::from_millis(100)));
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..5).filter(|_| limiter.allow(later)).count(), 3);
+    }
+
+    #[test]
+    fn test_outbox_coalesces_row_updates_and_drops_when_full() {
+        let now = Instant::now();
+        let (row, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let mut outbox = Outbox::new(3);
+
+        assert_eq!(outbox.push("update 1".into(), Some(row), now), Queued::Wake);
+        assert_eq!(outbox.push("joined".into(), None, now), Queued::Behind);
+        assert_eq!(outbox.push("update 2".into(), Some(row), now), Queued::Coalesced);
+        assert_eq!(outbox.push("other".into(), Some(other), now), Queued::Behind);
+        // Coalescing still works on a full outbox
+        assert_eq!(outbox.push("update 3".into(), Some(row), now), Queued::Coalesced);
+        assert_eq!(outbox.push("left".into(), None, now), Queued::Dropped);
+
+        assert_eq!(outbox.drain(), vec!["joined", "other", "update 3"]);
+    }
+
+    #[test]
+    fn test_outbox_that_dropped_a_message_needs_resync() {
+        let now = Instant::now();
+        let row = Uuid::new_v4();
+        let mut outbox = Outbox::new(2);
+
+        outbox.push("update 1".into(), Some(row), now);
+        outbox.push("joined".into(), None, now);
+        assert!(!outbox.needs_resync());
+        assert_eq!(outbox.push("update 2".into(), None, now), Queued::Dropped);
+        assert!(outbox.needs_resync());
+        // Once behind, nothing more gets through, even after the outbox drains
+        assert_eq!(outbox.push("update 3".into(), Some(row), now), Queued::Dropped);
+
+        assert_eq!(outbox.drain(), vec!["update 1", "joined"]);
+        assert!(outbox.needs_resync());
+        assert_eq!(outbox.push("after".into(), None, now), Queued::Dropped);
+        assert!(outbox.drain().is_empty());
+    }
+
+    #[test]
+    fn test_outbox_lags_only_while_it_stays_full() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(10);
+        let mut outbox = Outbox::new(1);
+        outbox.push("a".into(), None, start);
+        outbox.push("b".into(), None, start);
+
+        assert!(!outbox.lagging(start + Duration::from_secs(5), timeout));
+        assert!(outbox.lagging(start + Duration::from_secs(11), timeout));
+        outbox.drain();
+        assert!(!outbox.lagging(start + Duration::from_secs(11), timeout));
+
+        outbox.close();
+        assert!(outbox.is_closed());
+        assert_eq!(outbox.push("c".into(), None, start), Queued::Dropped);
+    }
+
+    #[test]
+    fn test_metrics_render_counters_and_connections() {
+        let metrics = SocketMetrics::default();
+        metrics.record(SocketEvent::Coalesced);
+        metrics.record(SocketEvent::Coalesced);
+        metrics.record(SocketEvent::SlowConsumerClosed);
+
+        let text = metrics.render(7);
+        assert!(text.contains("\ncontrivance_ws_outbound_coalesced_total 2\n"));
+        assert!(text.contains("\ncontrivance_ws_slow_consumers_closed_total 1\n"));
+        assert!(text.contains("\ncontrivance_ws_inbound_dropped_total 0\n"));
+        assert!(text.contains("# TYPE contrivance_ws_connections gauge\ncontrivance_ws_connections 7\n"));
+    }
+
+    #[test]
+    fn test_only_row_updates_coalesce() {
+        let row = Uuid::new_v4();
+        let update = json!({"type": "RowUpdated", "seq": 4, "row": {"id": row, "row_data": {}}});
+        assert_eq!(coalescing_key(&update), Some(row));
+        assert_eq!(coalescing_key(&json!({"type": "RowCreated", "row": {"id": row}})), None);
+        assert_eq!(coalescing_key(&json!({"type": "TextEdited", "row_id": row})), None);
+    }
+}
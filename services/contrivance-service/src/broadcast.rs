@@ -0,0 +1,309 @@
+//! Fan-out of live messages to WebSocket clients on every replica.
+//!
+//! Each instance only holds its own sockets, so a mutation handled by one
+//! replica has to reach the clients connected to the others. Messages are
+//! delivered to local sockets straight away and, with the Postgres backbone,
+//! also sent with `NOTIFY` on one channel that every instance `LISTEN`s to and
+//! fans out to its own sockets. Notices carry the sending instance's id so it
+//! skips its own. `NOTIFY` payloads are capped at 8000 bytes; change feed
+//! events too large for that are sent as a reference and read back from the
+//! feed by the receiving instances.
+
+use common::WebSocketMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{events::event_payload, repository::ContrivanceRepository, websocket::ConnectionManager};
+
+/// Postgres channel every instance listens on
+const CHANNEL: &str = "contrivance_broadcast";
+/// Largest notice sent inline, just under Postgres' 8000 byte limit
+const MAX_NOTICE_BYTES: usize = 7900;
+/// How long to wait before listening again after the connection fails
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How messages reach the other replicas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastBackend {
+    /// Only this instance's sockets; for running a single replica
+    Local,
+    /// Postgres `LISTEN`/`NOTIFY`
+    Postgres,
+}
+
+impl BroadcastBackend {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "local" => Some(Self::Local),
+            "postgres" => Some(Self::Postgres),
+            _ => None,
+        }
+    }
+}
+
+/// Who a message is for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "to", rename_all = "snake_case")]
+enum Envelope {
+    /// Every connection to a spreadsheet
+    Spreadsheet { spreadsheet_id: Uuid, payload: Value },
+    /// A change feed event too large to send inline
+    Event { spreadsheet_id: Uuid, seq: i64 },
+    /// A user's notification connections
+    User { user_id: Uuid, payload: Value },
+}
+
+/// An envelope as sent over `NOTIFY`
+#[derive(Debug, Serialize, Deserialize)]
+struct Notice {
+    origin: Uuid,
+    #[serde(flatten)]
+    envelope: Envelope,
+}
+
+/// Serialize a notice, or `None` if it's too large to send
+fn encode(origin: Uuid, envelope: Envelope) -> Option<String> {
+    match serde_json::to_string(&Notice { origin, envelope }) {
+        Ok(json) if json.len() <= MAX_NOTICE_BYTES => Some(json),
+        Ok(_) => None,
+        Err(e) => {
+            error!("Failed to serialize broadcast notice: {}", e);
+            None
+        }
+    }
+}
+
+/// The notice for a spreadsheet message: inline when it fits, otherwise a
+/// reference to its change feed event if it has one
+fn spreadsheet_notice(origin: Uuid, spreadsheet_id: Uuid, payload: &Value, seq: Option<i64>) -> Option<String> {
+    encode(origin, Envelope::Spreadsheet { spreadsheet_id, payload: payload.clone() })
+        .or_else(|| encode(origin, Envelope::Event { spreadsheet_id, seq: seq? }))
+}
+
+/// Sends live messages to this instance's sockets and, through the backbone,
+/// to every other instance's
+#[derive(Clone)]
+pub struct Broadcaster {
+    connections: Arc<RwLock<ConnectionManager>>,
+    repository: ContrivanceRepository,
+    backend: BroadcastBackend,
+    /// Identifies this instance's notices
+    origin: Uuid,
+}
+
+impl Broadcaster {
+    /// The broadcaster, and for the Postgres backbone the listener that fans
+    /// other instances' messages out to local sockets
+    pub fn new(
+        connections: Arc<RwLock<ConnectionManager>>,
+        repository: ContrivanceRepository,
+        backend: BroadcastBackend,
+    ) -> (Self, Option<BroadcastListener>) {
+        let broadcaster = Self {
+            connections: connections.clone(),
+            repository: repository.clone(),
+            backend,
+            origin: Uuid::new_v4(),
+        };
+        let listener = (backend == BroadcastBackend::Postgres).then(|| BroadcastListener {
+            connections,
+            repository,
+            origin: broadcaster.origin,
+        });
+        (broadcaster, listener)
+    }
+
+    /// Send a message to everyone connected to a spreadsheet. `seq` is the
+    /// change feed event it came from, if any.
+    pub async fn to_spreadsheet(&self, spreadsheet_id: Uuid, payload: Value, seq: Option<i64>) {
+        if self.backend == BroadcastBackend::Postgres {
+            match spreadsheet_notice(self.origin, spreadsheet_id, &payload, seq) {
+                Some(notice) => self.notify(notice).await,
+                None => warn!("Message for spreadsheet {} is too large to send to other instances", spreadsheet_id),
+            }
+        }
+
+        self.connections
+            .read()
+            .await
+            .broadcast_to_spreadsheet(spreadsheet_id, payload)
+            .await;
+    }
+
+    /// Send a message to a user's notification connections
+    pub async fn to_user(&self, user_id: Uuid, message: &WebSocketMessage) {
+        let payload = match serde_json::to_value(message) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize {} message: {}", message.event_type(), e);
+                return;
+            }
+        };
+
+        if self.backend == BroadcastBackend::Postgres {
+            match encode(self.origin, Envelope::User { user_id, payload: payload.clone() }) {
+                Some(notice) => self.notify(notice).await,
+                None => warn!("Message for user {} is too large to send to other instances", user_id),
+            }
+        }
+
+        self.connections.read().await.send_to_user(user_id, payload);
+    }
+
+    async fn notify(&self, notice: String) {
+        if let Err(e) = self.repository.notify(CHANNEL, &notice).await {
+            error!("Failed to send broadcast to other instances: {}", e);
+        }
+    }
+}
+
+/// Delivers messages published by other instances to this one's sockets
+pub struct BroadcastListener {
+    connections: Arc<RwLock<ConnectionManager>>,
+    repository: ContrivanceRepository,
+    origin: Uuid,
+}
+
+impl BroadcastListener {
+    /// Listen until the process exits. Messages sent while the connection is
+    /// down are lost, so once it's back every local spreadsheet socket is
+    /// told to resync.
+    pub async fn run(self) {
+        let mut reconnecting = false;
+        loop {
+            let mut listener = match self.repository.listen(CHANNEL).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Failed to listen for broadcasts: {}", e);
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    continue;
+                }
+            };
+            info!("Listening for broadcasts from other instances");
+            if reconnecting {
+                self.request_resync().await;
+            }
+            reconnecting = true;
+
+            loop {
+                match listener.recv().await {
+                    Ok(notification) => self.deliver(notification.payload()).await,
+                    Err(e) => {
+                        error!("Lost the broadcast listener connection: {}", e);
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    /// Tell every spreadsheet connection on this instance that it may have
+    /// missed messages and should reload
+    async fn request_resync(&self) {
+        let spreadsheet_ids = self.connections.read().await.spreadsheet_ids();
+        for spreadsheet_id in spreadsheet_ids {
+            let seq = match self.repository.get_latest_seq(spreadsheet_id).await {
+                Ok(seq) => seq,
+                Err(e) => {
+                    error!("Failed to load the latest event of spreadsheet {}: {}", spreadsheet_id, e);
+                    continue;
+                }
+            };
+            let message = WebSocketMessage::ResyncRequired { spreadsheet_id, seq };
+            self.connections.read().await.broadcast_to_spreadsheet(spreadsheet_id, &message).await;
+        }
+    }
+
+    async fn deliver(&self, notice: &str) {
+        let notice: Notice = match serde_json::from_str(notice) {
+            Ok(notice) => notice,
+            Err(e) => {
+                warn!("Ignoring malformed broadcast: {}", e);
+                return;
+            }
+        };
+        if notice.origin == self.origin {
+            return;
+        }
+
+        match notice.envelope {
+            Envelope::Spreadsheet { spreadsheet_id, payload } => {
+                self.connections.read().await.broadcast_to_spreadsheet(spreadsheet_id, payload).await;
+            }
+            Envelope::Event { spreadsheet_id, seq } => {
+                if let Some(payload) = self.load_event(spreadsheet_id, seq).await {
+                    self.connections.read().await.broadcast_to_spreadsheet(spreadsheet_id, payload).await;
+                }
+            }
+            Envelope::User { user_id, payload } => {
+                self.connections.read().await.send_to_user(user_id, payload);
+            }
+        }
+    }
+
+    /// A change feed event as it was broadcast, stamped with its sequence number
+    async fn load_event(&self, spreadsheet_id: Uuid, seq: i64) -> Option<Value> {
+        let event = match self.repository.get_events_since(spreadsheet_id, seq - 1, 1).await {
+            Ok(events) => events.into_iter().find(|event| event.seq == seq)?,
+            Err(e) => {
+                error!("Failed to load event {} of spreadsheet {}: {}", seq, spreadsheet_id, e);
+                return None;
+            }
+        };
+
+        match event_payload(event) {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                error!("Failed to read event {} of spreadsheet {}: {}", seq, spreadsheet_id, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notices_round_trip() {
+        let origin = Uuid::new_v4();
+        let envelope = Envelope::User {
+            user_id: Uuid::new_v4(),
+            payload: serde_json::json!({ "type": "Pong" }),
+        };
+        let json = encode(origin, envelope.clone()).unwrap();
+        let notice: Notice = serde_json::from_str(&json).unwrap();
+        assert_eq!(notice.origin, origin);
+        assert_eq!(notice.envelope, envelope);
+    }
+
+    #[test]
+    fn test_large_events_are_sent_by_reference() {
+        let (origin, spreadsheet_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let small = serde_json::json!({ "type": "RowDeleted" });
+        let large = serde_json::json!({ "type": "RowUpdated", "notes": "x".repeat(MAX_NOTICE_BYTES) });
+
+        let inline: Notice = serde_json::from_str(&spreadsheet_notice(origin, spreadsheet_id, &small, Some(3)).unwrap()).unwrap();
+        assert!(matches!(inline.envelope, Envelope::Spreadsheet { .. }));
+
+        let reference: Notice = serde_json::from_str(&spreadsheet_notice(origin, spreadsheet_id, &large, Some(4)).unwrap()).unwrap();
+        assert_eq!(reference.envelope, Envelope::Event { spreadsheet_id, seq: 4 });
+
+        // Without a change feed event there's nothing to refer to
+        assert!(spreadsheet_notice(origin, spreadsheet_id, &large, None).is_none());
+    }
+
+    #[test]
+    fn test_backend_names() {
+        assert_eq!(BroadcastBackend::parse("Postgres"), Some(BroadcastBackend::Postgres));
+        assert_eq!(BroadcastBackend::parse("local"), Some(BroadcastBackend::Local));
+        assert_eq!(BroadcastBackend::parse("carrier-pigeon"), None);
+    }
+}
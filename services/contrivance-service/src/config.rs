@@ -1,12 +1,50 @@
-use common::EnvUtils;
+use common::{mailer::MailerConfig, EnvUtils};
+
+use crate::broadcast::BroadcastBackend;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
     pub database_url: String,
     pub auth_service_url: String,
+    pub salesforce_service_url: String,
     pub cors_origins: Vec<String>,
     pub jwt_secret: String,
+    /// Optional CSV of exchange rates loaded at startup
+    pub exchange_rates_file: Option<String>,
+    /// Attempts per webhook delivery before it is marked failed
+    pub webhook_max_attempts: u32,
+    /// First retry delay; each later retry waits twice as long
+    pub webhook_retry_base_secs: u64,
+    pub webhook_timeout_secs: u64,
+    /// Let webhooks and automation calls reach private, loopback and
+    /// link-local addresses, e.g. for receivers on an internal network
+    pub webhook_allow_private_targets: bool,
+    pub mailer: MailerConfig,
+    /// Attempts per email before it is marked failed
+    pub email_max_attempts: u32,
+    /// First retry delay; each later retry waits twice as long
+    pub email_retry_base_secs: u64,
+    /// Filters on a column within the idle window before it gets an index
+    pub index_filter_threshold: i64,
+    /// Spreadsheets smaller than this aren't indexed on usage alone
+    pub index_min_rows: i64,
+    /// Usage indexes are dropped after this many days without a filter
+    pub index_idle_days: i64,
+    /// How live messages reach clients connected to other instances
+    pub broadcast_backend: BroadcastBackend,
+    /// How often sockets are pinged
+    pub ws_heartbeat_interval_secs: u64,
+    /// Sockets not heard from for this long are closed
+    pub ws_client_timeout_secs: u64,
+    /// Sustained rate of messages each socket may send
+    pub ws_max_messages_per_second: u32,
+    /// Messages a socket may send at once before the rate applies
+    pub ws_message_burst: u32,
+    /// Messages held for a socket that isn't keeping up
+    pub ws_outbound_queue_size: usize,
+    /// Sockets whose queue stays full this long are closed
+    pub ws_slow_consumer_timeout_secs: u64,
 }
 
 impl Config {
@@ -18,11 +56,32 @@ impl Config {
             port: EnvUtils::get_var_as_int("PORT", 3003) as u16,
             database_url: EnvUtils::require_var("DATABASE_URL"),
             auth_service_url: EnvUtils::get_var("AUTH_SERVICE_URL", "http://auth-service:3001"),
+            salesforce_service_url: EnvUtils::get_var("SALESFORCE_SERVICE_URL", "http://salesforce-service:8004"),
             cors_origins: EnvUtils::get_var("CORS_ORIGINS", "http://localhost:3000,http://localhost:80")
                 .split(',')
                 .map(|s| s.trim().to_string())
                 .collect(),
             jwt_secret: EnvUtils::require_var("JWT_SECRET"),
+            exchange_rates_file: Some(EnvUtils::get_var("EXCHANGE_RATES_FILE", ""))
+                .filter(|path| !path.is_empty()),
+            webhook_max_attempts: EnvUtils::get_var_as_int("WEBHOOK_MAX_ATTEMPTS", 8).max(1) as u32,
+            webhook_retry_base_secs: EnvUtils::get_var_as_int("WEBHOOK_RETRY_BASE_SECONDS", 30).max(1) as u64,
+            webhook_timeout_secs: EnvUtils::get_var_as_int("WEBHOOK_TIMEOUT_SECONDS", 10).max(1) as u64,
+            webhook_allow_private_targets: EnvUtils::get_var_as_bool("WEBHOOK_ALLOW_PRIVATE_TARGETS", false),
+            mailer: MailerConfig::from_env(),
+            email_max_attempts: EnvUtils::get_var_as_int("EMAIL_MAX_ATTEMPTS", 6).max(1) as u32,
+            email_retry_base_secs: EnvUtils::get_var_as_int("EMAIL_RETRY_BASE_SECONDS", 60).max(1) as u64,
+            index_filter_threshold: EnvUtils::get_var_as_int("INDEX_FILTER_THRESHOLD", 20).max(1) as i64,
+            index_min_rows: EnvUtils::get_var_as_int("INDEX_MIN_ROWS", 5000).max(0) as i64,
+            index_idle_days: EnvUtils::get_var_as_int("INDEX_IDLE_DAYS", 30).max(1) as i64,
+            broadcast_backend: BroadcastBackend::parse(&EnvUtils::get_var("BROADCAST_BACKEND", "postgres"))
+                .expect("BROADCAST_BACKEND must be local or postgres"),
+            ws_heartbeat_interval_secs: EnvUtils::get_var_as_int("WS_HEARTBEAT_INTERVAL_SECONDS", 10).max(1) as u64,
+            ws_client_timeout_secs: EnvUtils::get_var_as_int("WS_CLIENT_TIMEOUT_SECONDS", 30).max(1) as u64,
+            ws_max_messages_per_second: EnvUtils::get_var_as_int("WS_MAX_MESSAGES_PER_SECOND", 20).max(1) as u32,
+            ws_message_burst: EnvUtils::get_var_as_int("WS_MESSAGE_BURST", 40).max(1) as u32,
+            ws_outbound_queue_size: EnvUtils::get_var_as_int("WS_OUTBOUND_QUEUE_SIZE", 256).max(1) as usize,
+            ws_slow_consumer_timeout_secs: EnvUtils::get_var_as_int("WS_SLOW_CONSUMER_TIMEOUT_SECONDS", 10).max(1) as u64,
         }
     }
 }
\ No newline at end of file
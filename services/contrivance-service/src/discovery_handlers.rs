@@ -1,9 +1,11 @@
-use actix_web::{web, HttpRequest, HttpResponse, HttpMessage};
+use actix_web::{web, HttpRequest, HttpResponse, HttpMessage, ResponseError};
 use serde_json::json;
 use uuid::Uuid;
 use tracing::{info, error};
 use crate::discovery_models::*;
 use crate::discovery_repository::DiscoveryRepository;
+use crate::events::EventPublisher;
+use common::{ContrivanceError, ContrivanceResult, Keyset, PaginationParams, WebSocketMessage};
 
 // Create a new discovery session
 pub async fn create_discovery_session(
@@ -44,12 +46,17 @@ pub async fn create_discovery_session(
 
 // Get a discovery session with all responses and notes
 pub async fn get_discovery_session(
+    req: HttpRequest,
     session_id: web::Path<Uuid>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let session_id = session_id.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
 
-    match repo.get_session_with_responses(session_id).await {
+    match repo.get_session_with_responses(session_id, user_id).await {
         Ok(session_data) => HttpResponse::Ok().json(session_data),
         Err(_) => HttpResponse::NotFound().json(json!({"error": "Session not found"})),
     }
@@ -59,6 +66,7 @@ pub async fn get_discovery_session(
 pub async fn get_account_discovery_sessions(
     req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<PaginationParams>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let account_id = path.into_inner();
@@ -76,7 +84,12 @@ pub async fn get_account_discovery_sessions(
         }
     };
 
-    match repo.get_sessions_by_account(&account_id, user_id).await {
+    let keyset = match Keyset::from_params(&query, 20, 100) {
+        Ok(keyset) => keyset,
+        Err(_) => return HttpResponse::BadRequest().json(json!({"error": "Invalid cursor"})),
+    };
+
+    match repo.get_sessions_by_account(&account_id, user_id, keyset).await {
         Ok(sessions) => HttpResponse::Ok().json(sessions),
         Err(e) => {
             error!("Error fetching sessions: {}", e);
@@ -88,14 +101,19 @@ pub async fn get_account_discovery_sessions(
 
 // Save a discovery response for a question
 pub async fn save_discovery_response(
+    req: HttpRequest,
     session_id: web::Path<Uuid>,
     body: web::Json<SaveDiscoveryResponseRequest>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let session_id = session_id.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
 
     // Verify session exists
-    if repo.get_session(session_id).await.is_err() {
+    if repo.get_session(session_id, user_id).await.is_err() {
         return HttpResponse::NotFound().json(json!({"error": "Session not found"}));
     }
 
@@ -111,10 +129,19 @@ pub async fn save_discovery_response(
 
 // Get all responses for a session
 pub async fn get_discovery_responses(
+    req: HttpRequest,
     session_id: web::Path<Uuid>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let session_id = session_id.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
+
+    if repo.get_session(session_id, user_id).await.is_err() {
+        return HttpResponse::NotFound().json(json!({"error": "Session not found"}));
+    }
 
     match repo.get_responses(session_id).await {
         Ok(responses) => HttpResponse::Ok().json(responses),
@@ -141,7 +168,7 @@ pub async fn add_discovery_note(
     };
 
     // Verify session exists
-    if repo.get_session(session_id).await.is_err() {
+    if repo.get_session(session_id, user_id).await.is_err() {
         return HttpResponse::NotFound().json(json!({"error": "Session not found"}));
     }
 
@@ -156,10 +183,19 @@ pub async fn add_discovery_note(
 
 // Get all notes for a session
 pub async fn get_discovery_notes(
+    req: HttpRequest,
     session_id: web::Path<Uuid>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let session_id = session_id.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
+
+    if repo.get_session(session_id, user_id).await.is_err() {
+        return HttpResponse::NotFound().json(json!({"error": "Session not found"}));
+    }
 
     match repo.get_notes(session_id).await {
         Ok(notes) => HttpResponse::Ok().json(notes),
@@ -173,11 +209,16 @@ pub async fn get_discovery_notes(
 
 // Update a note
 pub async fn update_discovery_note(
+    req: HttpRequest,
     path: web::Path<Uuid>,
     body: web::Json<serde_json::Value>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let note_id = path.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
 
     let note_text = match body.get("note_text").and_then(|v| v.as_str()) {
         Some(text) => text.to_string(),
@@ -187,7 +228,7 @@ pub async fn update_discovery_note(
         }
     };
 
-    match repo.update_note(note_id, note_text).await {
+    match repo.update_note(note_id, user_id, note_text).await {
         Ok(note) => HttpResponse::Ok().json(note),
         Err(_) => HttpResponse::NotFound().json(json!({"error": "Note not found"})),
     }
@@ -195,14 +236,19 @@ pub async fn update_discovery_note(
 
 // Delete a note
 pub async fn delete_discovery_note(
+    req: HttpRequest,
     path: web::Path<Uuid>,
     repo: web::Data<DiscoveryRepository>,
 ) -> HttpResponse {
     let note_id = path.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
 
-    match repo.delete_note(note_id).await {
-        Ok(_) => HttpResponse::NoContent().finish(),
-        Err(_) => HttpResponse::NotFound().json(json!({"error": "Note not found"})),
+    match repo.delete_note(note_id, user_id).await {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) | Err(_) => HttpResponse::NotFound().json(json!({"error": "Note not found"})),
     }
 }
 
@@ -221,7 +267,7 @@ pub async fn export_discovery_session(
     };
 
     // Verify session exists
-    if repo.get_session(session_id).await.is_err() {
+    if repo.get_session(session_id, user_id).await.is_err() {
         return HttpResponse::NotFound().json(json!({"error": "Session not found"}));
     }
 
@@ -231,7 +277,7 @@ pub async fn export_discovery_session(
     };
 
     // Get session with all responses and notes
-    match repo.get_session_with_responses(session_id).await {
+    match repo.get_session_with_responses(session_id, user_id).await {
         Ok(session_data) => {
             // Build export data
             let export_data = json!({
@@ -277,11 +323,17 @@ pub async fn export_discovery_session(
 
 // Update session status (mark as complete, in-progress, etc.)
 pub async fn update_discovery_session_status(
+    req: HttpRequest,
     session_id: web::Path<Uuid>,
     body: web::Json<serde_json::Value>,
     repo: web::Data<DiscoveryRepository>,
+    events: web::Data<EventPublisher>,
 ) -> HttpResponse {
     let session_id = session_id.into_inner();
+    let user_id = match request_user(&req) {
+        Ok(id) => id,
+        Err(e) => return e.error_response(),
+    };
 
     let status = match body.get("status").and_then(|v| v.as_str()) {
         Some(s) => s,
@@ -297,12 +349,38 @@ pub async fn update_discovery_session_status(
             .json(json!({"error": "Invalid status. Must be in_progress, completed, or archived"}));
     }
 
-    match repo.update_session_status(session_id, status).await {
-        Ok(session) => HttpResponse::Ok().json(session),
+    let was_completed = matches!(
+        repo.get_session(session_id, user_id).await,
+        Ok(ref session) if session.status == "completed"
+    );
+
+    match repo.update_session_status(session_id, user_id, status).await {
+        Ok(session) => {
+            if session.status == "completed" && !was_completed {
+                let message = WebSocketMessage::DiscoverySessionCompleted {
+                    session_id: session.id,
+                    account_id: session.account_id.clone(),
+                    account_name: session.account_name.clone(),
+                    user_id: session.user_id,
+                    completed_at: session.completed_at,
+                };
+                events.publish_for_user(session.user_id, message).await;
+            }
+            HttpResponse::Ok().json(session)
+        }
         Err(_) => HttpResponse::NotFound().json(json!({"error": "Session not found"})),
     }
 }
 
+// The authenticated user, set by the auth middleware; sessions are only
+// visible within the user's organization
+fn request_user(req: &HttpRequest) -> ContrivanceResult<Uuid> {
+    req.extensions()
+        .get::<Uuid>()
+        .copied()
+        .ok_or_else(|| ContrivanceError::unauthorized("Unauthorized"))
+}
+
 // Helper function to format export as CSV
 fn format_export_as_csv(
     session_data: &DiscoverySessionWithResponses,
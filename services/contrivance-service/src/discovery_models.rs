@@ -9,6 +9,7 @@ pub struct DiscoverySession {
     pub account_id: String,
     pub account_name: String,
     pub user_id: Uuid,
+    pub organization_id: Uuid,
     pub vertical: String,
     pub status: String,
     pub started_at: DateTime<Utc>,
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+use common::{Keyset, PaginatedResponse, SortOrder};
 use sqlx::PgPool;
 use uuid::Uuid;
 use crate::discovery_models::*;
@@ -22,8 +24,8 @@ impl DiscoveryRepository {
         sqlx::query_as::<_, DiscoverySession>(
             r#"
             INSERT INTO discovery_sessions 
-            (account_id, account_name, user_id, vertical)
-            VALUES ($1, $2, $3, $4)
+            (account_id, account_name, user_id, vertical, organization_id)
+            VALUES ($1, $2, $3, $4, user_organization($3))
             RETURNING *
             "#,
         )
@@ -35,11 +37,13 @@ impl DiscoveryRepository {
         .await
     }
 
-    pub async fn get_session(&self, session_id: Uuid) -> Result<DiscoverySession, sqlx::Error> {
+    /// Get a session in the user's organization
+    pub async fn get_session(&self, session_id: Uuid, user_id: Uuid) -> Result<DiscoverySession, sqlx::Error> {
         sqlx::query_as::<_, DiscoverySession>(
-            "SELECT * FROM discovery_sessions WHERE id = $1",
+            "SELECT * FROM discovery_sessions WHERE id = $1 AND organization_id = user_organization($2)",
         )
         .bind(session_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
     }
@@ -48,26 +52,48 @@ impl DiscoveryRepository {
         &self,
         account_id: &str,
         user_id: Uuid,
-    ) -> Result<Vec<DiscoverySession>, sqlx::Error> {
-        sqlx::query_as::<_, DiscoverySession>(
-            "SELECT * FROM discovery_sessions WHERE account_id = $1 AND user_id = $2 ORDER BY created_at DESC",
+        keyset: Keyset<DateTime<Utc>>,
+    ) -> Result<PaginatedResponse<DiscoverySession>, sqlx::Error> {
+        let (op, dir) = keyset.sql(SortOrder::Desc);
+
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM discovery_sessions WHERE account_id = $1 AND user_id = $2",
         )
         .bind(account_id)
         .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let sessions = sqlx::query_as::<_, DiscoverySession>(&format!(
+            "SELECT * FROM discovery_sessions WHERE account_id = $1 AND user_id = $2 \
+             AND ($3::timestamptz IS NULL OR (created_at, id) {op} ($3, $4)) \
+             ORDER BY created_at {dir}, id {dir} LIMIT $5 OFFSET $6",
+        ))
+        .bind(account_id)
+        .bind(user_id)
+        .bind(keyset.key())
+        .bind(keyset.id())
+        .bind(keyset.fetch_limit())
+        .bind(keyset.offset())
         .fetch_all(&self.pool)
-        .await
+        .await?;
+
+        Ok(keyset.into_response(sessions, total as u64, |session| (session.created_at, session.id)))
     }
 
     pub async fn update_session_status(
         &self,
         session_id: Uuid,
+        user_id: Uuid,
         status: &str,
     ) -> Result<DiscoverySession, sqlx::Error> {
         sqlx::query_as::<_, DiscoverySession>(
-            "UPDATE discovery_sessions SET status = $1, completed_at = CASE WHEN $1 = 'completed' THEN NOW() ELSE completed_at END WHERE id = $2 RETURNING *",
+            "UPDATE discovery_sessions SET status = $1, completed_at = CASE WHEN $1 = 'completed' THEN NOW() ELSE completed_at END \
+             WHERE id = $2 AND organization_id = user_organization($3) RETURNING *",
         )
         .bind(status)
         .bind(session_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
     }
@@ -118,8 +144,9 @@ impl DiscoveryRepository {
     pub async fn get_session_with_responses(
         &self,
         session_id: Uuid,
+        user_id: Uuid,
     ) -> Result<DiscoverySessionWithResponses, Box<dyn std::error::Error>> {
-        let session = self.get_session(session_id).await?;
+        let session = self.get_session(session_id, user_id).await?;
         let responses = self.get_responses(session_id).await?;
         let notes = self.get_notes(session_id).await?;
         let total_questions_answered = responses.len() as i32;
@@ -165,26 +192,36 @@ impl DiscoveryRepository {
         .await
     }
 
+    /// Update a note on a session in the user's organization
     pub async fn update_note(
         &self,
         note_id: Uuid,
+        user_id: Uuid,
         note_text: String,
     ) -> Result<DiscoveryNote, sqlx::Error> {
         sqlx::query_as::<_, DiscoveryNote>(
-            "UPDATE discovery_notes SET note_text = $1 WHERE id = $2 RETURNING *",
+            "UPDATE discovery_notes n SET note_text = $1 FROM discovery_sessions s \
+             WHERE n.id = $2 AND s.id = n.session_id AND s.organization_id = user_organization($3) RETURNING n.*",
         )
         .bind(note_text)
         .bind(note_id)
+        .bind(user_id)
         .fetch_one(&self.pool)
         .await
     }
 
-    pub async fn delete_note(&self, note_id: Uuid) -> Result<(), sqlx::Error> {
-        sqlx::query("DELETE FROM discovery_notes WHERE id = $1")
-            .bind(note_id)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+    /// Delete a note on a session in the user's organization, returning
+    /// whether there was one
+    pub async fn delete_note(&self, note_id: Uuid, user_id: Uuid) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM discovery_notes n USING discovery_sessions s \
+             WHERE n.id = $1 AND s.id = n.session_id AND s.organization_id = user_organization($2)",
+        )
+        .bind(note_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
     }
 
     // Discovery Exports
@@ -0,0 +1,312 @@
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use common::mailer::{
+    DigestSpreadsheet, DigestTodo, EmailOutbox, EmailTemplate, Mailer, OutboxEmail, PipelineDigest,
+};
+use futures::future::join_all;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{config::Config, repository::ContrivanceRepository, webhooks::retry_delay};
+
+/// How often the sender looks for queued email
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Emails sent per poll
+const BATCH_SIZE: i64 = 20;
+/// How often digest schedules are checked
+const DIGEST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// The digest covers this many days back and lists todos due this many days ahead
+const DIGEST_PERIOD_DAYS: i64 = 7;
+/// Most todos listed in one digest
+const DIGEST_TODO_LIMIT: i64 = 25;
+
+/// Where a user's email goes
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct EmailRecipient {
+    pub email: String,
+    pub name: String,
+}
+
+/// A digest schedule that is due, joined with its user
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DueDigest {
+    pub user_id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub day_of_week: i16,
+    pub hour: i16,
+    pub timezone: String,
+    pub next_send_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DigestSpreadsheetSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub row_count: i64,
+    pub rows_created: i64,
+    pub rows_updated: i64,
+    pub open_todos: i64,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DigestTodoSummary {
+    pub title: String,
+    pub spreadsheet_id: Uuid,
+    pub spreadsheet_name: String,
+    pub due_date: Option<DateTime<Utc>>,
+}
+
+/// Sends queued email from the outbox and retries failures with exponential backoff
+#[derive(Clone)]
+pub struct EmailSender {
+    outbox: EmailOutbox,
+    mailer: Mailer,
+    max_attempts: u32,
+    retry_base: Duration,
+}
+
+impl EmailSender {
+    pub fn new(outbox: EmailOutbox, mailer: Mailer, config: &Config) -> Self {
+        Self {
+            outbox,
+            mailer,
+            max_attempts: config.email_max_attempts,
+            retry_base: Duration::from_secs(config.email_retry_base_secs),
+        }
+    }
+
+    /// Run the send loop until the process exits
+    pub async fn run(self) {
+        info!("Email sender started");
+        loop {
+            match self.send_due().await {
+                // A full batch means more are probably waiting
+                Ok(sent) if sent as i64 == BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => error!("Email sending failed: {}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Send every queued email that is currently due, returning how many were attempted
+    pub async fn send_due(&self) -> common::ContrivanceResult<usize> {
+        let due = self.outbox.claim_due(BATCH_SIZE, 120).await?;
+
+        let count = due.len();
+        join_all(due.into_iter().map(|email| self.attempt(email))).await;
+
+        Ok(count)
+    }
+
+    async fn attempt(&self, email: OutboxEmail) {
+        let result = self.mailer.send(&email.to_address, &email.rendered()).await;
+        let attempts = email.attempt_count as u32 + 1;
+
+        let (error, retry_at) = match &result {
+            Ok(()) => (None, None),
+            Err(e) => {
+                warn!(
+                    "Sending {} email {} to {} failed (attempt {}/{}): {}",
+                    email.template, email.id, email.to_address, attempts, self.max_attempts, e
+                );
+                let retry_at = (attempts < self.max_attempts).then(|| {
+                    let delay = retry_delay(attempts, self.retry_base);
+                    Utc::now() + ChronoDuration::seconds(delay.as_secs() as i64)
+                });
+                (Some(e.to_string()), retry_at)
+            }
+        };
+
+        if let Err(e) = self.outbox.record_attempt(email.id, error.as_deref(), retry_at).await {
+            error!("Failed to record email {}: {}", email.id, e);
+        }
+    }
+}
+
+/// Queues each user's weekly pipeline digest when their schedule comes round
+#[derive(Clone)]
+pub struct DigestScheduler {
+    repository: ContrivanceRepository,
+    outbox: EmailOutbox,
+    app_url: String,
+}
+
+impl DigestScheduler {
+    pub fn new(repository: ContrivanceRepository, outbox: EmailOutbox, app_url: String) -> Self {
+        Self { repository, outbox, app_url }
+    }
+
+    /// Check digest schedules every few minutes until the process exits
+    pub async fn run(self) {
+        info!("Digest scheduler started");
+        loop {
+            if let Err(e) = self.queue_due_digests(Utc::now()).await {
+                error!("Digest scheduling failed: {}", e);
+            }
+            tokio::time::sleep(DIGEST_INTERVAL).await;
+        }
+    }
+
+    /// Queue digests that are due and work out each schedule's next send time.
+    /// Returns how many digests were queued.
+    pub async fn queue_due_digests(&self, now: DateTime<Utc>) -> common::ContrivanceResult<usize> {
+        self.repository.create_missing_digest_schedules().await?;
+
+        let mut queued = 0;
+        for due in self.repository.get_due_digest_schedules(now).await? {
+            let timezone = parse_timezone(&due.timezone).unwrap_or(Tz::UTC);
+            let next = next_digest_at(due.day_of_week, due.hour, timezone, now);
+
+            // A schedule without a send time has just been created or changed;
+            // the first digest goes out at the next slot rather than right away
+            let Some(scheduled_for) = due.next_send_at else {
+                self.repository.advance_digest_schedule(due.user_id, next, None).await?;
+                continue;
+            };
+
+            match self.queue_digest(&due, scheduled_for, now).await {
+                Ok(true) => queued += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    error!("Failed to queue digest for user {}: {}", due.user_id, e);
+                    continue;
+                }
+            }
+            self.repository.advance_digest_schedule(due.user_id, next, Some(now)).await?;
+        }
+
+        Ok(queued)
+    }
+
+    /// Build and queue one user's digest, skipping it if there's nothing to report
+    async fn queue_digest(
+        &self,
+        due: &DueDigest,
+        scheduled_for: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> common::ContrivanceResult<bool> {
+        let period_start = now - ChronoDuration::days(DIGEST_PERIOD_DAYS);
+        let spreadsheets = self.repository.get_digest_spreadsheets(due.user_id, period_start).await?;
+        let todos = self.repository
+            .get_digest_todos(due.user_id, now + ChronoDuration::days(DIGEST_PERIOD_DAYS), DIGEST_TODO_LIMIT)
+            .await?;
+
+        let (overdue, upcoming): (Vec<_>, Vec<_>) = todos
+            .into_iter()
+            .map(|todo| DigestTodo {
+                title: crate::notifications::strip_mentions(&todo.title),
+                spreadsheet: todo.spreadsheet_name,
+                due_date: todo.due_date,
+                url: self.spreadsheet_url(todo.spreadsheet_id),
+            })
+            .partition(|todo| todo.due_date.is_some_and(|due_date| due_date < now));
+
+        let digest = PipelineDigest {
+            recipient: due.name.clone(),
+            period_start,
+            period_end: now,
+            spreadsheets: spreadsheets
+                .into_iter()
+                .map(|s| DigestSpreadsheet {
+                    url: self.spreadsheet_url(s.id),
+                    name: s.name,
+                    row_count: s.row_count,
+                    rows_created: s.rows_created,
+                    rows_updated: s.rows_updated,
+                    open_todos: s.open_todos,
+                })
+                .collect(),
+            overdue_todos: overdue,
+            upcoming_todos: upcoming,
+            settings_url: format!("{}/settings", self.app_url),
+        };
+        if digest.is_empty() {
+            return Ok(false);
+        }
+
+        // Keyed on the slot so a second instance can't send the same digest
+        let dedupe_key = format!("weekly_digest:{}:{}", due.user_id, scheduled_for.timestamp());
+        let queued = self.outbox
+            .enqueue(Some(due.user_id), &due.email, &EmailTemplate::WeeklyDigest(digest), Some(&dedupe_key))
+            .await?;
+
+        Ok(queued.is_some())
+    }
+
+    fn spreadsheet_url(&self, spreadsheet_id: Uuid) -> String {
+        format!("{}/spreadsheet/{}", self.app_url, spreadsheet_id)
+    }
+}
+
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+/// The first `hour:00` on `day_of_week` (0 = Monday) in `timezone` that is
+/// strictly after `after`. A slot that falls in a DST gap moves to the next hour.
+pub fn next_digest_at(day_of_week: i16, hour: i16, timezone: Tz, after: DateTime<Utc>) -> DateTime<Utc> {
+    let day_of_week = day_of_week.clamp(0, 6) as u32;
+    let hour = hour.clamp(0, 23) as u32;
+    let today = after.with_timezone(&timezone).date_naive();
+
+    (0..=7)
+        .filter_map(|offset| today.checked_add_signed(ChronoDuration::days(offset)))
+        .filter(|date| date.weekday().num_days_from_monday() == day_of_week)
+        .filter_map(|date| local_slot(timezone, date, hour))
+        .find(|slot| *slot > after)
+        .unwrap_or_else(|| after + ChronoDuration::days(7))
+}
+
+fn local_slot(timezone: Tz, date: NaiveDate, hour: u32) -> Option<DateTime<Utc>> {
+    let at = |hour: u32| {
+        date.and_hms_opt(hour, 0, 0)
+            .and_then(|naive| timezone.from_local_datetime(&naive).earliest())
+    };
+    at(hour)
+        .or_else(|| at(hour + 1))
+        .map(|slot| slot.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_next_digest_at_in_utc() {
+        // Wednesday 15 Oct 2025
+        let wednesday = utc("2025-10-15T12:00:00Z");
+        assert_eq!(next_digest_at(0, 8, Tz::UTC, wednesday), utc("2025-10-20T08:00:00Z"));
+        assert_eq!(next_digest_at(2, 13, Tz::UTC, wednesday), utc("2025-10-15T13:00:00Z"));
+        // Same day but the hour has passed: a week later
+        assert_eq!(next_digest_at(2, 12, Tz::UTC, wednesday), utc("2025-10-22T12:00:00Z"));
+    }
+
+    #[test]
+    fn test_next_digest_at_in_local_time() {
+        let new_york = parse_timezone("America/New_York").unwrap();
+        // Monday 08:00 in New York is 12:00 UTC during daylight saving time...
+        assert_eq!(
+            next_digest_at(0, 8, new_york, utc("2025-10-15T12:00:00Z")),
+            utc("2025-10-20T12:00:00Z")
+        );
+        // ...and 13:00 UTC after the clocks go back
+        assert_eq!(
+            next_digest_at(0, 8, new_york, utc("2025-11-05T12:00:00Z")),
+            utc("2025-11-10T13:00:00Z")
+        );
+        // 02:00 on the spring-forward Sunday doesn't exist; it moves to 03:00 EDT
+        assert_eq!(
+            next_digest_at(6, 2, new_york, utc("2026-03-04T00:00:00Z")),
+            utc("2026-03-08T07:00:00Z")
+        );
+
+        assert!(parse_timezone("Mars/Olympus_Mons").is_none());
+    }
+}
@@ -0,0 +1,270 @@
+//! Server-Sent Events fallback for networks that block WebSocket upgrades.
+//!
+//! `GET /api/spreadsheets/{id}/events` streams the messages a spreadsheet
+//! socket would receive, one JSON `WebSocketMessage` per `data:` line. The
+//! stream is registered with the `ConnectionManager` like a socket, with its
+//! own `Outbox`, so it gets the same fan-out, coalescing and slow consumer
+//! handling; it only listens, so it doesn't make its user present and can't
+//! edit. Change feed events carry their sequence number as the event `id`, and
+//! a client reconnecting with `Last-Event-ID` has the events it missed
+//! replayed first.
+
+use actix_web::web::{self, Bytes};
+use chrono::{DateTime, Utc};
+use common::WebSocketMessage;
+use futures::{stream, Stream};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, RwLock};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    backpressure::Outbox,
+    events::Replay,
+    handlers::ContrivanceHandlers,
+    socket_auth::{SocketAuth, SocketSession},
+    websocket::{ConnectionManager, Outlet, ACCESS_CHECK_INTERVAL},
+};
+
+pub const EVENT_STREAM_CONTENT_TYPE: &str = "text/event-stream";
+
+/// The change feed sequence number of a queued message, if it has one
+#[derive(Deserialize)]
+struct Sequenced {
+    seq: Option<i64>,
+}
+
+/// One client's stream of a spreadsheet's messages
+pub struct EventStream {
+    connection_id: Uuid,
+    spreadsheet_id: Uuid,
+    user_id: Uuid,
+    /// The stream ends when the credentials it was opened with expire
+    expires_at: DateTime<Utc>,
+    connection_manager: Arc<RwLock<ConnectionManager>>,
+    auth: web::Data<SocketAuth>,
+    /// Fired by the manager when something is queued in `outbox`
+    notify: Arc<Notify>,
+    outbox: Arc<Mutex<Outbox>>,
+    /// How long the stream may go quiet before a comment keeps proxies from
+    /// timing it out
+    keepalive: Duration,
+    /// Frames ready to be written, ahead of anything in the outbox
+    pending: VecDeque<Bytes>,
+    /// Last event replayed on opening; live copies of it and earlier ones are skipped
+    replayed_seq: i64,
+    access_checked: Instant,
+    finished: bool,
+}
+
+impl EventStream {
+    /// Start listening to a spreadsheet and queue up what the client missed
+    /// after `last_seq`. Live messages wait until the replay has been written.
+    pub async fn open(
+        session: SocketSession,
+        spreadsheet_id: Uuid,
+        last_seq: Option<i64>,
+        connection_manager: Arc<RwLock<ConnectionManager>>,
+        auth: web::Data<SocketAuth>,
+        handlers: web::Data<ContrivanceHandlers>,
+    ) -> Self {
+        let connection_id = Uuid::new_v4();
+        let notify = Arc::new(Notify::new());
+        let (keepalive, outbox) = {
+            let mut manager = connection_manager.write().await;
+            let outbox = Arc::new(Mutex::new(Outbox::new(manager.limits().outbox_capacity)));
+            let outlet = Outlet::for_stream(connection_id, &notify, outbox.clone());
+            manager.listen(spreadsheet_id, session.user_id, outlet);
+            (manager.heartbeat().interval, outbox)
+        };
+        info!("Event stream started for user {} on spreadsheet {}", session.user_id, spreadsheet_id);
+
+        let mut stream = Self {
+            connection_id,
+            spreadsheet_id,
+            user_id: session.user_id,
+            expires_at: session.expires_at,
+            connection_manager,
+            auth,
+            notify,
+            outbox,
+            keepalive,
+            pending: VecDeque::new(),
+            replayed_seq: 0,
+            access_checked: Instant::now(),
+            finished: false,
+        };
+        stream.replay(handlers.missed_events(spreadsheet_id, last_seq).await);
+        stream
+    }
+
+    fn replay(&mut self, replay: common::ContrivanceResult<Replay>) {
+        let spreadsheet_id = self.spreadsheet_id;
+        match replay {
+            Ok(Replay::Events(events, seq)) => {
+                if !events.is_empty() {
+                    info!("Replaying {} events to user {} on spreadsheet {}", events.len(), self.user_id, spreadsheet_id);
+                }
+                for event in &events {
+                    self.pending.push_back(frame(event["seq"].as_i64(), &event.to_string()));
+                }
+                // Give a client that missed nothing a place to resume from
+                if events.is_empty() {
+                    self.pending.push_back(Bytes::from(format!("id: {}\n\n", seq)));
+                }
+                self.replayed_seq = seq;
+            }
+            Ok(Replay::TooFarBehind(seq)) => {
+                self.send_with_id(Some(seq), &WebSocketMessage::ResyncRequired { spreadsheet_id, seq });
+                self.replayed_seq = seq;
+            }
+            Err(e) => {
+                error!("Failed to load missed events for spreadsheet {}: {}", spreadsheet_id, e);
+                self.finish("RESYNC_REQUIRED", "Failed to catch up; reload the spreadsheet");
+            }
+        }
+    }
+
+    /// The response body
+    pub fn into_body(self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        stream::unfold(self, |mut stream| async move {
+            let frame = stream.next_frame().await?;
+            Some((Ok(frame), stream))
+        })
+    }
+
+    /// The next chunk of the response, waiting for messages if none are
+    /// queued; `None` once the stream has ended
+    async fn next_frame(&mut self) -> Option<Bytes> {
+        loop {
+            // Checked however busy the stream is, so a steady flow of messages
+            // can't keep it open past expiry or revocation
+            self.check_credentials().await;
+            if let Some(frame) = self.pending.pop_front() {
+                return Some(frame);
+            }
+            if self.finished {
+                return None;
+            }
+
+            let (messages, closed, resync) = {
+                let mut outbox = self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                (outbox.drain(), outbox.is_closed(), outbox.needs_resync())
+            };
+            if closed {
+                self.finish("SLOW_CONSUMER", "Stream fell too far behind; reconnect to resume");
+                continue;
+            }
+            if resync {
+                self.finish("RESYNC_REQUIRED", "Messages were dropped while the stream was behind; reload the spreadsheet");
+                continue;
+            }
+            for json in messages {
+                self.queue(&json);
+            }
+            if !self.pending.is_empty() {
+                continue;
+            }
+
+            // Keep the connection open while nothing is happening
+            let notify = self.notify.clone();
+            let until_expiry = (self.expires_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = notify.notified() => {}
+                _ = tokio::time::sleep(self.keepalive) => self.pending.push_back(Bytes::from_static(b": keepalive\n\n")),
+                _ = tokio::time::sleep(until_expiry) => {}
+            }
+        }
+    }
+
+    /// Frame a message from the outbox, skipping change feed events the replay already sent
+    fn queue(&mut self, json: &str) {
+        let seq = serde_json::from_str::<Sequenced>(json).ok().and_then(|sequenced| sequenced.seq);
+        if seq.is_some_and(|seq| seq <= self.replayed_seq) {
+            return;
+        }
+        self.pending.push_back(frame(seq, json));
+    }
+
+    /// End the stream if the credentials have expired or the user's access
+    /// was removed. Access is looked up every `ACCESS_CHECK_INTERVAL`.
+    async fn check_credentials(&mut self) {
+        if self.finished {
+            return;
+        }
+        if Utc::now() >= self.expires_at {
+            info!("Closing event stream of user {}: token expired", self.user_id);
+            self.finish("TOKEN_EXPIRED", "Token expired; reconnect with a new one");
+            return;
+        }
+        if self.access_checked.elapsed() < ACCESS_CHECK_INTERVAL {
+            return;
+        }
+        self.access_checked = Instant::now();
+        match self.auth.access(self.user_id, self.spreadsheet_id).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                info!("Closing event stream of user {} on spreadsheet {}: access removed", self.user_id, self.spreadsheet_id);
+                self.finish("ACCESS_REVOKED", "Access to this spreadsheet was removed");
+            }
+            // Keep the stream and try again at the next check
+            Err(e) => warn!("Failed to check access of user {} to spreadsheet {}: {}", self.user_id, self.spreadsheet_id, e),
+        }
+    }
+
+    fn send_with_id(&mut self, id: Option<i64>, message: &WebSocketMessage) {
+        match serde_json::to_string(message) {
+            Ok(json) => self.pending.push_back(frame(id, &json)),
+            Err(e) => error!("Failed to serialize {} message: {}", message.event_type(), e),
+        }
+    }
+
+    /// Tell the client why the stream is ending, then end it without sending
+    /// anything still queued
+    fn finish(&mut self, code: &str, message: &str) {
+        self.pending.clear();
+        let error = WebSocketMessage::Error {
+            message: message.to_string(),
+            code: Some(code.to_string()),
+        };
+        self.send_with_id(None, &error);
+        self.finished = true;
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        info!("Event stream stopped for user {} on spreadsheet {}", self.user_id, self.spreadsheet_id);
+        let (spreadsheet_id, user_id, connection_id) = (self.spreadsheet_id, self.user_id, self.connection_id);
+        let connection_manager = self.connection_manager.clone();
+        // Without a runtime the sweeper reaps the stream instead
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                connection_manager.write().await.leave(spreadsheet_id, user_id, connection_id);
+            });
+        }
+    }
+}
+
+/// An SSE event carrying one message, with its sequence number as the id
+fn frame(id: Option<i64>, json: &str) -> Bytes {
+    match id {
+        Some(id) => Bytes::from(format!("id: {}\ndata: {}\n\n", id, json)),
+        None => Bytes::from(format!("data: {}\n\n", json)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_carry_sequence_numbers_as_ids() {
+        assert_eq!(frame(Some(42), r#"{"seq":42}"#), Bytes::from("id: 42\ndata: {\"seq\":42}\n\n"));
+        assert_eq!(frame(None, r#"{"type":"Ping"}"#), Bytes::from("data: {\"type\":\"Ping\"}\n\n"));
+    }
+}
@@ -0,0 +1,291 @@
+use common::{
+    webhooks::is_webhook_event_type, ContrivanceError, ContrivanceResult, SequencedMessage, SpreadsheetEvent,
+    WebSocketMessage,
+};
+use serde_json::Value;
+use sqlx::{PgConnection, Postgres};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{broadcast::Broadcaster, repository::ContrivanceRepository};
+
+/// Most events replayed to a resuming socket; further behind, it reloads
+const MAX_REPLAY_EVENTS: i64 = 1000;
+
+/// The events a client missed, as they were broadcast
+#[derive(Debug)]
+pub enum Replay {
+    /// Every missed event, oldest first, and the last sequence number sent
+    Events(Vec<Value>, i64),
+    /// Too many were missed; the client should reload from the latest sequence number
+    TooFarBehind(i64),
+}
+
+/// A change feed event as it was broadcast, stamped with its sequence number
+pub fn event_payload(event: SpreadsheetEvent) -> serde_json::Result<Value> {
+    let message = serde_json::from_value::<WebSocketMessage>(event.payload)?;
+    serde_json::to_value(SequencedMessage { seq: event.seq, message })
+}
+
+/// Records mutations in the per-spreadsheet change feed and broadcasts them,
+/// stamped with their sequence number, to connected clients on every instance
+/// and to webhooks.
+#[derive(Clone)]
+pub struct EventPublisher {
+    repository: ContrivanceRepository,
+    broadcaster: Broadcaster,
+}
+
+impl EventPublisher {
+    pub fn new(repository: ContrivanceRepository, broadcaster: Broadcaster) -> Self {
+        Self {
+            repository,
+            broadcaster,
+        }
+    }
+
+    /// Start a publication: the caller makes its writes in the publication's
+    /// transaction and records their events, which are broadcast once it commits
+    pub async fn begin(&self) -> ContrivanceResult<Publication> {
+        Ok(Publication {
+            tx: self.repository.begin().await?,
+            repository: self.repository.clone(),
+            broadcaster: self.broadcaster.clone(),
+            messages: Vec::new(),
+        })
+    }
+
+    /// Publish an event that has no write of its own, such as a notification
+    pub async fn publish(&self, spreadsheet_id: Uuid, actor_id: Uuid, message: WebSocketMessage) -> ContrivanceResult<i64> {
+        let mut publication = self.begin().await?;
+        let seq = publication.record(spreadsheet_id, actor_id, message).await?;
+        publication.commit().await?;
+        Ok(seq)
+    }
+
+    /// Delete a spreadsheet and tell its connected clients and webhooks. Its
+    /// change feed goes with it, so the event isn't recorded.
+    pub async fn delete_spreadsheet(&self, spreadsheet_id: Uuid, deleted_by: Uuid) -> ContrivanceResult<()> {
+        let message = WebSocketMessage::SpreadsheetDeleted {
+            spreadsheet_id,
+            deleted_by,
+        };
+        let payload = serde_json::to_value(&message).map_err(|e| ContrivanceError::serialization(e.to_string()))?;
+
+        self.repository
+            .delete_spreadsheet(spreadsheet_id, (message.event_type(), &payload))
+            .await?;
+        self.broadcaster.to_spreadsheet(spreadsheet_id, payload, None).await;
+
+        Ok(())
+    }
+
+    /// Send a message to everyone connected to a spreadsheet without recording
+    /// it in the change feed, for state that doesn't outlive the sockets or that
+    /// clients load afresh when they reconnect
+    pub async fn broadcast(&self, spreadsheet_id: Uuid, message: &WebSocketMessage) {
+        match serde_json::to_value(message) {
+            Ok(payload) => self.broadcaster.to_spreadsheet(spreadsheet_id, payload, None).await,
+            Err(e) => error!("Failed to serialize {} message: {}", message.event_type(), e),
+        }
+    }
+
+    /// The events on a spreadsheet after `since`, for a client catching up
+    /// after a dropped connection
+    pub async fn replay(&self, spreadsheet_id: Uuid, since: i64) -> ContrivanceResult<Replay> {
+        let latest_seq = self.repository.get_latest_seq(spreadsheet_id).await?;
+        let since = since.clamp(0, latest_seq);
+        if since == latest_seq {
+            return Ok(Replay::Events(Vec::new(), latest_seq));
+        }
+        if latest_seq - since > MAX_REPLAY_EVENTS {
+            return Ok(Replay::TooFarBehind(latest_seq));
+        }
+
+        let events = self.repository.get_events_since(spreadsheet_id, since, MAX_REPLAY_EVENTS).await?;
+        let mut seq = since;
+        let mut payloads = Vec::with_capacity(events.len());
+        for event in events {
+            seq = event.seq;
+            payloads.push(event_payload(event).map_err(|e| ContrivanceError::serialization(e.to_string()))?);
+        }
+        Ok(Replay::Events(payloads, seq))
+    }
+
+    /// Publish an event that isn't tied to a spreadsheet, such as a completed
+    /// discovery session. These only go to the user's own webhooks.
+    pub async fn publish_for_user(&self, user_id: Uuid, message: WebSocketMessage) {
+        let event_type = message.event_type();
+        if !is_webhook_event_type(event_type) {
+            return;
+        }
+
+        let result = match serde_json::to_value(&message) {
+            Ok(payload) => self.repository.enqueue_user_webhooks(user_id, event_type, &payload).await,
+            Err(e) => Err(ContrivanceError::serialization(e.to_string())),
+        };
+        if let Err(e) = result {
+            error!("Failed to queue webhooks for {} event for user {}: {}", event_type, user_id, e);
+        }
+    }
+}
+
+/// A transaction whose writes and change feed events commit together.
+///
+/// Recording an event appends it to the change feed and queues its webhooks in
+/// the transaction, so a change is never committed without its event or the
+/// other way round. Clients only hear about the events, and any messages
+/// broadcast alongside them, once `commit` succeeds; dropping the publication
+/// rolls everything back.
+pub struct Publication {
+    tx: sqlx::Transaction<'static, Postgres>,
+    repository: ContrivanceRepository,
+    broadcaster: Broadcaster,
+    messages: Vec<(Uuid, Value, Option<i64>)>,
+}
+
+impl Publication {
+    /// The transaction to make the mutation's writes in
+    pub fn tx(&mut self) -> &mut PgConnection {
+        &mut self.tx
+    }
+
+    /// Record an event for the writes made so far and return its sequence
+    /// number. Record after the writes: appending locks the spreadsheet's
+    /// change feed until the transaction ends.
+    pub async fn record(&mut self, spreadsheet_id: Uuid, actor_id: Uuid, message: WebSocketMessage) -> ContrivanceResult<i64> {
+        let event_type = message.event_type();
+        let seq = self
+            .repository
+            .append_event(&mut self.tx, spreadsheet_id, Some(actor_id), &message)
+            .await?;
+        let payload = serde_json::to_value(SequencedMessage { seq, message })
+            .map_err(|e| ContrivanceError::serialization(e.to_string()))?;
+
+        if is_webhook_event_type(event_type) {
+            self.repository
+                .enqueue_spreadsheet_webhooks(&mut self.tx, spreadsheet_id, event_type, &payload)
+                .await?;
+        }
+
+        self.messages.push((spreadsheet_id, payload, Some(seq)));
+        Ok(seq)
+    }
+
+    /// Send a message to everyone connected to a spreadsheet once the
+    /// transaction commits, without recording it in the change feed
+    pub fn broadcast(&mut self, spreadsheet_id: Uuid, message: &WebSocketMessage) {
+        match serde_json::to_value(message) {
+            Ok(payload) => self.messages.push((spreadsheet_id, payload, None)),
+            Err(e) => error!("Failed to serialize {} message: {}", message.event_type(), e),
+        }
+    }
+
+    /// Commit the writes and their events, then broadcast the events and
+    /// messages in the order they were added
+    pub async fn commit(self) -> ContrivanceResult<()> {
+        self.tx.commit().await?;
+        for (spreadsheet_id, payload, seq) in self.messages {
+            self.broadcaster.to_spreadsheet(spreadsheet_id, payload, seq).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        broadcast::BroadcastBackend,
+        websocket::{ConnectionManager, Heartbeat},
+    };
+    use common::{
+        testing::{create_user, test_pool},
+        CreateSpreadsheetRequest,
+    };
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_missed_events_are_replayed_in_order() {
+        let pool = test_pool().await;
+        let repository = ContrivanceRepository::new(pool.clone());
+        let (user_id, _) = create_user(&pool, "Replay").await;
+        let request = CreateSpreadsheetRequest {
+            name: "Pipeline".to_string(),
+            description: None,
+            is_public: Some(false),
+            settings: None,
+            columns: None,
+            workspace_id: None,
+            folder_id: None,
+            team_id: None,
+        };
+        let spreadsheet_id = repository.create_spreadsheet(&request, user_id, None).await.unwrap().id;
+
+        let connections = Arc::new(RwLock::new(ConnectionManager::new(Heartbeat::default())));
+        let (broadcaster, _) = Broadcaster::new(connections, repository.clone(), BroadcastBackend::Local);
+        let publisher = EventPublisher::new(repository, broadcaster);
+        let mut row_ids = Vec::new();
+        for _ in 0..3 {
+            let row_id = Uuid::new_v4();
+            let message = WebSocketMessage::RowDeleted { spreadsheet_id, row_id, deleted_by: user_id };
+            publisher.publish(spreadsheet_id, user_id, message).await.unwrap();
+            row_ids.push(row_id);
+        }
+
+        match publisher.replay(spreadsheet_id, 1).await.unwrap() {
+            Replay::Events(events, seq) => {
+                assert_eq!(seq, 3);
+                let replayed: Vec<(i64, String)> = events
+                    .iter()
+                    .map(|event| (event["seq"].as_i64().unwrap(), event["row_id"].as_str().unwrap().to_string()))
+                    .collect();
+                assert_eq!(replayed, vec![(2, row_ids[1].to_string()), (3, row_ids[2].to_string())]);
+                assert_eq!(events[0]["type"], "RowDeleted");
+            }
+            other => panic!("expected events, got {:?}", other),
+        }
+
+        // Caught up, or claiming to be ahead, there's nothing to send
+        for since in [3, i64::MAX] {
+            assert!(matches!(publisher.replay(spreadsheet_id, since).await.unwrap(), Replay::Events(events, 3) if events.is_empty()));
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_events_only_outlive_their_transaction_if_it_commits() {
+        let pool = test_pool().await;
+        let repository = ContrivanceRepository::new(pool.clone());
+        let (user_id, _) = create_user(&pool, "Rollback").await;
+        let request = CreateSpreadsheetRequest {
+            name: "Pipeline".to_string(),
+            description: None,
+            is_public: Some(false),
+            settings: None,
+            columns: None,
+            workspace_id: None,
+            folder_id: None,
+            team_id: None,
+        };
+        let spreadsheet_id = repository.create_spreadsheet(&request, user_id, None).await.unwrap().id;
+
+        let connections = Arc::new(RwLock::new(ConnectionManager::new(Heartbeat::default())));
+        let (broadcaster, _) = Broadcaster::new(connections, repository.clone(), BroadcastBackend::Local);
+        let publisher = EventPublisher::new(repository.clone(), broadcaster);
+        let message = || WebSocketMessage::RowDeleted { spreadsheet_id, row_id: Uuid::new_v4(), deleted_by: user_id };
+
+        // Dropped, as when the mutation fails after its event was recorded
+        let mut publication = publisher.begin().await.unwrap();
+        assert_eq!(publication.record(spreadsheet_id, user_id, message()).await.unwrap(), 1);
+        drop(publication);
+        assert_eq!(repository.get_latest_seq(spreadsheet_id).await.unwrap(), 0);
+
+        let mut publication = publisher.begin().await.unwrap();
+        assert_eq!(publication.record(spreadsheet_id, user_id, message()).await.unwrap(), 1);
+        publication.commit().await.unwrap();
+        assert_eq!(repository.get_latest_seq(spreadsheet_id).await.unwrap(), 1);
+    }
+}
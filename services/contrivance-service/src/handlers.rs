@@ -1,27 +1,100 @@
 use actix_web::{web, HttpResponse, HttpRequest};
+use serde::Deserialize;
 use uuid::Uuid;
+use chrono::{NaiveDate, Utc};
 use crate::{
+    automation::AutomationEngine,
+    broadcast::Broadcaster,
+    events::{EventPublisher, Publication, Replay},
+    indexing::{self, IndexManager, RowFilter},
+    notifications::Notifier,
     repository::ContrivanceRepository,
-    websocket::ConnectionManager,
+    reporting::{self, CellConverter},
+    streaming,
+    text_documents::{self, TextDocument},
     middleware::auth::get_user_from_request,
 };
 use common::WebSocketMessage;
 use common::{
     CreateSpreadsheetRequest, UpdateSpreadsheetRequest,
     CreateRowRequest, UpdateRowRequest, PaginationParams, ApiResponse,
-    ContrivanceError, CreateTodoRequest, UpdateTodoRequest,
+    ContrivanceError, ContrivanceResult, CreateTodoRequest, UpdateTodoRequest,
+    AddTodoDependencyRequest, CreateChecklistItemRequest, UpdateChecklistItemRequest,
+    CurrencyConverter, NewExchangeRate, UserRole, ChangeFeed,
+    CreateWebhookRequest, UpdateWebhookRequest, SpreadsheetRow,
+    CreateAutomationRuleRequest, UpdateAutomationRuleRequest,
+    AddCollaboratorRequest, UpdateNotificationPreferencesRequest,
+    UpdateDigestScheduleRequest, RowKeys, UpdateColumnRequest,
+    CreateWorkspaceRequest, UpdateWorkspaceRequest, AddWorkspaceMemberRequest,
+    CreateFolderRequest, UpdateFolderRequest, MoveFolderRequest, MoveSpreadsheetRequest,
+    DefaultPermission, UpdateOrganizationRequest, InviteOrganizationMemberRequest,
+    AcceptOrganizationInvitationRequest,
+    UpdateOrganizationMemberRequest, CreateTeamRequest, UpdateTeamRequest, AddTeamMemberRequest,
+    AssignTeamRequest, CellLock, SpreadsheetColumn, ColumnType,
 };
+use validator::Validate;
+use common::currency::{normalize_currency_code, parse_rates_csv};
+
+/// How long a cell lock lasts unless its socket renews it
+const CELL_LOCK_TTL_SECONDS: i64 = 15;
+
+/// What to do to a text cell's collaborative document
+enum TextEdit<'a> {
+    /// Start a document if the cell has none
+    Open,
+    /// Merge a client's changes
+    Merge(&'a [u8]),
+    /// Only catch an existing document up with the cell
+    Sync,
+}
+
+/// The text a cell's document holds for its value
+fn cell_text(cell: &serde_json::Value) -> String {
+    match cell {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Record a row update in the change feed. Events keep name-keyed cells until
+/// subscribers have moved to column ids.
+async fn record_row_update(
+    publication: &mut Publication,
+    user_id: Uuid,
+    row: &SpreadsheetRow,
+    columns: &[SpreadsheetColumn],
+) -> ContrivanceResult<i64> {
+    let message = WebSocketMessage::RowUpdated {
+        spreadsheet_id: row.spreadsheet_id,
+        row: row.clone().keyed(columns, RowKeys::Name),
+        updated_by: user_id,
+    };
+    publication.record(row.spreadsheet_id, user_id, message).await
+}
 
 pub struct ContrivanceHandlers {
     repository: ContrivanceRepository,
-    connection_manager: web::Data<ConnectionManager>,
+    events: EventPublisher,
+    automation: AutomationEngine,
+    notifier: Notifier,
+    indexes: IndexManager,
 }
 
 impl ContrivanceHandlers {
-    pub fn new(repository: ContrivanceRepository, connection_manager: web::Data<ConnectionManager>) -> Self {
+    pub fn new(
+        repository: ContrivanceRepository,
+        broadcaster: Broadcaster,
+        automation: AutomationEngine,
+        notifier: Notifier,
+        indexes: IndexManager,
+    ) -> Self {
         Self {
+            events: EventPublisher::new(repository.clone(), broadcaster),
             repository,
-            connection_manager,
+            automation,
+            notifier,
+            indexes,
         }
     }
 
@@ -44,10 +117,30 @@ impl ContrivanceHandlers {
             }
         }
         
+        validate_settings(payload.settings.as_ref())?;
+
+        let location = self.repository
+            .resolve_location(payload.workspace_id, payload.folder_id)
+            .await?;
+        if let Some(location) = location {
+            let permission = self.repository.workspace_permission(user.id, location).await?;
+            if !permission.is_some_and(DefaultPermission::can_edit) {
+                return Err(ContrivanceError::forbidden("Edit access denied to this folder"));
+            }
+        }
+        if let Some(team_id) = payload.team_id {
+            if !self.repository.can_user_use_team(user.id, team_id).await? {
+                return Err(ContrivanceError::forbidden("Only team members and organization admins can give the team spreadsheets"));
+            }
+        }
+
         let spreadsheet = self.repository
-            .create_spreadsheet(&payload, user.id)
+            .create_spreadsheet(&payload, user.id, location)
             .await?;
 
+        // Template columns may ask to be indexed
+        self.indexes.refresh(spreadsheet.id);
+
         Ok(HttpResponse::Created().json(ApiResponse::success(spreadsheet)))
     }
 
@@ -56,6 +149,7 @@ impl ContrivanceHandlers {
         &self,
         req: HttpRequest,
         path: web::Path<Uuid>,
+        query: web::Query<DetailsQuery>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let spreadsheet_id = path.into_inner();
@@ -65,12 +159,31 @@ impl ContrivanceHandlers {
             return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
         }
 
+        // Large spreadsheets can be streamed a row per line instead of as one document
+        if streaming::wants_ndjson(&req, query.stream) {
+            let details = self.repository
+                .get_spreadsheet_details_without_rows(spreadsheet_id)
+                .await?
+                .ok_or_else(|| ContrivanceError::not_found("Spreadsheet not found"))?;
+            let rows = Box::pin(self.repository.stream_spreadsheet_rows(spreadsheet_id));
+
+            return Ok(HttpResponse::Ok()
+                .content_type(streaming::NDJSON_CONTENT_TYPE)
+                .streaming(streaming::details_ndjson(details, rows, query.keys)));
+        }
+
         let spreadsheet_details = self.repository
             .get_spreadsheet_details(spreadsheet_id)
             .await?;
 
         match spreadsheet_details {
-            Some(details) => Ok(HttpResponse::Ok().json(ApiResponse::success(details))),
+            Some(mut details) => {
+                details.rows = std::mem::take(&mut details.rows)
+                    .into_iter()
+                    .map(|row| row.keyed(&details.columns, query.keys))
+                    .collect();
+                Ok(HttpResponse::Ok().json(ApiResponse::success(details)))
+            }
             None => Err(ContrivanceError::not_found("Spreadsheet not found")),
         }
     }
@@ -105,8 +218,11 @@ impl ContrivanceHandlers {
             return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
         }
 
+        validate_settings(payload.settings.as_ref())?;
+
+        let mut publication = self.events.begin().await?;
         let spreadsheet = self.repository
-            .update_spreadsheet(spreadsheet_id, &payload)
+            .update_spreadsheet(publication.tx(), spreadsheet_id, &payload)
             .await?;
 
         // Notify collaborators of the update
@@ -115,9 +231,8 @@ impl ContrivanceHandlers {
             updated_by: user.id,
         };
 
-        self.connection_manager
-            .broadcast_to_spreadsheet(spreadsheet_id, message)
-            .await;
+        publication.record(spreadsheet_id, user.id, message).await?;
+        publication.commit().await?;
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(spreadsheet)))
     }
@@ -141,17 +256,8 @@ impl ContrivanceHandlers {
             return Err(ContrivanceError::forbidden("Only the owner can delete this spreadsheet"));
         }
 
-        self.repository.delete_spreadsheet(spreadsheet_id).await?;
-
-        // Notify all connected clients
-        let message = WebSocketMessage::SpreadsheetDeleted {
-            spreadsheet_id,
-            deleted_by: user.id,
-        };
-
-        self.connection_manager
-            .broadcast_to_spreadsheet(spreadsheet_id, message)
-            .await;
+        self.events.delete_spreadsheet(spreadsheet_id, user.id).await?;
+        self.indexes.refresh(spreadsheet_id);
 
         Ok(HttpResponse::NoContent().finish())
     }
@@ -196,37 +302,46 @@ impl ContrivanceHandlers {
             .get_spreadsheet_columns(spreadsheet_id)
             .await?;
 
-        let existing_column_names: std::collections::HashSet<String> = existing_columns
+        let existing_column_names: std::collections::HashSet<&str> = existing_columns
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+
+        // Columns created by an earlier sync remember their field, so a
+        // renamed column isn't added again
+        let existing_column_fields: std::collections::HashSet<&str> = existing_columns
             .iter()
-            .map(|c| c.name.clone())
+            .filter_map(|c| c.display_options.as_ref()?.get("salesforce_field")?.as_str())
             .collect();
 
-        // Define the Salesforce columns we want to ensure exist
+        // Define the Salesforce columns we want to ensure exist, with the
+        // opportunity field each one holds
         let salesforce_column_defs = vec![
-            ("Salesforce ID", "text"),
-            ("Opportunity Name", "text"),
-            ("Stage", "text"),
-            ("Probability", "number"),
-            ("Expected Revenue", "currency"),
-            ("Close Date", "date"),
-            ("Owner", "text"),
-            ("Last Modified By", "text"),
-            ("Last Modified Date", "date"),
-            ("Technical Win", "checkbox"),
+            ("Salesforce ID", "Id", "text"),
+            ("Opportunity Name", "Name", "text"),
+            ("Stage", "StageName", "text"),
+            ("Probability", "Probability", "percent"),
+            ("Expected Revenue", "ExpectedRevenue", "currency"),
+            ("Close Date", "CloseDate", "date"),
+            ("Owner", "Owner.Name", "text"),
+            ("Last Modified By", "LastModifiedBy.Name", "text"),
+            ("Last Modified Date", "LastModifiedDate", "date"),
+            ("Technical Win", "Technical_Win__c", "checkbox"),
         ];
 
         // Filter to only columns that don't already exist
         let mut columns_to_add = Vec::new();
         let mut position = existing_columns.len() as i32;
 
-        for (name, col_type) in salesforce_column_defs {
-            if !existing_column_names.contains(name) {
+        for (name, field, col_type) in salesforce_column_defs {
+            if !existing_column_names.contains(name) && !existing_column_fields.contains(field) {
                 let column_type = match col_type {
                     "text" => common::ColumnType::Text,
                     "number" => common::ColumnType::Number,
+                    "percent" => common::ColumnType::Percent,
                     "currency" => common::ColumnType::Currency,
                     "date" => common::ColumnType::Date,
-                    "checkbox" => common::ColumnType::Text,  // Store as text for checkbox values
+                    "checkbox" => common::ColumnType::Checkbox,
                     _ => common::ColumnType::Text,
                 };
 
@@ -237,7 +352,7 @@ impl ContrivanceHandlers {
                     is_required: Some(false),
                     default_value: None,
                     validation_rules: None,
-                    display_options: None,
+                    display_options: Some(serde_json::json!({ "salesforce_field": field })),
                 });
 
                 position += 1;
@@ -249,8 +364,9 @@ impl ContrivanceHandlers {
             tracing::info!("Adding {} Salesforce columns to spreadsheet {}", 
                 columns_to_add.len(), spreadsheet_id);
             
+            let mut publication = self.events.begin().await?;
             let new_cols = self.repository
-                .add_columns(spreadsheet_id, columns_to_add)
+                .add_columns(publication.tx(), spreadsheet_id, columns_to_add)
                 .await?;
 
             // Notify all connected clients about each new column
@@ -261,10 +377,9 @@ impl ContrivanceHandlers {
                     created_by: user.id,
                 };
 
-                self.connection_manager
-                    .broadcast_to_spreadsheet(spreadsheet_id, message)
-                    .await;
+                publication.record(spreadsheet_id, user.id, message).await?;
             }
+            publication.commit().await?;
 
             new_cols
         } else {
@@ -278,12 +393,80 @@ impl ContrivanceHandlers {
         }))))
     }
 
+    /// Update a column
+    pub async fn update_column(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+        payload: web::Json<UpdateColumnRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, column_id) = path.into_inner();
+
+        // Check edit permissions
+        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        payload.validate()?;
+
+        let mut publication = self.events.begin().await?;
+        let column = self.repository
+            .update_column(publication.tx(), spreadsheet_id, column_id, &payload)
+            .await?;
+
+        let message = WebSocketMessage::ColumnUpdated {
+            spreadsheet_id,
+            column: column.clone(),
+            updated_by: user.id,
+        };
+
+        publication.record(spreadsheet_id, user.id, message).await?;
+        publication.commit().await?;
+
+        // `display_options.indexed` may have changed
+        self.indexes.refresh(spreadsheet_id);
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(column)))
+    }
+
+    /// Delete a column and its cells
+    pub async fn delete_column(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, column_id) = path.into_inner();
+
+        // Check edit permissions
+        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        let mut publication = self.events.begin().await?;
+        self.repository.delete_column(publication.tx(), spreadsheet_id, column_id).await?;
+
+        let message = WebSocketMessage::ColumnDeleted {
+            spreadsheet_id,
+            column_id,
+            deleted_by: user.id,
+        };
+
+        publication.record(spreadsheet_id, user.id, message).await?;
+        publication.commit().await?;
+        self.indexes.refresh(spreadsheet_id);
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
     /// Get spreadsheet rows
     pub async fn get_rows(
         &self,
         req: HttpRequest,
         path: web::Path<Uuid>,
         query: web::Query<PaginationParams>,
+        keys: web::Query<KeysQuery>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let spreadsheet_id = path.into_inner();
@@ -293,9 +476,19 @@ impl ContrivanceHandlers {
             return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
         }
 
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        let filters = web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+            .map_err(|e| ContrivanceError::bad_request(format!("Invalid query string: {}", e)))?;
+        let filters = indexing::resolve_filters(&columns, &RowFilter::from_query(&filters)?)?;
+
         let rows = self.repository
-            .get_spreadsheet_rows(spreadsheet_id, Some(&query))
-            .await?;
+            .list_spreadsheet_rows(spreadsheet_id, &query, &filters)
+            .await?
+            .map(|row| row.keyed(&columns, keys.keys));
+
+        if !filters.is_empty() {
+            self.indexes.record_filters(spreadsheet_id, &filters).await;
+        }
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(rows)))
     }
@@ -305,31 +498,14 @@ impl ContrivanceHandlers {
         &self,
         req: HttpRequest,
         path: web::Path<Uuid>,
+        keys: web::Query<KeysQuery>,
         payload: web::Json<CreateRowRequest>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
-        let spreadsheet_id = path.into_inner();
-
-        // Check edit permissions
-        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
-            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
-        }
-
-        let row = self.repository
-            .create_row(spreadsheet_id, &payload, user.id)
+        let (row, _) = self
+            .apply_create_row(user.id, path.into_inner(), &payload, keys.keys)
             .await?;
 
-        // Notify collaborators of the new row
-        let message = WebSocketMessage::RowCreated {
-            spreadsheet_id,
-            row: row.clone(),
-            created_by: user.id,
-        };
-
-        self.connection_manager
-            .broadcast_to_spreadsheet(spreadsheet_id, message)
-            .await;
-
         Ok(HttpResponse::Created().json(ApiResponse::success(row)))
     }
 
@@ -338,69 +514,356 @@ impl ContrivanceHandlers {
         &self,
         req: HttpRequest,
         path: web::Path<(Uuid, Uuid)>,
+        keys: web::Query<KeysQuery>,
         payload: web::Json<UpdateRowRequest>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let (spreadsheet_id, row_id) = path.into_inner();
+        let (row, _) = self
+            .apply_update_row(user.id, spreadsheet_id, row_id, &payload, keys.keys)
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(row)))
+    }
+
+    /// Delete a row
+    pub async fn delete_row(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (spreadsheet_id, row_id) = path.into_inner();
+        self.apply_delete_row(user.id, spreadsheet_id, row_id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
 
+    /// Create a row for a user, publish it and run automations. Shared by REST
+    /// and WebSocket ops; returns the row keyed as asked and its event's sequence number.
+    pub async fn apply_create_row(
+        &self,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        request: &CreateRowRequest,
+        keys: RowKeys,
+    ) -> ContrivanceResult<(SpreadsheetRow, Option<i64>)> {
         // Check edit permissions
-        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
             return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
         }
 
+        let mut publication = self.events.begin().await?;
         let row = self.repository
-            .update_row(row_id, &payload, user.id)
+            .create_row(publication.tx(), spreadsheet_id, request, user_id)
             .await?;
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
 
-        // Notify collaborators of the row update
-        let message = WebSocketMessage::RowUpdated {
+        // Notify collaborators of the new row. Events keep name-keyed cells
+        // until subscribers have moved to column ids.
+        let message = WebSocketMessage::RowCreated {
             spreadsheet_id,
-            row: row.clone(),
-            updated_by: user.id,
+            row: row.clone().keyed(&columns, RowKeys::Name),
+            created_by: user_id,
         };
 
-        self.connection_manager
-            .broadcast_to_spreadsheet(spreadsheet_id, message)
-            .await;
+        let seq = publication.record(spreadsheet_id, user_id, message).await?;
+        publication.commit().await?;
+        self.run_automations(None, row.clone(), user_id);
 
-        Ok(HttpResponse::Ok().json(ApiResponse::success(row)))
+        Ok((row.keyed(&columns, keys), Some(seq)))
     }
 
-    /// Delete a row
-    pub async fn delete_row(
+    /// Update a row's cells for a user, publish it and run automations
+    pub async fn apply_update_row(
         &self,
-        req: HttpRequest,
-        path: web::Path<(Uuid, Uuid)>,
-    ) -> Result<HttpResponse, ContrivanceError> {
-        let user = get_user_from_request(&req)?;
-        let (spreadsheet_id, row_id) = path.into_inner();
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        request: &UpdateRowRequest,
+        keys: RowKeys,
+    ) -> ContrivanceResult<(SpreadsheetRow, Option<i64>)> {
+        // Check edit permissions
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+
+        let previous = self.spreadsheet_row(spreadsheet_id, row_id).await?;
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        let mut publication = self.events.begin().await?;
+        let row = self.repository
+            .update_row(publication.tx(), row_id, request, user_id)
+            .await?;
+        self.sync_cell_text(&mut publication, user_id, &previous, &row).await;
+        let seq = record_row_update(&mut publication, user_id, &row, &columns).await?;
+        publication.commit().await?;
+        self.run_automations(Some(previous), row.clone(), user_id);
 
+        Ok((row.keyed(&columns, keys), Some(seq)))
+    }
+
+    /// Delete a row for a user and publish it
+    pub async fn apply_delete_row(&self, user_id: Uuid, spreadsheet_id: Uuid, row_id: Uuid) -> ContrivanceResult<Option<i64>> {
         // Check edit permissions
-        if !self.repository.can_user_edit_spreadsheet(user.id, spreadsheet_id).await? {
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
             return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
         }
 
-        self.repository.delete_row(row_id).await?;
+        self.spreadsheet_row(spreadsheet_id, row_id).await?;
+        let mut publication = self.events.begin().await?;
+        self.repository.delete_row(publication.tx(), row_id).await?;
 
         // Notify collaborators of the row deletion
         let message = WebSocketMessage::RowDeleted {
             spreadsheet_id,
             row_id,
-            deleted_by: user.id,
+            deleted_by: user_id,
         };
 
-        self.connection_manager
-            .broadcast_to_spreadsheet(spreadsheet_id, message)
-            .await;
+        let seq = publication.record(spreadsheet_id, user_id, message).await?;
+        publication.commit().await?;
+        Ok(Some(seq))
+    }
 
-        Ok(HttpResponse::NoContent().finish())
+    /// Lock a cell for a user's socket, or renew their lock, and tell the
+    /// spreadsheet's viewers. If someone else holds it their lock is returned
+    /// and nothing is announced.
+    pub async fn lock_cell(
+        &self,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        connection_id: Uuid,
+    ) -> ContrivanceResult<CellLock> {
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+        self.spreadsheet_row(spreadsheet_id, row_id).await?;
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        if !columns.iter().any(|column| column.id == column_id) {
+            return Err(ContrivanceError::not_found("Column not found"));
+        }
+
+        let expires_at = Utc::now() + chrono::Duration::seconds(CELL_LOCK_TTL_SECONDS);
+        let lock = self.repository
+            .lock_cell(spreadsheet_id, row_id, column_id, user_id, connection_id, expires_at)
+            .await?;
+        if lock.user_id == user_id {
+            let message = WebSocketMessage::CellLocked { spreadsheet_id, lock: lock.clone() };
+            self.events.broadcast(spreadsheet_id, &message).await;
+        }
+        Ok(lock)
     }
 
-    /// Get collaborators for a spreadsheet
-    pub async fn get_collaborators(
+    /// Release a user's lock on a cell, telling the spreadsheet's viewers
+    pub async fn unlock_cell(&self, user_id: Uuid, spreadsheet_id: Uuid, row_id: Uuid, column_id: Uuid) -> ContrivanceResult<()> {
+        if self.repository.unlock_cell(row_id, column_id, user_id).await? {
+            let message = WebSocketMessage::CellUnlocked { spreadsheet_id, row_id, column_id, user_id };
+            self.events.broadcast(spreadsheet_id, &message).await;
+        }
+        Ok(())
+    }
+
+    /// Release the locks a closed socket held
+    pub async fn release_cell_locks(&self, user_id: Uuid, connection_id: Uuid) -> ContrivanceResult<()> {
+        for (spreadsheet_id, row_id, column_id) in self.repository.release_cell_locks(connection_id).await? {
+            let message = WebSocketMessage::CellUnlocked { spreadsheet_id, row_id, column_id, user_id };
+            self.events.broadcast(spreadsheet_id, &message).await;
+        }
+        Ok(())
+    }
+
+    /// Cells currently locked on a spreadsheet
+    pub async fn cell_locks(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<CellLock>> {
+        self.repository.get_cell_locks(spreadsheet_id, None).await
+    }
+
+    /// Open a text cell for collaborative editing, starting its document from
+    /// the cell's text the first time. Returns the `TextSnapshot` to send.
+    pub async fn open_cell_text(
+        &self,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+    ) -> ContrivanceResult<WebSocketMessage> {
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+        self.text_column(spreadsheet_id, column_id).await?;
+        self.spreadsheet_row(spreadsheet_id, row_id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let (_, _, document) = self
+            .edit_cell_text(&mut publication, user_id, spreadsheet_id, row_id, column_id, TextEdit::Open)
+            .await?;
+        publication.commit().await?;
+        let (state, text) = document.ok_or_else(|| ContrivanceError::internal("Text document was not started"))?;
+        Ok(WebSocketMessage::TextSnapshot {
+            spreadsheet_id,
+            row_id,
+            column_id,
+            state: text_documents::encode(&state),
+            text,
+        })
+    }
+
+    /// Merge a client's changes into a text cell's document and write the
+    /// merged text to the cell, publishing it like any other row update.
+    /// Cell locks don't apply, since nothing is overwritten.
+    pub async fn apply_text_edit(
+        &self,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        changes: &str,
+        keys: RowKeys,
+    ) -> ContrivanceResult<(SpreadsheetRow, Option<i64>)> {
+        if !self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"));
+        }
+        let changes = text_documents::decode(changes)?;
+        let columns = self.text_column(spreadsheet_id, column_id).await?;
+        self.spreadsheet_row(spreadsheet_id, row_id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let (previous, row, _) = self
+            .edit_cell_text(&mut publication, user_id, spreadsheet_id, row_id, column_id, TextEdit::Merge(&changes))
+            .await?;
+        if previous.row_data == row.row_data {
+            publication.commit().await?;
+            return Ok((row.keyed(&columns, keys), None));
+        }
+        let seq = record_row_update(&mut publication, user_id, &row, &columns).await?;
+        publication.commit().await?;
+        self.run_automations(Some(previous), row.clone(), user_id);
+
+        Ok((row.keyed(&columns, keys), Some(seq)))
+    }
+
+    /// Fold cells written directly into their documents, for the cells that
+    /// have one, so collaborative editors see the write as an edit
+    async fn sync_cell_text(&self, publication: &mut Publication, user_id: Uuid, previous: &SpreadsheetRow, row: &SpreadsheetRow) {
+        let columns = match self.repository.get_cell_document_columns(row.id).await {
+            Ok(columns) => columns,
+            Err(e) => {
+                tracing::warn!("Failed to look up text documents for row {}: {}", row.id, e);
+                return;
+            }
+        };
+
+        for column_id in columns {
+            let key = column_id.to_string();
+            if previous.row_data.get(&key) == row.row_data.get(&key) {
+                continue;
+            }
+            let edit = self.edit_cell_text(publication, user_id, row.spreadsheet_id, row.id, column_id, TextEdit::Sync);
+            if let Err(e) = edit.await {
+                tracing::warn!("Failed to update the text document for row {} column {}: {}", row.id, column_id, e);
+            }
+        }
+    }
+
+    /// Apply an edit to a text cell's document and save it, after first
+    /// folding in any write to the cell that didn't go through the document.
+    /// Peers are sent the changes once the publication commits. Returns the row before and after and,
+    /// unless a `Sync` found no document, the document's state and text
+    /// afterwards.
+    async fn edit_cell_text(
+        &self,
+        publication: &mut Publication,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        edit: TextEdit<'_>,
+    ) -> ContrivanceResult<(SpreadsheetRow, SpreadsheetRow, Option<(Vec<u8>, String)>)> {
+        let mut outcome = None;
+        let (previous, row) = self.repository
+            .edit_cell_document(publication.tx(), row_id, column_id, user_id, |state, cell| {
+                let mut document = match (state, &edit) {
+                    (Some(state), _) => TextDocument::load(state)?,
+                    (None, TextEdit::Sync) => return Ok(None),
+                    (None, _) => TextDocument::new()?,
+                };
+                document.replace(&cell_text(cell))?;
+                if let TextEdit::Merge(changes) = edit {
+                    document.apply(changes)?;
+                }
+
+                let changes = document.changes();
+                let saved = document.save();
+                let text = document.text()?;
+                let started = state.is_none();
+                let write = (started || !changes.is_empty()).then(|| (saved.clone(), text.clone()));
+                outcome = Some((saved, text, changes, started));
+                Ok(write)
+            })
+            .await?;
+
+        let Some((state, text, changes, started)) = outcome else { return Ok((previous, row, None)) };
+        // A document just started only holds what the cell already did
+        if !started && !changes.is_empty() {
+            let message = WebSocketMessage::TextEdited {
+                spreadsheet_id,
+                row_id,
+                column_id,
+                changes: text_documents::encode(&changes),
+                text: text.clone(),
+                edited_by: user_id,
+            };
+            publication.broadcast(spreadsheet_id, &message);
+        }
+        Ok((previous, row, Some((state, text))))
+    }
+
+    /// A spreadsheet's columns, as long as `column_id` is one of its text
+    /// columns
+    async fn text_column(&self, spreadsheet_id: Uuid, column_id: Uuid) -> ContrivanceResult<Vec<SpreadsheetColumn>> {
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        match columns.iter().find(|column| column.id == column_id) {
+            None => Err(ContrivanceError::not_found("Column not found")),
+            Some(column) if column.column_type != ColumnType::Text => {
+                Err(ContrivanceError::validation("Only text cells can be edited collaboratively"))
+            }
+            Some(_) => Ok(columns),
+        }
+    }
+
+    /// A row, as long as it belongs to the spreadsheet the caller was checked against
+    async fn spreadsheet_row(&self, spreadsheet_id: Uuid, row_id: Uuid) -> ContrivanceResult<SpreadsheetRow> {
+        self.repository
+            .get_row(row_id)
+            .await?
+            .filter(|row| row.spreadsheet_id == spreadsheet_id)
+            .ok_or_else(|| ContrivanceError::not_found("Row not found"))
+    }
+
+    /// Evaluate automation rules and raise mention notifications for a row
+    /// write in the background
+    fn run_automations(&self, previous: Option<SpreadsheetRow>, row: SpreadsheetRow, user_id: Uuid) {
+        let automation = self.automation.clone();
+        let notifier = self.notifier.clone();
+        tokio::spawn(async move {
+            notifier.mentioned_in_row(previous.as_ref(), &row, user_id).await;
+            automation.on_row_written(previous.as_ref(), &row).await;
+        });
+    }
+
+    /// Events a reconnecting socket missed after `since`. Without one nothing
+    /// is replayed and the socket starts from the latest event.
+    pub async fn missed_events(&self, spreadsheet_id: Uuid, since: Option<i64>) -> ContrivanceResult<Replay> {
+        self.events.replay(spreadsheet_id, since.unwrap_or(i64::MAX)).await
+    }
+
+    /// Get change feed events after a sequence number
+    pub async fn get_changes(
         &self,
         req: HttpRequest,
         path: web::Path<Uuid>,
+        query: web::Query<ChangesQuery>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let spreadsheet_id = path.into_inner();
@@ -410,61 +873,413 @@ impl ContrivanceHandlers {
             return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
         }
 
-        let collaborators = self.repository
-            .get_collaborators_with_user_info(spreadsheet_id)
+        let since = query.since.unwrap_or(0).max(0);
+        let limit = query.limit.unwrap_or(500).clamp(1, 1000);
+
+        let latest_seq = self.repository.get_latest_seq(spreadsheet_id).await?;
+        let events = self.repository
+            .get_events_since(spreadsheet_id, since, limit)
             .await?;
+        let has_more = events.last().map(|e| e.seq < latest_seq).unwrap_or(false);
 
-        Ok(HttpResponse::Ok().json(ApiResponse::success(collaborators)))
+        Ok(HttpResponse::Ok().json(ApiResponse::success(ChangeFeed {
+            spreadsheet_id,
+            since,
+            latest_seq,
+            has_more,
+            events,
+        })))
     }
-}
 
-// Function wrappers for actix-web handlers
-pub async fn create_spreadsheet(
-    req: HttpRequest,
-    payload: web::Json<CreateSpreadsheetRequest>,
-    data: web::Data<ContrivanceHandlers>,
-) -> Result<HttpResponse, ContrivanceError> {
-    tracing::info!("Received create_spreadsheet request wrapper");
-    data.create_spreadsheet(req, payload).await
-}
+    /// Export spreadsheet rows as CSV
+    ///
+    /// Each Currency column is followed by its amount in the reporting currency and
+    /// the date of the exchange rate that was used.
+    pub async fn export_spreadsheet(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        query: web::Query<ExportQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
 
-pub async fn get_spreadsheet(
-    req: HttpRequest,
-    path: web::Path<Uuid>,
-    data: web::Data<ContrivanceHandlers>,
-) -> Result<HttpResponse, ContrivanceError> {
-    data.get_spreadsheet(req, path).await
-}
+        // Check access permissions
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
 
-pub async fn list_spreadsheets(
-    req: HttpRequest,
-    query: web::Query<PaginationParams>,
-    data: web::Data<ContrivanceHandlers>,
-) -> Result<HttpResponse, ContrivanceError> {
-    data.list_spreadsheets(req, query).await
-}
+        let format = query.format.as_deref().unwrap_or("csv");
+        if format != "csv" {
+            return Err(ContrivanceError::bad_request(format!("Unsupported export format: {}", format)));
+        }
 
-pub async fn update_spreadsheet(
-    req: HttpRequest,
-    path: web::Path<Uuid>,
-    payload: web::Json<UpdateSpreadsheetRequest>,
-    data: web::Data<ContrivanceHandlers>,
-) -> Result<HttpResponse, ContrivanceError> {
-    data.update_spreadsheet(req, path, payload).await
-}
+        let spreadsheet = self.repository
+            .get_spreadsheet(spreadsheet_id)
+            .await?
+            .ok_or(ContrivanceError::not_found("Spreadsheet not found"))?;
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        let rows = self.repository.get_spreadsheet_rows(spreadsheet_id).await?;
+
+        // Resolve Person cells to user names
+        let mut person_ids: Vec<Uuid> = Vec::new();
+        for column in columns.iter().filter(|c| c.column_type == common::ColumnType::Person) {
+            for row in &rows {
+                if let Some(ids) = column.cell(&row.row_data).and_then(common::person_ids) {
+                    person_ids.extend(ids);
+                }
+            }
+        }
+        person_ids.sort();
+        person_ids.dedup();
+        let people = self.repository.get_user_names(&person_ids).await?;
+
+        let as_of = query.as_of.unwrap_or_else(|| Utc::now().date_naive());
+        let rates = CurrencyConverter::new(&self.repository.get_exchange_rates(None, None, Some(as_of)).await?);
+        let mut converter = CellConverter::new(&rates, spreadsheet.reporting_currency(), as_of);
+
+        let mut csv = String::new();
+        let mut header = Vec::new();
+        for column in &columns {
+            header.push(csv_field(&column.name));
+            if column.column_type == common::ColumnType::Currency {
+                header.push(csv_field(&format!("{} ({})", column.name, converter.reporting_currency())));
+                header.push(csv_field(&format!("{} rate date", column.name)));
+            }
+        }
+        csv.push_str(&header.join(","));
+        csv.push_str("\r\n");
+
+        for row in &rows {
+            let mut fields = Vec::new();
+            for column in &columns {
+                let value = column.cell(&row.row_data).unwrap_or(&serde_json::Value::Null);
+                fields.push(csv_field(&column.column_type.export_value(value, &people)));
+
+                if column.column_type == common::ColumnType::Currency {
+                    match converter.convert_cell(column, row) {
+                        Some((_, Some(conversion))) => {
+                            fields.push(format!("{:.2}", conversion.amount));
+                            fields.push(conversion.rate_date.map(|d| d.to_string()).unwrap_or_default());
+                        }
+                        _ => fields.extend([String::new(), String::new()]),
+                    }
+                }
+            }
+            csv.push_str(&fields.join(","));
+            csv.push_str("\r\n");
+        }
 
-pub async fn delete_spreadsheet(
-    req: HttpRequest,
-    path: web::Path<Uuid>,
-    data: web::Data<ContrivanceHandlers>,
-) -> Result<HttpResponse, ContrivanceError> {
-    data.delete_spreadsheet(req, path).await
-}
+        let filename = common::FileUtils::safe_filename(&spreadsheet.name);
+        Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header((
+                "Content-Disposition",
+                format!("attachment; filename=\"{}.csv\"", filename),
+            ))
+            .body(csv))
+    }
 
-pub async fn get_columns(
-    req: HttpRequest,
-    path: web::Path<Uuid>,
-    data: web::Data<ContrivanceHandlers>,
+    /// Get Currency column totals and forecast in the reporting currency
+    pub async fn get_summary(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        query: web::Query<SummaryQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        // Check access permissions
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        let spreadsheet = self.repository
+            .get_spreadsheet(spreadsheet_id)
+            .await?
+            .ok_or(ContrivanceError::not_found("Spreadsheet not found"))?;
+
+        let reporting_currency = match &query.currency {
+            Some(code) => normalize_currency_code(code)
+                .ok_or_else(|| ContrivanceError::validation(format!("Invalid currency code: {}", code)))?,
+            None => spreadsheet.reporting_currency(),
+        };
+
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        let rows = self.repository.get_spreadsheet_rows(spreadsheet_id).await?;
+
+        let as_of = query.as_of.unwrap_or_else(|| Utc::now().date_naive());
+        let rates = CurrencyConverter::new(&self.repository.get_exchange_rates(None, None, Some(as_of)).await?);
+        let mut converter = CellConverter::new(&rates, reporting_currency, as_of);
+
+        let summary = reporting::summarize(&spreadsheet, &columns, &rows, &mut converter);
+        Ok(HttpResponse::Ok().json(ApiResponse::success(summary)))
+    }
+
+    /// List exchange rates
+    pub async fn list_exchange_rates(
+        &self,
+        req: HttpRequest,
+        query: web::Query<ExchangeRateQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        get_user_from_request(&req)?;
+
+        let base = query.base.as_deref().and_then(normalize_currency_code);
+        let quote = query.quote.as_deref().and_then(normalize_currency_code);
+        let rates = self.repository
+            .get_exchange_rates(base.as_deref(), quote.as_deref(), query.as_of)
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(rates)))
+    }
+
+    /// Create or replace exchange rates (admins only)
+    pub async fn upsert_exchange_rates(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<Vec<NewExchangeRate>>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        if user.role != UserRole::Admin {
+            return Err(ContrivanceError::forbidden("Only admins can maintain exchange rates"));
+        }
+
+        let rates: Vec<NewExchangeRate> = payload
+            .into_inner()
+            .into_iter()
+            .map(|mut rate| {
+                rate.base_currency = rate.base_currency.trim().to_ascii_uppercase();
+                rate.quote_currency = rate.quote_currency.trim().to_ascii_uppercase();
+                rate.check().map(|_| rate).map_err(ContrivanceError::validation)
+            })
+            .collect::<Result<_, _>>()?;
+
+        let saved = self.repository.upsert_exchange_rates(&rates).await?;
+        Ok(HttpResponse::Ok().json(ApiResponse::success(saved)))
+    }
+
+    /// Import exchange rates from CSV text (admins only)
+    pub async fn import_exchange_rates(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<ImportExchangeRatesRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        if user.role != UserRole::Admin {
+            return Err(ContrivanceError::forbidden("Only admins can maintain exchange rates"));
+        }
+
+        let rates = parse_rates_csv(&payload.csv).map_err(ContrivanceError::validation)?;
+        let saved = self.repository.upsert_exchange_rates(&rates).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+            "imported": saved.len(),
+        }))))
+    }
+
+    /// Get collaborators for a spreadsheet
+    pub async fn get_collaborators(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        // Check access permissions
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        let collaborators = self.repository
+            .get_collaborators_with_user_info(spreadsheet_id)
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(collaborators)))
+    }
+
+    /// Invite a user to collaborate on a spreadsheet by email
+    pub async fn invite_collaborator(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AddCollaboratorRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+        payload.validate()?;
+
+        if !self.repository.can_user_manage_collaborators(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Only the owner or an admin can invite collaborators"));
+        }
+
+        let spreadsheet = self.repository
+            .get_spreadsheet(spreadsheet_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Spreadsheet not found"))?;
+        let invitee = self.repository
+            .get_user_id_by_email(spreadsheet.organization_id, &payload.email)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("No user with that email"))?;
+        if invitee == spreadsheet.owner_id {
+            return Err(ContrivanceError::validation("The owner is already a collaborator"));
+        }
+
+        let collaborator = self.repository
+            .invite_collaborator(spreadsheet_id, invitee, &payload.permission_level, user.id)
+            .await?;
+
+        if collaborator.accepted_at.is_none() {
+            self.notifier
+                .collaborator_invited(&spreadsheet, invitee, &payload.permission_level, user.id)
+                .await;
+        }
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(collaborator)))
+    }
+
+    /// Accept an invite to a spreadsheet
+    pub async fn accept_invite(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        let collaborator = self.repository
+            .accept_collaborator_invite(spreadsheet_id, user.id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Invite not found"))?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(collaborator)))
+    }
+}
+
+/// Query parameters for the change feed
+#[derive(Debug, Deserialize)]
+pub struct DetailsQuery {
+    /// Stream the spreadsheet as NDJSON, same as `Accept: application/x-ndjson`
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub keys: RowKeys,
+}
+
+/// `?keys=id` returns cells keyed by column id instead of column name
+#[derive(Debug, Deserialize)]
+pub struct KeysQuery {
+    #[serde(default)]
+    pub keys: RowKeys,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    /// Return events with a sequence number greater than this
+    pub since: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// Query parameters for spreadsheet export
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+    /// Convert currency amounts using rates up to this date (defaults to today)
+    pub as_of: Option<NaiveDate>,
+}
+
+/// Query parameters for the spreadsheet summary
+#[derive(Debug, Deserialize)]
+pub struct SummaryQuery {
+    pub as_of: Option<NaiveDate>,
+    /// Override the spreadsheet's reporting currency
+    pub currency: Option<String>,
+}
+
+/// Query parameters for listing exchange rates
+#[derive(Debug, Deserialize)]
+pub struct ExchangeRateQuery {
+    pub base: Option<String>,
+    pub quote: Option<String>,
+    pub as_of: Option<NaiveDate>,
+}
+
+/// Exchange rates as CSV: `rate_date,base_currency,quote_currency,rate[,source]`
+#[derive(Debug, Deserialize)]
+pub struct ImportExchangeRatesRequest {
+    pub csv: String,
+}
+
+/// Check spreadsheet settings that the service interprets
+fn validate_settings(settings: Option<&serde_json::Value>) -> Result<(), ContrivanceError> {
+    if let Some(code) = settings.and_then(|s| s.get("reporting_currency")) {
+        let valid = code.as_str().map(common::currency::is_currency_code).unwrap_or(false);
+        if !valid {
+            return Err(ContrivanceError::validation(
+                "settings.reporting_currency must be an ISO 4217 code such as USD",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Quote a CSV field when it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Function wrappers for actix-web handlers
+pub async fn create_spreadsheet(
+    req: HttpRequest,
+    payload: web::Json<CreateSpreadsheetRequest>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    tracing::info!("Received create_spreadsheet request wrapper");
+    data.create_spreadsheet(req, payload).await
+}
+
+pub async fn get_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<DetailsQuery>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_spreadsheet(req, path, query).await
+}
+
+pub async fn list_spreadsheets(
+    req: HttpRequest,
+    query: web::Query<PaginationParams>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_spreadsheets(req, query).await
+}
+
+pub async fn update_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateSpreadsheetRequest>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_spreadsheet(req, path, payload).await
+}
+
+pub async fn delete_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_spreadsheet(req, path).await
+}
+
+pub async fn get_columns(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<ContrivanceHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
     data.get_columns(req, path).await
 }
@@ -477,31 +1292,51 @@ pub async fn sync_salesforce_columns(
     data.sync_salesforce_columns(req, path).await
 }
 
+pub async fn update_column(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    payload: web::Json<UpdateColumnRequest>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_column(req, path, payload).await
+}
+
+pub async fn delete_column(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_column(req, path).await
+}
+
 pub async fn get_rows(
     req: HttpRequest,
     path: web::Path<Uuid>,
     query: web::Query<PaginationParams>,
+    keys: web::Query<KeysQuery>,
     data: web::Data<ContrivanceHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.get_rows(req, path, query).await
+    data.get_rows(req, path, query, keys).await
 }
 
 pub async fn create_row(
     req: HttpRequest,
     path: web::Path<Uuid>,
+    keys: web::Query<KeysQuery>,
     payload: web::Json<CreateRowRequest>,
     data: web::Data<ContrivanceHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.create_row(req, path, payload).await
+    data.create_row(req, path, keys, payload).await
 }
 
 pub async fn update_row(
     req: HttpRequest,
     path: web::Path<(Uuid, Uuid)>,
+    keys: web::Query<KeysQuery>,
     payload: web::Json<UpdateRowRequest>,
     data: web::Data<ContrivanceHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.update_row(req, path, payload).await
+    data.update_row(req, path, keys, payload).await
 }
 
 pub async fn delete_row(
@@ -512,6 +1347,57 @@ pub async fn delete_row(
     data.delete_row(req, path).await
 }
 
+pub async fn export_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<ExportQuery>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.export_spreadsheet(req, path, query).await
+}
+
+pub async fn get_changes(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<ChangesQuery>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_changes(req, path, query).await
+}
+
+pub async fn get_summary(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<SummaryQuery>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_summary(req, path, query).await
+}
+
+pub async fn list_exchange_rates(
+    req: HttpRequest,
+    query: web::Query<ExchangeRateQuery>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_exchange_rates(req, query).await
+}
+
+pub async fn upsert_exchange_rates(
+    req: HttpRequest,
+    payload: web::Json<Vec<NewExchangeRate>>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.upsert_exchange_rates(req, payload).await
+}
+
+pub async fn import_exchange_rates(
+    req: HttpRequest,
+    payload: web::Json<ImportExchangeRatesRequest>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.import_exchange_rates(req, payload).await
+}
+
 pub async fn get_collaborators(
     req: HttpRequest,
     path: web::Path<Uuid>,
@@ -520,6 +1406,23 @@ pub async fn get_collaborators(
     data.get_collaborators(req, path).await
 }
 
+pub async fn invite_collaborator(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AddCollaboratorRequest>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.invite_collaborator(req, path, payload).await
+}
+
+pub async fn accept_collaborator_invite(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.accept_invite(req, path).await
+}
+
 pub async fn get_spreadsheets(
     req: HttpRequest,
     query: web::Query<PaginationParams>,
@@ -539,25 +1442,29 @@ pub async fn create_todo(
 
 pub async fn get_todos(
     req: HttpRequest,
+    query: web::Query<PaginationParams>,
     data: web::Data<crate::todo_handlers::TodoHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.get_todos(req).await
+    data.get_todos(req, query).await
 }
 
 pub async fn get_todos_by_spreadsheet(
     req: HttpRequest,
     path: web::Path<Uuid>,
+    query: web::Query<PaginationParams>,
     data: web::Data<crate::todo_handlers::TodoHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.get_todos_by_spreadsheet(req, path).await
+    data.get_todos_by_spreadsheet(req, path, query).await
 }
 
 pub async fn get_todos_by_row(
     req: HttpRequest,
     path: web::Path<(Uuid, Uuid)>,
+    query: web::Query<PaginationParams>,
+    tree: web::Query<crate::todo_handlers::TreeQuery>,
     data: web::Data<crate::todo_handlers::TodoHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.get_todos_by_row(req, path).await
+    data.get_todos_by_row(req, path, query, tree).await
 }
 
 pub async fn get_todo_stats(
@@ -609,9 +1516,548 @@ pub async fn uncomplete_todo(
     data.uncomplete_todo(req, path).await
 }
 
-pub async fn get_users_for_assignment(
+pub async fn add_todo_dependency(
     req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AddTodoDependencyRequest>,
     data: web::Data<crate::todo_handlers::TodoHandlers>,
 ) -> Result<HttpResponse, ContrivanceError> {
-    data.get_users_for_assignment(req).await
-}
\ No newline at end of file
+    data.add_dependency(req, path, payload).await
+}
+
+pub async fn remove_todo_dependency(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::todo_handlers::TodoHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.remove_dependency(req, path).await
+}
+
+pub async fn add_todo_checklist_item(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<CreateChecklistItemRequest>,
+    data: web::Data<crate::todo_handlers::TodoHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.add_checklist_item(req, path, payload).await
+}
+
+pub async fn update_todo_checklist_item(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    payload: web::Json<UpdateChecklistItemRequest>,
+    data: web::Data<crate::todo_handlers::TodoHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_checklist_item(req, path, payload).await
+}
+
+pub async fn delete_todo_checklist_item(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::todo_handlers::TodoHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_checklist_item(req, path).await
+}
+
+pub async fn get_users_for_assignment(
+    req: HttpRequest,
+    data: web::Data<crate::todo_handlers::TodoHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_users_for_assignment(req).await
+}
+// Webhook handler functions
+pub async fn create_webhook(
+    req: HttpRequest,
+    payload: web::Json<CreateWebhookRequest>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_webhook(req, payload).await
+}
+
+pub async fn list_webhooks(
+    req: HttpRequest,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_webhooks(req).await
+}
+
+pub async fn get_webhook(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_webhook(req, path).await
+}
+
+pub async fn update_webhook(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateWebhookRequest>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_webhook(req, path, payload).await
+}
+
+pub async fn delete_webhook(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_webhook(req, path).await
+}
+
+pub async fn list_webhook_deliveries(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<crate::webhook_handlers::DeliveriesQuery>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_deliveries(req, path, query).await
+}
+
+pub async fn replay_webhook_delivery(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::webhook_handlers::WebhookHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.replay_delivery(req, path).await
+}
+
+// Automation rule handler functions
+pub async fn list_automation_rules(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_rules(req, path).await
+}
+
+pub async fn create_automation_rule(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<CreateAutomationRuleRequest>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_rule(req, path, payload).await
+}
+
+pub async fn get_automation_rule(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_rule(req, path).await
+}
+
+pub async fn update_automation_rule(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    payload: web::Json<UpdateAutomationRuleRequest>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_rule(req, path, payload).await
+}
+
+pub async fn delete_automation_rule(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_rule(req, path).await
+}
+
+pub async fn list_automation_runs(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    query: web::Query<crate::automation_handlers::RunsQuery>,
+    data: web::Data<crate::automation_handlers::AutomationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_runs(req, path, query).await
+}
+
+// Notification handler functions
+pub async fn list_notifications(
+    req: HttpRequest,
+    query: web::Query<crate::notification_handlers::NotificationsQuery>,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_notifications(req, query).await
+}
+
+pub async fn mark_notification_read(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.mark_read(req, path).await
+}
+
+pub async fn mark_all_notifications_read(
+    req: HttpRequest,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.mark_all_read(req).await
+}
+
+pub async fn get_notification_preferences(
+    req: HttpRequest,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_preferences(req).await
+}
+
+pub async fn update_notification_preferences(
+    req: HttpRequest,
+    payload: web::Json<UpdateNotificationPreferencesRequest>,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_preferences(req, payload).await
+}
+
+pub async fn get_digest_schedule(
+    req: HttpRequest,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_digest_schedule(req).await
+}
+
+pub async fn update_digest_schedule(
+    req: HttpRequest,
+    payload: web::Json<UpdateDigestScheduleRequest>,
+    data: web::Data<crate::notification_handlers::NotificationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_digest_schedule(req, payload).await
+}
+
+pub async fn list_workspaces(
+    req: HttpRequest,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_workspaces(req).await
+}
+
+pub async fn create_workspace(
+    req: HttpRequest,
+    payload: web::Json<CreateWorkspaceRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_workspace(req, payload).await
+}
+
+pub async fn get_workspace(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_workspace(req, path).await
+}
+
+pub async fn update_workspace(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateWorkspaceRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_workspace(req, path, payload).await
+}
+
+pub async fn delete_workspace(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_workspace(req, path).await
+}
+
+pub async fn list_workspace_members(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_members(req, path).await
+}
+
+pub async fn add_workspace_member(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AddWorkspaceMemberRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.add_member(req, path, payload).await
+}
+
+pub async fn remove_workspace_member(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.remove_member(req, path).await
+}
+
+pub async fn get_workspace_contents(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<PaginationParams>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_contents(req, path, query).await
+}
+
+pub async fn create_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<CreateFolderRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_folder(req, path, payload).await
+}
+
+pub async fn get_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<PaginationParams>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_folder(req, path, query).await
+}
+
+pub async fn update_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateFolderRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_folder(req, path, payload).await
+}
+
+pub async fn move_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<MoveFolderRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.move_folder(req, path, payload).await
+}
+
+pub async fn delete_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_folder(req, path).await
+}
+
+pub async fn move_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<MoveSpreadsheetRequest>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.move_spreadsheet(req, path, payload).await
+}
+
+pub async fn get_favorites(
+    req: HttpRequest,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_favorites(req).await
+}
+
+pub async fn favorite_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.favorite_spreadsheet(req, path).await
+}
+
+pub async fn unfavorite_spreadsheet(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.unfavorite_spreadsheet(req, path).await
+}
+
+pub async fn favorite_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.favorite_folder(req, path).await
+}
+
+pub async fn unfavorite_folder(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::workspace_handlers::WorkspaceHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.unfavorite_folder(req, path).await
+}
+
+pub async fn get_organization(
+    req: HttpRequest,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_organization(req).await
+}
+
+pub async fn update_organization(
+    req: HttpRequest,
+    payload: web::Json<UpdateOrganizationRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_organization(req, payload).await
+}
+
+pub async fn list_organization_members(
+    req: HttpRequest,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_members(req).await
+}
+
+pub async fn invite_organization_member(
+    req: HttpRequest,
+    payload: web::Json<InviteOrganizationMemberRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.invite_member(req, payload).await
+}
+
+pub async fn list_organization_invitations(
+    req: HttpRequest,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_invitations(req).await
+}
+
+pub async fn revoke_organization_invitation(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.revoke_invitation(req, path).await
+}
+
+pub async fn list_received_invitations(
+    req: HttpRequest,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_received_invitations(req).await
+}
+
+pub async fn accept_organization_invitation(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AcceptOrganizationInvitationRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.accept_invitation(req, path, payload).await
+}
+
+pub async fn decline_organization_invitation(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.decline_invitation(req, path).await
+}
+
+pub async fn update_organization_member(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateOrganizationMemberRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_member(req, path, payload).await
+}
+
+pub async fn remove_organization_member(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.remove_member(req, path).await
+}
+
+pub async fn list_teams(
+    req: HttpRequest,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_teams(req).await
+}
+
+pub async fn create_team(
+    req: HttpRequest,
+    payload: web::Json<CreateTeamRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_team(req, payload).await
+}
+
+pub async fn get_team(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.get_team(req, path).await
+}
+
+pub async fn update_team(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<UpdateTeamRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.update_team(req, path, payload).await
+}
+
+pub async fn delete_team(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.delete_team(req, path).await
+}
+
+pub async fn list_team_members(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.list_team_members(req, path).await
+}
+
+pub async fn add_team_member(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AddTeamMemberRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.add_team_member(req, path, payload).await
+}
+
+pub async fn remove_team_member(
+    req: HttpRequest,
+    path: web::Path<(Uuid, Uuid)>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.remove_team_member(req, path).await
+}
+
+pub async fn assign_spreadsheet_team(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    payload: web::Json<AssignTeamRequest>,
+    data: web::Data<crate::organization_handlers::OrganizationHandlers>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.assign_spreadsheet_team(req, path, payload).await
+}
+
+pub async fn create_socket_ticket(
+    req: HttpRequest,
+    data: web::Data<crate::socket_auth::SocketAuth>,
+) -> Result<HttpResponse, ContrivanceError> {
+    data.create_ticket(req).await
+}
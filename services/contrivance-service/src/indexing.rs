@@ -0,0 +1,595 @@
+//! Row filters and the per-column expression indexes that serve them.
+//!
+//! Cells live in `spreadsheet_rows.row_data` keyed by column id, so a filter
+//! on one column can only use an index built on that column's expression.
+//! Each indexed column gets a partial index on its typed cell expression,
+//! limited to its spreadsheet, which a background worker builds and drops
+//! with `CONCURRENTLY` so writes never wait on it. A column is indexed when
+//! its `display_options.indexed` is true, or when it is filtered on often in
+//! a spreadsheet large enough to need it; `indexed: false` opts a column out.
+
+use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+use common::{cells::parse_date, ColumnType, ContrivanceError, ContrivanceResult, SpreadsheetColumn};
+use serde_json::Value;
+use sqlx::{postgres::PgArguments, Arguments};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{config::Config, repository::ContrivanceRepository};
+
+/// How often every managed spreadsheet is checked, e.g. for idle indexes
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Text cells are indexed on their leading characters, which keeps long
+/// notes under the btree entry size limit
+const TEXT_PREFIX_CHARS: usize = 200;
+
+/// How a column's cells are compared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    Text,
+    Numeric,
+    Date,
+    Boolean,
+    /// Lists of options or people, matched by containment
+    Contains,
+}
+
+impl CellKind {
+    pub fn of(column_type: &ColumnType) -> Self {
+        match column_type {
+            ColumnType::Text | ColumnType::Select | ColumnType::Email | ColumnType::Url => CellKind::Text,
+            ColumnType::Number | ColumnType::Currency | ColumnType::Percent => CellKind::Numeric,
+            ColumnType::Date => CellKind::Date,
+            ColumnType::Boolean | ColumnType::Checkbox => CellKind::Boolean,
+            ColumnType::MultiSelect | ColumnType::Person => CellKind::Contains,
+        }
+    }
+}
+
+/// The typed SQL expression for a column's cell. Column ids are uuids, so
+/// they can be inlined, which an index expression needs.
+pub fn cell_expression(column_id: Uuid, kind: CellKind) -> String {
+    match kind {
+        CellKind::Text => format!("left(row_data->>'{}', {})", column_id, TEXT_PREFIX_CHARS),
+        CellKind::Numeric => format!("cell_numeric(row_data->'{}')", column_id),
+        CellKind::Date => format!("cell_date(row_data->'{}')", column_id),
+        CellKind::Boolean => format!("cell_bool(row_data->'{}')", column_id),
+        CellKind::Contains => format!("(row_data->'{}')", column_id),
+    }
+}
+
+/// `display_options.indexed`, if the column sets it
+pub fn declared_indexed(column: &SpreadsheetColumn) -> Option<bool> {
+    column.display_options.as_ref()?.get("indexed")?.as_bool()
+}
+
+/// Why a column is indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexReason {
+    Declared,
+    Usage,
+}
+
+impl IndexReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexReason::Declared => "declared",
+            IndexReason::Usage => "usage",
+        }
+    }
+}
+
+/// A managed cell index, either as recorded or as a column needs it
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct CellIndex {
+    pub column_id: Uuid,
+    pub spreadsheet_id: Uuid,
+    pub index_name: String,
+    /// Everything after `CREATE INDEX <name>`
+    pub definition: String,
+    pub reason: String,
+}
+
+impl CellIndex {
+    pub fn for_column(column: &SpreadsheetColumn, reason: IndexReason) -> Self {
+        let kind = CellKind::of(&column.column_type);
+        let (method, opclass) = match kind {
+            CellKind::Contains => ("gin", " jsonb_path_ops"),
+            _ => ("btree", ""),
+        };
+
+        Self {
+            column_id: column.id,
+            spreadsheet_id: column.spreadsheet_id,
+            index_name: format!("idx_cell_{}", column.id.simple()),
+            definition: format!(
+                "ON spreadsheet_rows USING {} (({}){}) WHERE spreadsheet_id = '{}'",
+                method,
+                cell_expression(column.id, kind),
+                opclass,
+                column.spreadsheet_id,
+            ),
+            reason: reason.as_str().to_string(),
+        }
+    }
+}
+
+/// The indexes a spreadsheet should have. Usage indexes are only started on
+/// large spreadsheets, but an existing one is kept while the column stays hot.
+pub fn wanted_indexes(
+    columns: &[SpreadsheetColumn],
+    existing: &[CellIndex],
+    hot: &HashSet<Uuid>,
+    large: bool,
+) -> Vec<CellIndex> {
+    columns
+        .iter()
+        .filter_map(|column| {
+            let reason = match declared_indexed(column) {
+                Some(true) => IndexReason::Declared,
+                Some(false) => return None,
+                None if hot.contains(&column.id)
+                    && (large || existing.iter().any(|index| index.column_id == column.id)) =>
+                {
+                    IndexReason::Usage
+                }
+                None => return None,
+            };
+            Some(CellIndex::for_column(column, reason))
+        })
+        .collect()
+}
+
+/// A filter comparison
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+}
+
+impl FilterOp {
+    fn parse(op: &str) -> Option<Self> {
+        match op {
+            "eq" => Some(FilterOp::Eq),
+            "ne" => Some(FilterOp::Ne),
+            "lt" => Some(FilterOp::Lt),
+            "lte" => Some(FilterOp::Lte),
+            "gt" => Some(FilterOp::Gt),
+            "gte" => Some(FilterOp::Gte),
+            _ => None,
+        }
+    }
+
+    fn sql(&self) -> &'static str {
+        match self {
+            FilterOp::Eq => "=",
+            // Rows without the cell count as different
+            FilterOp::Ne => "IS DISTINCT FROM",
+            FilterOp::Lt => "<",
+            FilterOp::Lte => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Gte => ">=",
+        }
+    }
+
+    fn is_range(&self) -> bool {
+        !matches!(self, FilterOp::Eq | FilterOp::Ne)
+    }
+}
+
+/// One `filter[<column>]=<value>` or `filter[<column>][<op>]=<value>` query
+/// parameter. The column is an id or a name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowFilter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+impl RowFilter {
+    /// Pick the filters out of a request's query parameters
+    pub fn from_query(pairs: &[(String, String)]) -> ContrivanceResult<Vec<Self>> {
+        pairs
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix("filter[")?, value)))
+            .map(|(rest, value)| {
+                let invalid = || ContrivanceError::bad_request(format!("Invalid filter parameter 'filter[{}'", rest));
+                let (column, op) = match rest.rsplit_once("][") {
+                    Some((column, op)) => {
+                        let op = op.strip_suffix(']').and_then(FilterOp::parse).ok_or_else(invalid)?;
+                        (column, op)
+                    }
+                    None => (rest.strip_suffix(']').ok_or_else(invalid)?, FilterOp::Eq),
+                };
+                if column.is_empty() {
+                    return Err(invalid());
+                }
+                Ok(RowFilter { column: column.to_string(), op, value: value.clone() })
+            })
+            .collect()
+    }
+}
+
+/// A filter value, typed for its column
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Text(String),
+    /// A plain decimal string, cast to `numeric` in SQL
+    Numeric(String),
+    Date(NaiveDate),
+    Bool(bool),
+    Json(Value),
+}
+
+impl FilterValue {
+    pub fn add_to(&self, args: &mut PgArguments) {
+        match self {
+            FilterValue::Text(value) | FilterValue::Numeric(value) => args.add(value.clone()),
+            FilterValue::Date(value) => args.add(*value),
+            FilterValue::Bool(value) => args.add(*value),
+            FilterValue::Json(value) => args.add(value.clone()),
+        }
+    }
+}
+
+/// A filter resolved against a spreadsheet's columns
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellFilter {
+    pub column_id: Uuid,
+    /// SQL condition with `{}` for the bound value
+    condition: String,
+    pub value: FilterValue,
+}
+
+impl CellFilter {
+    /// The condition, with the value bound as parameter `$param`
+    pub fn sql(&self, param: usize) -> String {
+        self.condition.replace("{}", &format!("${}", param))
+    }
+}
+
+/// Resolve filters to their columns and type their values
+pub fn resolve_filters(columns: &[SpreadsheetColumn], filters: &[RowFilter]) -> ContrivanceResult<Vec<CellFilter>> {
+    filters.iter().map(|filter| resolve_filter(columns, filter)).collect()
+}
+
+fn resolve_filter(columns: &[SpreadsheetColumn], filter: &RowFilter) -> ContrivanceResult<CellFilter> {
+    let column = match columns.iter().find(|c| c.key() == filter.column) {
+        Some(column) => column,
+        None => {
+            let mut named = columns.iter().filter(|c| c.name == filter.column);
+            match (named.next(), named.next()) {
+                (Some(column), None) => column,
+                (Some(_), Some(_)) => {
+                    return Err(ContrivanceError::bad_request(format!(
+                        "Column name '{}' is shared by several columns; filter by column id",
+                        filter.column
+                    )))
+                }
+                (None, _) => {
+                    return Err(ContrivanceError::bad_request(format!("Unknown filter column '{}'", filter.column)))
+                }
+            }
+        }
+    };
+
+    let kind = CellKind::of(&column.column_type);
+    let expression = cell_expression(column.id, kind);
+    let op = filter.op.sql();
+    let invalid = |expected: &str| {
+        ContrivanceError::validation(format!("Filter on '{}' expects {}", column.name, expected))
+    };
+
+    if filter.op.is_range() && !matches!(kind, CellKind::Numeric | CellKind::Date) {
+        return Err(ContrivanceError::bad_request(format!(
+            "Column '{}' only supports eq and ne filters",
+            column.name
+        )));
+    }
+
+    let (condition, value) = match kind {
+        CellKind::Text => {
+            let full = format!("row_data->>'{}'", column.id);
+            let condition = match filter.op {
+                // The prefix comparison is what the index can answer
+                FilterOp::Eq => format!("({} = left({{}}, {}) AND {} = {{}})", expression, TEXT_PREFIX_CHARS, full),
+                _ => format!("{} {} {{}}", full, op),
+            };
+            (condition, FilterValue::Text(filter.value.clone()))
+        }
+        CellKind::Numeric => {
+            let number = parse_filter_number(&filter.value).ok_or_else(|| invalid("a number"))?;
+            (format!("{} {} {{}}::numeric", expression, op), FilterValue::Numeric(number))
+        }
+        CellKind::Date => {
+            let date = parse_date(&filter.value).ok_or_else(|| invalid("a date"))?;
+            (format!("{} {} {{}}", expression, op), FilterValue::Date(date))
+        }
+        CellKind::Boolean => {
+            let value = match filter.value.trim().to_lowercase().as_str() {
+                "true" | "yes" | "1" => true,
+                "false" | "no" | "0" => false,
+                _ => return Err(invalid("true or false")),
+            };
+            (format!("{} {} {{}}", expression, op), FilterValue::Bool(value))
+        }
+        CellKind::Contains => {
+            let condition = match filter.op {
+                FilterOp::Eq => format!("{} @> {{}}", expression),
+                _ => format!("NOT COALESCE({} @> {{}}, false)", expression),
+            };
+            (condition, FilterValue::Json(Value::String(filter.value.clone())))
+        }
+    };
+
+    Ok(CellFilter { column_id: column.id, condition, value })
+}
+
+/// A number as people type one into a filter: "1500", "1,500", "$20" or
+/// "45%". Anything else in the value makes it not a number.
+fn parse_filter_number(value: &str) -> Option<String> {
+    let value = value.trim();
+    let value = value.strip_prefix('$').unwrap_or(value);
+    let value = value.strip_suffix('%').unwrap_or(value).trim().replace(',', "");
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Some(value),
+        _ => None,
+    }
+}
+
+/// Counts filtered reads and hands spreadsheets to the index worker
+#[derive(Clone)]
+pub struct IndexManager {
+    repository: ContrivanceRepository,
+    requests: mpsc::UnboundedSender<Uuid>,
+    filter_threshold: i64,
+    idle: ChronoDuration,
+}
+
+impl IndexManager {
+    pub fn new(repository: ContrivanceRepository, config: &Config) -> (Self, IndexWorker) {
+        let (requests, receiver) = mpsc::unbounded_channel();
+        let idle = ChronoDuration::days(config.index_idle_days);
+
+        let manager = Self {
+            repository: repository.clone(),
+            requests,
+            filter_threshold: config.index_filter_threshold,
+            idle,
+        };
+        let worker = IndexWorker {
+            repository,
+            requests: receiver,
+            filter_threshold: config.index_filter_threshold,
+            min_rows: config.index_min_rows,
+            idle,
+        };
+        (manager, worker)
+    }
+
+    /// Queue a spreadsheet's indexes to be brought in line with its columns
+    pub fn refresh(&self, spreadsheet_id: Uuid) {
+        // The worker only stops with the process
+        let _ = self.requests.send(spreadsheet_id);
+    }
+
+    /// Count a filtered read, queueing a refresh when a column becomes hot
+    pub async fn record_filters(&self, spreadsheet_id: Uuid, filters: &[CellFilter]) {
+        let mut column_ids: Vec<Uuid> = filters.iter().map(|filter| filter.column_id).collect();
+        column_ids.sort();
+        column_ids.dedup();
+
+        match self.repository
+            .record_column_filters(spreadsheet_id, &column_ids, Utc::now() - self.idle)
+            .await
+        {
+            Ok(counts) if counts.contains(&self.filter_threshold) => self.refresh(spreadsheet_id),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to record filter usage for spreadsheet {}: {}", spreadsheet_id, e),
+        }
+    }
+}
+
+/// Builds and drops cell indexes one at a time, so builds never pile up
+pub struct IndexWorker {
+    repository: ContrivanceRepository,
+    requests: mpsc::UnboundedReceiver<Uuid>,
+    filter_threshold: i64,
+    min_rows: i64,
+    idle: ChronoDuration,
+}
+
+impl IndexWorker {
+    /// Handle refresh requests, and sweep every managed spreadsheet once at
+    /// startup and then hourly, until the process exits
+    pub async fn run(mut self) {
+        info!("Index worker started");
+        let mut sweep = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            let spreadsheet_ids: HashSet<Uuid> = tokio::select! {
+                Some(spreadsheet_id) = self.requests.recv() => {
+                    let mut ids = HashSet::from([spreadsheet_id]);
+                    while let Ok(spreadsheet_id) = self.requests.try_recv() {
+                        ids.insert(spreadsheet_id);
+                    }
+                    ids
+                }
+                _ = sweep.tick() => match self.repository.get_managed_spreadsheet_ids().await {
+                    Ok(ids) => ids.into_iter().collect(),
+                    Err(e) => {
+                        error!("Failed to list spreadsheets for the index sweep: {}", e);
+                        continue;
+                    }
+                },
+            };
+
+            for spreadsheet_id in spreadsheet_ids {
+                if let Err(e) = self.reconcile(spreadsheet_id).await {
+                    error!("Failed to update indexes for spreadsheet {}: {}", spreadsheet_id, e);
+                }
+            }
+        }
+    }
+
+    /// Create the indexes a spreadsheet should have and drop the rest
+    pub async fn reconcile(&self, spreadsheet_id: Uuid) -> ContrivanceResult<()> {
+        let columns = self.repository.get_spreadsheet_columns(spreadsheet_id).await?;
+        let existing = self.repository.get_cell_indexes(spreadsheet_id).await?;
+        let hot: HashSet<Uuid> = self.repository
+            .get_frequently_filtered_columns(spreadsheet_id, self.filter_threshold, Utc::now() - self.idle)
+            .await?
+            .into_iter()
+            .collect();
+        let large = !hot.is_empty() && self.repository.count_spreadsheet_rows(spreadsheet_id).await? >= self.min_rows;
+
+        let wanted = wanted_indexes(&columns, &existing, &hot, large);
+
+        for index in &existing {
+            let keep = wanted
+                .iter()
+                .any(|w| w.index_name == index.index_name && w.definition == index.definition);
+            if !keep && self.repository.drop_cell_index(index).await? {
+                info!("Dropped index {} on spreadsheet {}", index.index_name, spreadsheet_id);
+            }
+        }
+
+        for index in &wanted {
+            match existing.iter().find(|e| e.column_id == index.column_id) {
+                Some(current) if current.definition == index.definition => {
+                    if current.reason != index.reason {
+                        self.repository.save_cell_index(index).await?;
+                    }
+                }
+                _ => {
+                    if self.repository.create_cell_index(index).await? {
+                        info!("Created {} index {} on spreadsheet {}", index.reason, index.index_name, spreadsheet_id);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, column_type: ColumnType, display_options: Option<Value>) -> SpreadsheetColumn {
+        SpreadsheetColumn {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::nil(),
+            name: name.to_string(),
+            column_type,
+            position: 0,
+            is_required: None,
+            default_value: None,
+            validation_rules: None,
+            display_options,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse_filters() {
+        let filters = RowFilter::from_query(&pairs(&[
+            ("limit", "10"),
+            ("filter[SE Stage]", "POC"),
+            ("filter[Deal Value][gte]", "50000"),
+        ]))
+        .unwrap();
+        assert_eq!(filters, vec![
+            RowFilter { column: "SE Stage".into(), op: FilterOp::Eq, value: "POC".into() },
+            RowFilter { column: "Deal Value".into(), op: FilterOp::Gte, value: "50000".into() },
+        ]);
+
+        assert!(RowFilter::from_query(&pairs(&[("filter[Deal Value][about]", "1")])).is_err());
+        assert!(RowFilter::from_query(&pairs(&[("filter[]", "1")])).is_err());
+        assert!(RowFilter::from_query(&pairs(&[("filter[Stage", "1")])).is_err());
+    }
+
+    #[test]
+    fn test_resolve_filters_types_values() {
+        let stage = column("SE Stage", ColumnType::Select, None);
+        let value = column("Deal Value", ColumnType::Currency, None);
+        let owners = column("Owners", ColumnType::Person, None);
+        let columns = vec![stage.clone(), value.clone(), owners.clone()];
+
+        let filter = |column: &str, op, value: &str| RowFilter { column: column.into(), op, value: value.into() };
+
+        let resolved = resolve_filters(&columns, &[filter("SE Stage", FilterOp::Eq, "POC")]).unwrap();
+        assert_eq!(resolved[0].column_id, stage.id);
+        assert_eq!(resolved[0].value, FilterValue::Text("POC".into()));
+        assert!(resolved[0].sql(6).starts_with(&format!("(left(row_data->>'{}', 200) = left($6, 200)", stage.id)));
+
+        let resolved = resolve_filters(&columns, &[filter(&value.id.to_string(), FilterOp::Gte, "$50,000")]).unwrap();
+        assert_eq!(resolved[0].value, FilterValue::Numeric("50000".into()));
+        assert_eq!(resolved[0].sql(2), format!("cell_numeric(row_data->'{}') >= $2::numeric", value.id));
+
+        let resolved = resolve_filters(&columns, &[filter("Owners", FilterOp::Eq, "u1")]).unwrap();
+        assert_eq!(resolved[0].value, FilterValue::Json(json!("u1")));
+
+        let resolved = resolve_filters(&columns, &[filter("Deal Value", FilterOp::Lt, "1e3")]).unwrap();
+        assert_eq!(resolved[0].value, FilterValue::Numeric("1e3".into()));
+
+        assert!(resolve_filters(&columns, &[filter("Deal Value", FilterOp::Eq, "lots")]).is_err());
+        for garbled in ["abc12", "12abc", "1-2", "NaN", "inf"] {
+            assert!(matches!(
+                resolve_filters(&columns, &[filter("Deal Value", FilterOp::Eq, garbled)]),
+                Err(ContrivanceError::Validation { .. })
+            ));
+        }
+        assert!(resolve_filters(&columns, &[filter("SE Stage", FilterOp::Gt, "POC")]).is_err());
+        assert!(resolve_filters(&columns, &[filter("Nope", FilterOp::Eq, "x")]).is_err());
+    }
+
+    #[test]
+    fn test_wanted_indexes() {
+        let declared = column("SE Stage", ColumnType::Select, Some(json!({"indexed": true})));
+        let opted_out = column("Notes", ColumnType::Text, Some(json!({"indexed": false})));
+        let hot = column("Deal Value", ColumnType::Number, None);
+        let cold = column("Owner", ColumnType::Person, None);
+        let columns = vec![declared.clone(), opted_out.clone(), hot.clone(), cold];
+        let filtered = HashSet::from([opted_out.id, hot.id]);
+
+        let names = |indexes: Vec<CellIndex>| indexes.into_iter().map(|i| (i.column_id, i.reason)).collect::<Vec<_>>();
+
+        // Small spreadsheets only get the declared index
+        assert_eq!(names(wanted_indexes(&columns, &[], &filtered, false)), vec![(declared.id, "declared".to_string())]);
+
+        let large = wanted_indexes(&columns, &[], &filtered, true);
+        assert_eq!(names(large.clone()), vec![
+            (declared.id, "declared".to_string()),
+            (hot.id, "usage".to_string()),
+        ]);
+
+        // An existing usage index survives the spreadsheet shrinking
+        assert_eq!(wanted_indexes(&columns, &large, &filtered, false).len(), 2);
+
+        let index = CellIndex::for_column(&hot, IndexReason::Usage);
+        assert_eq!(index.index_name, format!("idx_cell_{}", hot.id.simple()));
+        assert!(index.index_name.len() <= 63);
+        assert_eq!(
+            index.definition,
+            format!(
+                "ON spreadsheet_rows USING btree ((cell_numeric(row_data->'{}'))) WHERE spreadsheet_id = '{}'",
+                hot.id,
+                Uuid::nil()
+            )
+        );
+    }
+}
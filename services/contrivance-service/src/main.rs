@@ -1,4 +1,6 @@
 mod config;
+mod backpressure;
+mod broadcast;
 mod websocket;
 mod repository;
 mod handlers;
@@ -7,6 +9,23 @@ mod middleware;
 mod discovery_models;
 mod discovery_repository;
 mod discovery_handlers;
+mod reporting;
+mod events;
+mod webhooks;
+mod webhook_handlers;
+mod automation;
+mod automation_handlers;
+mod notifications;
+mod notification_handlers;
+mod email;
+mod streaming;
+mod indexing;
+mod workspace_handlers;
+mod organization_handlers;
+mod socket_auth;
+mod text_documents;
+mod event_stream;
+mod recurrence;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer, middleware::Logger, HttpResponse, HttpRequest};
@@ -20,7 +39,10 @@ use uuid::Uuid;
 
 use repository::ContrivanceRepository;
 use handlers::ContrivanceHandlers;
-use websocket::{ConnectionManager, WebSocketConnection};
+use socket_auth::{SocketAuth, SocketAuthQuery};
+use websocket::{ConnectionManager, Heartbeat, Resume, WebSocketConnection};
+use backpressure::{SocketLimits, SocketMetrics};
+use event_stream::EventStream;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -41,9 +63,18 @@ async fn main() -> std::io::Result<()> {
         .expect("Failed to connect to database");
 
     // Initialize WebSocket connection manager
-    let connection_manager_raw = ConnectionManager::new();
-    let connection_manager = Arc::new(RwLock::new(connection_manager_raw));
-    let connection_manager_data = web::Data::new(ConnectionManager::new()); // Separate instance for handlers
+    let connection_manager = Arc::new(RwLock::new(
+        ConnectionManager::new(Heartbeat {
+            interval: std::time::Duration::from_secs(config.ws_heartbeat_interval_secs),
+            timeout: std::time::Duration::from_secs(config.ws_client_timeout_secs),
+        })
+        .with_limits(SocketLimits {
+            messages_per_second: f64::from(config.ws_max_messages_per_second),
+            burst: config.ws_message_burst,
+            outbox_capacity: config.ws_outbound_queue_size,
+            slow_consumer_timeout: std::time::Duration::from_secs(config.ws_slow_consumer_timeout_secs),
+        }),
+    ));
 
     // Initialize JWT service
     let jwt_service = web::Data::new(JwtService::new(
@@ -54,15 +85,96 @@ async fn main() -> std::io::Result<()> {
 
     // Initialize repository and handlers
     let repository = ContrivanceRepository::new(database.pool().clone());
+
+    // Load exchange rates from file if configured
+    if let Some(path) = &config.exchange_rates_file {
+        load_exchange_rates(&repository, path).await;
+    }
+
+    // Live messages go to this instance's sockets and, through the backbone, to every other instance's
+    let (broadcaster, broadcast_listener) = broadcast::Broadcaster::new(
+        connection_manager.clone(),
+        repository.clone(),
+        config.broadcast_backend,
+    );
+
+    // Email is queued in the outbox and sent by the email sender below
+    let outbox = common::mailer::EmailOutbox::new(database.pool().clone());
+    let mailer = common::mailer::Mailer::new(&config.mailer).expect("Invalid SMTP configuration");
+
+    let notifier = notifications::Notifier::new(
+        repository.clone(),
+        broadcaster.clone(),
+        outbox.clone(),
+        config.mailer.app_url.clone(),
+    );
+
+    let automation_engine = automation::AutomationEngine::new(
+        repository.clone(),
+        events::EventPublisher::new(repository.clone(), broadcaster.clone()),
+        notifier.clone(),
+        jwt_service.get_ref().clone(),
+        &config,
+    );
+
+    // Cell indexes are built one at a time by the index worker below
+    let (indexes, index_worker) = indexing::IndexManager::new(repository.clone(), &config);
+
     let contrivance_handlers = web::Data::new(ContrivanceHandlers::new(
         repository.clone(),
-        connection_manager_data.clone(),
+        broadcaster.clone(),
+        automation_engine.clone(),
+        notifier.clone(),
+        indexes,
     ));
     let todo_handlers = web::Data::new(todo_handlers::TodoHandlers::new(
-        repository,
-        connection_manager_data.clone(),
+        repository.clone(),
+        broadcaster.clone(),
+        notifier.clone(),
+    ));
+    let notification_handlers = web::Data::new(notification_handlers::NotificationHandlers::new(repository.clone()));
+    let webhook_handlers = web::Data::new(webhook_handlers::WebhookHandlers::new(
+        repository.clone(),
+        webhooks::WebhookClient::new(config.webhook_allow_private_targets),
+    ));
+    let automation_handlers = web::Data::new(automation_handlers::AutomationHandlers::new(repository.clone()));
+    let workspace_handlers = web::Data::new(workspace_handlers::WorkspaceHandlers::new(repository.clone()));
+    let organization_handlers = web::Data::new(organization_handlers::OrganizationHandlers::new(repository.clone()));
+    let socket_auth = web::Data::new(SocketAuth::new(
+        repository.clone(),
+        jwt_service.get_ref().clone(),
+        config.jwt_secret.clone(),
+    ));
+    let event_publisher = web::Data::new(events::EventPublisher::new(
+        repository.clone(),
+        broadcaster,
     ));
 
+    // Send queued email and weekly digests in the background
+    tokio::spawn(email::EmailSender::new(outbox.clone(), mailer, &config).run());
+    tokio::spawn(email::DigestScheduler::new(repository.clone(), outbox, config.mailer.app_url.clone()).run());
+
+    // Deliver queued webhooks in the background
+    tokio::spawn(webhooks::WebhookDispatcher::new(repository, &config).run());
+
+    // Check close-date and overdue-todo automation triggers in the background
+    tokio::spawn(automation_engine.run());
+
+    // Raise due-soon and overdue todo notifications in the background
+    tokio::spawn(notifier.run());
+
+    // Create and drop managed cell indexes in the background
+    tokio::spawn(index_worker.run());
+
+    // Fan messages published by other instances out to this one's sockets
+    if let Some(listener) = broadcast_listener {
+        tokio::spawn(listener.run());
+    }
+
+    // Lapse the presence of viewers whose connections stop answering heartbeats,
+    // and drop connections that stopped without leaving
+    tokio::spawn(websocket::ConnectionSweeper::new(connection_manager.clone()).run());
+
     // Initialize discovery repository
     let discovery_repository = web::Data::new(discovery_repository::DiscoveryRepository::new(database.pool().clone()));
 
@@ -80,6 +192,13 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(contrivance_handlers.clone())
             .app_data(todo_handlers.clone())
+            .app_data(webhook_handlers.clone())
+            .app_data(automation_handlers.clone())
+            .app_data(workspace_handlers.clone())
+            .app_data(organization_handlers.clone())
+            .app_data(notification_handlers.clone())
+            .app_data(socket_auth.clone())
+            .app_data(event_publisher.clone())
             .app_data(discovery_repository.clone())
             .app_data(web::Data::new(connection_manager.clone()))
             .app_data(jwt_service.clone())
@@ -100,6 +219,8 @@ async fn main() -> std::io::Result<()> {
                             .route(web::get().to(discovery_handlers::discovery_health_check))
                     )
             )
+            // Authenticates like a socket, since EventSource can't send headers
+            .route("/api/spreadsheets/{id}/events", web::get().to(event_stream_handler))
             .service(
                 web::scope("/api")
                     .wrap(middleware::auth::auth_middleware())
@@ -118,6 +239,11 @@ async fn main() -> std::io::Result<()> {
                         web::resource("/spreadsheets/{id}/columns")
                             .route(web::get().to(handlers::get_columns))
                     )
+                    .service(
+                        web::resource("/spreadsheets/{spreadsheet_id}/columns/{column_id}")
+                            .route(web::put().to(handlers::update_column))
+                            .route(web::delete().to(handlers::delete_column))
+                    )
                     .service(
                         web::resource("/spreadsheets/{id}/salesforce/columns")
                             .route(web::post().to(handlers::sync_salesforce_columns))
@@ -132,9 +258,150 @@ async fn main() -> std::io::Result<()> {
                             .route(web::put().to(handlers::update_row))
                             .route(web::delete().to(handlers::delete_row))
                     )
+                    .service(
+                        web::resource("/spreadsheets/{id}/export")
+                            .route(web::get().to(handlers::export_spreadsheet))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/summary")
+                            .route(web::get().to(handlers::get_summary))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/changes")
+                            .route(web::get().to(handlers::get_changes))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/move")
+                            .route(web::post().to(handlers::move_spreadsheet))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/favorite")
+                            .route(web::put().to(handlers::favorite_spreadsheet))
+                            .route(web::delete().to(handlers::unfavorite_spreadsheet))
+                    )
+                    .service(
+                        web::resource("/workspaces")
+                            .route(web::get().to(handlers::list_workspaces))
+                            .route(web::post().to(handlers::create_workspace))
+                    )
+                    .service(
+                        web::resource("/workspaces/{id}")
+                            .route(web::get().to(handlers::get_workspace))
+                            .route(web::put().to(handlers::update_workspace))
+                            .route(web::delete().to(handlers::delete_workspace))
+                    )
+                    .service(
+                        web::resource("/workspaces/{id}/members")
+                            .route(web::get().to(handlers::list_workspace_members))
+                            .route(web::post().to(handlers::add_workspace_member))
+                    )
+                    .service(
+                        web::resource("/workspaces/{id}/members/{user_id}")
+                            .route(web::delete().to(handlers::remove_workspace_member))
+                    )
+                    .service(
+                        web::resource("/workspaces/{id}/contents")
+                            .route(web::get().to(handlers::get_workspace_contents))
+                    )
+                    .service(
+                        web::resource("/workspaces/{id}/folders")
+                            .route(web::post().to(handlers::create_folder))
+                    )
+                    .service(
+                        web::resource("/folders/{id}")
+                            .route(web::get().to(handlers::get_folder))
+                            .route(web::put().to(handlers::update_folder))
+                            .route(web::delete().to(handlers::delete_folder))
+                    )
+                    .service(
+                        web::resource("/folders/{id}/move")
+                            .route(web::post().to(handlers::move_folder))
+                    )
+                    .service(
+                        web::resource("/folders/{id}/favorite")
+                            .route(web::put().to(handlers::favorite_folder))
+                            .route(web::delete().to(handlers::unfavorite_folder))
+                    )
+                    .service(
+                        web::resource("/favorites")
+                            .route(web::get().to(handlers::get_favorites))
+                    )
+                    .service(
+                        web::resource("/organization")
+                            .route(web::get().to(handlers::get_organization))
+                            .route(web::put().to(handlers::update_organization))
+                    )
+                    .service(
+                        web::resource("/organization/members")
+                            .route(web::get().to(handlers::list_organization_members))
+                    )
+                    .service(
+                        web::resource("/organization/invitations")
+                            .route(web::get().to(handlers::list_organization_invitations))
+                            .route(web::post().to(handlers::invite_organization_member))
+                    )
+                    .service(
+                        web::resource("/organization/invitations/received")
+                            .route(web::get().to(handlers::list_received_invitations))
+                    )
+                    .service(
+                        web::resource("/organization/invitations/{id}")
+                            .route(web::delete().to(handlers::revoke_organization_invitation))
+                    )
+                    .service(
+                        web::resource("/organization/invitations/{id}/accept")
+                            .route(web::post().to(handlers::accept_organization_invitation))
+                    )
+                    .service(
+                        web::resource("/organization/invitations/{id}/decline")
+                            .route(web::post().to(handlers::decline_organization_invitation))
+                    )
+                    .service(
+                        web::resource("/organization/members/{user_id}")
+                            .route(web::put().to(handlers::update_organization_member))
+                            .route(web::delete().to(handlers::remove_organization_member))
+                    )
+                    .service(
+                        web::resource("/teams")
+                            .route(web::get().to(handlers::list_teams))
+                            .route(web::post().to(handlers::create_team))
+                    )
+                    .service(
+                        web::resource("/teams/{id}")
+                            .route(web::get().to(handlers::get_team))
+                            .route(web::put().to(handlers::update_team))
+                            .route(web::delete().to(handlers::delete_team))
+                    )
+                    .service(
+                        web::resource("/teams/{id}/members")
+                            .route(web::get().to(handlers::list_team_members))
+                            .route(web::post().to(handlers::add_team_member))
+                    )
+                    .service(
+                        web::resource("/teams/{id}/members/{user_id}")
+                            .route(web::delete().to(handlers::remove_team_member))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/team")
+                            .route(web::put().to(handlers::assign_spreadsheet_team))
+                    )
+                    .service(
+                        web::resource("/exchange-rates")
+                            .route(web::get().to(handlers::list_exchange_rates))
+                            .route(web::post().to(handlers::upsert_exchange_rates))
+                    )
+                    .service(
+                        web::resource("/exchange-rates/import")
+                            .route(web::post().to(handlers::import_exchange_rates))
+                    )
                     .service(
                         web::resource("/spreadsheets/{id}/collaborators")
                             .route(web::get().to(handlers::get_collaborators))
+                            .route(web::post().to(handlers::invite_collaborator))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/collaborators/accept")
+                            .route(web::post().to(handlers::accept_collaborator_invite))
                     )
                     // Todo routes with owner assignment
                     .service(
@@ -156,6 +423,23 @@ async fn main() -> std::io::Result<()> {
                         web::resource("/todos/{id}/uncomplete")
                             .route(web::put().to(handlers::uncomplete_todo))
                     )
+                    .service(
+                        web::resource("/todos/{id}/dependencies")
+                            .route(web::post().to(handlers::add_todo_dependency))
+                    )
+                    .service(
+                        web::resource("/todos/{id}/dependencies/{blocked_by_id}")
+                            .route(web::delete().to(handlers::remove_todo_dependency))
+                    )
+                    .service(
+                        web::resource("/todos/{id}/checklist")
+                            .route(web::post().to(handlers::add_todo_checklist_item))
+                    )
+                    .service(
+                        web::resource("/todos/{id}/checklist/{item_id}")
+                            .route(web::put().to(handlers::update_todo_checklist_item))
+                            .route(web::delete().to(handlers::delete_todo_checklist_item))
+                    )
                     .service(
                         web::resource("/spreadsheets/{id}/todos")
                             .route(web::get().to(handlers::get_todos_by_spreadsheet))
@@ -172,6 +456,62 @@ async fn main() -> std::io::Result<()> {
                         web::resource("/users/for-assignment")
                             .route(web::get().to(handlers::get_users_for_assignment))
                     )
+                    .service(
+                        web::resource("/spreadsheets/{id}/automations")
+                            .route(web::get().to(handlers::list_automation_rules))
+                            .route(web::post().to(handlers::create_automation_rule))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/automations/{rule_id}")
+                            .route(web::get().to(handlers::get_automation_rule))
+                            .route(web::put().to(handlers::update_automation_rule))
+                            .route(web::delete().to(handlers::delete_automation_rule))
+                    )
+                    .service(
+                        web::resource("/spreadsheets/{id}/automations/{rule_id}/runs")
+                            .route(web::get().to(handlers::list_automation_runs))
+                    )
+                    .service(
+                        web::resource("/notifications")
+                            .route(web::get().to(handlers::list_notifications))
+                    )
+                    .service(
+                        web::resource("/notifications/read-all")
+                            .route(web::post().to(handlers::mark_all_notifications_read))
+                    )
+                    .service(
+                        web::resource("/notifications/preferences")
+                            .route(web::get().to(handlers::get_notification_preferences))
+                            .route(web::put().to(handlers::update_notification_preferences))
+                    )
+                    .service(
+                        web::resource("/notifications/digest")
+                            .route(web::get().to(handlers::get_digest_schedule))
+                            .route(web::put().to(handlers::update_digest_schedule))
+                    )
+                    .service(
+                        web::resource("/notifications/{id}/read")
+                            .route(web::post().to(handlers::mark_notification_read))
+                    )
+                    .service(
+                        web::resource("/webhooks")
+                            .route(web::get().to(handlers::list_webhooks))
+                            .route(web::post().to(handlers::create_webhook))
+                    )
+                    .service(
+                        web::resource("/webhooks/{id}")
+                            .route(web::get().to(handlers::get_webhook))
+                            .route(web::put().to(handlers::update_webhook))
+                            .route(web::delete().to(handlers::delete_webhook))
+                    )
+                    .service(
+                        web::resource("/webhooks/{id}/deliveries")
+                            .route(web::get().to(handlers::list_webhook_deliveries))
+                    )
+                    .service(
+                        web::resource("/webhooks/{id}/deliveries/{delivery_id}/replay")
+                            .route(web::post().to(handlers::replay_webhook_delivery))
+                    )
                     // Discovery routes
                     .service(
                         web::resource("/discovery/sessions")
@@ -209,51 +549,177 @@ async fn main() -> std::io::Result<()> {
                             .route(web::put().to(discovery_handlers::update_discovery_session_status))
                     )
             )
+            .service(
+                web::resource("/ws/ticket")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route(web::post().to(handlers::create_socket_ticket))
+            )
             .route("/ws/spreadsheet/{id}", web::get().to(websocket_handler))
+            .route("/ws/notifications", web::get().to(notifications_websocket_handler))
             .route("/health", web::get().to(health_check))
+            .route("/metrics", web::get().to(metrics))
     })
     .bind(format!("0.0.0.0:{}", config.port))?
     .run()
     .await
 }
 
+/// Spreadsheet channel. The user must be able to see the spreadsheet; View
+/// collaborators get changes and presence but their edits are rejected. A
+/// client reconnecting with `?resume=` and `?last_seq=` gets the events it
+/// missed replayed first.
 async fn websocket_handler(
     req: HttpRequest,
     stream: web::Payload,
     path: web::Path<Uuid>,
+    query: web::Query<SocketAuthQuery>,
+    auth: web::Data<SocketAuth>,
     connection_manager: web::Data<Arc<RwLock<ConnectionManager>>>,
+    handlers: web::Data<ContrivanceHandlers>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let spreadsheet_id = path.into_inner();
-    
-    // Extract user from request (should be set by auth middleware)
-    let user = match crate::middleware::auth::get_user_from_request(&req) {
-        Ok(user) => user,
-        Err(_) => {
-            // Default user for now - in production this should be properly authenticated
-            common::User {
-                id: uuid::Uuid::new_v4(),
-                email: "anonymous@example.com".to_string(),
-                password_hash: "".to_string(),
-                name: "Anonymous".to_string(),
-                role: common::UserRole::User,
-                created_at: Some(chrono::Utc::now()),
-                updated_at: Some(chrono::Utc::now()),
-                is_active: Some(true),
-                last_login: None,
-            }
-        }
-    };
-    
-    // Create WebSocket connection
+    let query = query.into_inner();
+    let session = auth.authenticate(&req, &query).await?;
+    let access = auth
+        .access(session.user_id, spreadsheet_id)
+        .await?
+        .ok_or_else(|| common::ContrivanceError::forbidden("Access denied to this spreadsheet"))?;
+    let user_name = auth.user_name(session.user_id).await?;
+    let resume = query.resume.map(|token| match query.last_seq {
+        Some(last_seq) if auth.verify_resume_token(&token, session.user_id, spreadsheet_id) => Resume::After(last_seq),
+        _ => Resume::Rejected,
+    });
+    let resume_token = auth.resume_token(session.user_id, spreadsheet_id);
+    let (heartbeat, limits, metrics) = socket_settings(&connection_manager).await;
+
     let ws_conn = WebSocketConnection::new(
-        user.id,
+        session,
+        user_name,
         spreadsheet_id,
-        connection_manager.get_ref().clone()
-    );
-    
-    ws::start(ws_conn, &req, stream)
+        access,
+        connection_manager.get_ref().clone(),
+        auth,
+        handlers,
+    )
+    .with_heartbeat(heartbeat)
+    .with_limits(limits, metrics)
+    .with_resume(resume_token, resume);
+
+    start_socket(ws_conn, &req, stream)
+}
+
+/// Per-user notification channel
+async fn notifications_websocket_handler(
+    req: HttpRequest,
+    stream: web::Payload,
+    query: web::Query<SocketAuthQuery>,
+    auth: web::Data<SocketAuth>,
+    connection_manager: web::Data<Arc<RwLock<ConnectionManager>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let session = auth.authenticate(&req, &query).await?;
+    let (heartbeat, limits, metrics) = socket_settings(&connection_manager).await;
+    let ws_conn = WebSocketConnection::for_user(session, connection_manager.get_ref().clone())
+        .with_heartbeat(heartbeat)
+        .with_limits(limits, metrics);
+
+    start_socket(ws_conn, &req, stream)
+}
+
+/// Server-Sent Events fallback for the spreadsheet channel, for networks that
+/// block WebSocket upgrades. Credentials are taken as for a socket. A client
+/// reconnecting with `Last-Event-ID` (or `?last_seq=`) gets the events it
+/// missed replayed first.
+async fn event_stream_handler(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    query: web::Query<SocketAuthQuery>,
+    auth: web::Data<SocketAuth>,
+    connection_manager: web::Data<Arc<RwLock<ConnectionManager>>>,
+    handlers: web::Data<ContrivanceHandlers>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let spreadsheet_id = path.into_inner();
+    let session = auth.authenticate(&req, &query).await?;
+    auth.access(session.user_id, spreadsheet_id)
+        .await?
+        .ok_or_else(|| common::ContrivanceError::forbidden("Access denied to this spreadsheet"))?;
+    let last_seq = match req.headers().get("Last-Event-ID") {
+        Some(value) => Some(
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| common::ContrivanceError::validation("Last-Event-ID must be an event sequence number"))?,
+        ),
+        None => query.last_seq,
+    };
+
+    let stream = EventStream::open(
+        session,
+        spreadsheet_id,
+        last_seq,
+        connection_manager.get_ref().clone(),
+        auth,
+        handlers,
+    )
+    .await;
+    Ok(HttpResponse::Ok()
+        .content_type(event_stream::EVENT_STREAM_CONTENT_TYPE)
+        .insert_header((actix_web::http::header::CACHE_CONTROL, "no-cache"))
+        // Keep nginx and similar proxies from holding events back
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(stream.into_body()))
+}
+
+/// The heartbeat and limits new sockets get, and where they count what the limits do
+async fn socket_settings(connection_manager: &RwLock<ConnectionManager>) -> (Heartbeat, SocketLimits, Arc<SocketMetrics>) {
+    let manager = connection_manager.read().await;
+    (manager.heartbeat(), manager.limits(), manager.metrics())
+}
+
+/// Complete the handshake, accepting our subprotocol if the client sent its
+/// token that way. The user is named in the response so the gateway can
+/// count connections opened with a ticket.
+fn start_socket(
+    ws_conn: WebSocketConnection,
+    req: &HttpRequest,
+    stream: web::Payload,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = ws_conn.user_id;
+    let mut response = ws::WsResponseBuilder::new(ws_conn, req, stream)
+        .protocols(&[socket_auth::SOCKET_PROTOCOL])
+        .start()?;
+    if let Ok(value) = actix_web::http::header::HeaderValue::from_str(&user_id.to_string()) {
+        response.headers_mut().insert(socket_auth::USER_ID_HEADER, value);
+    }
+    Ok(response)
+}
+
+async fn load_exchange_rates(repository: &ContrivanceRepository, path: &str) {
+    let contents = match common::FileUtils::read_to_string(std::path::Path::new(path)).await {
+        Ok(contents) => contents,
+        Err(e) => {
+            tracing::warn!("Could not read exchange rates file {}: {}", path, e);
+            return;
+        }
+    };
+
+    match common::currency::parse_rates_csv(&contents) {
+        Ok(rates) => match repository.upsert_exchange_rates(&rates).await {
+            Ok(saved) => info!("Loaded {} exchange rates from {}", saved.len(), path),
+            Err(e) => tracing::warn!("Failed to store exchange rates from {}: {}", path, e),
+        },
+        Err(e) => tracing::warn!("Invalid exchange rates file {}: {}", path, e),
+    }
 }
 
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(ApiResponse::success("Contrivance service is healthy"))
+}
+
+/// WebSocket counters in the Prometheus text format
+async fn metrics(connection_manager: web::Data<Arc<RwLock<ConnectionManager>>>) -> HttpResponse {
+    let manager = connection_manager.read().await;
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(manager.metrics().render(manager.connection_count()))
 }
\ No newline at end of file
@@ -0,0 +1,150 @@
+use actix_web::{web, HttpResponse, HttpRequest};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+use crate::{
+    email::{next_digest_at, parse_timezone},
+    repository::ContrivanceRepository,
+    middleware::auth::get_user_from_request,
+};
+use common::{
+    ApiResponse, ContrivanceError, NotificationList, UpdateDigestScheduleRequest,
+    UpdateNotificationPreferencesRequest,
+};
+
+pub struct NotificationHandlers {
+    repository: ContrivanceRepository,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationsQuery {
+    #[serde(default)]
+    pub unread_only: bool,
+    /// Only notifications created before this time, for paging back
+    pub before: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl NotificationHandlers {
+    pub fn new(repository: ContrivanceRepository) -> Self {
+        Self { repository }
+    }
+
+    /// List the authenticated user's notifications, newest first, with their unread count
+    pub async fn list_notifications(
+        &self,
+        req: HttpRequest,
+        query: web::Query<NotificationsQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let limit = query.limit.unwrap_or(50).clamp(1, 200);
+
+        let notifications = self.repository
+            .list_notifications(user.id, query.unread_only, query.before, limit)
+            .await?;
+        let unread_count = self.repository.count_unread_notifications(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(NotificationList {
+            notifications,
+            unread_count,
+        })))
+    }
+
+    /// Mark one notification read
+    pub async fn mark_read(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let notification = self.repository
+            .mark_notification_read(user.id, path.into_inner())
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Notification not found"))?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(notification)))
+    }
+
+    /// Mark all of the user's notifications read
+    pub async fn mark_all_read(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let updated = self.repository.mark_all_notifications_read(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(serde_json::json!({
+            "updated": updated,
+        }))))
+    }
+
+    /// Get the user's preference for every notification kind
+    pub async fn get_preferences(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let preferences = self.repository.get_notification_preferences(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+    }
+
+    /// Turn notification kinds on or off
+    pub async fn update_preferences(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<UpdateNotificationPreferencesRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        self.repository
+            .set_notification_preferences(user.id, &payload.preferences)
+            .await?;
+        let preferences = self.repository.get_notification_preferences(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(preferences)))
+    }
+
+    /// Get when the user receives the weekly pipeline digest email
+    pub async fn get_digest_schedule(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let schedule = self.repository.get_digest_schedule(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(schedule)))
+    }
+
+    /// Change the digest day, hour or time zone, or turn it off
+    pub async fn update_digest_schedule(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<UpdateDigestScheduleRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let mut schedule = self.repository.get_digest_schedule(user.id).await?;
+        let payload = payload.into_inner();
+        if let Some(enabled) = payload.enabled {
+            schedule.enabled = enabled;
+        }
+        if let Some(day_of_week) = payload.day_of_week {
+            schedule.day_of_week = day_of_week;
+        }
+        if let Some(hour) = payload.hour {
+            schedule.hour = hour;
+        }
+        if let Some(timezone) = payload.timezone {
+            schedule.timezone = timezone;
+        }
+
+        let timezone = parse_timezone(&schedule.timezone)
+            .ok_or_else(|| ContrivanceError::validation(format!("Unknown time zone: {}", schedule.timezone)))?;
+        schedule.next_send_at = schedule
+            .enabled
+            .then(|| next_digest_at(schedule.day_of_week, schedule.hour, timezone, Utc::now()));
+
+        let schedule = self.repository.save_digest_schedule(user.id, &schedule).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(schedule)))
+    }
+}
@@ -0,0 +1,431 @@
+use chrono::{DateTime, Utc};
+use common::mailer::{EmailOutbox, EmailTemplate};
+use common::{
+    ColumnType, Notification, NotificationKind, PermissionLevel, Spreadsheet, SpreadsheetRow, Todo,
+    WebSocketMessage,
+};
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{broadcast::Broadcaster, repository::ContrivanceRepository};
+
+/// How often due-soon and overdue todos are checked
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// Todos due within this many hours count as due soon
+const DUE_SOON_HOURS: i64 = 24;
+
+/// A notification waiting to be stored
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: Option<String>,
+    pub spreadsheet_id: Option<Uuid>,
+    pub row_id: Option<Uuid>,
+    pub todo_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    /// Notifications with the same key are only raised once per user
+    pub dedupe_key: Option<String>,
+}
+
+impl NewNotification {
+    pub fn new(user_id: Uuid, kind: NotificationKind, title: impl Into<String>) -> Self {
+        Self {
+            user_id,
+            kind,
+            title: title.into(),
+            body: None,
+            spreadsheet_id: None,
+            row_id: None,
+            todo_id: None,
+            actor_id: None,
+            dedupe_key: None,
+        }
+    }
+
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    pub fn spreadsheet(mut self, spreadsheet_id: Uuid) -> Self {
+        self.spreadsheet_id = Some(spreadsheet_id);
+        self
+    }
+
+    pub fn row(mut self, row_id: Option<Uuid>) -> Self {
+        self.row_id = row_id;
+        self
+    }
+
+    pub fn todo(mut self, todo: &Todo) -> Self {
+        self.spreadsheet_id = Some(todo.spreadsheet_id);
+        self.row_id = todo.row_id;
+        self.todo_id = Some(todo.id);
+        self
+    }
+
+    pub fn actor(mut self, actor_id: Uuid) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn dedupe(mut self, key: impl Into<String>) -> Self {
+        self.dedupe_key = Some(key.into());
+        self
+    }
+}
+
+/// Raises in-app notifications and pushes them to the recipient's
+/// `/ws/notifications` connections. Invites and overdue todos are also emailed.
+///
+/// Failures are logged rather than returned: a notification is never worth
+/// failing the request that raised it.
+#[derive(Clone)]
+pub struct Notifier {
+    repository: ContrivanceRepository,
+    broadcaster: Broadcaster,
+    outbox: EmailOutbox,
+    /// Frontend base URL for links in emails
+    app_url: String,
+}
+
+impl Notifier {
+    pub fn new(
+        repository: ContrivanceRepository,
+        broadcaster: Broadcaster,
+        outbox: EmailOutbox,
+        app_url: String,
+    ) -> Self {
+        Self { repository, broadcaster, outbox, app_url }
+    }
+
+    /// Store a notification and push it live. Returns `None` if the user has
+    /// this kind turned off or it was already raised.
+    pub async fn notify(&self, notification: NewNotification) -> Option<Notification> {
+        let created = match self.repository.create_notification(&notification).await {
+            Ok(created) => created?,
+            Err(e) => {
+                error!("Failed to create {} notification for user {}: {}",
+                    notification.kind.as_str(), notification.user_id, e);
+                return None;
+            }
+        };
+
+        let message = WebSocketMessage::NotificationCreated { notification: created.clone() };
+        self.broadcaster.to_user(created.user_id, &message).await;
+
+        Some(created)
+    }
+
+    /// Tell a todo's assignee it was assigned to them, unless they did it themselves
+    /// or it was already theirs
+    pub async fn todo_assigned(&self, todo: &Todo, previous_assignee: Option<Uuid>, actor_id: Uuid) {
+        let Some(assignee) = todo.assigned_to else {
+            return;
+        };
+        if assignee == actor_id || previous_assignee == Some(assignee) {
+            return;
+        }
+
+        let notification = NewNotification::new(assignee, NotificationKind::TodoAssigned, format!("You were assigned: {}", strip_mentions(&todo.title)))
+            .todo(todo)
+            .actor(actor_id);
+        let notification = match &todo.description {
+            Some(description) => notification.body(strip_mentions(description)),
+            None => notification,
+        };
+        self.notify(notification).await;
+    }
+
+    /// Tell a user they were invited to a spreadsheet, in the app and by email
+    pub async fn collaborator_invited(
+        &self,
+        spreadsheet: &Spreadsheet,
+        invitee: Uuid,
+        permission: &PermissionLevel,
+        actor_id: Uuid,
+    ) {
+        let notification = NewNotification::new(
+            invitee,
+            NotificationKind::CollaboratorInvite,
+            format!("You were invited to {}", spreadsheet.name),
+        )
+        .spreadsheet(spreadsheet.id)
+        .actor(actor_id);
+        if self.notify(notification).await.is_none() {
+            return;
+        }
+
+        let inviter = match self.repository.get_email_recipient(actor_id).await {
+            Ok(Some(inviter)) => inviter.name,
+            Ok(None) => "Someone".to_string(),
+            Err(e) => {
+                error!("Failed to load inviter {}: {}", actor_id, e);
+                return;
+            }
+        };
+        let permission = match permission {
+            PermissionLevel::View => "view",
+            PermissionLevel::Edit => "edit",
+            PermissionLevel::Admin => "admin",
+        };
+        let template = EmailTemplate::CollaboratorInvite {
+            inviter,
+            spreadsheet: spreadsheet.name.clone(),
+            permission: permission.to_string(),
+            accept_url: format!("{}?invite=accept", self.spreadsheet_url(spreadsheet.id)),
+        };
+        self.email(invitee, &template, None).await;
+    }
+
+    /// Notify users newly mentioned in a todo's title or description
+    pub async fn mentioned_in_todo(&self, previous: Option<&Todo>, todo: &Todo, actor_id: Uuid) {
+        let text = |t: &Todo| format!("{}\n{}", t.title, t.description.as_deref().unwrap_or_default());
+        let previous_text = previous.map(text);
+        let mentioned = new_mentions(previous_text.as_deref(), &text(todo));
+
+        for user_id in mentioned {
+            let notification = NewNotification::new(user_id, NotificationKind::Mention, format!("You were mentioned in a todo: {}", strip_mentions(&todo.title)))
+                .todo(todo)
+                .actor(actor_id);
+            let notification = match &todo.description {
+                Some(description) => notification.body(strip_mentions(description)),
+                None => notification,
+            };
+            self.mention(todo.spreadsheet_id, user_id, actor_id, notification).await;
+        }
+    }
+
+    /// Notify users newly mentioned in a row's Text cells
+    pub async fn mentioned_in_row(&self, previous: Option<&SpreadsheetRow>, row: &SpreadsheetRow, actor_id: Uuid) {
+        let columns = match self.repository.get_spreadsheet_columns(row.spreadsheet_id).await {
+            Ok(columns) => columns,
+            Err(e) => {
+                error!("Failed to load columns for spreadsheet {}: {}", row.spreadsheet_id, e);
+                return;
+            }
+        };
+
+        for column in columns.iter().filter(|c| c.column_type == ColumnType::Text) {
+            let Some(text) = column.cell(&row.row_data).and_then(Value::as_str) else {
+                continue;
+            };
+            let previous_text = previous
+                .and_then(|p| column.cell(&p.row_data))
+                .and_then(Value::as_str);
+
+            for user_id in new_mentions(previous_text, text) {
+                let notification = NewNotification::new(user_id, NotificationKind::Mention, format!("You were mentioned in {}", column.name))
+                    .body(strip_mentions(text))
+                    .spreadsheet(row.spreadsheet_id)
+                    .row(Some(row.id))
+                    .actor(actor_id);
+                self.mention(row.spreadsheet_id, user_id, actor_id, notification).await;
+            }
+        }
+    }
+
+    /// Raise a mention unless it's a self-mention or the user can't see the spreadsheet
+    async fn mention(&self, spreadsheet_id: Uuid, user_id: Uuid, actor_id: Uuid, notification: NewNotification) {
+        if user_id == actor_id {
+            return;
+        }
+        match self.repository.can_user_access_spreadsheet(user_id, spreadsheet_id).await {
+            Ok(true) => {
+                self.notify(notification).await;
+            }
+            Ok(false) => {}
+            Err(e) => error!("Failed to check access for mentioned user {}: {}", user_id, e),
+        }
+    }
+
+    /// Check for due-soon and overdue todos every few minutes until the process exits
+    pub async fn run(self) {
+        info!("Notification scheduler started");
+        loop {
+            if let Err(e) = self.notify_due_todos(Utc::now()).await {
+                error!("Due todo notifications failed: {}", e);
+            }
+            tokio::time::sleep(SCHEDULE_INTERVAL).await;
+        }
+    }
+
+    /// Notify assignees (or creators, for unassigned todos) once when a todo
+    /// becomes due soon and once when it becomes overdue
+    pub async fn notify_due_todos(&self, now: DateTime<Utc>) -> common::ContrivanceResult<()> {
+        let cutoff = now + chrono::Duration::hours(DUE_SOON_HOURS);
+
+        for todo in self.repository.get_todos_due_before(cutoff).await? {
+            let Some(due_date) = todo.due_date else {
+                continue;
+            };
+            let recipient = todo.assigned_to.unwrap_or(todo.user_id);
+            let (kind, title) = if due_date < now {
+                (NotificationKind::TodoOverdue, format!("Overdue: {}", strip_mentions(&todo.title)))
+            } else {
+                (NotificationKind::TodoDueSoon, format!("Due soon: {}", strip_mentions(&todo.title)))
+            };
+
+            // Keyed on the due date so moving it raises a fresh notification
+            let notification = NewNotification::new(recipient, kind, title)
+                .body(format!("Due {}", due_date.format("%Y-%m-%d %H:%M UTC")))
+                .todo(&todo)
+                .dedupe(format!("{}:{}:{}", kind.as_str(), todo.id, due_date.timestamp()));
+            let raised = self.notify(notification).await.is_some();
+
+            if raised && kind == NotificationKind::TodoOverdue {
+                self.email_overdue(&todo, recipient, due_date).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn email_overdue(&self, todo: &Todo, recipient: Uuid, due_date: DateTime<Utc>) {
+        let spreadsheet = match self.repository.get_spreadsheet(todo.spreadsheet_id).await {
+            Ok(Some(spreadsheet)) => spreadsheet.name,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to load spreadsheet {}: {}", todo.spreadsheet_id, e);
+                return;
+            }
+        };
+        let template = EmailTemplate::OverdueReminder {
+            todo_title: strip_mentions(&todo.title),
+            spreadsheet,
+            due_date,
+            todo_url: self.spreadsheet_url(todo.spreadsheet_id),
+        };
+        let dedupe_key = format!("overdue_reminder:{}:{}", todo.id, due_date.timestamp());
+        self.email(recipient, &template, Some(&dedupe_key)).await;
+    }
+
+    /// Queue an email to a user, logging rather than returning failures
+    async fn email(&self, user_id: Uuid, template: &EmailTemplate, dedupe_key: Option<&str>) {
+        let recipient = match self.repository.get_email_recipient(user_id).await {
+            Ok(Some(recipient)) => recipient,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Failed to load email address for user {}: {}", user_id, e);
+                return;
+            }
+        };
+
+        if let Err(e) = self.outbox.enqueue(Some(user_id), &recipient.email, template, dedupe_key).await {
+            error!("Failed to queue {} email for user {}: {}", template.name(), user_id, e);
+        }
+    }
+
+    fn spreadsheet_url(&self, spreadsheet_id: Uuid) -> String {
+        format!("{}/spreadsheet/{}", self.app_url, spreadsheet_id)
+    }
+}
+
+/// Users mentioned in `text` as `@[Display Name](user-id)`, the markup the
+/// mention picker inserts
+pub fn mentioned_user_ids(text: &str) -> Vec<Uuid> {
+    let mut ids = Vec::new();
+    for (_, id) in mention_spans(text) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Mentions in `text` that weren't already in `previous`
+fn new_mentions(previous: Option<&str>, text: &str) -> Vec<Uuid> {
+    let before = previous.map(mentioned_user_ids).unwrap_or_default();
+    mentioned_user_ids(text)
+        .into_iter()
+        .filter(|id| !before.contains(id))
+        .collect()
+}
+
+/// Render mention markup as plain `@Display Name`
+pub fn strip_mentions(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("@[") {
+        plain.push_str(&rest[..start]);
+        match parse_mention(&rest[start..]) {
+            Some((name, _, len)) => {
+                plain.push('@');
+                plain.push_str(name);
+                rest = &rest[start + len..];
+            }
+            None => {
+                plain.push_str("@[");
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    plain.push_str(rest);
+    plain
+}
+
+fn mention_spans(text: &str) -> Vec<(&str, Uuid)> {
+    let mut spans = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("@[") {
+        match parse_mention(&rest[start..]) {
+            Some((name, id, len)) => {
+                spans.push((name, id));
+                rest = &rest[start + len..];
+            }
+            None => rest = &rest[start + 2..],
+        }
+    }
+    spans
+}
+
+/// Parse `@[name](uuid)` at the start of `text`, returning the name, id and length
+fn parse_mention(text: &str) -> Option<(&str, Uuid, usize)> {
+    let inner = text.strip_prefix("@[")?;
+    let name_end = inner.find(']')?;
+    let name = &inner[..name_end];
+    let after = inner[name_end + 1..].strip_prefix('(')?;
+    let id_end = after.find(')')?;
+    let id = Uuid::parse_str(&after[..id_end]).ok()?;
+    Some((name, id, 2 + name_end + 2 + id_end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALICE: &str = "6f1c1f52-9b55-4a8e-8a49-0d8e3c1a2b01";
+    const BOB: &str = "0b7d4e0a-3f0e-4d42-9a3e-5c2f1e9d8c02";
+
+    #[test]
+    fn test_mentioned_user_ids() {
+        let text = format!("Ping @[Alice Smith]({ALICE}) and @[Bob]({BOB}), then @[Alice Smith]({ALICE}) again");
+        assert_eq!(
+            mentioned_user_ids(&text),
+            vec![Uuid::parse_str(ALICE).unwrap(), Uuid::parse_str(BOB).unwrap()]
+        );
+
+        assert!(mentioned_user_ids("email me @ alice@example.com").is_empty());
+        assert!(mentioned_user_ids("@[Broken](not-a-uuid) @[Unclosed](").is_empty());
+    }
+
+    #[test]
+    fn test_only_new_mentions_notify() {
+        let before = format!("cc @[Alice]({ALICE})");
+        let after = format!("cc @[Alice]({ALICE}) @[Bob]({BOB})");
+
+        assert_eq!(new_mentions(Some(&before), &after), vec![Uuid::parse_str(BOB).unwrap()]);
+        assert!(new_mentions(Some(&after), &before).is_empty());
+        assert_eq!(new_mentions(None, &before), vec![Uuid::parse_str(ALICE).unwrap()]);
+    }
+
+    #[test]
+    fn test_strip_mentions() {
+        let text = format!("Thanks @[Alice Smith]({ALICE})! See @[notes] and @[x](y)");
+        assert_eq!(strip_mentions(&text), "Thanks @Alice Smith! See @[notes] and @[x](y)");
+    }
+}
@@ -0,0 +1,428 @@
+use actix_web::{web, HttpResponse, HttpRequest};
+use uuid::Uuid;
+use validator::Validate;
+use crate::{
+    repository::ContrivanceRepository,
+    middleware::auth::get_user_from_request,
+};
+use common::{
+    AcceptOrganizationInvitationRequest, AddTeamMemberRequest, ApiResponse, AssignTeamRequest,
+    ContrivanceError, CreateTeamRequest, InviteOrganizationMemberRequest, OrganizationMembership,
+    OrganizationRole, Team, TeamRole,
+    UpdateOrganizationMemberRequest, UpdateOrganizationRequest, UpdateTeamRequest,
+};
+
+pub struct OrganizationHandlers {
+    repository: ContrivanceRepository,
+}
+
+impl OrganizationHandlers {
+    pub fn new(repository: ContrivanceRepository) -> Self {
+        Self { repository }
+    }
+
+    /// Get the user's organization and their role in it
+    pub async fn get_organization(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let membership = self.membership(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(membership)))
+    }
+
+    /// Rename the organization
+    pub async fn update_organization(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<UpdateOrganizationRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let membership = self.require_admin(user.id).await?;
+        let organization = self.repository
+            .update_organization(membership.organization.id, payload.name.trim())
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(organization)))
+    }
+
+    /// List the organization's members
+    pub async fn list_members(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let membership = self.membership(user.id).await?;
+        let members = self.repository.list_organization_members(membership.organization.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(members)))
+    }
+
+    /// Invite someone to the organization by email. The response is the same
+    /// whether or not they have an account; they join by accepting.
+    pub async fn invite_member(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<InviteOrganizationMemberRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let membership = self.require_admin(user.id).await?;
+        let role = payload.role.unwrap_or(OrganizationRole::Member);
+        if role == OrganizationRole::Owner && membership.role != OrganizationRole::Owner {
+            return Err(ContrivanceError::forbidden("Only owners can invite owners"));
+        }
+
+        let invitation = self.repository
+            .create_organization_invitation(membership.organization.id, &payload.email, role, user.id)
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(invitation)))
+    }
+
+    /// List the organization's pending invitations
+    pub async fn list_invitations(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let membership = self.require_admin(user.id).await?;
+        let invitations = self.repository.list_organization_invitations(membership.organization.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(invitations)))
+    }
+
+    /// Withdraw a pending invitation
+    pub async fn revoke_invitation(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let membership = self.require_admin(user.id).await?;
+        if !self.repository.delete_organization_invitation(membership.organization.id, path.into_inner()).await? {
+            return Err(ContrivanceError::not_found("Invitation not found"));
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// List the invitations other organizations have sent the user
+    pub async fn list_received_invitations(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let invitations = self.repository.list_received_invitations(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(invitations)))
+    }
+
+    /// Join the organization that sent an invitation. The user must not share
+    /// their current organization with anyone; what they have in it only comes
+    /// along with `bring_data`.
+    pub async fn accept_invitation(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AcceptOrganizationInvitationRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let member = self.repository
+            .accept_organization_invitation(path.into_inner(), user.id, payload.bring_data)
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(member)))
+    }
+
+    /// Turn down an invitation
+    pub async fn decline_invitation(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        if !self.repository.decline_organization_invitation(path.into_inner(), user.id).await? {
+            return Err(ContrivanceError::not_found("Invitation not found"));
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Change a member's role; only owners can make or unmake owners
+    pub async fn update_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<UpdateOrganizationMemberRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let member_id = path.into_inner();
+
+        let membership = self.require_admin(user.id).await?;
+        let organization_id = membership.organization.id;
+        let current = self.repository
+            .get_organization_role(organization_id, member_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Member not found"))?;
+        let touches_owner = current == OrganizationRole::Owner || payload.role == OrganizationRole::Owner;
+        if touches_owner && membership.role != OrganizationRole::Owner {
+            return Err(ContrivanceError::forbidden("Only owners can change who owns the organization"));
+        }
+        if current == OrganizationRole::Owner && payload.role != OrganizationRole::Owner {
+            self.ensure_another_owner(organization_id, member_id).await?;
+        }
+
+        let member = self.repository
+            .set_organization_role(organization_id, member_id, payload.role)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Member not found"))?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(member)))
+    }
+
+    /// Remove a member, who gets an organization of their own; members can
+    /// also leave on their own
+    pub async fn remove_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let member_id = path.into_inner();
+
+        let membership = if member_id == user.id {
+            self.membership(user.id).await?
+        } else {
+            self.require_admin(user.id).await?
+        };
+        let organization_id = membership.organization.id;
+        let role = self.repository
+            .get_organization_role(organization_id, member_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Member not found"))?;
+        if role == OrganizationRole::Owner {
+            if member_id != user.id && membership.role != OrganizationRole::Owner {
+                return Err(ContrivanceError::forbidden("Only owners can remove owners"));
+            }
+            self.ensure_another_owner(organization_id, member_id).await?;
+        }
+
+        if !self.repository.remove_organization_member(organization_id, member_id).await? {
+            return Err(ContrivanceError::not_found("Member not found"));
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// List the organization's teams
+    pub async fn list_teams(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let membership = self.membership(user.id).await?;
+        let teams = self.repository.list_teams(membership.organization.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(teams)))
+    }
+
+    /// Create a team; organization admins only
+    pub async fn create_team(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<CreateTeamRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let membership = self.require_admin(user.id).await?;
+        let team = self.repository.create_team(membership.organization.id, &payload).await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(team)))
+    }
+
+    /// Get a team
+    pub async fn get_team(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (_, team) = self.team(user.id, path.into_inner()).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(team)))
+    }
+
+    /// Rename or describe a team; organization admins and team leads
+    pub async fn update_team(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<UpdateTeamRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let (membership, team) = self.team(user.id, path.into_inner()).await?;
+        self.require_team_lead(&membership, team.id, user.id).await?;
+        let team = self.repository.update_team(team.id, &payload).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(team)))
+    }
+
+    /// Delete a team; its spreadsheets stay in the organization
+    pub async fn delete_team(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (membership, team) = self.team(user.id, path.into_inner()).await?;
+        if !membership.role.is_admin() {
+            return Err(ContrivanceError::forbidden("Only organization admins can delete teams"));
+        }
+        self.repository.delete_team(team.id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// List a team's members
+    pub async fn list_team_members(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (_, team) = self.team(user.id, path.into_inner()).await?;
+        let members = self.repository.list_team_members(team.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(members)))
+    }
+
+    /// Add an organization member to a team by email, or change their role on it
+    pub async fn add_team_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AddTeamMemberRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+
+        let (membership, team) = self.team(user.id, path.into_inner()).await?;
+        self.require_team_lead(&membership, team.id, user.id).await?;
+        let member_id = self.repository
+            .get_user_id_by_email(membership.organization.id, &payload.email)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("No member of the organization with that email"))?;
+
+        let member = self.repository
+            .add_team_member(team.id, member_id, payload.role.unwrap_or(TeamRole::Member))
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(member)))
+    }
+
+    /// Remove a member from a team; members can also leave on their own
+    pub async fn remove_team_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (team_id, member_id) = path.into_inner();
+
+        let (membership, team) = self.team(user.id, team_id).await?;
+        if member_id != user.id {
+            self.require_team_lead(&membership, team.id, user.id).await?;
+        }
+
+        if !self.repository.remove_team_member(team.id, member_id).await? {
+            return Err(ContrivanceError::not_found("Member not found"));
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Give a spreadsheet to a team or take it away from its team. Needs admin
+    /// on the spreadsheet, and to be on the new team or an organization admin.
+    pub async fn assign_spreadsheet_team(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AssignTeamRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::not_found("Spreadsheet not found"));
+        }
+        if !self.repository.can_user_manage_collaborators(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Only spreadsheet admins can change its team"));
+        }
+        if let Some(team_id) = payload.team_id {
+            if !self.repository.can_user_use_team(user.id, team_id).await? {
+                return Err(ContrivanceError::forbidden("Only team members and organization admins can give the team spreadsheets"));
+            }
+        }
+
+        let spreadsheet = self.repository.assign_spreadsheet_team(spreadsheet_id, payload.team_id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(spreadsheet)))
+    }
+
+    async fn membership(&self, user_id: Uuid) -> Result<OrganizationMembership, ContrivanceError> {
+        self.repository
+            .get_membership(user_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Organization not found"))
+    }
+
+    async fn require_admin(&self, user_id: Uuid) -> Result<OrganizationMembership, ContrivanceError> {
+        let membership = self.membership(user_id).await?;
+        if !membership.role.is_admin() {
+            return Err(ContrivanceError::forbidden("Organization admin access required"));
+        }
+        Ok(membership)
+    }
+
+    /// The user's membership and a team in their organization; teams in other
+    /// organizations are reported missing
+    async fn team(&self, user_id: Uuid, team_id: Uuid) -> Result<(OrganizationMembership, Team), ContrivanceError> {
+        let membership = self.membership(user_id).await?;
+        let team = self.repository
+            .get_team(membership.organization.id, team_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Team not found"))?;
+        Ok((membership, team))
+    }
+
+    async fn require_team_lead(
+        &self,
+        membership: &OrganizationMembership,
+        team_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), ContrivanceError> {
+        if membership.role.is_admin() {
+            return Ok(());
+        }
+        match self.repository.get_team_role(team_id, user_id).await? {
+            Some(TeamRole::Lead) => Ok(()),
+            _ => Err(ContrivanceError::forbidden("Only team leads and organization admins can manage the team")),
+        }
+    }
+
+    /// Organizations always keep at least one owner
+    async fn ensure_another_owner(&self, organization_id: Uuid, member_id: Uuid) -> Result<(), ContrivanceError> {
+        let members = self.repository.list_organization_members(organization_id).await?;
+        let other_owner = members
+            .iter()
+            .any(|m| m.role == OrganizationRole::Owner && m.user_id != member_id);
+        if !other_owner {
+            return Err(ContrivanceError::validation("The organization needs another owner first"));
+        }
+        Ok(())
+    }
+}
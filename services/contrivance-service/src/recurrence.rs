@@ -0,0 +1,447 @@
+//! Recurring todos.
+//!
+//! A recurring todo carries an RFC 5545 `RRULE` and the time zone it is read
+//! in. The first occurrence's due date is the rule's `DTSTART`, and later
+//! occurrences fall at the same local time of day, so a 09:00 Monday check-in
+//! stays at 09:00 across daylight saving changes. A time skipped by a DST gap
+//! moves to the next hour.
+//!
+//! Supported parts: `FREQ` (`DAILY`, `WEEKLY`, `MONTHLY`, `YEARLY`),
+//! `INTERVAL`, `COUNT`, `UNTIL`, `BYDAY` (with ordinals such as `1MO` or
+//! `-1FR` on monthly and yearly rules), `BYMONTHDAY`, `BYMONTH` and `WKST`.
+//! Anything else is rejected rather than silently ignored.
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use common::{ContrivanceError, ContrivanceResult};
+
+/// Periods searched for the next occurrence before the series is treated as
+/// finished, so a rule that can never match (`BYMONTH=2;BYMONTHDAY=30`) ends
+const MAX_PERIODS: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// `UNTIL` as a date, a local date-time or a UTC date-time (`...Z`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Until {
+    Date(NaiveDate),
+    Local(NaiveDateTime),
+    Utc(DateTime<Utc>),
+}
+
+/// A parsed `RRULE`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecurrenceRule {
+    freq: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<Until>,
+    /// Weekdays, each optionally the nth (negative: from the end) in its month
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+    week_start: Weekday,
+}
+
+impl RecurrenceRule {
+    /// Parse a rule such as `FREQ=WEEKLY;BYDAY=MO,WE`, with or without the `RRULE:` prefix
+    pub fn parse(rule: &str) -> ContrivanceResult<Self> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut freq = None;
+        let mut parsed = Self {
+            freq: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            week_start: Weekday::Mon,
+        };
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| invalid(format!("'{}' is not NAME=VALUE", part)))?;
+            let value = value.to_ascii_uppercase();
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        other => {
+                            return Err(invalid(format!(
+                                "FREQ={} is not supported; use DAILY, WEEKLY, MONTHLY or YEARLY",
+                                other
+                            )))
+                        }
+                    })
+                }
+                "INTERVAL" => parsed.interval = parse_number("INTERVAL", &value, 1, i32::MAX)? as u32,
+                "COUNT" => parsed.count = Some(parse_number("COUNT", &value, 1, i32::MAX)? as u32),
+                "UNTIL" => parsed.until = Some(parse_until(&value)?),
+                "BYDAY" => {
+                    parsed.by_day = value.split(',').map(parse_day).collect::<ContrivanceResult<_>>()?;
+                }
+                "BYMONTHDAY" => {
+                    parsed.by_month_day = value
+                        .split(',')
+                        .map(|day| match parse_number("BYMONTHDAY", day, -31, 31)? {
+                            0 => Err(invalid("BYMONTHDAY cannot be 0".to_string())),
+                            day => Ok(day),
+                        })
+                        .collect::<ContrivanceResult<_>>()?;
+                }
+                "BYMONTH" => {
+                    parsed.by_month = value
+                        .split(',')
+                        .map(|month| parse_number("BYMONTH", month, 1, 12).map(|month| month as u32))
+                        .collect::<ContrivanceResult<_>>()?;
+                }
+                "WKST" => parsed.week_start = parse_weekday(&value)?,
+                other => return Err(invalid(format!("{} is not supported", other))),
+            }
+        }
+
+        parsed.freq = freq.ok_or_else(|| invalid("FREQ is required".to_string()))?;
+        if parsed.count.is_some() && parsed.until.is_some() {
+            return Err(invalid("COUNT and UNTIL cannot both be set".to_string()));
+        }
+        let has_ordinals = parsed.by_day.iter().any(|(ordinal, _)| ordinal.is_some());
+        match parsed.freq {
+            Frequency::Daily | Frequency::Weekly if has_ordinals => {
+                return Err(invalid("BYDAY ordinals such as 1MO need a MONTHLY or YEARLY rule".to_string()))
+            }
+            Frequency::Weekly if !parsed.by_month_day.is_empty() => {
+                return Err(invalid("BYMONTHDAY cannot be used with a WEEKLY rule".to_string()))
+            }
+            Frequency::Yearly if !parsed.by_day.is_empty() && parsed.by_month.is_empty() => {
+                return Err(invalid("BYDAY on a YEARLY rule needs BYMONTH".to_string()))
+            }
+            _ => {}
+        }
+        Ok(parsed)
+    }
+}
+
+/// A rule anchored at its first occurrence in a time zone
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    rule: RecurrenceRule,
+    timezone: Tz,
+    /// `DTSTART`, in the time zone's wall-clock time
+    start: NaiveDateTime,
+}
+
+impl Schedule {
+    /// Validate `rule` and `timezone` (an IANA name such as `Europe/London`)
+    /// for a series whose first occurrence is due at `start`
+    pub fn new(rule: &str, timezone: &str, start: DateTime<Utc>) -> ContrivanceResult<Self> {
+        let timezone: Tz = timezone
+            .parse()
+            .map_err(|_| ContrivanceError::validation(format!("Unknown time zone: {}", timezone)))?;
+        Ok(Self {
+            rule: RecurrenceRule::parse(rule)?,
+            timezone,
+            start: start.with_timezone(&timezone).naive_local(),
+        })
+    }
+
+    /// The first occurrence scheduled after `after`, or `None` once the series has ended
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.occurrences().find(|at| *at > after)
+    }
+
+    /// Every occurrence in order, starting with `DTSTART` itself
+    fn occurrences(&self) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let start = self.start;
+        let later = (0..MAX_PERIODS)
+            .flat_map(move |period| self.dates_in(period))
+            .filter(move |date| *date > start.date());
+
+        std::iter::once(start.date())
+            .chain(later)
+            .map(move |date| date.and_time(start.time()))
+            .take_while(move |local| self.rule.until.is_none_or(|until| self.before_until(*local, until)))
+            .take(self.rule.count.map_or(usize::MAX, |count| count as usize))
+            .filter_map(move |local| self.to_utc(local))
+    }
+
+    /// The candidate dates of the `period`th interval after the one holding
+    /// `DTSTART`, in order
+    fn dates_in(&self, period: u32) -> Vec<NaiveDate> {
+        let rule = &self.rule;
+        let start = self.start.date();
+        let step = i64::from(period) * i64::from(rule.interval);
+
+        let mut dates: Vec<NaiveDate> = match rule.freq {
+            Frequency::Daily => start.checked_add_signed(Duration::days(step)).into_iter().collect(),
+            Frequency::Weekly => {
+                let first_day = start - Duration::days(days_between(rule.week_start, start.weekday()));
+                let Some(week) = first_day.checked_add_signed(Duration::weeks(step)) else {
+                    return Vec::new();
+                };
+                let weekdays = if rule.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    rule.by_day.iter().map(|(_, weekday)| *weekday).collect()
+                };
+                weekdays
+                    .into_iter()
+                    .filter_map(|weekday| week.checked_add_signed(Duration::days(days_between(rule.week_start, weekday))))
+                    .collect()
+            }
+            Frequency::Monthly => {
+                let month = i64::from(start.year()) * 12 + i64::from(start.month0()) + step;
+                match i32::try_from(month.div_euclid(12)) {
+                    Ok(year) => self.days_of_month(year, month.rem_euclid(12) as u32 + 1),
+                    Err(_) => Vec::new(),
+                }
+            }
+            Frequency::Yearly => {
+                let Some(year) = i32::try_from(step).ok().and_then(|step| start.year().checked_add(step)) else {
+                    return Vec::new();
+                };
+                let months = if rule.by_month.is_empty() { vec![start.month()] } else { rule.by_month.clone() };
+                months.into_iter().flat_map(|month| self.days_of_month(year, month)).collect()
+            }
+        };
+
+        dates.retain(|date| self.matches(*date));
+        dates.sort();
+        dates.dedup();
+        dates
+    }
+
+    /// Days of a month a MONTHLY or YEARLY rule may fall on, before filtering
+    fn days_of_month(&self, year: i32, month: u32) -> Vec<NaiveDate> {
+        let rule = &self.rule;
+        let Some(first) = NaiveDate::from_ymd_opt(year, month, 1) else {
+            return Vec::new();
+        };
+        let length = days_in_month(first);
+
+        let days: Vec<u32> = if !rule.by_month_day.is_empty() {
+            rule.by_month_day.iter().filter_map(|day| resolve_month_day(*day, length)).collect()
+        } else if !rule.by_day.is_empty() {
+            (1..=length)
+                .filter(|day| {
+                    let weekday = (first + Duration::days(i64::from(*day) - 1)).weekday();
+                    rule.by_day.iter().any(|(_, by_day)| *by_day == weekday)
+                })
+                .collect()
+        } else {
+            vec![self.start.day()]
+        };
+        days.into_iter().filter_map(|day| NaiveDate::from_ymd_opt(year, month, day)).collect()
+    }
+
+    fn matches(&self, date: NaiveDate) -> bool {
+        let rule = &self.rule;
+        let length = days_in_month(date);
+        (rule.by_month.is_empty() || rule.by_month.contains(&date.month()))
+            && (rule.by_month_day.is_empty()
+                || rule.by_month_day.iter().any(|day| resolve_month_day(*day, length) == Some(date.day())))
+            && (rule.by_day.is_empty()
+                || rule.by_day.iter().any(|(ordinal, weekday)| {
+                    date.weekday() == *weekday && ordinal.is_none_or(|n| is_nth_in_month(date, length, n))
+                }))
+    }
+
+    fn before_until(&self, local: NaiveDateTime, until: Until) -> bool {
+        match until {
+            Until::Date(date) => local.date() <= date,
+            Until::Local(until) => local <= until,
+            Until::Utc(until) => self.to_utc(local).is_some_and(|at| at <= until),
+        }
+    }
+
+    fn to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let at = |local: NaiveDateTime| self.timezone.from_local_datetime(&local).earliest();
+        at(local)
+            .or_else(|| at(local + Duration::hours(1)))
+            .map(|at| at.with_timezone(&Utc))
+    }
+}
+
+fn invalid(message: String) -> ContrivanceError {
+    ContrivanceError::validation(format!("Invalid recurrence: {}", message))
+}
+
+fn parse_number(name: &str, value: &str, min: i32, max: i32) -> ContrivanceResult<i32> {
+    value
+        .trim_start_matches('+')
+        .parse::<i32>()
+        .ok()
+        .filter(|n| (min..=max).contains(n))
+        .ok_or_else(|| invalid(format!("{}={} is out of range", name, value)))
+}
+
+fn parse_weekday(value: &str) -> ContrivanceResult<Weekday> {
+    Ok(match value {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        other => return Err(invalid(format!("'{}' is not a weekday; use MO, TU, WE, TH, FR, SA or SU", other))),
+    })
+}
+
+/// `MO`, `2TU` or `-1FR`
+fn parse_day(value: &str) -> ContrivanceResult<(Option<i32>, Weekday)> {
+    let split = value.len().saturating_sub(2);
+    let (Some(ordinal), Some(weekday)) = (value.get(..split), value.get(split..)) else {
+        return Err(invalid(format!("'{}' is not a weekday such as MO or -1FR", value)));
+    };
+    let ordinal = match ordinal {
+        "" => None,
+        ordinal => match parse_number("BYDAY", ordinal, -5, 5)? {
+            0 => return Err(invalid("BYDAY ordinals cannot be 0".to_string())),
+            n => Some(n),
+        },
+    };
+    Ok((ordinal, parse_weekday(weekday)?))
+}
+
+/// `20251231`, `20251231T170000` (local time) or `20251231T170000Z` (UTC)
+fn parse_until(value: &str) -> ContrivanceResult<Until> {
+    let until = if let Some(utc) = value.strip_suffix('Z') {
+        NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").map(|at| Until::Utc(at.and_utc()))
+    } else if value.contains('T') {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").map(Until::Local)
+    } else {
+        NaiveDate::parse_from_str(value, "%Y%m%d").map(Until::Date)
+    };
+    until.map_err(|_| invalid(format!("UNTIL={} is not a date such as 20251231 or 20251231T170000Z", value)))
+}
+
+/// Days from `from` forward to the next `to`, 0 if they're the same
+fn days_between(from: Weekday, to: Weekday) -> i64 {
+    i64::from((to.num_days_from_monday() + 7 - from.num_days_from_monday()) % 7)
+}
+
+fn days_in_month(date: NaiveDate) -> u32 {
+    (28..=31)
+        .rev()
+        .find(|day| date.with_day(*day).is_some())
+        .unwrap_or(28)
+}
+
+/// A `BYMONTHDAY` value as a day of a month `length` days long; negative
+/// values count from the end, and days the month doesn't have are skipped
+fn resolve_month_day(day: i32, length: u32) -> Option<u32> {
+    let day = if day < 0 { length as i32 + 1 + day } else { day };
+    u32::try_from(day).ok().filter(|day| (1..=length).contains(day))
+}
+
+/// Whether `date` is the `n`th of its weekday in its month, counting from
+/// the end when `n` is negative
+fn is_nth_in_month(date: NaiveDate, length: u32, n: i32) -> bool {
+    if n > 0 {
+        ((date.day() - 1) / 7 + 1) as i32 == n
+    } else {
+        ((length - date.day()) / 7 + 1) as i32 == -n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn following(schedule: &Schedule, from: &str, n: usize) -> Vec<DateTime<Utc>> {
+        let mut at = utc(from);
+        let mut occurrences = Vec::new();
+        for _ in 0..n {
+            let Some(next) = schedule.next_after(at) else { break };
+            occurrences.push(next);
+            at = next;
+        }
+        occurrences
+    }
+
+    #[test]
+    fn test_weekly_rule_keeps_local_time_across_dst() {
+        // Monday 27 Oct 2025, 09:00 EDT; New York falls back on 2 Nov
+        let schedule = Schedule::new("FREQ=WEEKLY;BYDAY=MO,TH", "America/New_York", utc("2025-10-27T13:00:00Z")).unwrap();
+        assert_eq!(
+            following(&schedule, "2025-10-27T13:00:00Z", 3),
+            vec![utc("2025-10-30T13:00:00Z"), utc("2025-11-03T14:00:00Z"), utc("2025-11-06T14:00:00Z")]
+        );
+
+        // 01:30 doesn't exist in London on 30 Mar 2025, so that one is at 02:30 BST
+        let schedule = Schedule::new("FREQ=DAILY", "Europe/London", utc("2025-03-29T01:30:00Z")).unwrap();
+        assert_eq!(
+            following(&schedule, "2025-03-29T01:30:00Z", 2),
+            vec![utc("2025-03-30T01:30:00Z"), utc("2025-03-31T00:30:00Z")]
+        );
+    }
+
+    #[test]
+    fn test_monthly_rules_skip_months_without_the_day() {
+        // Last Friday of the month, from Friday 31 Oct 2025
+        let schedule = Schedule::new("FREQ=MONTHLY;BYDAY=-1FR", "UTC", utc("2025-10-31T15:00:00Z")).unwrap();
+        assert_eq!(
+            following(&schedule, "2025-10-31T15:00:00Z", 2),
+            vec![utc("2025-11-28T15:00:00Z"), utc("2025-12-26T15:00:00Z")]
+        );
+
+        // The 31st only happens in some months
+        let schedule = Schedule::new("FREQ=MONTHLY", "UTC", utc("2025-01-31T10:00:00Z")).unwrap();
+        assert_eq!(
+            following(&schedule, "2025-01-31T10:00:00Z", 2),
+            vec![utc("2025-03-31T10:00:00Z"), utc("2025-05-31T10:00:00Z")]
+        );
+
+        let schedule = Schedule::new("RRULE:FREQ=monthly;BYMONTHDAY=-1", "UTC", utc("2025-01-31T10:00:00Z")).unwrap();
+        assert_eq!(following(&schedule, "2025-01-31T10:00:00Z", 1), vec![utc("2025-02-28T10:00:00Z")]);
+    }
+
+    #[test]
+    fn test_count_and_until_end_the_series() {
+        // DTSTART is the first of the three
+        let schedule = Schedule::new("FREQ=DAILY;COUNT=3", "UTC", utc("2025-11-01T09:00:00Z")).unwrap();
+        assert_eq!(following(&schedule, "2025-11-01T09:00:00Z", 5).len(), 2);
+
+        // A date UNTIL includes that whole day
+        let schedule = Schedule::new("FREQ=DAILY;INTERVAL=2;UNTIL=20251105", "UTC", utc("2025-11-01T09:00:00Z")).unwrap();
+        assert_eq!(
+            following(&schedule, "2025-11-01T09:00:00Z", 5),
+            vec![utc("2025-11-03T09:00:00Z"), utc("2025-11-05T09:00:00Z")]
+        );
+        assert_eq!(schedule.next_after(utc("2025-11-05T09:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_rejects_what_it_cannot_follow() {
+        let start = utc("2025-11-01T09:00:00Z");
+        for rule in [
+            "FREQ=HOURLY",
+            "FREQ=DAILY;BYSETPOS=1",
+            "FREQ=DAILY;COUNT=2;UNTIL=20251231",
+            "FREQ=WEEKLY;BYDAY=1MO",
+            "FREQ=YEARLY;BYDAY=MO",
+            "FREQ=MONTHLY;BYDAY=MÖ",
+            "BYDAY=MO",
+            "FREQ=DAILY;INTERVAL=0",
+        ] {
+            assert!(Schedule::new(rule, "UTC", start).is_err(), "{} should be rejected", rule);
+        }
+        assert!(Schedule::new("FREQ=DAILY", "Mars/Olympus_Mons", start).is_err());
+    }
+}
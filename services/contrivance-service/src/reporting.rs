@@ -0,0 +1,213 @@
+use chrono::NaiveDate;
+use common::{
+    ColumnType, Conversion, CurrencyConverter, Money, Spreadsheet, SpreadsheetColumn, SpreadsheetRow,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Aggregated Currency column totals, converted into the reporting currency
+#[derive(Debug, Serialize)]
+pub struct SpreadsheetSummary {
+    pub spreadsheet_id: Uuid,
+    pub reporting_currency: String,
+    pub as_of: NaiveDate,
+    pub row_count: usize,
+    pub columns: Vec<CurrencyColumnSummary>,
+    pub forecast: Option<ForecastSummary>,
+    /// Every rate applied, so readers can see which date each conversion used
+    pub rates_used: Vec<RateUsed>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CurrencyColumnSummary {
+    pub column_id: Uuid,
+    pub name: String,
+    /// Sum of all convertible amounts in the reporting currency
+    pub total: f64,
+    /// Sums in each original currency before conversion
+    pub by_currency: BTreeMap<String, f64>,
+    /// Amounts that couldn't be converted because no rate is known
+    pub unconverted: BTreeMap<String, f64>,
+    pub values_counted: usize,
+}
+
+/// Probability-weighted pipeline forecast
+#[derive(Debug, Serialize)]
+pub struct ForecastSummary {
+    pub amount_column: String,
+    pub probability_column: String,
+    pub weighted_total: f64,
+    pub unweighted_total: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RateUsed {
+    pub from: String,
+    pub to: String,
+    pub rate: f64,
+    pub rate_date: NaiveDate,
+}
+
+/// Converts Currency cells for a single spreadsheet and remembers the rates used
+pub struct CellConverter<'a> {
+    converter: &'a CurrencyConverter,
+    reporting_currency: String,
+    as_of: NaiveDate,
+    rates_used: BTreeMap<String, RateUsed>,
+}
+
+impl<'a> CellConverter<'a> {
+    pub fn new(converter: &'a CurrencyConverter, reporting_currency: String, as_of: NaiveDate) -> Self {
+        Self {
+            converter,
+            reporting_currency,
+            as_of,
+            rates_used: BTreeMap::new(),
+        }
+    }
+
+    pub fn reporting_currency(&self) -> &str {
+        &self.reporting_currency
+    }
+
+    /// Parse a Currency cell in `column`, returning the original amount and its conversion
+    pub fn convert_cell(
+        &mut self,
+        column: &SpreadsheetColumn,
+        row: &SpreadsheetRow,
+    ) -> Option<(Money, Option<Conversion>)> {
+        let default_currency = column
+            .default_currency()
+            .unwrap_or_else(|| self.reporting_currency.clone());
+        let money = Money::from_cell(column.cell(&row.row_data)?, &default_currency)?;
+        let conversion = self.converter.convert(&money, &self.reporting_currency, self.as_of);
+
+        if let Some(Conversion { rate, rate_date: Some(rate_date), .. }) = &conversion {
+            self.rates_used
+                .entry(money.currency.clone())
+                .or_insert_with(|| RateUsed {
+                    from: money.currency.clone(),
+                    to: self.reporting_currency.clone(),
+                    rate: *rate,
+                    rate_date: *rate_date,
+                });
+        }
+
+        Some((money, conversion))
+    }
+
+    pub fn rates_used(&self) -> Vec<RateUsed> {
+        self.rates_used.values().cloned().collect()
+    }
+}
+
+/// Build Currency column totals and a weighted forecast for a spreadsheet.
+///
+/// The forecast uses `settings.forecast.amount_column` and
+/// `settings.forecast.probability_column` when set, otherwise the first Currency
+/// column and the first Percent column.
+pub fn summarize(
+    spreadsheet: &Spreadsheet,
+    columns: &[SpreadsheetColumn],
+    rows: &[SpreadsheetRow],
+    converter: &mut CellConverter,
+) -> SpreadsheetSummary {
+    let currency_columns: Vec<&SpreadsheetColumn> = columns
+        .iter()
+        .filter(|c| c.column_type == ColumnType::Currency)
+        .collect();
+
+    let mut column_summaries = Vec::new();
+    for column in &currency_columns {
+        let mut summary = CurrencyColumnSummary {
+            column_id: column.id,
+            name: column.name.clone(),
+            total: 0.0,
+            by_currency: BTreeMap::new(),
+            unconverted: BTreeMap::new(),
+            values_counted: 0,
+        };
+
+        for row in rows {
+            if let Some((money, conversion)) = converter.convert_cell(column, row) {
+                summary.values_counted += 1;
+                *summary.by_currency.entry(money.currency.clone()).or_default() += money.amount;
+                match conversion {
+                    Some(c) => summary.total += c.amount,
+                    None => *summary.unconverted.entry(money.currency).or_default() += money.amount,
+                }
+            }
+        }
+
+        column_summaries.push(summary);
+    }
+
+    let forecast = forecast_columns(spreadsheet, columns).map(|(amount_column, probability_column)| {
+        let mut weighted_total = 0.0;
+        let mut unweighted_total = 0.0;
+
+        for row in rows {
+            let converted = match converter.convert_cell(amount_column, row) {
+                Some((_, Some(c))) => c.amount,
+                _ => continue,
+            };
+            let probability = probability_column
+                .cell(&row.row_data)
+                .and_then(parse_percent)
+                .unwrap_or(0.0);
+
+            unweighted_total += converted;
+            weighted_total += converted * probability / 100.0;
+        }
+
+        ForecastSummary {
+            amount_column: amount_column.name.clone(),
+            probability_column: probability_column.name.clone(),
+            weighted_total,
+            unweighted_total,
+        }
+    });
+
+    SpreadsheetSummary {
+        spreadsheet_id: spreadsheet.id,
+        reporting_currency: converter.reporting_currency().to_string(),
+        as_of: converter.as_of,
+        row_count: rows.len(),
+        columns: column_summaries,
+        forecast,
+        rates_used: converter.rates_used(),
+    }
+}
+
+fn forecast_columns<'c>(
+    spreadsheet: &Spreadsheet,
+    columns: &'c [SpreadsheetColumn],
+) -> Option<(&'c SpreadsheetColumn, &'c SpreadsheetColumn)> {
+    let configured = spreadsheet.settings.as_ref().and_then(|s| s.get("forecast"));
+    let named = |key: &str| {
+        configured
+            .and_then(|f| f.get(key))
+            .and_then(|v| v.as_str())
+            .and_then(|name| columns.iter().find(|c| c.name == name))
+    };
+
+    let amount = named("amount_column")
+        .or_else(|| columns.iter().find(|c| c.column_type == ColumnType::Currency))?;
+    let probability = named("probability_column").or_else(|| {
+        columns
+            .iter()
+            .find(|c| c.column_type == ColumnType::Percent)
+            .or_else(|| columns.iter().find(|c| c.name == "Probability"))
+    })?;
+
+    Some((amount, probability))
+}
+
+fn parse_percent(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().trim_end_matches('%').trim().parse().ok(),
+        _ => None,
+    }
+}
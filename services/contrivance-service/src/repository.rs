@@ -1,12 +1,126 @@
 use common::{
     ContrivanceError, ContrivanceResult, Spreadsheet, SpreadsheetColumn, SpreadsheetRow,
     SpreadsheetDetails, CreateSpreadsheetRequest, UpdateSpreadsheetRequest,
-    CreateRowRequest, UpdateRowRequest,
+    CreateRowRequest, UpdateRowRequest, UpdateColumnRequest,
     UserResponse, PermissionLevel, PaginationParams, PaginatedResponse,
+    ExchangeRate, NewExchangeRate, SpreadsheetEvent, WebSocketMessage,
+    CreateWebhookRequest, UpdateWebhookRequest, WebhookSubscription, WebhookDelivery,
+    WebhookDeliveryStatus, AutomationRule, AutomationRun,
+    CreateAutomationRuleRequest, UpdateAutomationRuleRequest,
+    Notification, NotificationKind, NotificationPreference, SpreadsheetCollaborator,
+    DigestSchedule, Keyset, SortOrder,
+    Workspace, WorkspaceMember, WorkspaceRole, DefaultPermission, Folder, FolderSummary, Favorites,
+    CreateWorkspaceRequest, UpdateWorkspaceRequest, UpdateFolderRequest,
+    Organization, OrganizationMembership, OrganizationMember, OrganizationRole, OrganizationInvitation,
+    Team, TeamMember, TeamRole, CreateTeamRequest, UpdateTeamRequest, CellLock,
 };
-use sqlx::{PgPool, Row};
+use futures::{channel::mpsc, SinkExt, Stream, StreamExt};
+use sqlx::{postgres::{PgArguments, PgListener}, types::Json, Acquire, Arguments, PgPool, Postgres, Row};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::HashMap;
+
+use crate::notifications::NewNotification;
+use crate::email::{DigestSpreadsheetSummary, DigestTodoSummary, DueDigest, EmailRecipient};
+use crate::webhooks::{DeliveryOutcome, DueWebhookDelivery};
+use crate::indexing::{CellFilter, CellIndex};
+
+/// Rows buffered between the database and a slow reader when streaming
+const ROW_STREAM_BUFFER: usize = 256;
+
+const SPREADSHEET_FIELDS: &str =
+    "id, name, description, owner_id, created_at, updated_at, is_public, settings, workspace_id, folder_id, organization_id, team_id";
+
+const SPREADSHEET_FIELDS_S: &str =
+    "s.id, s.name, s.description, s.owner_id, s.created_at, s.updated_at, s.is_public, s.settings, s.workspace_id, s.folder_id, s.organization_id, s.team_id";
+
+/// Whether the user bound as `$1` can see spreadsheet `s`: it's in their
+/// organization, and they own it, it's public, they're an accepted
+/// collaborator, they get access through its team or as an organization admin,
+/// or its workspace location grants them access
+const VISIBLE_SPREADSHEET: &str = r#"(
+    s.organization_id = user_organization($1)
+    AND (
+        s.owner_id = $1
+        OR s.is_public = true
+        OR EXISTS (
+            SELECT 1 FROM spreadsheet_collaborators sc
+            WHERE sc.spreadsheet_id = s.id AND sc.user_id = $1 AND sc.accepted_at IS NOT NULL
+        )
+        OR tenant_permission($1, s.team_id) IS NOT NULL
+        OR (s.workspace_id IS NOT NULL AND workspace_permission($1, s.workspace_id, s.folder_id) IS NOT NULL)
+    )
+)"#;
+
+const WORKSPACE_FIELDS: &str = "id, name, owner_id, organization_id, default_permission, created_at, updated_at";
+
+const TEAM_FIELDS: &str = "id, organization_id, name, description, created_at, updated_at";
+
+/// An invitation `i` joined to its organization `o`
+const INVITATION_FIELDS: &str =
+    "i.id, i.organization_id, o.name AS organization_name, i.email, i.role, i.invited_by, i.created_at";
+
+const FOLDER_FIELDS: &str =
+    "id, workspace_id, parent_id, name, default_permission, created_by, created_at, updated_at";
+
+/// Queue a spreadsheet event ($2, $3) for the webhooks subscribed to it on
+/// spreadsheet $1; see `ContrivanceRepository::enqueue_spreadsheet_webhooks`
+const ENQUEUE_SPREADSHEET_WEBHOOKS: &str = r#"
+    INSERT INTO webhook_deliveries (subscription_id, event_type, payload)
+    SELECT w.id, $2, $3
+    FROM webhook_subscriptions w
+    WHERE w.is_active AND $2 = ANY(w.event_types)
+      AND EXISTS (
+        SELECT 1 FROM spreadsheets s
+        LEFT JOIN spreadsheet_collaborators sc
+            ON sc.spreadsheet_id = s.id AND sc.user_id = w.owner_id AND sc.accepted_at IS NOT NULL
+        WHERE s.id = $1 AND s.organization_id = user_organization(w.owner_id)
+          AND (w.spreadsheet_id = $1 OR w.spreadsheet_id IS NULL)
+          AND (
+            s.owner_id = w.owner_id OR s.is_public = true OR sc.user_id IS NOT NULL
+            OR tenant_permission(w.owner_id, s.team_id) IS NOT NULL
+            OR (s.workspace_id IS NOT NULL
+                AND workspace_permission(w.owner_id, s.workspace_id, s.folder_id) IS NOT NULL)
+          )
+      )
+"#;
+
+/// Columns of `common::Todo`, with its dependencies and checklist, selected
+/// from or returned by a statement on `todos`
+const TODO_FIELDS: &str = "id, title, description, priority, completed, created_at, updated_at, due_date, \
+    supporting_artifact, spreadsheet_id, row_id, user_id, assigned_to, \
+    series_id, recurrence, recurrence_timezone, series_start, scheduled_at, parent_id, \
+    ARRAY(SELECT d.blocked_by_id FROM todo_dependencies d WHERE d.todo_id = todos.id \
+          ORDER BY d.created_at, d.blocked_by_id) AS blocked_by, \
+    EXISTS (SELECT 1 FROM todo_dependencies d JOIN todos b ON b.id = d.blocked_by_id \
+            WHERE d.todo_id = todos.id AND NOT b.completed) AS blocked, \
+    COALESCE((SELECT json_agg(json_build_object('id', c.id, 'title', c.title, 'completed', c.completed, 'position', c.position) \
+                              ORDER BY c.position, c.created_at) \
+              FROM todo_checklist_items c WHERE c.todo_id = todos.id), '[]') AS checklist";
+
+/// Where a spreadsheet or folder is filed: a workspace, and a folder in it
+/// unless at the workspace root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkspaceLocation {
+    pub workspace_id: Uuid,
+    pub folder_id: Option<Uuid>,
+}
+
+/// Something a user can pin to their favorites
+#[derive(Debug, Clone, Copy)]
+pub enum FavoriteTarget {
+    Spreadsheet(Uuid),
+    Folder(Uuid),
+}
+
+impl FavoriteTarget {
+    fn ids(self) -> (Option<Uuid>, Option<Uuid>) {
+        match self {
+            FavoriteTarget::Spreadsheet(id) => (Some(id), None),
+            FavoriteTarget::Folder(id) => (None, Some(id)),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct ContrivanceRepository {
@@ -18,11 +132,19 @@ impl ContrivanceRepository {
         Self { pool }
     }
 
-    /// Create a new spreadsheet
+    /// Start a transaction for writes that must commit together, such as a
+    /// mutation and its change feed event
+    pub async fn begin(&self) -> ContrivanceResult<sqlx::Transaction<'static, Postgres>> {
+        Ok(self.pool.begin().await?)
+    }
+
+    /// Create a new spreadsheet in the owner's organization, optionally filed
+    /// in a workspace location
     pub async fn create_spreadsheet(
         &self, 
         request: &CreateSpreadsheetRequest, 
-        owner_id: Uuid
+        owner_id: Uuid,
+        location: Option<WorkspaceLocation>,
     ) -> ContrivanceResult<Spreadsheet> {
         let spreadsheet_id = Uuid::new_v4();
         let now = Utc::now();
@@ -30,22 +152,24 @@ impl ContrivanceRepository {
         let mut tx = self.pool.begin().await?;
 
         // Create spreadsheet
-        let spreadsheet = sqlx::query_as!(
-            Spreadsheet,
+        let spreadsheet = sqlx::query_as::<_, Spreadsheet>(&format!(
             r#"
-            INSERT INTO spreadsheets (id, name, description, owner_id, created_at, updated_at, is_public, settings)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, '{}'::jsonb))
-            RETURNING id, name, description, owner_id, created_at, updated_at, is_public, settings
-            "#,
-            spreadsheet_id,
-            request.name,
-            request.description,
-            owner_id,
-            now,
-            now,
-            request.is_public.unwrap_or(false),
-            request.settings.as_ref()
-        )
+            INSERT INTO spreadsheets (id, name, description, owner_id, created_at, updated_at, is_public, settings, workspace_id, folder_id, organization_id, team_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, '{{}}'::jsonb), $9, $10, user_organization($4), $11)
+            RETURNING {SPREADSHEET_FIELDS}
+            "#
+        ))
+        .bind(spreadsheet_id)
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(owner_id)
+        .bind(now)
+        .bind(now)
+        .bind(request.is_public.unwrap_or(false))
+        .bind(&request.settings)
+        .bind(location.map(|l| l.workspace_id))
+        .bind(location.and_then(|l| l.folder_id))
+        .bind(request.team_id)
         .fetch_one(&mut *tx)
         .await?;
 
@@ -82,11 +206,10 @@ impl ContrivanceRepository {
 
     /// Get spreadsheet by ID
     pub async fn get_spreadsheet(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Option<Spreadsheet>> {
-        let spreadsheet = sqlx::query_as!(
-            Spreadsheet,
-            "SELECT id, name, description, owner_id, created_at, updated_at, is_public, settings FROM spreadsheets WHERE id = $1",
-            spreadsheet_id
-        )
+        let spreadsheet = sqlx::query_as::<_, Spreadsheet>(&format!(
+            "SELECT {SPREADSHEET_FIELDS} FROM spreadsheets WHERE id = $1"
+        ))
+        .bind(spreadsheet_id)
         .fetch_optional(&self.pool)
         .await?;
 
@@ -95,6 +218,19 @@ impl ContrivanceRepository {
 
     /// Get spreadsheet with full details (columns, rows, collaborators)
     pub async fn get_spreadsheet_details(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Option<SpreadsheetDetails>> {
+        let Some(mut details) = self.get_spreadsheet_details_without_rows(spreadsheet_id).await? else {
+            return Ok(None);
+        };
+        details.rows = self.get_spreadsheet_rows(spreadsheet_id).await?;
+
+        Ok(Some(details))
+    }
+
+    /// Get spreadsheet details with `rows` left empty, for callers that stream the rows separately
+    pub async fn get_spreadsheet_details_without_rows(
+        &self,
+        spreadsheet_id: Uuid,
+    ) -> ContrivanceResult<Option<SpreadsheetDetails>> {
         // Get spreadsheet
         let spreadsheet = match self.get_spreadsheet(spreadsheet_id).await? {
             Some(s) => s,
@@ -116,153 +252,153 @@ impl ContrivanceRepository {
         // Get columns
         let columns = self.get_spreadsheet_columns(spreadsheet_id).await?;
 
-        // Get rows
-        let rows = self.get_spreadsheet_rows(spreadsheet_id, None).await?;
-
         // Get collaborators
         let collaborators = self.get_collaborators_with_user_info(spreadsheet_id).await?;
 
         Ok(Some(SpreadsheetDetails {
             spreadsheet,
             columns,
-            rows,
+            rows: Vec::new(),
             collaborators,
             owner: UserResponse::from(owner),
         }))
     }
 
-    /// List spreadsheets for a user
+    /// List spreadsheets for a user, most recently updated first
     pub async fn list_spreadsheets(
         &self, 
         user_id: Uuid, 
         pagination: &PaginationParams
     ) -> ContrivanceResult<PaginatedResponse<Spreadsheet>> {
-        let limit = pagination.limit.unwrap_or(20).min(100) as i64;
-        let offset = ((pagination.page.unwrap_or(1) - 1) * limit as u32) as i64;
+        self.list_visible_spreadsheets(user_id, pagination, None).await
+    }
+
+    /// List the spreadsheets a user can see, either all of them or those filed
+    /// directly in one workspace location
+    async fn list_visible_spreadsheets(
+        &self,
+        user_id: Uuid,
+        pagination: &PaginationParams,
+        location: Option<WorkspaceLocation>,
+    ) -> ContrivanceResult<PaginatedResponse<Spreadsheet>> {
+        let keyset = Keyset::<DateTime<Utc>>::from_params(pagination, 20, 100)?;
+        let (op, dir) = keyset.sql(SortOrder::Desc);
+        let workspace_id = location.map(|l| l.workspace_id);
+        let folder_id = location.and_then(|l| l.folder_id);
 
         // Get total count
-        let total: i64 = sqlx::query_scalar!(
+        let total: i64 = sqlx::query_scalar(&format!(
             r#"
-            SELECT COUNT(DISTINCT s.id) 
+            SELECT COUNT(*)
             FROM spreadsheets s
-            LEFT JOIN spreadsheet_collaborators sc ON s.id = sc.spreadsheet_id
-            WHERE s.owner_id = $1 
-               OR s.is_public = true 
-               OR (sc.user_id = $1 AND sc.accepted_at IS NOT NULL)
-            "#,
-            user_id
-        )
+            WHERE {VISIBLE_SPREADSHEET}
+              AND ($2::uuid IS NULL OR (s.workspace_id = $2 AND s.folder_id IS NOT DISTINCT FROM $3::uuid))
+            "#
+        ))
+        .bind(user_id)
+        .bind(workspace_id)
+        .bind(folder_id)
         .fetch_one(&self.pool)
-        .await?
-        .unwrap_or(0);
+        .await?;
 
         // Get spreadsheets
-        let spreadsheets = sqlx::query_as!(
-            Spreadsheet,
+        let spreadsheets = sqlx::query_as::<_, Spreadsheet>(&format!(
             r#"
-            SELECT DISTINCT s.id, s.name, s.description, s.owner_id, s.created_at, s.updated_at, s.is_public, s.settings
+            SELECT {SPREADSHEET_FIELDS_S}
             FROM spreadsheets s
-            LEFT JOIN spreadsheet_collaborators sc ON s.id = sc.spreadsheet_id
-            WHERE s.owner_id = $1 
-               OR s.is_public = true 
-               OR (sc.user_id = $1 AND sc.accepted_at IS NOT NULL)
-            ORDER BY s.updated_at DESC
-            LIMIT $2 OFFSET $3
-            "#,
-            user_id,
-            limit,
-            offset
-        )
+            WHERE {VISIBLE_SPREADSHEET}
+              AND ($6::uuid IS NULL OR (s.workspace_id = $6 AND s.folder_id IS NOT DISTINCT FROM $7::uuid))
+              AND ($2::timestamptz IS NULL OR (COALESCE(s.updated_at, 'epoch'), s.id) {op} ($2, $3))
+            ORDER BY COALESCE(s.updated_at, 'epoch') {dir}, s.id {dir}
+            LIMIT $4 OFFSET $5
+            "#
+        ))
+        .bind(user_id)
+        .bind(keyset.key())
+        .bind(keyset.id())
+        .bind(keyset.fetch_limit())
+        .bind(keyset.offset())
+        .bind(workspace_id)
+        .bind(folder_id)
         .fetch_all(&self.pool)
         .await?;
 
-        let page = pagination.page.unwrap_or(1);
-        let limit = limit as u32;
-        let total_pages = ((total as f64) / (limit as f64)).ceil() as u32;
-
-        Ok(PaginatedResponse {
-            data: spreadsheets,
-            total: total as u64,
-            page,
-            limit,
-            total_pages,
-            has_next: page < total_pages,
-            has_prev: page > 1,
-        })
+        Ok(keyset.into_response(spreadsheets, total as u64, |s| {
+            (s.updated_at.unwrap_or(DateTime::UNIX_EPOCH), s.id)
+        }))
     }
 
     /// Update spreadsheet
+    ///
+    /// `settings` is merged into the existing settings rather than replacing them.
     pub async fn update_spreadsheet(
         &self, 
+        conn: &mut sqlx::PgConnection,
         spreadsheet_id: Uuid, 
         request: &UpdateSpreadsheetRequest
     ) -> ContrivanceResult<Spreadsheet> {
-        let now = Utc::now();
-        
-        // Build dynamic update query
-        let mut set_clauses = vec!["updated_at = $1".to_string()];
-        let mut param_count = 1;
-        let mut bind_values: Vec<Box<dyn sqlx::Encode<sqlx::Postgres> + Send>> = vec![Box::new(now)];
-
-        if let Some(name) = &request.name {
-            param_count += 1;
-            set_clauses.push(format!("name = ${}", param_count));
-            bind_values.push(Box::new(name.clone()));
-        }
-
-        if let Some(description) = &request.description {
-            param_count += 1; 
-            set_clauses.push(format!("description = ${}", param_count));
-            bind_values.push(Box::new(description.clone()));
+        if request.name.is_none()
+            && request.description.is_none()
+            && request.is_public.is_none()
+            && request.settings.is_none()
+        {
+            return Err(ContrivanceError::validation("At least one field must be provided for update"));
         }
 
-        if let Some(is_public) = request.is_public {
-            param_count += 1;
-            set_clauses.push(format!("is_public = ${}", param_count));
-            bind_values.push(Box::new(is_public));
-        }
+        let spreadsheet = sqlx::query_as::<_, Spreadsheet>(&format!(
+            r#"
+            UPDATE spreadsheets
+            SET name = COALESCE($1, name),
+                description = COALESCE($2, description),
+                is_public = COALESCE($3, is_public),
+                settings = CASE WHEN $4::jsonb IS NULL THEN settings ELSE COALESCE(settings, '{{}}'::jsonb) || $4::jsonb END,
+                updated_at = $5
+            WHERE id = $6
+            RETURNING {SPREADSHEET_FIELDS}
+            "#
+        ))
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(request.is_public)
+        .bind(&request.settings)
+        .bind(Utc::now())
+        .bind(spreadsheet_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Spreadsheet not found"))?;
 
-        if let Some(settings) = &request.settings {
-            param_count += 1;
-            set_clauses.push(format!("settings = ${}", param_count));
-            bind_values.push(Box::new(settings.clone()));
-        }
+        Ok(spreadsheet)
+    }
 
-        param_count += 1;
-        let where_clause = format!("WHERE id = ${}", param_count);
-        bind_values.push(Box::new(spreadsheet_id));
+    /// Delete spreadsheet. Deliveries of `event` to the webhooks that outlive
+    /// it are queued in the same transaction, so they only go out if it's deleted.
+    pub async fn delete_spreadsheet(
+        &self,
+        spreadsheet_id: Uuid,
+        event: (&str, &serde_json::Value),
+    ) -> ContrivanceResult<()> {
+        let mut tx = self.pool.begin().await?;
 
-        // For simplicity, handle the most common case
-        if let Some(name) = &request.name {
-            let spreadsheet = sqlx::query_as!(
-                Spreadsheet,
-                "UPDATE spreadsheets SET name = $1, updated_at = $2 WHERE id = $3 RETURNING id, name, description, owner_id, created_at, updated_at, is_public, settings",
-                name,
-                now,
-                spreadsheet_id
-            )
-            .fetch_one(&self.pool)
+        let (event_type, payload) = event;
+        sqlx::query(ENQUEUE_SPREADSHEET_WEBHOOKS)
+            .bind(spreadsheet_id)
+            .bind(event_type)
+            .bind(payload)
+            .execute(&mut *tx)
             .await?;
-            
-            return Ok(spreadsheet);
-        }
 
-        Err(ContrivanceError::validation("At least one field must be provided for update"))
-    }
-
-    /// Delete spreadsheet
-    pub async fn delete_spreadsheet(&self, spreadsheet_id: Uuid) -> ContrivanceResult<()> {
         let result = sqlx::query!(
             "DELETE FROM spreadsheets WHERE id = $1",
             spreadsheet_id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
 
         if result.rows_affected() == 0 {
             return Err(ContrivanceError::not_found("Spreadsheet not found"));
         }
 
+        tx.commit().await?;
         Ok(())
     }
 
@@ -287,13 +423,14 @@ impl ContrivanceRepository {
     /// Add columns to an existing spreadsheet
     pub async fn add_columns(
         &self,
+        conn: &mut sqlx::PgConnection,
         spreadsheet_id: Uuid,
         columns: Vec<common::CreateColumnRequest>,
     ) -> ContrivanceResult<Vec<SpreadsheetColumn>> {
         let now = Utc::now();
         let mut created_columns = Vec::new();
 
-        let mut tx = self.pool.begin().await?;
+        let mut tx = conn.begin().await?;
 
         // Verify spreadsheet exists
         let result = sqlx::query("SELECT EXISTS(SELECT 1 FROM spreadsheets WHERE id = $1)")
@@ -350,53 +487,230 @@ impl ContrivanceRepository {
         Ok(created_columns)
     }
 
-    /// Get spreadsheet rows
-    pub async fn get_spreadsheet_rows(
-        &self, 
-        spreadsheet_id: Uuid, 
-        pagination: Option<&PaginationParams>
-    ) -> ContrivanceResult<Vec<SpreadsheetRow>> {
-        let (limit, offset) = if let Some(p) = pagination {
-            let limit = p.limit.unwrap_or(1000).min(1000) as i64;
-            let offset = ((p.page.unwrap_or(1) - 1) * limit as u32) as i64;
-            (Some(limit), Some(offset))
-        } else {
-            (None, None)
-        };
+    /// Update a column's name, position, rules or display options
+    ///
+    /// `display_options` is merged into the existing options rather than replacing them.
+    pub async fn update_column(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        spreadsheet_id: Uuid,
+        column_id: Uuid,
+        request: &UpdateColumnRequest,
+    ) -> ContrivanceResult<SpreadsheetColumn> {
+        if request.name.is_none()
+            && request.position.is_none()
+            && request.is_required.is_none()
+            && request.default_value.is_none()
+            && request.validation_rules.is_none()
+            && request.display_options.is_none()
+        {
+            return Err(ContrivanceError::validation("At least one field must be provided for update"));
+        }
 
-        let rows = if let (Some(limit), Some(offset)) = (limit, offset) {
-            sqlx::query_as!(
-                SpreadsheetRow,
-                "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE spreadsheet_id = $1 ORDER BY position LIMIT $2 OFFSET $3",
-                spreadsheet_id,
-                limit,
-                offset
-            )
-            .fetch_all(&self.pool)
-            .await?
-        } else {
-            sqlx::query_as!(
-                SpreadsheetRow,
-                "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE spreadsheet_id = $1 ORDER BY position",
-                spreadsheet_id
+        let column = sqlx::query_as::<_, SpreadsheetColumn>(
+            r#"
+            UPDATE spreadsheet_columns
+            SET name = COALESCE($1, name),
+                position = COALESCE($2, position),
+                is_required = COALESCE($3, is_required),
+                default_value = COALESCE($4, default_value),
+                validation_rules = COALESCE($5, validation_rules),
+                display_options = CASE WHEN $6::jsonb IS NULL THEN display_options ELSE COALESCE(display_options, '{}'::jsonb) || $6::jsonb END,
+                updated_at = $7
+            WHERE id = $8 AND spreadsheet_id = $9
+            RETURNING id, spreadsheet_id, name, column_type, position, is_required, default_value, validation_rules, display_options, created_at, updated_at
+            "#
+        )
+        .bind(&request.name)
+        .bind(request.position)
+        .bind(request.is_required)
+        .bind(&request.default_value)
+        .bind(&request.validation_rules)
+        .bind(&request.display_options)
+        .bind(Utc::now())
+        .bind(column_id)
+        .bind(spreadsheet_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Column not found"))?;
+
+        Ok(column)
+    }
+
+    /// Delete a column along with its cells
+    pub async fn delete_column(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        spreadsheet_id: Uuid,
+        column_id: Uuid,
+    ) -> ContrivanceResult<()> {
+        let mut tx = conn.begin().await?;
+
+        let result = sqlx::query("DELETE FROM spreadsheet_columns WHERE id = $1 AND spreadsheet_id = $2")
+            .bind(column_id)
+            .bind(spreadsheet_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ContrivanceError::not_found("Column not found"));
+        }
+
+        sqlx::query("UPDATE spreadsheet_rows SET row_data = row_data - $1 WHERE spreadsheet_id = $2 AND row_data ? $1")
+            .bind(column_id.to_string())
+            .bind(spreadsheet_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Get every row of a spreadsheet in position order
+    pub async fn get_spreadsheet_rows(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<SpreadsheetRow>> {
+        let rows = sqlx::query_as!(
+            SpreadsheetRow,
+            "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE spreadsheet_id = $1 ORDER BY position",
+            spreadsheet_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows)
+    }
+
+    /// Stream every row of a spreadsheet in position order without loading
+    /// them all into memory. Rows are read on a background task and handed
+    /// over through a small buffer, so a slow reader holds back the query
+    /// rather than the other way round. Dropping the stream stops the query.
+    pub fn stream_spreadsheet_rows(
+        &self,
+        spreadsheet_id: Uuid,
+    ) -> impl Stream<Item = ContrivanceResult<SpreadsheetRow>> + Send + 'static {
+        let (mut tx, rx) = mpsc::channel(ROW_STREAM_BUFFER);
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut rows = sqlx::query_as::<_, SpreadsheetRow>(
+                "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE spreadsheet_id = $1 ORDER BY position, id",
             )
-            .fetch_all(&self.pool)
-            .await?
+            .bind(spreadsheet_id)
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row.map_err(ContrivanceError::from)).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        rx
+    }
+
+    /// Get one page of a spreadsheet's rows, keyed on (position, id), with
+    /// only the rows matching every filter
+    pub async fn list_spreadsheet_rows(
+        &self,
+        spreadsheet_id: Uuid,
+        pagination: &PaginationParams,
+        filters: &[CellFilter],
+    ) -> ContrivanceResult<PaginatedResponse<SpreadsheetRow>> {
+        let keyset = Keyset::<i32>::from_params(pagination, 1000, 1000)?;
+        let (op, dir) = keyset.sql(SortOrder::Asc);
+
+        // Cell indexes are partial on the spreadsheet, and the planner can
+        // only use them when the id is a literal rather than a parameter
+        let filter_sql = |first_param: usize| {
+            if filters.is_empty() {
+                return String::new();
+            }
+            let conditions: Vec<String> = filters
+                .iter()
+                .enumerate()
+                .map(|(i, filter)| filter.sql(first_param + i))
+                .collect();
+            format!("AND spreadsheet_id = '{}' AND {}", spreadsheet_id, conditions.join(" AND "))
         };
 
-        Ok(rows)
+        let mut args = PgArguments::default();
+        args.add(spreadsheet_id);
+        for filter in filters {
+            filter.value.add_to(&mut args);
+        }
+        let total: i64 = sqlx::query_scalar_with(
+            &format!("SELECT COUNT(*) FROM spreadsheet_rows WHERE spreadsheet_id = $1 {}", filter_sql(2)),
+            args,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let mut args = PgArguments::default();
+        args.add(spreadsheet_id);
+        args.add(keyset.key());
+        args.add(keyset.id());
+        args.add(keyset.fetch_limit());
+        args.add(keyset.offset());
+        for filter in filters {
+            filter.value.add_to(&mut args);
+        }
+        let rows = sqlx::query_as_with::<_, SpreadsheetRow, _>(
+            &format!(
+                r#"
+                SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by
+                FROM spreadsheet_rows
+                WHERE spreadsheet_id = $1
+                  AND ($2::int IS NULL OR (position, id) {op} ($2, $3))
+                  {filters}
+                ORDER BY position {dir}, id {dir}
+                LIMIT $4 OFFSET $5
+                "#,
+                filters = filter_sql(6),
+            ),
+            args,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keyset.into_response(rows, total as u64, |row| (row.position, row.id)))
+    }
+
+    pub async fn count_spreadsheet_rows(&self, spreadsheet_id: Uuid) -> ContrivanceResult<i64> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM spreadsheet_rows WHERE spreadsheet_id = $1")
+            .bind(spreadsheet_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// The currency a spreadsheet's bare amounts are taken to be in
+    async fn reporting_currency(&self, spreadsheet_id: Uuid) -> ContrivanceResult<String> {
+        Ok(self
+            .get_spreadsheet(spreadsheet_id)
+            .await?
+            .map(|spreadsheet| spreadsheet.reporting_currency())
+            .unwrap_or_else(|| common::currency::DEFAULT_REPORTING_CURRENCY.to_string()))
     }
 
     /// Create spreadsheet row
     pub async fn create_row(
         &self, 
+        conn: &mut sqlx::PgConnection,
         spreadsheet_id: Uuid, 
         request: &CreateRowRequest, 
         user_id: Uuid
     ) -> ContrivanceResult<SpreadsheetRow> {
         let row_id = Uuid::new_v4();
         let now = Utc::now();
-        
+
+        // Store cells under column ids and validate them against the column types
+        let columns = self.get_spreadsheet_columns(spreadsheet_id).await?;
+        let mut row_data = common::cells_by_id(&columns, &request.row_data)?;
+        let people = common::validate_row_data(&columns, &row_data, None)?;
+        self.ensure_users_exist(spreadsheet_id, &people).await?;
+        common::normalize_row_data(&columns, &mut row_data, &self.reporting_currency(spreadsheet_id).await?);
+
         // Get next position if not specified
         let position = if let Some(pos) = request.position {
             pos
@@ -405,7 +719,7 @@ impl ContrivanceRepository {
                 "SELECT MAX(position) FROM spreadsheet_rows WHERE spreadsheet_id = $1",
                 spreadsheet_id
             )
-            .fetch_one(&self.pool)
+            .fetch_one(&mut *conn)
             .await?;
             
             max_position.unwrap_or(0) + 1
@@ -416,53 +730,193 @@ impl ContrivanceRepository {
             "INSERT INTO spreadsheet_rows (id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by",
             row_id,
             spreadsheet_id,
-            request.row_data,
+            row_data,
             position,
             now,
             now,
             user_id,
             user_id
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(row)
+    }
+
+    /// Get a single spreadsheet row
+    pub async fn get_row(&self, row_id: Uuid) -> ContrivanceResult<Option<SpreadsheetRow>> {
+        let row = sqlx::query_as::<_, SpreadsheetRow>(
+            "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE id = $1"
+        )
+        .bind(row_id)
+        .fetch_optional(&self.pool)
         .await?;
 
         Ok(row)
     }
 
-    /// Update spreadsheet row
+    /// Verify that every user referenced by a Person cell, todo or automation
+    /// exists, is active and is in the spreadsheet's organization
+    pub async fn ensure_users_exist(&self, spreadsheet_id: Uuid, user_ids: &[Uuid]) -> ContrivanceResult<()> {
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+
+        let found: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT u.id
+            FROM users u
+            JOIN organization_members m ON m.user_id = u.id
+            JOIN spreadsheets s ON s.organization_id = m.organization_id
+            WHERE u.id = ANY($1) AND u.is_active = true AND s.id = $2
+            "#
+        )
+        .bind(user_ids)
+        .bind(spreadsheet_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        match user_ids.iter().find(|id| !found.contains(id)) {
+            Some(missing) => Err(ContrivanceError::validation(format!("Unknown user: {}", missing))),
+            None => Ok(()),
+        }
+    }
+
+    /// Get display names for a set of users, keyed by user id
+    pub async fn get_user_names(&self, user_ids: &[Uuid]) -> ContrivanceResult<HashMap<Uuid, String>> {
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let rows = sqlx::query("SELECT id, name FROM users WHERE id = ANY($1)")
+            .bind(user_ids)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get::<Uuid, _>("id"), row.get::<String, _>("name")))
+            .collect())
+    }
+
+    /// Update spreadsheet row. Cells someone else has locked can't be changed.
     pub async fn update_row(
-        &self, 
-        row_id: Uuid, 
-        request: &UpdateRowRequest, 
-        user_id: Uuid
+        &self,
+        conn: &mut sqlx::PgConnection,
+        row_id: Uuid,
+        request: &UpdateRowRequest,
+        user_id: Uuid,
     ) -> ContrivanceResult<SpreadsheetRow> {
-        let now = Utc::now();
+        let Some(row_data) = &request.row_data else {
+            return Err(ContrivanceError::validation("At least one field must be provided for update"));
+        };
+        let spreadsheet_id = self.get_row(row_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Row not found"))?
+            .spreadsheet_id;
+        let columns = self.get_spreadsheet_columns(spreadsheet_id).await?;
+        let mut row_data = common::cells_by_id(&columns, row_data)?;
+        let reporting_currency = self.reporting_currency(spreadsheet_id).await?;
 
-        if let Some(row_data) = &request.row_data {
-            let row = sqlx::query_as!(
-                SpreadsheetRow,
-                "UPDATE spreadsheet_rows SET row_data = $1, updated_at = $2, updated_by = $3 WHERE id = $4 RETURNING id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by",
-                row_data,
-                now,
-                user_id,
-                row_id
-            )
-            .fetch_one(&self.pool)
-            .await?;
-            
-            return Ok(row);
+        // Holding the row keeps locks from being taken between the check and
+        // the write; lock_cell waits on it too
+        let mut tx = conn.begin().await?;
+        let existing: serde_json::Value = sqlx::query_scalar(
+            "SELECT row_data FROM spreadsheet_rows WHERE id = $1 FOR UPDATE"
+        )
+        .bind(row_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Row not found"))?;
+
+        // Validate changed cells against the column types
+        let people = common::validate_row_data(&columns, &row_data, Some(&existing))?;
+        self.ensure_users_exist(spreadsheet_id, &people).await?;
+        common::normalize_row_data(&columns, &mut row_data, &reporting_currency);
+
+        // Cells open in someone else's editor can't be changed
+        let locks = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+            SELECT l.column_id, u.name
+            FROM cell_locks l
+            JOIN users u ON u.id = l.user_id
+            WHERE l.row_id = $1 AND l.user_id <> $2 AND l.expires_at > NOW()
+            FOR SHARE OF l
+            "#
+        )
+        .bind(row_id)
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        check_cell_locks(&columns, &locks, &existing, &row_data)?;
+
+        let row = sqlx::query_as::<_, SpreadsheetRow>(
+            r#"
+            UPDATE spreadsheet_rows SET row_data = $1, updated_at = NOW(), updated_by = $2
+            WHERE id = $3
+            RETURNING id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by
+            "#
+        )
+        .bind(&row_data)
+        .bind(user_id)
+        .bind(row_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(row)
+    }
+
+    /// Set one cell of a row, leaving the others as they are now rather than as
+    /// the caller last saw them. The value is checked and stored like a row write.
+    pub async fn update_cell(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        row_id: Uuid,
+        column: &SpreadsheetColumn,
+        value: serde_json::Value,
+        user_id: Uuid,
+    ) -> ContrivanceResult<SpreadsheetRow> {
+        column.validate_cell(&value)?;
+        if column.column_type == common::ColumnType::Person {
+            let people = common::person_ids(&value).unwrap_or_default();
+            self.ensure_users_exist(column.spreadsheet_id, &people).await?;
         }
+        let value = column.normalize_cell(value, &self.reporting_currency(column.spreadsheet_id).await?);
+
+        // A cell still keyed by the column's name is replaced by the id-keyed one
+        let row = sqlx::query_as::<_, SpreadsheetRow>(
+            r#"
+            UPDATE spreadsheet_rows
+            SET row_data = jsonb_set(row_data - $3, ARRAY[$2], $4), updated_at = NOW(), updated_by = $5
+            WHERE id = $1 AND spreadsheet_id = $6
+            RETURNING id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by
+            "#
+        )
+        .bind(row_id)
+        .bind(column.key())
+        .bind(&column.name)
+        .bind(&value)
+        .bind(user_id)
+        .bind(column.spreadsheet_id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Row not found"))?;
 
-        Err(ContrivanceError::validation("At least one field must be provided for update"))
+        Ok(row)
     }
 
     /// Delete spreadsheet row
-    pub async fn delete_row(&self, row_id: Uuid) -> ContrivanceResult<()> {
+    pub async fn delete_row(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        row_id: Uuid,
+    ) -> ContrivanceResult<()> {
         let result = sqlx::query!(
             "DELETE FROM spreadsheet_rows WHERE id = $1",
             row_id
         )
-        .execute(&self.pool)
+        .execute(&mut *conn)
         .await?;
 
         if result.rows_affected() == 0 {
@@ -472,385 +926,3917 @@ impl ContrivanceRepository {
         Ok(())
     }
 
-    /// Get collaborators with user information
-    pub async fn get_collaborators_with_user_info(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<common::CollaboratorInfo>> {
-        let collaborators = sqlx::query!(
+    /// Append an event to a spreadsheet's change feed and return its sequence number
+    pub async fn append_event(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        spreadsheet_id: Uuid,
+        actor_id: Option<Uuid>,
+        message: &WebSocketMessage,
+    ) -> ContrivanceResult<i64> {
+        let payload = serde_json::to_value(message)
+            .map_err(|e| ContrivanceError::serialization(e.to_string()))?;
+
+        // The counter row lock serializes writers, so events commit in sequence order
+        let seq: i64 = sqlx::query_scalar(
             r#"
-            SELECT 
-                sc.permission_level as "permission_level: PermissionLevel",
-                sc.invited_at,
-                sc.accepted_at,
-                u.id as user_id,
-                u.email,
-                u.name,
-                u.role as "role: common::UserRole",
-                u.created_at as user_created_at,
-                u.last_login,
-                u.is_active
-            FROM spreadsheet_collaborators sc
-            JOIN users u ON sc.user_id = u.id
-            WHERE sc.spreadsheet_id = $1 AND sc.accepted_at IS NOT NULL
-            ORDER BY sc.invited_at
-            "#,
-            spreadsheet_id
+            WITH next AS (
+                INSERT INTO spreadsheet_event_sequences (spreadsheet_id, last_seq)
+                VALUES ($1, 1)
+                ON CONFLICT (spreadsheet_id)
+                DO UPDATE SET last_seq = spreadsheet_event_sequences.last_seq + 1
+                RETURNING last_seq
+            )
+            INSERT INTO spreadsheet_events (spreadsheet_id, seq, event_type, payload, actor_id)
+            SELECT $1, last_seq, $2, $3, $4 FROM next
+            RETURNING seq
+            "#
         )
-        .fetch_all(&self.pool)
+        .bind(spreadsheet_id)
+        .bind(message.event_type())
+        .bind(payload)
+        .bind(actor_id)
+        .fetch_one(&mut *conn)
         .await?;
 
-        let collaborator_infos = collaborators
-            .into_iter()
-            .map(|row| {
-                let user = UserResponse {
-                    id: row.user_id,
-                    email: row.email,
-                    name: row.name,
-                    role: row.role,
-                    created_at: row.user_created_at.unwrap_or_else(Utc::now),
-                    last_login: row.last_login,
-                    is_active: row.is_active.unwrap_or(true),
-                };
+        Ok(seq)
+    }
 
-                common::CollaboratorInfo {
-                    user,
-                    permission_level: row.permission_level,
-                    invited_at: row.invited_at.unwrap_or_else(Utc::now),
-                    accepted_at: row.accepted_at,
-                }
-            })
-            .collect();
+    /// Get change feed events after `since`, oldest first
+    pub async fn get_events_since(
+        &self,
+        spreadsheet_id: Uuid,
+        since: i64,
+        limit: i64,
+    ) -> ContrivanceResult<Vec<SpreadsheetEvent>> {
+        let events = sqlx::query_as::<_, SpreadsheetEvent>(
+            r#"
+            SELECT id, spreadsheet_id, seq, event_type, payload, actor_id, created_at
+            FROM spreadsheet_events
+            WHERE spreadsheet_id = $1 AND seq > $2
+            ORDER BY seq
+            LIMIT $3
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
 
-        Ok(collaborator_infos)
+        Ok(events)
     }
 
-    /// Check if user can access spreadsheet
-    pub async fn can_user_access_spreadsheet(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<bool> {
-        let count: i64 = sqlx::query_scalar!(
-            r#"
-            SELECT COUNT(*) FROM spreadsheets s
-            LEFT JOIN spreadsheet_collaborators sc ON s.id = sc.spreadsheet_id
-            WHERE s.id = $1 AND (
-                s.owner_id = $2 
-                OR s.is_public = true 
-                OR (sc.user_id = $2 AND sc.accepted_at IS NOT NULL)
-            )
-            "#,
-            spreadsheet_id,
-            user_id
-        )
-        .fetch_one(&self.pool)
-        .await?
-        .unwrap_or(0);
+    /// Send a notification to every session listening on a channel
+    pub async fn notify(&self, channel: &str, payload: &str) -> ContrivanceResult<()> {
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(channel)
+            .bind(payload)
+            .execute(&self.pool)
+            .await?;
 
-        Ok(count > 0)
+        Ok(())
     }
 
-    /// Check if user can edit spreadsheet
-    pub async fn can_user_edit_spreadsheet(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<bool> {
-        let count: i64 = sqlx::query_scalar!(
-            r#"
-            SELECT COUNT(*) FROM spreadsheets s
-            LEFT JOIN spreadsheet_collaborators sc ON s.id = sc.spreadsheet_id
-            WHERE s.id = $1 AND (
-                s.owner_id = $2 
-                OR (sc.user_id = $2 AND sc.accepted_at IS NOT NULL AND sc.permission_level IN ('edit', 'admin'))
-            )
-            "#,
-            spreadsheet_id,
-            user_id
-        )
-        .fetch_one(&self.pool)
-        .await?
-        .unwrap_or(0);
+    /// Open a dedicated connection listening on a channel
+    pub async fn listen(&self, channel: &str) -> ContrivanceResult<PgListener> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(channel).await?;
 
-        Ok(count > 0)
+        Ok(listener)
     }
 
-    /// Create a new todo
-    pub async fn create_todo(
+    /// Store a WebSocket ticket, clearing out lapsed ones
+    pub async fn create_socket_ticket(
         &self,
-        request: &common::CreateTodoRequest,
+        ticket_hash: &str,
         user_id: Uuid,
-    ) -> ContrivanceResult<common::Todo> {
-        let todo_id = Uuid::new_v4();
-        let now = Utc::now();
-
-        let todo = sqlx::query_as!(
-            common::Todo,
-            r#"
-            INSERT INTO todos (id, title, description, priority, completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id, user_id, assigned_to)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
-            RETURNING id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            "#,
-            todo_id,
-            request.title,
-            request.description,
-            request.priority.clone() as common::TodoPriority,
-            false, // Always start as not completed
-            now,
-            now,
-            request.due_date,
-            request.supporting_artifact,
-            request.spreadsheet_id,
-            request.row_id,
-            user_id,
-            request.assigned_to
+        expires_at: DateTime<Utc>,
+        session_expires_at: DateTime<Utc>,
+    ) -> ContrivanceResult<()> {
+        sqlx::query("DELETE FROM websocket_tickets WHERE expires_at < NOW()")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            "INSERT INTO websocket_tickets (ticket_hash, user_id, expires_at, session_expires_at) VALUES ($1, $2, $3, $4)"
         )
-        .fetch_one(&self.pool)
+        .bind(ticket_hash)
+        .bind(user_id)
+        .bind(expires_at)
+        .bind(session_expires_at)
+        .execute(&self.pool)
         .await?;
 
-        Ok(todo)
+        Ok(())
     }
 
-    /// Get all todos for a user (assigned to or created by user)
-    pub async fn get_todos_for_user(
-        &self,
-        user_id: Uuid,
-    ) -> ContrivanceResult<Vec<common::Todo>> {
-        let todos = sqlx::query_as!(
-            common::Todo,
-            r#"
-            SELECT id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            FROM todos
-            WHERE user_id = $1 OR assigned_to = $1
-            ORDER BY created_at DESC
-            "#,
-            user_id
+    /// Use up a WebSocket ticket, returning its user and when their session
+    /// ends. `None` if it doesn't exist, was already used or has lapsed.
+    pub async fn redeem_socket_ticket(&self, ticket_hash: &str) -> ContrivanceResult<Option<(Uuid, DateTime<Utc>)>> {
+        let redeemed = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+            "DELETE FROM websocket_tickets WHERE ticket_hash = $1 AND expires_at > NOW() RETURNING user_id, session_expires_at"
         )
-        .fetch_all(&self.pool)
+        .bind(ticket_hash)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(todos)
+        Ok(redeemed)
     }
 
-    /// Get todos for a spreadsheet (pipeline-level) - show todos assigned to or created by user
-    pub async fn get_todos_by_spreadsheet(
+    /// Lock a cell for a user's socket, or renew the lock if it's already
+    /// theirs. Returns whoever holds the cell afterwards: the user if they got
+    /// it, otherwise the holder of the unexpired lock that stopped them.
+    pub async fn lock_cell(
         &self,
         spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
         user_id: Uuid,
-    ) -> ContrivanceResult<Vec<common::Todo>> {
-        let todos = sqlx::query_as!(
-            common::Todo,
+        connection_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> ContrivanceResult<CellLock> {
+        let mut tx = self.pool.begin().await?;
+
+        // Waits out a row write that's checking locks, so it can't miss this one
+        sqlx::query("SELECT 1 FROM spreadsheet_rows WHERE id = $1 FOR SHARE")
+            .bind(row_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
             r#"
-            SELECT id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            FROM todos
-            WHERE spreadsheet_id = $1 AND (user_id = $2 OR assigned_to = $2) AND row_id IS NULL
-            ORDER BY created_at DESC
-            "#,
-            spreadsheet_id,
-            user_id
+            INSERT INTO cell_locks (row_id, column_id, spreadsheet_id, user_id, connection_id, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (row_id, column_id) DO UPDATE
+            SET user_id = EXCLUDED.user_id, connection_id = EXCLUDED.connection_id, expires_at = EXCLUDED.expires_at
+            WHERE cell_locks.user_id = EXCLUDED.user_id OR cell_locks.expires_at <= NOW()
+            "#
         )
-        .fetch_all(&self.pool)
+        .bind(row_id)
+        .bind(column_id)
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .bind(connection_id)
+        .bind(expires_at)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(todos)
+        let lock = sqlx::query_as::<_, CellLock>(
+            r#"
+            SELECT l.row_id, l.column_id, l.user_id, u.name AS user_name, l.expires_at
+            FROM cell_locks l
+            JOIN users u ON u.id = l.user_id
+            WHERE l.row_id = $1 AND l.column_id = $2
+            "#
+        )
+        .bind(row_id)
+        .bind(column_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(lock)
     }
 
-    /// Get todos for a specific row - show todos assigned to or created by user
-    pub async fn get_todos_by_row(
-        &self,
-        spreadsheet_id: Uuid,
-        row_id: Uuid,
-        user_id: Uuid,
-    ) -> ContrivanceResult<Vec<common::Todo>> {
-        let todos = sqlx::query_as!(
-            common::Todo,
-            r#"
-            SELECT id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            FROM todos
-            WHERE spreadsheet_id = $1 AND row_id = $2 AND (user_id = $3 OR assigned_to = $3)
-            ORDER BY created_at DESC
-            "#,
-            spreadsheet_id,
-            row_id,
-            user_id
+    /// Release a user's lock on a cell; false if they didn't hold it
+    pub async fn unlock_cell(&self, row_id: Uuid, column_id: Uuid, user_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM cell_locks WHERE row_id = $1 AND column_id = $2 AND user_id = $3")
+            .bind(row_id)
+            .bind(column_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Release every lock a socket holds, returning the (spreadsheet, row,
+    /// column) of each one still in force
+    pub async fn release_cell_locks(&self, connection_id: Uuid) -> ContrivanceResult<Vec<(Uuid, Uuid, Uuid)>> {
+        let released = sqlx::query_as::<_, (Uuid, Uuid, Uuid, DateTime<Utc>)>(
+            "DELETE FROM cell_locks WHERE connection_id = $1 RETURNING spreadsheet_id, row_id, column_id, expires_at"
         )
+        .bind(connection_id)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(todos)
+        let now = Utc::now();
+        Ok(released
+            .into_iter()
+            .filter(|(_, _, _, expires_at)| *expires_at > now)
+            .map(|(spreadsheet_id, row_id, column_id, _)| (spreadsheet_id, row_id, column_id))
+            .collect())
     }
 
-    /// Get todo by ID - allow access if user created or is assigned to the todo
-    pub async fn get_todo_by_id(
-        &self,
-        todo_id: Uuid,
-        user_id: Uuid,
-    ) -> ContrivanceResult<Option<common::Todo>> {
-        let todo = sqlx::query_as!(
-            common::Todo,
+    /// Unexpired locks on a spreadsheet's cells, or just one row's
+    pub async fn get_cell_locks(&self, spreadsheet_id: Uuid, row_id: Option<Uuid>) -> ContrivanceResult<Vec<CellLock>> {
+        let locks = sqlx::query_as::<_, CellLock>(
             r#"
-            SELECT id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            FROM todos
-            WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
-            "#,
-            todo_id,
-            user_id
+            SELECT l.row_id, l.column_id, l.user_id, u.name AS user_name, l.expires_at
+            FROM cell_locks l
+            JOIN users u ON u.id = l.user_id
+            WHERE l.spreadsheet_id = $1 AND ($2::uuid IS NULL OR l.row_id = $2) AND l.expires_at > NOW()
+            ORDER BY l.row_id, l.column_id
+            "#
         )
-        .fetch_optional(&self.pool)
+        .bind(spreadsheet_id)
+        .bind(row_id)
+        .fetch_all(&self.pool)
         .await?;
 
-        Ok(todo)
+        Ok(locks)
     }
 
-    /// Update a todo - allow updates if user created or is assigned to the todo
-    pub async fn update_todo(
+    /// Columns of a row whose cells have a collaborative text document
+    pub async fn get_cell_document_columns(&self, row_id: Uuid) -> ContrivanceResult<Vec<Uuid>> {
+        let columns = sqlx::query_scalar("SELECT column_id FROM cell_documents WHERE row_id = $1")
+            .bind(row_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(columns)
+    }
+
+    /// Change a cell's collaborative text document, holding the row so edits
+    /// to it are merged one at a time. `edit` is given the stored state, if
+    /// the cell has a document yet, and the cell's current value, and returns
+    /// the state and text to save or `None` to leave both as they are. The
+    /// text is validated and written to the cell when it differs. Returns the
+    /// row before and after.
+    pub async fn edit_cell_document<F>(
         &self,
-        todo_id: Uuid,
-        request: &common::UpdateTodoRequest,
+        conn: &mut sqlx::PgConnection,
+        row_id: Uuid,
+        column_id: Uuid,
         user_id: Uuid,
-    ) -> ContrivanceResult<Option<common::Todo>> {
-        // Handle assignment updates
-        if let Some(assigned_to) = request.assigned_to {
-            let todo = sqlx::query_as!(
-                common::Todo,
-                r#"
-                UPDATE todos
-                SET assigned_to = $3, updated_at = CURRENT_TIMESTAMP
-                WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
-                RETURNING id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-                "#,
-                todo_id,
-                user_id,
-                assigned_to
-            )
-            .fetch_optional(&self.pool)
-            .await?;
-            
-            return Ok(todo);
-        }
+        edit: F,
+    ) -> ContrivanceResult<(SpreadsheetRow, SpreadsheetRow)>
+    where
+        F: FnOnce(Option<&[u8]>, &serde_json::Value) -> ContrivanceResult<Option<(Vec<u8>, String)>>,
+    {
+        let mut tx = conn.begin().await?;
 
-        // Handle completion status updates
-        if let Some(completed) = request.completed {
-            return self.update_todo_completion(todo_id, completed, user_id).await;
-        }
+        let row = sqlx::query_as::<_, SpreadsheetRow>(
+            "SELECT id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by FROM spreadsheet_rows WHERE id = $1 FOR UPDATE"
+        )
+        .bind(row_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Row not found"))?;
 
-        // Handle title updates
-        if let Some(title) = &request.title {
-            let todo = sqlx::query_as!(
-                common::Todo,
-                r#"
-                UPDATE todos
-                SET title = $3, updated_at = CURRENT_TIMESTAMP
-                WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
-                RETURNING id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-                "#,
-                todo_id,
-                user_id,
-                title
-            )
-            .fetch_optional(&self.pool)
-            .await?;
-            
-            return Ok(todo);
-        }
+        let state: Option<Vec<u8>> = sqlx::query_scalar(
+            "SELECT state FROM cell_documents WHERE row_id = $1 AND column_id = $2"
+        )
+        .bind(row_id)
+        .bind(column_id)
+        .fetch_optional(&mut *tx)
+        .await?;
 
-        // Handle priority updates
-        if let Some(priority) = &request.priority {
-            let todo = sqlx::query_as!(
-                common::Todo,
-                r#"
-                UPDATE todos
-                SET priority = $3, updated_at = CURRENT_TIMESTAMP
-                WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
-                RETURNING id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-                "#,
-                todo_id,
-                user_id,
-                priority.clone() as common::TodoPriority
-            )
-            .fetch_optional(&self.pool)
-            .await?;
-            
-            return Ok(todo);
+        let key = column_id.to_string();
+        let cell = row.row_data.get(&key).cloned().unwrap_or(serde_json::Value::Null);
+        let Some((state, text)) = edit(state.as_deref(), &cell)? else {
+            // Nothing to write, but end the transaction to release the row
+            tx.commit().await?;
+            return Ok((row.clone(), row));
+        };
+        let changed = cell.as_str() != Some(text.as_str());
+        if changed {
+            let columns = self.get_spreadsheet_columns(row.spreadsheet_id).await?;
+            common::validate_row_data(&columns, &serde_json::json!({ key.clone(): text }), Some(&row.row_data))?;
         }
 
-        Err(ContrivanceError::bad_request("No valid fields to update"))
-    }
-
-    /// Update todo completion status
-    pub async fn update_todo_completion(
-        &self,
-        todo_id: Uuid,
-        completed: bool,
-        user_id: Uuid,
-    ) -> ContrivanceResult<Option<common::Todo>> {
-        let todo = sqlx::query_as!(
-            common::Todo,
+        sqlx::query(
             r#"
-            UPDATE todos
-            SET completed = $3, updated_at = CURRENT_TIMESTAMP
-            WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
-            RETURNING id, title, description, priority as "priority: common::TodoPriority", completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id as "row_id?", user_id, assigned_to as "assigned_to?"
-            "#,
-            todo_id,
-            user_id,
-            completed
+            INSERT INTO cell_documents (row_id, column_id, state)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (row_id, column_id) DO UPDATE SET state = EXCLUDED.state, updated_at = NOW()
+            "#
         )
-        .fetch_optional(&self.pool)
+        .bind(row_id)
+        .bind(column_id)
+        .bind(state)
+        .execute(&mut *tx)
         .await?;
 
-        Ok(todo)
+        let updated = if !changed {
+            row.clone()
+        } else {
+            sqlx::query_as::<_, SpreadsheetRow>(
+                r#"
+                UPDATE spreadsheet_rows
+                SET row_data = jsonb_set(row_data, ARRAY[$2], to_jsonb($3::text)), updated_at = NOW(), updated_by = $4
+                WHERE id = $1
+                RETURNING id, spreadsheet_id, row_data, position, created_at, updated_at, created_by, updated_by
+                "#
+            )
+            .bind(row_id)
+            .bind(&key)
+            .bind(&text)
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?
+        };
+
+        tx.commit().await?;
+        Ok((row, updated))
     }
 
-    /// Delete a todo - allow deletion if user created the todo
-    pub async fn delete_todo(
-        &self,
-        todo_id: Uuid,
-        user_id: Uuid,
-    ) -> ContrivanceResult<bool> {
-        let result = sqlx::query!(
-            "DELETE FROM todos WHERE id = $1 AND user_id = $2",
-            todo_id,
-            user_id
+    /// Highest sequence number recorded for a spreadsheet (0 if none)
+    pub async fn get_latest_seq(&self, spreadsheet_id: Uuid) -> ContrivanceResult<i64> {
+        let seq: Option<i64> = sqlx::query_scalar(
+            "SELECT last_seq FROM spreadsheet_event_sequences WHERE spreadsheet_id = $1"
         )
-        .execute(&self.pool)
+        .bind(spreadsheet_id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(seq.unwrap_or(0))
     }
 
-    /// Get todo statistics for a spreadsheet - include todos assigned to user
-    pub async fn get_todo_stats(
+    /// Create a webhook subscription
+    pub async fn create_webhook(
         &self,
-        spreadsheet_id: Uuid,
-        user_id: Uuid,
-    ) -> ContrivanceResult<common::TodoStats> {
-        let stats = sqlx::query_as!(
-            common::TodoStats,
+        owner_id: Uuid,
+        request: &CreateWebhookRequest,
+        secret: &str,
+    ) -> ContrivanceResult<WebhookSubscription> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
             r#"
-            SELECT 
-                COUNT(*) as total,
-                COUNT(CASE WHEN completed = true THEN 1 END) as completed,
-                COUNT(CASE WHEN completed = false THEN 1 END) as pending,
-                COUNT(CASE WHEN priority = 'high' THEN 1 END) as high_priority,
-                COUNT(CASE WHEN priority = 'medium' THEN 1 END) as medium_priority,
-                COUNT(CASE WHEN priority = 'low' THEN 1 END) as low_priority
-            FROM todos 
-            WHERE spreadsheet_id = $1 AND (user_id = $2 OR assigned_to = $2)
-            "#,
-            spreadsheet_id,
-            user_id
+            INSERT INTO webhook_subscriptions (owner_id, spreadsheet_id, url, secret, event_types, description)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, owner_id, spreadsheet_id, url, secret, event_types, description, is_active, created_at, updated_at
+            "#
         )
+        .bind(owner_id)
+        .bind(request.spreadsheet_id)
+        .bind(&request.url)
+        .bind(secret)
+        .bind(&request.event_types)
+        .bind(&request.description)
         .fetch_one(&self.pool)
         .await?;
 
-        Ok(stats)
+        Ok(subscription)
     }
 
-    /// Get all users for assignment dropdown
-    pub async fn get_users_for_assignment(&self) -> ContrivanceResult<Vec<common::User>> {
-        let users = sqlx::query_as!(
-            common::User,
+    /// List webhook subscriptions owned by a user
+    pub async fn list_webhooks(&self, owner_id: Uuid) -> ContrivanceResult<Vec<WebhookSubscription>> {
+        let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
             r#"
-            SELECT id, email, password_hash, name, role as "role: common::UserRole", created_at, updated_at, is_active, last_login
-            FROM users
-            WHERE is_active = true
-            ORDER BY name ASC
+            SELECT id, owner_id, spreadsheet_id, url, secret, event_types, description, is_active, created_at, updated_at
+            FROM webhook_subscriptions
+            WHERE owner_id = $1
+            ORDER BY created_at DESC
             "#
         )
+        .bind(owner_id)
         .fetch_all(&self.pool)
         .await?;
 
-        Ok(users)
+        Ok(subscriptions)
     }
-}
\ No newline at end of file
+
+    /// Get a webhook subscription by ID
+    pub async fn get_webhook(&self, webhook_id: Uuid) -> ContrivanceResult<Option<WebhookSubscription>> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            SELECT id, owner_id, spreadsheet_id, url, secret, event_types, description, is_active, created_at, updated_at
+            FROM webhook_subscriptions
+            WHERE id = $1
+            "#
+        )
+        .bind(webhook_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    /// Update a webhook subscription
+    pub async fn update_webhook(
+        &self,
+        webhook_id: Uuid,
+        request: &UpdateWebhookRequest,
+    ) -> ContrivanceResult<Option<WebhookSubscription>> {
+        let subscription = sqlx::query_as::<_, WebhookSubscription>(
+            r#"
+            UPDATE webhook_subscriptions
+            SET url = COALESCE($2, url),
+                event_types = COALESCE($3, event_types),
+                description = COALESCE($4, description),
+                is_active = COALESCE($5, is_active)
+            WHERE id = $1
+            RETURNING id, owner_id, spreadsheet_id, url, secret, event_types, description, is_active, created_at, updated_at
+            "#
+        )
+        .bind(webhook_id)
+        .bind(&request.url)
+        .bind(&request.event_types)
+        .bind(&request.description)
+        .bind(request.is_active)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(subscription)
+    }
+
+    /// Delete a webhook subscription and its delivery log
+    pub async fn delete_webhook(&self, webhook_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1")
+            .bind(webhook_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Queue deliveries of a spreadsheet event to every matching subscription.
+    ///
+    /// Subscriptions, whether to this spreadsheet or to everything their owner
+    /// can see, only match while the owner can still access the spreadsheet,
+    /// and none match once the owner has left the spreadsheet's organization.
+    pub async fn enqueue_spreadsheet_webhooks(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        spreadsheet_id: Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> ContrivanceResult<u64> {
+        let result = sqlx::query(ENQUEUE_SPREADSHEET_WEBHOOKS)
+        .bind(spreadsheet_id)
+        .bind(event_type)
+        .bind(payload)
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Queue deliveries of an event that belongs to a user rather than a spreadsheet
+    pub async fn enqueue_user_webhooks(
+        &self,
+        user_id: Uuid,
+        event_type: &str,
+        payload: &serde_json::Value,
+    ) -> ContrivanceResult<u64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (subscription_id, event_type, payload)
+            SELECT id, $2, $3
+            FROM webhook_subscriptions
+            WHERE is_active AND $2 = ANY(event_types)
+              AND spreadsheet_id IS NULL AND owner_id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(event_type)
+        .bind(payload)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get a subscription's delivery log, newest first
+    pub async fn list_webhook_deliveries(
+        &self,
+        webhook_id: Uuid,
+        limit: i64,
+    ) -> ContrivanceResult<Vec<WebhookDelivery>> {
+        let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT id, subscription_id, event_type, payload, status, attempt_count, next_attempt_at,
+                   last_attempt_at, response_status, response_body, last_error, delivered_at, replay_of, created_at
+            FROM webhook_deliveries
+            WHERE subscription_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#
+        )
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    /// Queue a fresh copy of an earlier delivery
+    pub async fn replay_webhook_delivery(
+        &self,
+        webhook_id: Uuid,
+        delivery_id: Uuid,
+    ) -> ContrivanceResult<Option<WebhookDelivery>> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            INSERT INTO webhook_deliveries (subscription_id, event_type, payload, replay_of)
+            SELECT subscription_id, event_type, payload, id
+            FROM webhook_deliveries
+            WHERE id = $2 AND subscription_id = $1
+            RETURNING id, subscription_id, event_type, payload, status, attempt_count, next_attempt_at,
+                      last_attempt_at, response_status, response_body, last_error, delivered_at, replay_of, created_at
+            "#
+        )
+        .bind(webhook_id)
+        .bind(delivery_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(delivery)
+    }
+
+    /// Claim pending deliveries that are due.
+    ///
+    /// Claimed rows have their next attempt pushed out by `lease_seconds`, so
+    /// another instance won't pick them up while they're in flight, and a crash
+    /// mid-delivery just means a later retry.
+    pub async fn claim_due_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease_seconds: i64,
+    ) -> ContrivanceResult<Vec<DueWebhookDelivery>> {
+        let deliveries = sqlx::query_as::<_, DueWebhookDelivery>(
+            r#"
+            WITH due AS (
+                SELECT d.id
+                FROM webhook_deliveries d
+                JOIN webhook_subscriptions w ON w.id = d.subscription_id
+                WHERE d.status = 'pending' AND d.next_attempt_at <= NOW() AND w.is_active
+                ORDER BY d.next_attempt_at
+                LIMIT $1
+                FOR UPDATE OF d SKIP LOCKED
+            )
+            UPDATE webhook_deliveries d
+            SET next_attempt_at = NOW() + make_interval(secs => $2)
+            FROM due, webhook_subscriptions w
+            WHERE d.id = due.id AND w.id = d.subscription_id
+            RETURNING d.id, d.event_type, d.payload, d.attempt_count, w.url, w.secret
+            "#
+        )
+        .bind(limit)
+        .bind(lease_seconds as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    /// Record the outcome of a delivery attempt.
+    ///
+    /// `retry_at` is when to try again after a failure; `None` means give up.
+    pub async fn record_webhook_attempt(
+        &self,
+        delivery_id: Uuid,
+        outcome: &DeliveryOutcome,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> ContrivanceResult<()> {
+        let status = if outcome.succeeded() {
+            WebhookDeliveryStatus::Delivered
+        } else if retry_at.is_some() {
+            WebhookDeliveryStatus::Pending
+        } else {
+            WebhookDeliveryStatus::Failed
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2,
+                attempt_count = attempt_count + 1,
+                last_attempt_at = NOW(),
+                next_attempt_at = $3,
+                response_status = $4,
+                response_body = $5,
+                last_error = $6,
+                delivered_at = CASE WHEN $2 = 'delivered' THEN NOW() ELSE delivered_at END
+            WHERE id = $1
+            "#
+        )
+        .bind(delivery_id)
+        .bind(status)
+        .bind(retry_at)
+        .bind(outcome.status_code.map(i32::from))
+        .bind(&outcome.response_body)
+        .bind(&outcome.error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Create an automation rule
+    pub async fn create_automation_rule(
+        &self,
+        spreadsheet_id: Uuid,
+        request: &CreateAutomationRuleRequest,
+        user_id: Uuid,
+    ) -> ContrivanceResult<AutomationRule> {
+        let rule = sqlx::query_as::<_, AutomationRule>(
+            r#"
+            INSERT INTO automation_rules (spreadsheet_id, name, description, trigger, actions, is_active, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, spreadsheet_id, name, description, trigger, actions, is_active, created_by, created_at, updated_at
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(Json(&request.trigger))
+        .bind(Json(&request.actions))
+        .bind(request.is_active.unwrap_or(true))
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    /// List a spreadsheet's automation rules
+    pub async fn list_automation_rules(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<AutomationRule>> {
+        let rules = sqlx::query_as::<_, AutomationRule>(
+            r#"
+            SELECT id, spreadsheet_id, name, description, trigger, actions, is_active, created_by, created_at, updated_at
+            FROM automation_rules
+            WHERE spreadsheet_id = $1
+            ORDER BY created_at
+            "#
+        )
+        .bind(spreadsheet_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rules)
+    }
+
+    /// Get an automation rule by ID
+    pub async fn get_automation_rule(&self, rule_id: Uuid) -> ContrivanceResult<Option<AutomationRule>> {
+        let rule = sqlx::query_as::<_, AutomationRule>(
+            r#"
+            SELECT id, spreadsheet_id, name, description, trigger, actions, is_active, created_by, created_at, updated_at
+            FROM automation_rules
+            WHERE id = $1
+            "#
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    /// Update an automation rule
+    pub async fn update_automation_rule(
+        &self,
+        rule_id: Uuid,
+        request: &UpdateAutomationRuleRequest,
+        edited_by: Uuid,
+    ) -> ContrivanceResult<Option<AutomationRule>> {
+        let rule = sqlx::query_as::<_, AutomationRule>(
+            r#"
+            UPDATE automation_rules
+            SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                trigger = COALESCE($4, trigger),
+                actions = COALESCE($5, actions),
+                is_active = COALESCE($6, is_active),
+                created_by = $7
+            WHERE id = $1
+            RETURNING id, spreadsheet_id, name, description, trigger, actions, is_active, created_by, created_at, updated_at
+            "#
+        )
+        .bind(rule_id)
+        .bind(&request.name)
+        .bind(&request.description)
+        .bind(request.trigger.as_ref().map(Json))
+        .bind(request.actions.as_ref().map(Json))
+        .bind(request.is_active)
+        .bind(edited_by)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(rule)
+    }
+
+    /// Delete an automation rule and its run log
+    pub async fn delete_automation_rule(&self, rule_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM automation_rules WHERE id = $1")
+            .bind(rule_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Active rules on a spreadsheet, optionally only those with one trigger type
+    pub async fn get_active_automation_rules(
+        &self,
+        spreadsheet_id: Option<Uuid>,
+        trigger_type: Option<&str>,
+    ) -> ContrivanceResult<Vec<AutomationRule>> {
+        let rules = sqlx::query_as::<_, AutomationRule>(
+            r#"
+            SELECT id, spreadsheet_id, name, description, trigger, actions, is_active, created_by, created_at, updated_at
+            FROM automation_rules
+            WHERE is_active
+              AND ($1::uuid IS NULL OR spreadsheet_id = $1)
+              AND ($2::text IS NULL OR trigger->>'type' = $2)
+            ORDER BY created_at
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(trigger_type)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rules)
+    }
+
+    /// Record that a rule has started firing.
+    ///
+    /// Returns `None` when `trigger_key` has already been used for this rule,
+    /// meaning the scheduled trigger has fired before.
+    pub async fn start_automation_run(
+        &self,
+        rule_id: Uuid,
+        row_id: Option<Uuid>,
+        todo_id: Option<Uuid>,
+        trigger_key: Option<&str>,
+    ) -> ContrivanceResult<Option<Uuid>> {
+        let run_id: Option<Uuid> = sqlx::query_scalar(
+            r#"
+            INSERT INTO automation_runs (rule_id, row_id, todo_id, trigger_key)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (rule_id, trigger_key) WHERE trigger_key IS NOT NULL DO NOTHING
+            RETURNING id
+            "#
+        )
+        .bind(rule_id)
+        .bind(row_id)
+        .bind(todo_id)
+        .bind(trigger_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(run_id)
+    }
+
+    /// Store the outcome of a rule's actions
+    pub async fn finish_automation_run(
+        &self,
+        run_id: Uuid,
+        succeeded: bool,
+        results: &serde_json::Value,
+    ) -> ContrivanceResult<()> {
+        sqlx::query("UPDATE automation_runs SET status = $2, results = $3 WHERE id = $1")
+            .bind(run_id)
+            .bind(if succeeded { "succeeded" } else { "failed" })
+            .bind(results)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get a rule's run log, newest first
+    pub async fn list_automation_runs(&self, rule_id: Uuid, limit: i64) -> ContrivanceResult<Vec<AutomationRun>> {
+        let runs = sqlx::query_as::<_, AutomationRun>(
+            r#"
+            SELECT id, rule_id, row_id, todo_id, trigger_key, status, results, created_at
+            FROM automation_runs
+            WHERE rule_id = $1
+            ORDER BY created_at DESC
+            LIMIT $2
+            "#
+        )
+        .bind(rule_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(runs)
+    }
+
+    /// Incomplete todos on a spreadsheet whose due date has passed
+    pub async fn get_overdue_todos(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<common::Todo>> {
+        let todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            SELECT {TODO_FIELDS}
+            FROM todos
+            WHERE spreadsheet_id = $1 AND NOT completed AND due_date < NOW()
+            ORDER BY due_date
+            "#
+        ))
+        .bind(spreadsheet_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    /// Incomplete todos on any spreadsheet due before `cutoff`, overdue ones included
+    pub async fn get_todos_due_before(&self, cutoff: DateTime<Utc>) -> ContrivanceResult<Vec<common::Todo>> {
+        let todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            SELECT {TODO_FIELDS}
+            FROM todos
+            WHERE NOT completed AND due_date < $1
+            ORDER BY due_date
+            "#
+        ))
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    /// Store a notification unless the user has turned its kind off or it
+    /// duplicates an earlier one with the same dedupe key
+    pub async fn create_notification(&self, notification: &NewNotification) -> ContrivanceResult<Option<Notification>> {
+        let created = sqlx::query_as::<_, Notification>(
+            r#"
+            INSERT INTO notifications (user_id, kind, title, body, spreadsheet_id, row_id, todo_id, actor_id, dedupe_key)
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9
+            WHERE NOT EXISTS (
+                SELECT 1 FROM notification_preferences
+                WHERE user_id = $1 AND kind = $2 AND NOT enabled
+            )
+            ON CONFLICT (user_id, dedupe_key) WHERE dedupe_key IS NOT NULL DO NOTHING
+            RETURNING id, user_id, kind, title, body, spreadsheet_id, row_id, todo_id, actor_id, read_at, created_at
+            "#
+        )
+        .bind(notification.user_id)
+        .bind(notification.kind)
+        .bind(&notification.title)
+        .bind(&notification.body)
+        .bind(notification.spreadsheet_id)
+        .bind(notification.row_id)
+        .bind(notification.todo_id)
+        .bind(notification.actor_id)
+        .bind(&notification.dedupe_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(created)
+    }
+
+    /// Get a user's notifications, newest first
+    pub async fn list_notifications(
+        &self,
+        user_id: Uuid,
+        unread_only: bool,
+        before: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> ContrivanceResult<Vec<Notification>> {
+        let notifications = sqlx::query_as::<_, Notification>(
+            r#"
+            SELECT id, user_id, kind, title, body, spreadsheet_id, row_id, todo_id, actor_id, read_at, created_at
+            FROM notifications
+            WHERE user_id = $1
+              AND (NOT $2 OR read_at IS NULL)
+              AND ($3::timestamptz IS NULL OR created_at < $3)
+            ORDER BY created_at DESC
+            LIMIT $4
+            "#
+        )
+        .bind(user_id)
+        .bind(unread_only)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(notifications)
+    }
+
+    pub async fn count_unread_notifications(&self, user_id: Uuid) -> ContrivanceResult<i64> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL"
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// Mark one of a user's notifications read; `None` if it isn't theirs
+    pub async fn mark_notification_read(&self, user_id: Uuid, notification_id: Uuid) -> ContrivanceResult<Option<Notification>> {
+        let notification = sqlx::query_as::<_, Notification>(
+            r#"
+            UPDATE notifications
+            SET read_at = COALESCE(read_at, NOW())
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, user_id, kind, title, body, spreadsheet_id, row_id, todo_id, actor_id, read_at, created_at
+            "#
+        )
+        .bind(notification_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(notification)
+    }
+
+    /// Mark all of a user's notifications read, returning how many changed
+    pub async fn mark_all_notifications_read(&self, user_id: Uuid) -> ContrivanceResult<u64> {
+        let result = sqlx::query("UPDATE notifications SET read_at = NOW() WHERE user_id = $1 AND read_at IS NULL")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Get a user's preference for every notification kind
+    pub async fn get_notification_preferences(&self, user_id: Uuid) -> ContrivanceResult<Vec<NotificationPreference>> {
+        let stored = sqlx::query_as::<_, NotificationPreference>(
+            "SELECT kind, enabled FROM notification_preferences WHERE user_id = $1"
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(NotificationKind::ALL
+            .iter()
+            .map(|kind| NotificationPreference {
+                kind: *kind,
+                enabled: !stored.iter().any(|p| p.kind == *kind && !p.enabled),
+            })
+            .collect())
+    }
+
+    pub async fn set_notification_preferences(
+        &self,
+        user_id: Uuid,
+        preferences: &[NotificationPreference],
+    ) -> ContrivanceResult<()> {
+        let mut tx = self.pool.begin().await?;
+        for preference in preferences {
+            sqlx::query(
+                r#"
+                INSERT INTO notification_preferences (user_id, kind, enabled)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (user_id, kind) DO UPDATE SET enabled = EXCLUDED.enabled
+                "#
+            )
+            .bind(user_id)
+            .bind(preference.kind)
+            .bind(preference.enabled)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Email address and display name of an active user
+    pub async fn get_email_recipient(&self, user_id: Uuid) -> ContrivanceResult<Option<EmailRecipient>> {
+        let recipient = sqlx::query_as::<_, EmailRecipient>(
+            "SELECT email, name FROM users WHERE id = $1 AND is_active = true"
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(recipient)
+    }
+
+    /// Get a user's digest schedule, creating it with the defaults if needed
+    pub async fn get_digest_schedule(&self, user_id: Uuid) -> ContrivanceResult<DigestSchedule> {
+        let schedule = sqlx::query_as::<_, DigestSchedule>(
+            r#"
+            WITH created AS (
+                INSERT INTO email_digest_schedules (user_id) VALUES ($1)
+                ON CONFLICT (user_id) DO NOTHING
+                RETURNING enabled, day_of_week, hour, timezone, next_send_at, last_sent_at
+            )
+            SELECT enabled, day_of_week, hour, timezone, next_send_at, last_sent_at FROM created
+            UNION ALL
+            SELECT enabled, day_of_week, hour, timezone, next_send_at, last_sent_at
+            FROM email_digest_schedules WHERE user_id = $1
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(schedule)
+    }
+
+    /// Save a user's digest schedule
+    pub async fn save_digest_schedule(&self, user_id: Uuid, schedule: &DigestSchedule) -> ContrivanceResult<DigestSchedule> {
+        let saved = sqlx::query_as::<_, DigestSchedule>(
+            r#"
+            INSERT INTO email_digest_schedules (user_id, enabled, day_of_week, hour, timezone, next_send_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (user_id) DO UPDATE
+            SET enabled = EXCLUDED.enabled,
+                day_of_week = EXCLUDED.day_of_week,
+                hour = EXCLUDED.hour,
+                timezone = EXCLUDED.timezone,
+                next_send_at = EXCLUDED.next_send_at
+            RETURNING enabled, day_of_week, hour, timezone, next_send_at, last_sent_at
+            "#
+        )
+        .bind(user_id)
+        .bind(schedule.enabled)
+        .bind(schedule.day_of_week)
+        .bind(schedule.hour)
+        .bind(&schedule.timezone)
+        .bind(schedule.next_send_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(saved)
+    }
+
+    /// Give every active user without a digest schedule the default one
+    pub async fn create_missing_digest_schedules(&self) -> ContrivanceResult<u64> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO email_digest_schedules (user_id)
+            SELECT id FROM users u
+            WHERE u.is_active = true
+              AND NOT EXISTS (SELECT 1 FROM email_digest_schedules d WHERE d.user_id = u.id)
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Enabled schedules that are due, or that haven't had a send time worked out yet
+    pub async fn get_due_digest_schedules(&self, now: DateTime<Utc>) -> ContrivanceResult<Vec<DueDigest>> {
+        let due = sqlx::query_as::<_, DueDigest>(
+            r#"
+            SELECT d.user_id, u.email, u.name, d.day_of_week, d.hour, d.timezone, d.next_send_at
+            FROM email_digest_schedules d
+            JOIN users u ON u.id = d.user_id
+            WHERE d.enabled AND u.is_active = true
+              AND (d.next_send_at IS NULL OR d.next_send_at <= $1)
+            ORDER BY d.next_send_at NULLS FIRST
+            "#
+        )
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(due)
+    }
+
+    /// Move a schedule on to its next send time, recording a send if there was one
+    pub async fn advance_digest_schedule(
+        &self,
+        user_id: Uuid,
+        next_send_at: DateTime<Utc>,
+        sent_at: Option<DateTime<Utc>>,
+    ) -> ContrivanceResult<()> {
+        sqlx::query(
+            r#"
+            UPDATE email_digest_schedules
+            SET next_send_at = $2, last_sent_at = COALESCE($3, last_sent_at)
+            WHERE user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(next_send_at)
+        .bind(sent_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Row and todo activity since `since` for the spreadsheets a user owns or
+    /// collaborates on in their organization
+    pub async fn get_digest_spreadsheets(
+        &self,
+        user_id: Uuid,
+        since: DateTime<Utc>,
+    ) -> ContrivanceResult<Vec<DigestSpreadsheetSummary>> {
+        let spreadsheets = sqlx::query_as::<_, DigestSpreadsheetSummary>(
+            r#"
+            SELECT s.id, s.name,
+                (SELECT COUNT(*) FROM spreadsheet_rows r WHERE r.spreadsheet_id = s.id) AS row_count,
+                (SELECT COUNT(*) FROM spreadsheet_rows r
+                 WHERE r.spreadsheet_id = s.id AND r.created_at >= $2) AS rows_created,
+                (SELECT COUNT(*) FROM spreadsheet_rows r
+                 WHERE r.spreadsheet_id = s.id AND r.created_at < $2 AND r.updated_at >= $2) AS rows_updated,
+                (SELECT COUNT(*) FROM todos t
+                 WHERE t.spreadsheet_id = s.id AND NOT t.completed) AS open_todos
+            FROM spreadsheets s
+            WHERE s.organization_id = user_organization($1)
+              AND (
+                  s.owner_id = $1
+                  OR EXISTS (
+                      SELECT 1 FROM spreadsheet_collaborators sc
+                      WHERE sc.spreadsheet_id = s.id AND sc.user_id = $1 AND sc.accepted_at IS NOT NULL
+                  )
+              )
+            ORDER BY s.name
+            "#
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(spreadsheets)
+    }
+
+    /// Open todos assigned to a user (or created by them and unassigned) that
+    /// are due before `cutoff`, soonest first
+    pub async fn get_digest_todos(
+        &self,
+        user_id: Uuid,
+        cutoff: DateTime<Utc>,
+        limit: i64,
+    ) -> ContrivanceResult<Vec<DigestTodoSummary>> {
+        let todos = sqlx::query_as::<_, DigestTodoSummary>(
+            r#"
+            SELECT t.title, t.spreadsheet_id, s.name AS spreadsheet_name, t.due_date
+            FROM todos t
+            JOIN spreadsheets s ON s.id = t.spreadsheet_id
+            WHERE NOT t.completed
+              AND t.due_date < $2
+              AND (t.assigned_to = $1 OR (t.assigned_to IS NULL AND t.user_id = $1))
+              AND s.organization_id = user_organization($1)
+            ORDER BY t.due_date
+            LIMIT $3
+            "#
+        )
+        .bind(user_id)
+        .bind(cutoff)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    /// Get the id of an active user by email, among the members of `organization_id`
+    pub async fn get_user_id_by_email(&self, organization_id: Uuid, email: &str) -> ContrivanceResult<Option<Uuid>> {
+        let user_id = sqlx::query_scalar(
+            r#"
+            SELECT u.id
+            FROM users u
+            JOIN organization_members m ON m.user_id = u.id
+            WHERE LOWER(u.email) = LOWER($1) AND u.is_active = true AND m.organization_id = $2
+            "#
+        )
+        .bind(email)
+        .bind(organization_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user_id)
+    }
+
+    /// Check if user is the spreadsheet owner, an admin collaborator or has
+    /// admin on it through its team, organization or workspace
+    pub async fn can_user_manage_collaborators(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<bool> {
+        let allowed: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM spreadsheets s
+                LEFT JOIN spreadsheet_collaborators sc ON s.id = sc.spreadsheet_id
+                WHERE s.id = $1 AND s.organization_id = user_organization($2) AND (
+                    s.owner_id = $2
+                    OR (sc.user_id = $2 AND sc.accepted_at IS NOT NULL AND sc.permission_level = 'admin')
+                    OR tenant_permission($2, s.team_id) = 'admin'
+                    OR (s.workspace_id IS NOT NULL
+                        AND workspace_permission($2, s.workspace_id, s.folder_id) = 'admin')
+                )
+            )
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(allowed)
+    }
+
+    /// Invite a user to a spreadsheet. Re-inviting updates the permission level;
+    /// access starts once the invite is accepted.
+    pub async fn invite_collaborator(
+        &self,
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+        permission_level: &PermissionLevel,
+        invited_by: Uuid,
+    ) -> ContrivanceResult<SpreadsheetCollaborator> {
+        let collaborator = sqlx::query_as::<_, SpreadsheetCollaborator>(
+            r#"
+            INSERT INTO spreadsheet_collaborators (spreadsheet_id, user_id, permission_level, invited_by)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (spreadsheet_id, user_id) DO UPDATE
+            SET permission_level = EXCLUDED.permission_level
+            RETURNING id, spreadsheet_id, user_id, permission_level, invited_by, invited_at, accepted_at
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .bind(permission_level)
+        .bind(invited_by)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(collaborator)
+    }
+
+    /// Accept a pending invite, returning `None` if the user wasn't invited
+    pub async fn accept_collaborator_invite(
+        &self,
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<SpreadsheetCollaborator>> {
+        let collaborator = sqlx::query_as::<_, SpreadsheetCollaborator>(
+            r#"
+            UPDATE spreadsheet_collaborators
+            SET accepted_at = COALESCE(accepted_at, NOW())
+            WHERE spreadsheet_id = $1 AND user_id = $2
+              AND EXISTS (SELECT 1 FROM spreadsheets WHERE id = $1 AND organization_id = user_organization($2))
+            RETURNING id, spreadsheet_id, user_id, permission_level, invited_by, invited_at, accepted_at
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(collaborator)
+    }
+
+    /// Get exchange rates, optionally for one currency pair and up to a date
+    pub async fn get_exchange_rates(
+        &self,
+        base_currency: Option<&str>,
+        quote_currency: Option<&str>,
+        as_of: Option<NaiveDate>,
+    ) -> ContrivanceResult<Vec<ExchangeRate>> {
+        let rates = sqlx::query_as::<_, ExchangeRate>(
+            r#"
+            SELECT id, base_currency, quote_currency, rate, rate_date, source, created_at, updated_at
+            FROM exchange_rates
+            WHERE ($1::varchar IS NULL OR base_currency = $1)
+              AND ($2::varchar IS NULL OR quote_currency = $2)
+              AND ($3::date IS NULL OR rate_date <= $3)
+            ORDER BY rate_date DESC, base_currency, quote_currency
+            "#
+        )
+        .bind(base_currency)
+        .bind(quote_currency)
+        .bind(as_of)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rates)
+    }
+
+    /// Insert exchange rates, replacing any existing rate for the same pair and date
+    pub async fn upsert_exchange_rates(&self, rates: &[NewExchangeRate]) -> ContrivanceResult<Vec<ExchangeRate>> {
+        let mut tx = self.pool.begin().await?;
+        let mut saved = Vec::with_capacity(rates.len());
+
+        for rate in rates {
+            let row = sqlx::query_as::<_, ExchangeRate>(
+                r#"
+                INSERT INTO exchange_rates (base_currency, quote_currency, rate, rate_date, source)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (base_currency, quote_currency, rate_date)
+                DO UPDATE SET rate = EXCLUDED.rate, source = EXCLUDED.source
+                RETURNING id, base_currency, quote_currency, rate, rate_date, source, created_at, updated_at
+                "#
+            )
+            .bind(&rate.base_currency)
+            .bind(&rate.quote_currency)
+            .bind(rate.rate)
+            .bind(rate.rate_date)
+            .bind(&rate.source)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            saved.push(row);
+        }
+
+        tx.commit().await?;
+        Ok(saved)
+    }
+
+    /// Get collaborators with user information
+    pub async fn get_collaborators_with_user_info(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<common::CollaboratorInfo>> {
+        let collaborators = sqlx::query!(
+            r#"
+            SELECT 
+                sc.permission_level as "permission_level: PermissionLevel",
+                sc.invited_at,
+                sc.accepted_at,
+                u.id as user_id,
+                u.email,
+                u.name,
+                u.role as "role: common::UserRole",
+                u.created_at as user_created_at,
+                u.last_login,
+                u.is_active
+            FROM spreadsheet_collaborators sc
+            JOIN users u ON sc.user_id = u.id
+            WHERE sc.spreadsheet_id = $1 AND sc.accepted_at IS NOT NULL
+            ORDER BY sc.invited_at
+            "#,
+            spreadsheet_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let collaborator_infos = collaborators
+            .into_iter()
+            .map(|row| {
+                let user = UserResponse {
+                    id: row.user_id,
+                    email: row.email,
+                    name: row.name,
+                    role: row.role,
+                    created_at: row.user_created_at.unwrap_or_else(Utc::now),
+                    last_login: row.last_login,
+                    is_active: row.is_active.unwrap_or(true),
+                };
+
+                common::CollaboratorInfo {
+                    user,
+                    permission_level: row.permission_level,
+                    invited_at: row.invited_at.unwrap_or_else(Utc::now),
+                    accepted_at: row.accepted_at,
+                }
+            })
+            .collect();
+
+        Ok(collaborator_infos)
+    }
+
+    /// Check if user can access spreadsheet
+    pub async fn can_user_access_spreadsheet(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<bool> {
+        let allowed: bool = sqlx::query_scalar(&format!(
+            "SELECT EXISTS (SELECT 1 FROM spreadsheets s WHERE s.id = $2 AND {VISIBLE_SPREADSHEET})"
+        ))
+        .bind(user_id)
+        .bind(spreadsheet_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(allowed)
+    }
+
+    /// Check if user can edit spreadsheet
+    pub async fn can_user_edit_spreadsheet(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<bool> {
+        let allowed: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM spreadsheets s
+                WHERE s.id = $1 AND s.organization_id = user_organization($2) AND (
+                    s.owner_id = $2
+                    OR EXISTS (
+                        SELECT 1 FROM spreadsheet_collaborators sc
+                        WHERE sc.spreadsheet_id = s.id AND sc.user_id = $2 AND sc.accepted_at IS NOT NULL
+                          AND sc.permission_level IN ('edit', 'admin')
+                    )
+                    OR tenant_permission($2, s.team_id) IN ('edit', 'admin')
+                    OR (s.workspace_id IS NOT NULL
+                        AND workspace_permission($2, s.workspace_id, s.folder_id) IN ('edit', 'admin'))
+                )
+            )
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(allowed)
+    }
+
+    /// Create a new todo. A recurring one starts a series with itself as the
+    /// first occurrence; the rule and parent are validated by the caller.
+    pub async fn create_todo(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        request: &common::CreateTodoRequest,
+        user_id: Uuid,
+    ) -> ContrivanceResult<common::Todo> {
+        let todo_id = Uuid::new_v4();
+        let now = Utc::now();
+        let recurrence = request.recurrence.as_deref().filter(|rule| !rule.trim().is_empty());
+        let series = recurrence.map(|rule| {
+            let timezone = request.recurrence_timezone.as_deref().unwrap_or("UTC");
+            (todo_id, rule.trim(), timezone, request.due_date)
+        });
+
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            INSERT INTO todos (id, title, description, priority, completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id, user_id, assigned_to,
+                               series_id, recurrence, recurrence_timezone, series_start, scheduled_at, parent_id)
+            VALUES ($1, $2, $3, $4, false, $5, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $15, $16)
+            RETURNING {TODO_FIELDS}
+            "#
+        ))
+        .bind(todo_id)
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(&request.priority)
+        .bind(now)
+        .bind(request.due_date)
+        .bind(&request.supporting_artifact)
+        .bind(request.spreadsheet_id)
+        .bind(request.row_id)
+        .bind(user_id)
+        .bind(request.assigned_to)
+        .bind(series.map(|(series_id, ..)| series_id))
+        .bind(series.map(|(_, rule, ..)| rule))
+        .bind(series.map(|(_, _, timezone, _)| timezone))
+        .bind(series.and_then(|(.., start)| start))
+        .bind(request.parent_id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        Ok(todo)
+    }
+
+    /// Get todos created by or assigned to the user, newest first
+    pub async fn get_todos_for_user(
+        &self,
+        user_id: Uuid,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<common::Todo>> {
+        self.list_todos("true", user_id, None, None, pagination).await
+    }
+
+    /// Get todos for a spreadsheet (pipeline-level) - show todos assigned to or created by user
+    pub async fn get_todos_by_spreadsheet(
+        &self,
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<common::Todo>> {
+        self.list_todos("spreadsheet_id = $2 AND row_id IS NULL", user_id, Some(spreadsheet_id), None, pagination)
+            .await
+    }
+
+    /// Get todos for a specific row - show todos assigned to or created by user
+    pub async fn get_todos_by_row(
+        &self,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        user_id: Uuid,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<common::Todo>> {
+        self.list_todos("spreadsheet_id = $2 AND row_id = $3", user_id, Some(spreadsheet_id), Some(row_id), pagination)
+            .await
+    }
+
+    /// One page of the user's todos matching `filter`, keyed on (created_at, id).
+    /// `filter` may refer to the spreadsheet as `$2` and the row as `$3`.
+    async fn list_todos(
+        &self,
+        filter: &str,
+        user_id: Uuid,
+        spreadsheet_id: Option<Uuid>,
+        row_id: Option<Uuid>,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<common::Todo>> {
+        let keyset = Keyset::<DateTime<Utc>>::from_params(pagination, 100, 500)?;
+        let (op, dir) = keyset.sql(SortOrder::Desc);
+        let filter = format!("(user_id = $1 OR assigned_to = $1) AND {}", filter);
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM todos WHERE {}", filter))
+            .bind(user_id)
+            .bind(spreadsheet_id)
+            .bind(row_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        let todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            SELECT {TODO_FIELDS}
+            FROM todos
+            WHERE {filter}
+              AND ($4::timestamptz IS NULL OR (COALESCE(created_at, 'epoch'), id) {op} ($4, $5))
+            ORDER BY COALESCE(created_at, 'epoch') {dir}, id {dir}
+            LIMIT $6 OFFSET $7
+            "#
+        ))
+        .bind(user_id)
+        .bind(spreadsheet_id)
+        .bind(row_id)
+        .bind(keyset.key())
+        .bind(keyset.id())
+        .bind(keyset.fetch_limit())
+        .bind(keyset.offset())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keyset.into_response(todos, total as u64, |todo| {
+            (todo.created_at.unwrap_or(DateTime::UNIX_EPOCH), todo.id)
+        }))
+    }
+
+    /// Get todo by ID - allow access if user created or is assigned to the todo
+    pub async fn get_todo_by_id(
+        &self,
+        todo_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            "SELECT {TODO_FIELDS} FROM todos WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)"
+        ))
+        .bind(todo_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(todo)
+    }
+
+    /// Update a todo - allow updates if user created or is assigned to the todo.
+    /// Fields left out of the request keep their value.
+    pub async fn update_todo(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        request: &common::UpdateTodoRequest,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        if request.title.is_none()
+            && request.description.is_none()
+            && request.priority.is_none()
+            && request.completed.is_none()
+            && request.due_date.is_none()
+            && request.supporting_artifact.is_none()
+            && request.assigned_to.is_none()
+            && request.parent_id.is_none()
+        {
+            return Err(ContrivanceError::bad_request("No valid fields to update"));
+        }
+
+        let mut tx = conn.begin().await?;
+        if let Some(parent_id) = request.parent_id {
+            // The parent waits on its subtasks, so it can't be something the todo waits on
+            if let Some(spreadsheet_id) = todo_spreadsheet(&mut tx, todo_id).await? {
+                lock_todo_graph(&mut tx, spreadsheet_id).await?;
+            }
+            if todo_waits_on(&mut tx, todo_id, parent_id).await? {
+                return Err(ContrivanceError::validation(
+                    "A todo can't be moved under itself, one of its subtasks or a todo it's waiting on",
+                ));
+            }
+        }
+
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            UPDATE todos
+            SET title = COALESCE($3, title),
+                description = COALESCE($4, description),
+                priority = COALESCE($5, priority),
+                completed = COALESCE($6, completed),
+                due_date = COALESCE($7, due_date),
+                supporting_artifact = COALESCE($8, supporting_artifact),
+                assigned_to = COALESCE($9, assigned_to),
+                parent_id = COALESCE($10, parent_id),
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
+            RETURNING {TODO_FIELDS}
+            "#
+        ))
+        .bind(todo_id)
+        .bind(user_id)
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(&request.priority)
+        .bind(request.completed)
+        .bind(request.due_date)
+        .bind(&request.supporting_artifact)
+        .bind(request.assigned_to)
+        .bind(request.parent_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    /// Update a recurring todo's whole series. The rule, time zone and (from
+    /// `due_date`) start change on every occurrence so the next one follows
+    /// them; the other fields change on `todo_id` and the series' open
+    /// occurrences. `due_date` moves only `todo_id`. Returns the todos changed,
+    /// `todo_id` first; empty if the user can't update it.
+    pub async fn update_todo_series(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        series_id: Uuid,
+        request: &common::UpdateTodoRequest,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Vec<common::Todo>> {
+        let mut tx = conn.begin().await?;
+
+        let allowed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM todos WHERE id = $1 AND series_id = $2 AND (user_id = $3 OR assigned_to = $3))",
+        )
+        .bind(todo_id)
+        .bind(series_id)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !allowed {
+            return Ok(Vec::new());
+        }
+
+        if request.recurrence.is_some() || request.recurrence_timezone.is_some() || request.due_date.is_some() {
+            // An empty rule stops the series repeating
+            sqlx::query(
+                r#"
+                UPDATE todos
+                SET recurrence = CASE WHEN $2::text IS NULL THEN recurrence ELSE NULLIF(TRIM($2), '') END,
+                    recurrence_timezone = COALESCE($3, recurrence_timezone),
+                    series_start = COALESCE($4, series_start)
+                WHERE series_id = $1
+                "#,
+            )
+            .bind(series_id)
+            .bind(&request.recurrence)
+            .bind(&request.recurrence_timezone)
+            .bind(request.due_date)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let mut todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            UPDATE todos
+            SET title = COALESCE($3, title),
+                description = COALESCE($4, description),
+                priority = COALESCE($5, priority),
+                supporting_artifact = COALESCE($6, supporting_artifact),
+                assigned_to = COALESCE($7, assigned_to),
+                completed = CASE WHEN id = $2 THEN COALESCE($8, completed) ELSE completed END,
+                due_date = CASE WHEN id = $2 THEN COALESCE($9, due_date) ELSE due_date END,
+                scheduled_at = CASE WHEN id = $2 THEN COALESCE($9, scheduled_at) ELSE scheduled_at END,
+                updated_at = CURRENT_TIMESTAMP
+            WHERE series_id = $1 AND (id = $2 OR NOT completed)
+            RETURNING {TODO_FIELDS}
+            "#
+        ))
+        .bind(series_id)
+        .bind(todo_id)
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(&request.priority)
+        .bind(&request.supporting_artifact)
+        .bind(request.assigned_to)
+        .bind(request.completed)
+        .bind(request.due_date)
+        .fetch_all(&mut *tx)
+        .await
+        .map_err(occurrence_taken)?;
+
+        tx.commit().await?;
+        todos.sort_by_key(|todo| (todo.id != todo_id, todo.scheduled_at));
+        Ok(todos)
+    }
+
+    /// Create the occurrence of `previous`'s series scheduled at `scheduled_at`,
+    /// copying its details. `None` if that occurrence already exists.
+    pub async fn create_next_occurrence(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        previous: &common::Todo,
+        scheduled_at: DateTime<Utc>,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            INSERT INTO todos (id, title, description, priority, completed, created_at, updated_at, due_date, supporting_artifact, spreadsheet_id, row_id, user_id, assigned_to,
+                               series_id, recurrence, recurrence_timezone, series_start, scheduled_at)
+            SELECT $2, title, description, priority, false, NOW(), NOW(), $3, supporting_artifact, spreadsheet_id, row_id, user_id, assigned_to,
+                   series_id, recurrence, recurrence_timezone, series_start, $3
+            FROM todos
+            WHERE id = $1 AND series_id IS NOT NULL
+            ON CONFLICT (series_id, scheduled_at) WHERE series_id IS NOT NULL DO NOTHING
+            RETURNING {TODO_FIELDS}
+            "#
+        ))
+        .bind(previous.id)
+        .bind(Uuid::new_v4())
+        .bind(scheduled_at)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        Ok(todo)
+    }
+
+    /// Update todo completion status
+    pub async fn update_todo_completion(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        completed: bool,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            UPDATE todos
+            SET completed = $3, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1 AND (user_id = $2 OR assigned_to = $2)
+            RETURNING {TODO_FIELDS}
+            "#
+        ))
+        .bind(todo_id)
+        .bind(user_id)
+        .bind(completed)
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        Ok(todo)
+    }
+
+    /// A todo on a spreadsheet, whoever it belongs to
+    pub async fn get_spreadsheet_todo(
+        &self,
+        spreadsheet_id: Uuid,
+        todo_id: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let todo = sqlx::query_as::<_, common::Todo>(&format!(
+            "SELECT {TODO_FIELDS} FROM todos WHERE id = $1 AND spreadsheet_id = $2"
+        ))
+        .bind(todo_id)
+        .bind(spreadsheet_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(todo)
+    }
+
+    /// Every todo on a row the user created or is assigned, oldest first, for
+    /// arranging into a tree
+    pub async fn get_row_todo_tree(
+        &self,
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Vec<common::Todo>> {
+        let todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            SELECT {TODO_FIELDS}
+            FROM todos
+            WHERE spreadsheet_id = $1 AND row_id = $2 AND (user_id = $3 OR assigned_to = $3)
+            ORDER BY COALESCE(created_at, 'epoch'), id
+            "#
+        ))
+        .bind(spreadsheet_id)
+        .bind(row_id)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    /// Subtasks of a todo that are still open
+    pub async fn count_open_subtasks(&self, todo_id: Uuid) -> ContrivanceResult<i64> {
+        let count = sqlx::query_scalar("SELECT COUNT(*) FROM todos WHERE parent_id = $1 AND NOT completed")
+            .bind(todo_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Bring a parent's completion in line with its subtasks, then its
+    /// parent's, and so on up while anything changes: a parent is completed
+    /// when all its subtasks are (unless it's blocked) and reopened when one
+    /// isn't. Returns the todos changed, nearest first.
+    pub async fn roll_up_todo_completion(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        parent_id: Uuid,
+    ) -> ContrivanceResult<Vec<common::Todo>> {
+        let mut changed = Vec::new();
+        let mut next = Some(parent_id);
+
+        while let Some(todo_id) = next {
+            let todo = sqlx::query_as::<_, common::Todo>(&format!(
+                r#"
+                WITH subtasks AS (
+                    SELECT COUNT(*) AS total, COUNT(*) FILTER (WHERE completed) AS done
+                    FROM todos WHERE parent_id = $1
+                )
+                UPDATE todos
+                SET completed = (subtasks.done = subtasks.total), updated_at = CURRENT_TIMESTAMP
+                FROM subtasks
+                WHERE todos.id = $1
+                  AND subtasks.total > 0
+                  AND todos.completed <> (subtasks.done = subtasks.total)
+                  AND (todos.completed OR NOT EXISTS (
+                      SELECT 1 FROM todo_dependencies d JOIN todos b ON b.id = d.blocked_by_id
+                      WHERE d.todo_id = $1 AND NOT b.completed
+                  ))
+                RETURNING {TODO_FIELDS}
+                "#
+            ))
+            .bind(todo_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+            next = todo.as_ref().and_then(|todo| todo.parent_id);
+            changed.extend(todo);
+        }
+
+        Ok(changed)
+    }
+
+    /// Todos waiting on `todo_id`
+    pub async fn get_dependent_todos(&self, todo_id: Uuid) -> ContrivanceResult<Vec<common::Todo>> {
+        let todos = sqlx::query_as::<_, common::Todo>(&format!(
+            r#"
+            SELECT {TODO_FIELDS}
+            FROM todos
+            WHERE id IN (SELECT todo_id FROM todo_dependencies WHERE blocked_by_id = $1)
+            ORDER BY COALESCE(created_at, 'epoch'), id
+            "#
+        ))
+        .bind(todo_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(todos)
+    }
+
+    /// Make a todo wait until another on its spreadsheet is completed. Refuses
+    /// a dependency on a todo that already waits on this one, directly or
+    /// through other dependencies and subtasks. Returns the updated todo.
+    pub async fn add_todo_dependency(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        blocked_by: Uuid,
+    ) -> ContrivanceResult<common::Todo> {
+        let mut tx = conn.begin().await?;
+
+        let spreadsheet_id = todo_spreadsheet(&mut tx, todo_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+        lock_todo_graph(&mut tx, spreadsheet_id).await?;
+
+        if todo_spreadsheet(&mut tx, blocked_by).await? != Some(spreadsheet_id) {
+            return Err(ContrivanceError::not_found("Blocking todo not found on this spreadsheet"));
+        }
+        if todo_waits_on(&mut tx, blocked_by, todo_id).await? {
+            return Err(ContrivanceError::validation(
+                "That todo already waits on this one, so waiting on it would make a cycle",
+            ));
+        }
+
+        sqlx::query("INSERT INTO todo_dependencies (todo_id, blocked_by_id) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+            .bind(todo_id)
+            .bind(blocked_by)
+            .execute(&mut *tx)
+            .await?;
+        let todo = touch_todo(&mut tx, todo_id).await?;
+
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    /// Stop a todo waiting on another. `None` if it wasn't.
+    pub async fn remove_todo_dependency(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        blocked_by: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let mut tx = conn.begin().await?;
+
+        let removed = sqlx::query("DELETE FROM todo_dependencies WHERE todo_id = $1 AND blocked_by_id = $2")
+            .bind(todo_id)
+            .bind(blocked_by)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if removed == 0 {
+            return Ok(None);
+        }
+        let todo = touch_todo(&mut tx, todo_id).await?;
+
+        tx.commit().await?;
+        Ok(Some(todo))
+    }
+
+    /// Add a step to a todo's checklist. Returns the updated todo.
+    pub async fn add_checklist_item(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        request: &common::CreateChecklistItemRequest,
+    ) -> ContrivanceResult<common::Todo> {
+        let mut tx = conn.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO todo_checklist_items (todo_id, title, position)
+            SELECT $1, $2, COALESCE($3, (SELECT COALESCE(MAX(position) + 1, 0) FROM todo_checklist_items WHERE todo_id = $1))
+            "#,
+        )
+        .bind(todo_id)
+        .bind(&request.title)
+        .bind(request.position)
+        .execute(&mut *tx)
+        .await?;
+        let todo = touch_todo(&mut tx, todo_id).await?;
+
+        tx.commit().await?;
+        Ok(todo)
+    }
+
+    /// Change a checklist item. `None` if the todo has no such item.
+    pub async fn update_checklist_item(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        item_id: Uuid,
+        request: &common::UpdateChecklistItemRequest,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let mut tx = conn.begin().await?;
+
+        let updated = sqlx::query(
+            r#"
+            UPDATE todo_checklist_items
+            SET title = COALESCE($3, title),
+                completed = COALESCE($4, completed),
+                position = COALESCE($5, position),
+                updated_at = NOW()
+            WHERE id = $2 AND todo_id = $1
+            "#,
+        )
+        .bind(todo_id)
+        .bind(item_id)
+        .bind(&request.title)
+        .bind(request.completed)
+        .bind(request.position)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(None);
+        }
+        let todo = touch_todo(&mut tx, todo_id).await?;
+
+        tx.commit().await?;
+        Ok(Some(todo))
+    }
+
+    /// Remove a checklist item. `None` if the todo has no such item.
+    pub async fn delete_checklist_item(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        item_id: Uuid,
+    ) -> ContrivanceResult<Option<common::Todo>> {
+        let mut tx = conn.begin().await?;
+
+        let deleted = sqlx::query("DELETE FROM todo_checklist_items WHERE id = $2 AND todo_id = $1")
+            .bind(todo_id)
+            .bind(item_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        if deleted == 0 {
+            return Ok(None);
+        }
+        let todo = touch_todo(&mut tx, todo_id).await?;
+
+        tx.commit().await?;
+        Ok(Some(todo))
+    }
+
+    /// Delete a todo - allow deletion if user created the todo
+    pub async fn delete_todo(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        todo_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<bool> {
+        let result = sqlx::query!(
+            "DELETE FROM todos WHERE id = $1 AND user_id = $2",
+            todo_id,
+            user_id
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Get todo statistics for a spreadsheet - include todos assigned to user
+    pub async fn get_todo_stats(
+        &self,
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<common::TodoStats> {
+        let stats = sqlx::query_as::<_, common::TodoStats>(
+            r#"
+            SELECT
+                COUNT(*) as total,
+                COUNT(CASE WHEN completed = true THEN 1 END) as completed,
+                COUNT(CASE WHEN completed = false THEN 1 END) as pending,
+                COUNT(CASE WHEN priority = 'high' THEN 1 END) as high_priority,
+                COUNT(CASE WHEN priority = 'medium' THEN 1 END) as medium_priority,
+                COUNT(CASE WHEN priority = 'low' THEN 1 END) as low_priority,
+                COUNT(CASE WHEN completed = false AND EXISTS (
+                    SELECT 1 FROM todo_dependencies d JOIN todos b ON b.id = d.blocked_by_id
+                    WHERE d.todo_id = t.id AND NOT b.completed
+                ) THEN 1 END) as blocked
+            FROM todos t
+            WHERE spreadsheet_id = $1 AND (user_id = $2 OR assigned_to = $2)
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(stats)
+    }
+
+    /// Get the users in the user's organization for the assignment dropdown
+    pub async fn get_users_for_assignment(&self, user_id: Uuid) -> ContrivanceResult<Vec<common::User>> {
+        let users = sqlx::query_as::<_, common::User>(
+            r#"
+            SELECT u.id, u.email, u.password_hash, u.name, u.role, u.created_at, u.updated_at, u.is_active, u.last_login
+            FROM users u
+            JOIN organization_members m ON m.user_id = u.id
+            WHERE u.is_active = true AND m.organization_id = user_organization($1)
+            ORDER BY u.name ASC
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(users)
+    }
+
+    /// Count a filtered read against each column, restarting the count for
+    /// columns not filtered on since `idle_since`. Returns the new counts.
+    pub async fn record_column_filters(
+        &self,
+        spreadsheet_id: Uuid,
+        column_ids: &[Uuid],
+        idle_since: DateTime<Utc>,
+    ) -> ContrivanceResult<Vec<i64>> {
+        let counts = sqlx::query_scalar(
+            r#"
+            INSERT INTO spreadsheet_column_usage (column_id, spreadsheet_id, filter_count, last_filtered_at)
+            SELECT column_id, $2, 1, NOW() FROM UNNEST($1::uuid[]) AS column_id
+            ON CONFLICT (column_id) DO UPDATE
+            SET filter_count = CASE
+                    WHEN spreadsheet_column_usage.last_filtered_at < $3 THEN 1
+                    ELSE spreadsheet_column_usage.filter_count + 1
+                END,
+                last_filtered_at = NOW()
+            RETURNING filter_count
+            "#
+        )
+        .bind(column_ids)
+        .bind(spreadsheet_id)
+        .bind(idle_since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(counts)
+    }
+
+    /// Columns filtered on at least `threshold` times and since `idle_since`
+    pub async fn get_frequently_filtered_columns(
+        &self,
+        spreadsheet_id: Uuid,
+        threshold: i64,
+        idle_since: DateTime<Utc>,
+    ) -> ContrivanceResult<Vec<Uuid>> {
+        let column_ids = sqlx::query_scalar(
+            r#"
+            SELECT column_id FROM spreadsheet_column_usage
+            WHERE spreadsheet_id = $1 AND filter_count >= $2 AND last_filtered_at >= $3
+            "#
+        )
+        .bind(spreadsheet_id)
+        .bind(threshold)
+        .bind(idle_since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(column_ids)
+    }
+
+    /// Managed cell indexes recorded for a spreadsheet
+    pub async fn get_cell_indexes(&self, spreadsheet_id: Uuid) -> ContrivanceResult<Vec<CellIndex>> {
+        let indexes = sqlx::query_as::<_, CellIndex>(
+            r#"
+            SELECT column_id, spreadsheet_id, index_name, definition, reason
+            FROM spreadsheet_cell_indexes
+            WHERE spreadsheet_id = $1
+            "#
+        )
+        .bind(spreadsheet_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(indexes)
+    }
+
+    /// Spreadsheets that have, or may need, managed cell indexes. Deleted
+    /// spreadsheets stay here until their indexes are dropped.
+    pub async fn get_managed_spreadsheet_ids(&self) -> ContrivanceResult<Vec<Uuid>> {
+        let ids = sqlx::query_scalar(
+            r#"
+            SELECT spreadsheet_id FROM spreadsheet_cell_indexes
+            UNION
+            SELECT spreadsheet_id FROM spreadsheet_column_usage
+            UNION
+            SELECT spreadsheet_id FROM spreadsheet_columns WHERE display_options->>'indexed' = 'true'
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(ids)
+    }
+
+    /// Record a managed cell index, or update why it exists
+    pub async fn save_cell_index(&self, index: &CellIndex) -> ContrivanceResult<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO spreadsheet_cell_indexes (column_id, spreadsheet_id, index_name, definition, reason)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (column_id) DO UPDATE
+            SET index_name = EXCLUDED.index_name, definition = EXCLUDED.definition, reason = EXCLUDED.reason
+            "#
+        )
+        .bind(index.column_id)
+        .bind(index.spreadsheet_id)
+        .bind(&index.index_name)
+        .bind(&index.definition)
+        .bind(&index.reason)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Build a cell index without blocking writes to `spreadsheet_rows`.
+    /// Returns false if another instance is already working on it.
+    pub async fn create_cell_index(&self, index: &CellIndex) -> ContrivanceResult<bool> {
+        let built = self.run_cell_index_ddl(index, &[
+            // A build that failed part way leaves an invalid index behind
+            format!("DROP INDEX CONCURRENTLY IF EXISTS {}", index.index_name),
+            format!("CREATE INDEX CONCURRENTLY {} {}", index.index_name, index.definition),
+            // The planner needs statistics on the new expression before it will use it
+            "ANALYZE spreadsheet_rows".to_string(),
+        ])
+        .await?;
+
+        if built {
+            self.save_cell_index(index).await?;
+        }
+        Ok(built)
+    }
+
+    /// Drop a cell index without blocking writes to `spreadsheet_rows`.
+    /// Returns false if another instance is already working on it.
+    pub async fn drop_cell_index(&self, index: &CellIndex) -> ContrivanceResult<bool> {
+        let dropped = self
+            .run_cell_index_ddl(index, &[format!("DROP INDEX CONCURRENTLY IF EXISTS {}", index.index_name)])
+            .await?;
+
+        if dropped {
+            sqlx::query("DELETE FROM spreadsheet_cell_indexes WHERE column_id = $1")
+                .bind(index.column_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(dropped)
+    }
+
+    /// Create a workspace in the owner's organization, making the owner its first admin
+    pub async fn create_workspace(
+        &self,
+        request: &CreateWorkspaceRequest,
+        owner_id: Uuid,
+    ) -> ContrivanceResult<Workspace> {
+        let mut tx = self.pool.begin().await?;
+
+        let workspace = sqlx::query_as::<_, Workspace>(&format!(
+            r#"
+            INSERT INTO workspaces (name, owner_id, default_permission, organization_id)
+            VALUES ($1, $2, $3, user_organization($2))
+            RETURNING {WORKSPACE_FIELDS}
+            "#
+        ))
+        .bind(&request.name)
+        .bind(owner_id)
+        .bind(request.default_permission.unwrap_or(DefaultPermission::None))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query("INSERT INTO workspace_members (workspace_id, user_id, role) VALUES ($1, $2, $3)")
+            .bind(workspace.id)
+            .bind(owner_id)
+            .bind(WorkspaceRole::Admin)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(workspace)
+    }
+
+    /// Workspaces a user is a member of, by name
+    pub async fn list_workspaces(&self, user_id: Uuid) -> ContrivanceResult<Vec<Workspace>> {
+        let workspaces = sqlx::query_as::<_, Workspace>(&format!(
+            r#"
+            SELECT {WORKSPACE_FIELDS}
+            FROM workspaces
+            WHERE id IN (SELECT workspace_id FROM workspace_members WHERE user_id = $1)
+            ORDER BY lower(name), id
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(workspaces)
+    }
+
+    pub async fn get_workspace(&self, workspace_id: Uuid) -> ContrivanceResult<Option<Workspace>> {
+        let workspace = sqlx::query_as::<_, Workspace>(&format!(
+            "SELECT {WORKSPACE_FIELDS} FROM workspaces WHERE id = $1"
+        ))
+        .bind(workspace_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(workspace)
+    }
+
+    /// Rename a workspace or change its default permission
+    pub async fn update_workspace(
+        &self,
+        workspace_id: Uuid,
+        request: &UpdateWorkspaceRequest,
+    ) -> ContrivanceResult<Workspace> {
+        let workspace = sqlx::query_as::<_, Workspace>(&format!(
+            r#"
+            UPDATE workspaces
+            SET name = COALESCE($2, name),
+                default_permission = COALESCE($3, default_permission),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {WORKSPACE_FIELDS}
+            "#
+        ))
+        .bind(workspace_id)
+        .bind(&request.name)
+        .bind(request.default_permission)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Workspace not found"))?;
+
+        Ok(workspace)
+    }
+
+    /// Delete a workspace and its folders; its spreadsheets are kept, unfiled
+    pub async fn delete_workspace(&self, workspace_id: Uuid) -> ContrivanceResult<()> {
+        let result = sqlx::query("DELETE FROM workspaces WHERE id = $1")
+            .bind(workspace_id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(ContrivanceError::not_found("Workspace not found"));
+        }
+
+        Ok(())
+    }
+
+    /// A user's role in a workspace, `None` if they aren't a member or the
+    /// workspace is in another organization
+    pub async fn get_workspace_role(
+        &self,
+        workspace_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<WorkspaceRole>> {
+        let role = sqlx::query_scalar(
+            r#"
+            SELECT m.role
+            FROM workspace_members m
+            JOIN workspaces w ON w.id = m.workspace_id
+            WHERE m.workspace_id = $1 AND m.user_id = $2 AND w.organization_id = user_organization($2)
+            "#
+        )
+        .bind(workspace_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    pub async fn list_workspace_members(&self, workspace_id: Uuid) -> ContrivanceResult<Vec<WorkspaceMember>> {
+        let members = sqlx::query_as::<_, WorkspaceMember>(
+            r#"
+            SELECT m.user_id, u.name, u.email, m.role, m.added_at
+            FROM workspace_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.workspace_id = $1
+            ORDER BY m.role, u.name
+            "#
+        )
+        .bind(workspace_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Add a workspace member, or change the role of an existing one
+    pub async fn add_workspace_member(
+        &self,
+        workspace_id: Uuid,
+        user_id: Uuid,
+        role: WorkspaceRole,
+    ) -> ContrivanceResult<WorkspaceMember> {
+        let member = sqlx::query_as::<_, WorkspaceMember>(
+            r#"
+            WITH m AS (
+                INSERT INTO workspace_members (workspace_id, user_id, role)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (workspace_id, user_id) DO UPDATE SET role = EXCLUDED.role
+                RETURNING user_id, role, added_at
+            )
+            SELECT m.user_id, u.name, u.email, m.role, m.added_at
+            FROM m JOIN users u ON u.id = m.user_id
+            "#
+        )
+        .bind(workspace_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Remove a workspace member, returning whether they were one
+    pub async fn remove_workspace_member(&self, workspace_id: Uuid, user_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2")
+            .bind(workspace_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The permission a user gets on spreadsheets filed at a workspace
+    /// location through the folder and workspace defaults, `None` for none
+    pub async fn workspace_permission(
+        &self,
+        user_id: Uuid,
+        location: WorkspaceLocation,
+    ) -> ContrivanceResult<Option<DefaultPermission>> {
+        let permission = sqlx::query_scalar("SELECT workspace_permission($1, $2, $3)")
+            .bind(user_id)
+            .bind(location.workspace_id)
+            .bind(location.folder_id)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(permission)
+    }
+
+    /// Check a workspace and optional folder exist and belong together. A
+    /// folder on its own implies its workspace.
+    pub async fn resolve_location(
+        &self,
+        workspace_id: Option<Uuid>,
+        folder_id: Option<Uuid>,
+    ) -> ContrivanceResult<Option<WorkspaceLocation>> {
+        if let Some(folder_id) = folder_id {
+            let folder = self.get_folder(folder_id).await?
+                .ok_or_else(|| ContrivanceError::not_found("Folder not found"))?;
+            if workspace_id.is_some_and(|id| id != folder.workspace_id) {
+                return Err(ContrivanceError::validation("Folder is not in that workspace"));
+            }
+            return Ok(Some(WorkspaceLocation { workspace_id: folder.workspace_id, folder_id: Some(folder.id) }));
+        }
+
+        match workspace_id {
+            Some(workspace_id) => {
+                self.get_workspace(workspace_id).await?
+                    .ok_or_else(|| ContrivanceError::not_found("Workspace not found"))?;
+                Ok(Some(WorkspaceLocation { workspace_id, folder_id: None }))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Create a folder; `parent_id` must be a folder in the same workspace
+    pub async fn create_folder(
+        &self,
+        location: WorkspaceLocation,
+        name: &str,
+        default_permission: DefaultPermission,
+        created_by: Uuid,
+    ) -> ContrivanceResult<Folder> {
+        let folder = sqlx::query_as::<_, Folder>(&format!(
+            r#"
+            INSERT INTO folders (workspace_id, parent_id, name, default_permission, created_by)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING {FOLDER_FIELDS}
+            "#
+        ))
+        .bind(location.workspace_id)
+        .bind(location.folder_id)
+        .bind(name)
+        .bind(default_permission)
+        .bind(created_by)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(folder_name_taken)?;
+
+        Ok(folder)
+    }
+
+    pub async fn get_folder(&self, folder_id: Uuid) -> ContrivanceResult<Option<Folder>> {
+        let folder = sqlx::query_as::<_, Folder>(&format!(
+            "SELECT {FOLDER_FIELDS} FROM folders WHERE id = $1"
+        ))
+        .bind(folder_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(folder)
+    }
+
+    /// A folder and its ancestors, from the workspace root down
+    pub async fn get_folder_path(&self, folder_id: Uuid) -> ContrivanceResult<Vec<Folder>> {
+        let path = sqlx::query_as::<_, Folder>(&format!(
+            r#"
+            WITH RECURSIVE path AS (
+                SELECT f.*, 0 AS depth FROM folders f WHERE f.id = $1
+                UNION ALL
+                SELECT f.*, p.depth + 1 FROM folders f JOIN path p ON f.id = p.parent_id
+                WHERE p.depth < 64
+            )
+            SELECT {FOLDER_FIELDS} FROM path ORDER BY depth DESC
+            "#
+        ))
+        .bind(folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(path)
+    }
+
+    /// Folders directly inside a workspace location, by name, with how many
+    /// subfolders and (visible to the user) spreadsheets each holds
+    pub async fn list_folders(
+        &self,
+        user_id: Uuid,
+        location: WorkspaceLocation,
+    ) -> ContrivanceResult<Vec<FolderSummary>> {
+        let folders = sqlx::query_as::<_, FolderSummary>(&format!(
+            r#"
+            SELECT f.id, f.workspace_id, f.parent_id, f.name, f.default_permission, f.created_by, f.created_at, f.updated_at,
+                (SELECT COUNT(*) FROM folders c WHERE c.parent_id = f.id) AS folder_count,
+                (SELECT COUNT(*) FROM spreadsheets s WHERE s.folder_id = f.id AND {VISIBLE_SPREADSHEET}) AS spreadsheet_count
+            FROM folders f
+            WHERE f.workspace_id = $2 AND f.parent_id IS NOT DISTINCT FROM $3::uuid
+            ORDER BY lower(f.name), f.id
+            "#
+        ))
+        .bind(user_id)
+        .bind(location.workspace_id)
+        .bind(location.folder_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(folders)
+    }
+
+    /// Spreadsheets filed directly in a workspace location that the user can see
+    pub async fn list_location_spreadsheets(
+        &self,
+        user_id: Uuid,
+        location: WorkspaceLocation,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<Spreadsheet>> {
+        self.list_visible_spreadsheets(user_id, pagination, Some(location)).await
+    }
+
+    /// Rename a folder or change its default permission
+    pub async fn update_folder(&self, folder_id: Uuid, request: &UpdateFolderRequest) -> ContrivanceResult<Folder> {
+        let folder = sqlx::query_as::<_, Folder>(&format!(
+            r#"
+            UPDATE folders
+            SET name = COALESCE($2, name),
+                default_permission = COALESCE($3, default_permission),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {FOLDER_FIELDS}
+            "#
+        ))
+        .bind(folder_id)
+        .bind(&request.name)
+        .bind(request.default_permission)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(folder_name_taken)?
+        .ok_or_else(|| ContrivanceError::not_found("Folder not found"))?;
+
+        Ok(folder)
+    }
+
+    /// Move a folder under another folder in its workspace, or to the root.
+    /// Moves within a workspace are serialized so two can't form a cycle.
+    pub async fn move_folder(&self, folder_id: Uuid, parent_id: Option<Uuid>) -> ContrivanceResult<Folder> {
+        let mut tx = self.pool.begin().await?;
+
+        let workspace_id: Uuid = sqlx::query_scalar("SELECT workspace_id FROM folders WHERE id = $1")
+            .bind(folder_id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Folder not found"))?;
+
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+            .bind(workspace_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if let Some(parent_id) = parent_id {
+            // The new parent can't be the folder itself or anything beneath it
+            let cyclic: bool = sqlx::query_scalar(
+                r#"
+                WITH RECURSIVE ancestors AS (
+                    SELECT id, parent_id, 0 AS depth FROM folders WHERE id = $1
+                    UNION ALL
+                    SELECT f.id, f.parent_id, a.depth + 1 FROM folders f JOIN ancestors a ON f.id = a.parent_id
+                    WHERE a.depth < 64
+                )
+                SELECT EXISTS (SELECT 1 FROM ancestors WHERE id = $2)
+                "#
+            )
+            .bind(parent_id)
+            .bind(folder_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if cyclic {
+                return Err(ContrivanceError::validation(
+                    "A folder can't be moved into itself or one of its subfolders",
+                ));
+            }
+        }
+
+        let folder = sqlx::query_as::<_, Folder>(&format!(
+            r#"
+            UPDATE folders SET parent_id = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING {FOLDER_FIELDS}
+            "#
+        ))
+        .bind(folder_id)
+        .bind(parent_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(folder_name_taken)?;
+
+        tx.commit().await?;
+        Ok(folder)
+    }
+
+    /// Delete an empty folder
+    pub async fn delete_folder(&self, folder_id: Uuid) -> ContrivanceResult<()> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM folders f
+            WHERE f.id = $1
+              AND NOT EXISTS (SELECT 1 FROM folders c WHERE c.parent_id = f.id)
+              AND NOT EXISTS (SELECT 1 FROM spreadsheets s WHERE s.folder_id = f.id)
+            "#
+        )
+        .bind(folder_id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return match self.get_folder(folder_id).await? {
+                Some(_) => Err(ContrivanceError::conflict(
+                    "Folder is not empty; move or delete its contents first",
+                )),
+                None => Err(ContrivanceError::not_found("Folder not found")),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// File a spreadsheet at a workspace location, or take it out of
+    /// workspaces altogether
+    pub async fn move_spreadsheet(
+        &self,
+        spreadsheet_id: Uuid,
+        location: Option<WorkspaceLocation>,
+    ) -> ContrivanceResult<Spreadsheet> {
+        let spreadsheet = sqlx::query_as::<_, Spreadsheet>(&format!(
+            r#"
+            UPDATE spreadsheets SET workspace_id = $2, folder_id = $3
+            WHERE id = $1
+            RETURNING {SPREADSHEET_FIELDS}
+            "#
+        ))
+        .bind(spreadsheet_id)
+        .bind(location.map(|l| l.workspace_id))
+        .bind(location.and_then(|l| l.folder_id))
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Spreadsheet not found"))?;
+
+        Ok(spreadsheet)
+    }
+
+    /// A user's pinned spreadsheets and folders, most recently pinned first.
+    /// Pins the user has since lost access to are left out.
+    pub async fn get_favorites(&self, user_id: Uuid) -> ContrivanceResult<Favorites> {
+        let spreadsheets = sqlx::query_as::<_, Spreadsheet>(&format!(
+            r#"
+            SELECT {SPREADSHEET_FIELDS_S}
+            FROM favorites fav
+            JOIN spreadsheets s ON s.id = fav.spreadsheet_id
+            WHERE fav.user_id = $1 AND {VISIBLE_SPREADSHEET}
+            ORDER BY fav.created_at DESC
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let folders = sqlx::query_as::<_, Folder>(
+            r#"
+            SELECT f.id, f.workspace_id, f.parent_id, f.name, f.default_permission, f.created_by, f.created_at, f.updated_at
+            FROM favorites fav
+            JOIN folders f ON f.id = fav.folder_id
+            JOIN workspace_members m ON m.workspace_id = f.workspace_id AND m.user_id = fav.user_id
+            WHERE fav.user_id = $1
+            ORDER BY fav.created_at DESC
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Favorites { spreadsheets, folders })
+    }
+
+    /// Pin a spreadsheet or folder; pinning twice is a no-op
+    pub async fn add_favorite(&self, user_id: Uuid, target: FavoriteTarget) -> ContrivanceResult<()> {
+        let (spreadsheet_id, folder_id) = target.ids();
+        sqlx::query(
+            "INSERT INTO favorites (user_id, spreadsheet_id, folder_id) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING"
+        )
+        .bind(user_id)
+        .bind(spreadsheet_id)
+        .bind(folder_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Unpin a spreadsheet or folder
+    pub async fn remove_favorite(&self, user_id: Uuid, target: FavoriteTarget) -> ContrivanceResult<()> {
+        let (spreadsheet_id, folder_id) = target.ids();
+        sqlx::query(
+            r#"
+            DELETE FROM favorites
+            WHERE user_id = $1
+              AND spreadsheet_id IS NOT DISTINCT FROM $2::uuid
+              AND folder_id IS NOT DISTINCT FROM $3::uuid
+            "#
+        )
+        .bind(user_id)
+        .bind(spreadsheet_id)
+        .bind(folder_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The user's organization and their role in it
+    pub async fn get_membership(&self, user_id: Uuid) -> ContrivanceResult<Option<OrganizationMembership>> {
+        let membership = sqlx::query_as::<_, OrganizationMembership>(
+            r#"
+            SELECT o.id, o.name, o.created_at, o.updated_at, m.role
+            FROM organization_members m
+            JOIN organizations o ON o.id = m.organization_id
+            WHERE m.user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(membership)
+    }
+
+    pub async fn update_organization(&self, organization_id: Uuid, name: &str) -> ContrivanceResult<Organization> {
+        let organization = sqlx::query_as::<_, Organization>(
+            r#"
+            UPDATE organizations SET name = $2, updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, name, created_at, updated_at
+            "#
+        )
+        .bind(organization_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Organization not found"))?;
+
+        Ok(organization)
+    }
+
+    pub async fn list_organization_members(&self, organization_id: Uuid) -> ContrivanceResult<Vec<OrganizationMember>> {
+        let members = sqlx::query_as::<_, OrganizationMember>(
+            r#"
+            SELECT m.user_id, u.name, u.email, m.role, m.joined_at
+            FROM organization_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.organization_id = $1 AND u.is_active = true
+            ORDER BY u.name, u.id
+            "#
+        )
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Invite an email address to the organization, replacing any pending
+    /// invitation for it. Members of the organization can't be invited again.
+    pub async fn create_organization_invitation(
+        &self,
+        organization_id: Uuid,
+        email: &str,
+        role: OrganizationRole,
+        invited_by: Uuid,
+    ) -> ContrivanceResult<OrganizationInvitation> {
+        if self.get_user_id_by_email(organization_id, email).await?.is_some() {
+            return Err(ContrivanceError::conflict("They are already a member"));
+        }
+
+        let invitation = sqlx::query_as::<_, OrganizationInvitation>(&format!(
+            r#"
+            WITH invitation AS (
+                INSERT INTO organization_invitations (organization_id, email, role, invited_by)
+                VALUES ($1, lower($2), $3, $4)
+                ON CONFLICT (organization_id, lower(email))
+                DO UPDATE SET role = EXCLUDED.role, invited_by = EXCLUDED.invited_by, created_at = NOW()
+                RETURNING *
+            )
+            SELECT {INVITATION_FIELDS} FROM invitation i JOIN organizations o ON o.id = i.organization_id
+            "#
+        ))
+        .bind(organization_id)
+        .bind(email.trim())
+        .bind(role)
+        .bind(invited_by)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(invitation)
+    }
+
+    /// The organization's pending invitations, newest first
+    pub async fn list_organization_invitations(&self, organization_id: Uuid) -> ContrivanceResult<Vec<OrganizationInvitation>> {
+        let invitations = sqlx::query_as::<_, OrganizationInvitation>(&format!(
+            r#"
+            SELECT {INVITATION_FIELDS}
+            FROM organization_invitations i
+            JOIN organizations o ON o.id = i.organization_id
+            WHERE i.organization_id = $1
+            ORDER BY i.created_at DESC, i.id
+            "#
+        ))
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invitations)
+    }
+
+    /// Invitations addressed to the user's email, from organizations other
+    /// than their own
+    pub async fn list_received_invitations(&self, user_id: Uuid) -> ContrivanceResult<Vec<OrganizationInvitation>> {
+        let invitations = sqlx::query_as::<_, OrganizationInvitation>(&format!(
+            r#"
+            SELECT {INVITATION_FIELDS}
+            FROM organization_invitations i
+            JOIN organizations o ON o.id = i.organization_id
+            JOIN users u ON lower(u.email) = lower(i.email)
+            WHERE u.id = $1 AND i.organization_id IS DISTINCT FROM user_organization($1)
+            ORDER BY i.created_at DESC, i.id
+            "#
+        ))
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(invitations)
+    }
+
+    /// Withdraw one of the organization's invitations. Returns false if there
+    /// was no such invitation.
+    pub async fn delete_organization_invitation(&self, organization_id: Uuid, invitation_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM organization_invitations WHERE id = $1 AND organization_id = $2")
+            .bind(invitation_id)
+            .bind(organization_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Decline an invitation addressed to the user. Returns false if there
+    /// was no such invitation.
+    pub async fn decline_organization_invitation(&self, invitation_id: Uuid, user_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM organization_invitations i
+            USING users u
+            WHERE i.id = $1 AND u.id = $2 AND lower(u.email) = lower(i.email)
+            "#
+        )
+        .bind(invitation_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Accept an invitation addressed to the user, moving them into its
+    /// organization. They must be the only member of their current
+    /// organization, which is deleted. What they have there only comes along
+    /// with `bring_data` (off any team); without it, anything left makes this
+    /// a conflict rather than being moved or deleted.
+    pub async fn accept_organization_invitation(
+        &self,
+        invitation_id: Uuid,
+        user_id: Uuid,
+        bring_data: bool,
+    ) -> ContrivanceResult<OrganizationMember> {
+        let mut tx = self.pool.begin().await?;
+
+        let (organization_id, role): (Uuid, OrganizationRole) = sqlx::query_as(
+            r#"
+            SELECT i.organization_id, i.role
+            FROM organization_invitations i
+            JOIN users u ON lower(u.email) = lower(i.email)
+            WHERE i.id = $1 AND u.id = $2 AND u.is_active = true
+            FOR UPDATE OF i
+            "#
+        )
+        .bind(invitation_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Invitation not found"))?;
+
+        let current: Uuid = sqlx::query_scalar(
+            "SELECT organization_id FROM organization_members WHERE user_id = $1 FOR UPDATE"
+        )
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("User not found"))?;
+        if current == organization_id {
+            return Err(ContrivanceError::conflict("You are already a member"));
+        }
+
+        let others: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM organization_members WHERE organization_id = $1 AND user_id <> $2"
+        )
+        .bind(current)
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        if others > 0 {
+            return Err(ContrivanceError::conflict("Leave your current organization before joining another"));
+        }
+
+        if bring_data {
+            for statement in [
+                "UPDATE spreadsheets SET organization_id = $2, team_id = NULL WHERE organization_id = $1",
+                "UPDATE workspaces SET organization_id = $2 WHERE organization_id = $1",
+                "UPDATE discovery_sessions SET organization_id = $2 WHERE organization_id = $1",
+            ] {
+                sqlx::query(statement)
+                    .bind(current)
+                    .bind(organization_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        } else {
+            let has_data: bool = sqlx::query_scalar(
+                r#"
+                SELECT EXISTS (SELECT 1 FROM spreadsheets WHERE organization_id = $1)
+                    OR EXISTS (SELECT 1 FROM workspaces WHERE organization_id = $1)
+                    OR EXISTS (SELECT 1 FROM discovery_sessions WHERE organization_id = $1)
+                "#
+            )
+            .bind(current)
+            .fetch_one(&mut *tx)
+            .await?;
+            if has_data {
+                return Err(ContrivanceError::conflict(
+                    "Your organization still has spreadsheets, workspaces or discovery sessions; \
+                     accept with bring_data to bring them along, or delete them first",
+                ));
+            }
+        }
+
+        sqlx::query(
+            "UPDATE organization_members SET organization_id = $2, role = $3, joined_at = NOW() WHERE user_id = $1"
+        )
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(role)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(current)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM organization_invitations WHERE id = $1")
+            .bind(invitation_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let member = sqlx::query_as::<_, OrganizationMember>(
+            r#"
+            SELECT m.user_id, u.name, u.email, m.role, m.joined_at
+            FROM organization_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(member)
+    }
+
+    /// A member's role, `None` if the user isn't in the organization
+    pub async fn get_organization_role(
+        &self,
+        organization_id: Uuid,
+        user_id: Uuid,
+    ) -> ContrivanceResult<Option<OrganizationRole>> {
+        let role = sqlx::query_scalar(
+            "SELECT role FROM organization_members WHERE organization_id = $1 AND user_id = $2"
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(role)
+    }
+
+    pub async fn set_organization_role(
+        &self,
+        organization_id: Uuid,
+        user_id: Uuid,
+        role: OrganizationRole,
+    ) -> ContrivanceResult<Option<OrganizationMember>> {
+        let member = sqlx::query_as::<_, OrganizationMember>(
+            r#"
+            WITH updated AS (
+                UPDATE organization_members SET role = $3
+                WHERE organization_id = $1 AND user_id = $2
+                RETURNING user_id, role, joined_at
+            )
+            SELECT m.user_id, u.name, u.email, m.role, m.joined_at
+            FROM updated m
+            JOIN users u ON u.id = m.user_id
+            "#
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    /// Remove a member, dropping their team and workspace memberships and
+    /// collaborations in the organization, and give them a new organization of
+    /// their own. What they own stays behind. Returns false if they weren't a member.
+    pub async fn remove_organization_member(&self, organization_id: Uuid, user_id: Uuid) -> ContrivanceResult<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let member: Option<Uuid> = sqlx::query_scalar(
+            "SELECT user_id FROM organization_members WHERE organization_id = $1 AND user_id = $2 FOR UPDATE"
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if member.is_none() {
+            return Ok(false);
+        }
+
+        for statement in [
+            "DELETE FROM team_members WHERE user_id = $2 AND team_id IN (SELECT id FROM teams WHERE organization_id = $1)",
+            "DELETE FROM workspace_members WHERE user_id = $2 AND workspace_id IN (SELECT id FROM workspaces WHERE organization_id = $1)",
+            "DELETE FROM spreadsheet_collaborators WHERE user_id = $2 AND spreadsheet_id IN (SELECT id FROM spreadsheets WHERE organization_id = $1)",
+            "DELETE FROM favorites WHERE user_id = $2",
+        ] {
+            sqlx::query(statement)
+                .bind(organization_id)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        sqlx::query(
+            r#"
+            WITH personal AS (
+                INSERT INTO organizations (name) SELECT name FROM users WHERE id = $1 RETURNING id
+            )
+            UPDATE organization_members SET organization_id = (SELECT id FROM personal), role = 'owner', joined_at = NOW()
+            WHERE user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Create a team; team names are unique within an organization
+    pub async fn create_team(&self, organization_id: Uuid, request: &CreateTeamRequest) -> ContrivanceResult<Team> {
+        let team = sqlx::query_as::<_, Team>(&format!(
+            "INSERT INTO teams (organization_id, name, description) VALUES ($1, $2, $3) RETURNING {TEAM_FIELDS}"
+        ))
+        .bind(organization_id)
+        .bind(request.name.trim())
+        .bind(&request.description)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(team_name_taken)?;
+
+        Ok(team)
+    }
+
+    pub async fn list_teams(&self, organization_id: Uuid) -> ContrivanceResult<Vec<Team>> {
+        let teams = sqlx::query_as::<_, Team>(&format!(
+            "SELECT {TEAM_FIELDS} FROM teams WHERE organization_id = $1 ORDER BY lower(name), id"
+        ))
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(teams)
+    }
+
+    /// Get a team in an organization; teams elsewhere are reported missing
+    pub async fn get_team(&self, organization_id: Uuid, team_id: Uuid) -> ContrivanceResult<Option<Team>> {
+        let team = sqlx::query_as::<_, Team>(&format!(
+            "SELECT {TEAM_FIELDS} FROM teams WHERE id = $1 AND organization_id = $2"
+        ))
+        .bind(team_id)
+        .bind(organization_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(team)
+    }
+
+    pub async fn update_team(&self, team_id: Uuid, request: &UpdateTeamRequest) -> ContrivanceResult<Team> {
+        let team = sqlx::query_as::<_, Team>(&format!(
+            r#"
+            UPDATE teams
+            SET name = COALESCE($2, name),
+                description = COALESCE($3, description),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING {TEAM_FIELDS}
+            "#
+        ))
+        .bind(team_id)
+        .bind(request.name.as_deref().map(str::trim))
+        .bind(&request.description)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(team_name_taken)?
+        .ok_or_else(|| ContrivanceError::not_found("Team not found"))?;
+
+        Ok(team)
+    }
+
+    /// Delete a team; its spreadsheets stay in the organization, owned by no team
+    pub async fn delete_team(&self, team_id: Uuid) -> ContrivanceResult<()> {
+        sqlx::query("DELETE FROM teams WHERE id = $1")
+            .bind(team_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// A user's role in a team, `None` if they aren't on it
+    pub async fn get_team_role(&self, team_id: Uuid, user_id: Uuid) -> ContrivanceResult<Option<TeamRole>> {
+        let role = sqlx::query_scalar("SELECT role FROM team_members WHERE team_id = $1 AND user_id = $2")
+            .bind(team_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(role)
+    }
+
+    pub async fn list_team_members(&self, team_id: Uuid) -> ContrivanceResult<Vec<TeamMember>> {
+        let members = sqlx::query_as::<_, TeamMember>(
+            r#"
+            SELECT m.user_id, u.name, u.email, m.role, m.added_at
+            FROM team_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.team_id = $1
+            ORDER BY m.role, u.name
+            "#
+        )
+        .bind(team_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(members)
+    }
+
+    /// Add a member to a team, or change their role if already on it
+    pub async fn add_team_member(&self, team_id: Uuid, user_id: Uuid, role: TeamRole) -> ContrivanceResult<TeamMember> {
+        let member = sqlx::query_as::<_, TeamMember>(
+            r#"
+            WITH upserted AS (
+                INSERT INTO team_members (team_id, user_id, role) VALUES ($1, $2, $3)
+                ON CONFLICT (team_id, user_id) DO UPDATE SET role = EXCLUDED.role
+                RETURNING user_id, role, added_at
+            )
+            SELECT m.user_id, u.name, u.email, m.role, m.added_at
+            FROM upserted m
+            JOIN users u ON u.id = m.user_id
+            "#
+        )
+        .bind(team_id)
+        .bind(user_id)
+        .bind(role)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(member)
+    }
+
+    pub async fn remove_team_member(&self, team_id: Uuid, user_id: Uuid) -> ContrivanceResult<bool> {
+        let result = sqlx::query("DELETE FROM team_members WHERE team_id = $1 AND user_id = $2")
+            .bind(team_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether a user can give spreadsheets to a team: it's in their
+    /// organization and they're on it or an organization admin
+    pub async fn can_user_use_team(&self, user_id: Uuid, team_id: Uuid) -> ContrivanceResult<bool> {
+        let allowed: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM teams t
+                JOIN organization_members m ON m.organization_id = t.organization_id AND m.user_id = $1
+                WHERE t.id = $2
+                  AND (m.role IN ('owner', 'admin')
+                       OR EXISTS (SELECT 1 FROM team_members tm WHERE tm.team_id = t.id AND tm.user_id = $1))
+            )
+            "#
+        )
+        .bind(user_id)
+        .bind(team_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(allowed)
+    }
+
+    /// Set or clear the team that owns a spreadsheet
+    pub async fn assign_spreadsheet_team(
+        &self,
+        spreadsheet_id: Uuid,
+        team_id: Option<Uuid>,
+    ) -> ContrivanceResult<Spreadsheet> {
+        let spreadsheet = sqlx::query_as::<_, Spreadsheet>(&format!(
+            "UPDATE spreadsheets SET team_id = $2 WHERE id = $1 RETURNING {SPREADSHEET_FIELDS}"
+        ))
+        .bind(spreadsheet_id)
+        .bind(team_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| ContrivanceError::not_found("Spreadsheet not found"))?;
+
+        Ok(spreadsheet)
+    }
+
+    /// Run index DDL on one connection while holding a session advisory lock
+    /// on the index name, so two instances never build the same index.
+    /// `CONCURRENTLY` can't run in a transaction, hence the session lock.
+    async fn run_cell_index_ddl(&self, index: &CellIndex, statements: &[String]) -> ContrivanceResult<bool> {
+        let mut conn = self.pool.acquire().await?;
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock(hashtext($1))")
+            .bind(&index.index_name)
+            .fetch_one(&mut *conn)
+            .await?;
+        if !locked {
+            return Ok(false);
+        }
+
+        let mut result = Ok(());
+        for statement in statements {
+            if let Err(e) = sqlx::Executor::execute(&mut *conn, statement.as_str()).await {
+                result = Err(e);
+                break;
+            }
+        }
+
+        let unlocked = sqlx::query("SELECT pg_advisory_unlock(hashtext($1))")
+            .bind(&index.index_name)
+            .execute(&mut *conn)
+            .await;
+        if unlocked.is_err() {
+            // Never hand a connection that may still hold the lock back to the pool
+            drop(conn.detach());
+        }
+
+        result?;
+        Ok(true)
+    }
+}
+
+/// Report a team name clash as such rather than a bare constraint violation
+fn team_name_taken(e: sqlx::Error) -> ContrivanceError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            ContrivanceError::conflict("A team with this name already exists")
+        }
+        _ => e.into(),
+    }
+}
+
+/// The spreadsheet a todo is on
+async fn todo_spreadsheet(
+    conn: &mut sqlx::PgConnection,
+    todo_id: Uuid,
+) -> ContrivanceResult<Option<Uuid>> {
+    let spreadsheet_id = sqlx::query_scalar("SELECT spreadsheet_id FROM todos WHERE id = $1")
+        .bind(todo_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(spreadsheet_id)
+}
+
+/// Serialize changes to which todos wait on which within a spreadsheet, so
+/// two made at once can't form a cycle
+async fn lock_todo_graph(conn: &mut sqlx::PgConnection, spreadsheet_id: Uuid) -> ContrivanceResult<()> {
+    sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1::text))")
+        .bind(format!("todos:{}", spreadsheet_id))
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Whether `todo_id` is, or waits on, `other_id`: directly or through the
+/// todos it's blocked by and its subtasks, at any depth
+async fn todo_waits_on(
+    conn: &mut sqlx::PgConnection,
+    todo_id: Uuid,
+    other_id: Uuid,
+) -> ContrivanceResult<bool> {
+    let waits = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE waits_on(id) AS (
+            SELECT $1::uuid
+            UNION
+            SELECT edge.waits_on
+            FROM waits_on w
+            JOIN (
+                SELECT todo_id AS id, blocked_by_id AS waits_on FROM todo_dependencies
+                UNION ALL
+                SELECT parent_id, id FROM todos WHERE parent_id IS NOT NULL
+            ) edge ON edge.id = w.id
+        )
+        SELECT EXISTS (SELECT 1 FROM waits_on WHERE id = $2)
+        "#,
+    )
+    .bind(todo_id)
+    .bind(other_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    Ok(waits)
+}
+
+/// Mark a todo changed after editing its dependencies or checklist, returning it
+async fn touch_todo(conn: &mut sqlx::PgConnection, todo_id: Uuid) -> ContrivanceResult<common::Todo> {
+    let todo = sqlx::query_as::<_, common::Todo>(&format!(
+        "UPDATE todos SET updated_at = CURRENT_TIMESTAMP WHERE id = $1 RETURNING {TODO_FIELDS}"
+    ))
+    .bind(todo_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+    Ok(todo)
+}
+
+/// Report moving an occurrence onto another occurrence of its series as such
+/// rather than a bare constraint violation
+fn occurrence_taken(e: sqlx::Error) -> ContrivanceError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            ContrivanceError::conflict("Another occurrence of this series is already scheduled then")
+        }
+        _ => e.into(),
+    }
+}
+
+/// Refuse a row write that changes a cell someone else holds the lock on.
+/// `locks` are the other users' live locks on the row, by column id and holder name.
+fn check_cell_locks(
+    columns: &[SpreadsheetColumn],
+    locks: &[(Uuid, String)],
+    existing: &serde_json::Value,
+    row_data: &serde_json::Value,
+) -> ContrivanceResult<()> {
+    for (column_id, user_name) in locks {
+        let key = column_id.to_string();
+        if row_data.get(&key) != existing.get(&key) {
+            let column = columns.iter().find(|column| column.id == *column_id);
+            return Err(ContrivanceError::conflict(format!(
+                "{} is being edited by {}",
+                column.map_or("This cell", |column| column.name.as_str()),
+                user_name,
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Report a sibling folder name clash as such rather than a bare constraint violation
+fn folder_name_taken(e: sqlx::Error) -> ContrivanceError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => {
+            ContrivanceError::conflict("A folder with this name already exists here")
+        }
+        _ => e.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::testing::{create_user, test_pool};
+
+    /// The repository on the test database; see `common::testing`
+    async fn test_repository() -> ContrivanceRepository {
+        ContrivanceRepository::new(test_pool().await)
+    }
+
+    fn spreadsheet_request(is_public: bool) -> CreateSpreadsheetRequest {
+        CreateSpreadsheetRequest {
+            name: "Pipeline".to_string(),
+            description: None,
+            is_public: Some(is_public),
+            settings: None,
+            columns: None,
+            workspace_id: None,
+            folder_id: None,
+            team_id: None,
+        }
+    }
+
+    fn first_page() -> PaginationParams {
+        PaginationParams { page: None, limit: Some(100), sort_by: None, sort_order: None, cursor: None }
+    }
+
+    #[test]
+    fn test_locked_cells_can_only_be_left_as_they_are() {
+        let stage = SpreadsheetColumn {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::new_v4(),
+            name: "Stage".to_string(),
+            column_type: common::ColumnType::Text,
+            position: 0,
+            is_required: Some(false),
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+            created_at: None,
+            updated_at: None,
+        };
+        let (stage_key, notes_key, gone) = (stage.key(), Uuid::new_v4().to_string(), Uuid::new_v4());
+        let existing = serde_json::json!({ &stage_key: "POC", &notes_key: "Call back" });
+        let locks = vec![(stage.id, "Bob".to_string())];
+        let columns = vec![stage];
+
+        // Other cells change freely
+        let edit = serde_json::json!({ &stage_key: "POC", &notes_key: "Called" });
+        assert!(check_cell_locks(&columns, &locks, &existing, &edit).is_ok());
+        assert!(check_cell_locks(&columns, &[], &existing, &serde_json::json!({})).is_ok());
+
+        let edit = serde_json::json!({ &stage_key: "Won", &notes_key: "Call back" });
+        match check_cell_locks(&columns, &locks, &existing, &edit) {
+            Err(ContrivanceError::Conflict { message }) => assert_eq!(message, "Stage is being edited by Bob"),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        // Clearing a locked cell changes it too
+        let edit = serde_json::json!({ &notes_key: "Call back" });
+        assert!(check_cell_locks(&columns, &locks, &existing, &edit).is_err());
+
+        // A lock on a column that's since been deleted still holds off setting it
+        let locks = vec![(gone, "Bob".to_string())];
+        let edit = serde_json::json!({ gone.to_string(): "x" });
+        match check_cell_locks(&columns, &locks, &existing, &edit) {
+            Err(ContrivanceError::Conflict { message }) => assert_eq!(message, "This cell is being edited by Bob"),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_organizations_cannot_see_each_other() {
+        let repository = test_repository().await;
+        let (alice, alice_email) = create_user(&repository.pool, "Alice").await;
+        let (mallory, _) = create_user(&repository.pool, "Mallory").await;
+        let mallory_org = repository.get_membership(mallory).await.unwrap().unwrap().organization.id;
+
+        // Public spreadsheets are only public within their organization
+        let sheet = repository.create_spreadsheet(&spreadsheet_request(true), alice, None).await.unwrap();
+        assert!(repository.can_user_access_spreadsheet(alice, sheet.id).await.unwrap());
+        assert!(!repository.can_user_access_spreadsheet(mallory, sheet.id).await.unwrap());
+        assert!(!repository.can_user_edit_spreadsheet(mallory, sheet.id).await.unwrap());
+        let listed = repository.list_spreadsheets(mallory, &first_page()).await.unwrap();
+        assert!(listed.data.iter().all(|s| s.id != sheet.id));
+
+        // Users in other organizations can't be found, assigned or invited
+        assert!(repository.get_user_id_by_email(mallory_org, &alice_email).await.unwrap().is_none());
+        assert!(repository.ensure_users_exist(sheet.id, &[mallory]).await.is_err());
+        let assignable = repository.get_users_for_assignment(mallory).await.unwrap();
+        assert!(assignable.iter().all(|u| u.id != alice));
+
+        // Nor can their workspaces or teams
+        let workspace = repository
+            .create_workspace(&CreateWorkspaceRequest { name: "Sales".to_string(), default_permission: None }, alice)
+            .await
+            .unwrap();
+        assert!(repository.get_workspace_role(workspace.id, alice).await.unwrap().is_some());
+        assert!(repository.get_workspace_role(workspace.id, mallory).await.unwrap().is_none());
+        let alice_org = repository.get_membership(alice).await.unwrap().unwrap().organization.id;
+        let team = repository
+            .create_team(alice_org, &CreateTeamRequest { name: "Enterprise".to_string(), description: None })
+            .await
+            .unwrap();
+        assert!(repository.get_team(mallory_org, team.id).await.unwrap().is_none());
+        assert!(!repository.can_user_use_team(mallory, team.id).await.unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_team_and_membership_access() {
+        let repository = test_repository().await;
+        let (owner, _) = create_user(&repository.pool, "Owner").await;
+        let (rep, rep_email) = create_user(&repository.pool, "Rep").await;
+        let membership = repository.get_membership(owner).await.unwrap().unwrap();
+        assert_eq!(membership.role, OrganizationRole::Owner);
+        let organization_id = membership.organization.id;
+
+        // Only the invited user can accept, and their spreadsheets only come
+        // along if they say so
+        let own_sheet = repository.create_spreadsheet(&spreadsheet_request(false), rep, None).await.unwrap();
+        let invitation = repository
+            .create_organization_invitation(organization_id, &rep_email.to_uppercase(), OrganizationRole::Member, owner)
+            .await
+            .unwrap();
+        let received = repository.list_received_invitations(rep).await.unwrap();
+        assert_eq!(received.iter().map(|i| i.id).collect::<Vec<_>>(), vec![invitation.id]);
+        assert!(repository.get_organization_role(organization_id, rep).await.unwrap().is_none());
+        let (stranger, _) = create_user(&repository.pool, "Stranger").await;
+        assert!(repository.accept_organization_invitation(invitation.id, stranger, true).await.is_err());
+        assert!(matches!(
+            repository.accept_organization_invitation(invitation.id, rep, false).await,
+            Err(ContrivanceError::Conflict { .. })
+        ));
+        assert_ne!(repository.get_spreadsheet(own_sheet.id).await.unwrap().unwrap().organization_id, organization_id);
+        repository.accept_organization_invitation(invitation.id, rep, true).await.unwrap();
+        assert_eq!(repository.get_organization_role(organization_id, rep).await.unwrap(), Some(OrganizationRole::Member));
+        assert_eq!(repository.get_spreadsheet(own_sheet.id).await.unwrap().unwrap().organization_id, organization_id);
+        assert!(repository.can_user_access_spreadsheet(owner, own_sheet.id).await.unwrap());
+        assert!(repository.list_organization_invitations(organization_id).await.unwrap().is_empty());
+        assert!(repository
+            .create_organization_invitation(organization_id, &rep_email, OrganizationRole::Member, owner)
+            .await
+            .is_err());
+
+        // Team spreadsheets open up to team members
+        let sheet = repository.create_spreadsheet(&spreadsheet_request(false), owner, None).await.unwrap();
+        assert!(!repository.can_user_access_spreadsheet(rep, sheet.id).await.unwrap());
+        let team = repository
+            .create_team(organization_id, &CreateTeamRequest { name: "Mid-market".to_string(), description: None })
+            .await
+            .unwrap();
+        repository.assign_spreadsheet_team(sheet.id, Some(team.id)).await.unwrap();
+        repository.add_team_member(team.id, rep, TeamRole::Member).await.unwrap();
+        assert!(repository.can_user_edit_spreadsheet(rep, sheet.id).await.unwrap());
+        assert!(!repository.can_user_manage_collaborators(rep, sheet.id).await.unwrap());
+
+        // Someone who already shares an organization has to leave it first
+        let (outsider, _) = create_user(&repository.pool, "Outsider").await;
+        let outsider_org = repository.get_membership(outsider).await.unwrap().unwrap().organization.id;
+        let invitation = repository
+            .create_organization_invitation(outsider_org, &rep_email, OrganizationRole::Member, outsider)
+            .await
+            .unwrap();
+        assert!(repository.accept_organization_invitation(invitation.id, rep, true).await.is_err());
+        assert!(repository.decline_organization_invitation(invitation.id, rep).await.unwrap());
+
+        // Removed members lose access and end up on their own
+        assert!(repository.remove_organization_member(organization_id, rep).await.unwrap());
+        assert!(!repository.can_user_access_spreadsheet(rep, sheet.id).await.unwrap());
+        assert!(repository.get_team_role(team.id, rep).await.unwrap().is_none());
+        let own = repository.get_membership(rep).await.unwrap().unwrap();
+        assert_ne!(own.organization.id, organization_id);
+        assert_eq!(own.role, OrganizationRole::Owner);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_notifications_reach_listeners() {
+        let repository = test_repository().await;
+        // A channel of its own so concurrent runs don't see each other's notices
+        let channel = format!("test_{}", Uuid::new_v4().simple());
+        let mut listener = repository.listen(&channel).await.unwrap();
+
+        repository.notify(&channel, "first").await.unwrap();
+        repository.notify(&channel, "second").await.unwrap();
+
+        assert_eq!(listener.recv().await.unwrap().payload(), "first");
+        assert_eq!(listener.recv().await.unwrap().payload(), "second");
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_socket_tickets_are_single_use() {
+        let repository = test_repository().await;
+        let (user, _) = create_user(&repository.pool, "Alice").await;
+        let session_end = Utc::now() + chrono::Duration::hours(1);
+
+        let live = format!("live-{}", Uuid::new_v4());
+        repository.create_socket_ticket(&live, user, Utc::now() + chrono::Duration::seconds(30), session_end).await.unwrap();
+        let (redeemed_by, _) = repository.redeem_socket_ticket(&live).await.unwrap().unwrap();
+        assert_eq!(redeemed_by, user);
+        assert!(repository.redeem_socket_ticket(&live).await.unwrap().is_none());
+
+        let lapsed = format!("lapsed-{}", Uuid::new_v4());
+        repository.create_socket_ticket(&lapsed, user, Utc::now() - chrono::Duration::seconds(1), session_end).await.unwrap();
+        assert!(repository.redeem_socket_ticket(&lapsed).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_cell_locks_hold_off_other_users_until_released_or_expired() {
+        let repository = test_repository().await;
+        let mut conn = repository.pool.acquire().await.unwrap();
+        let (alice, _) = create_user(&repository.pool, "Alice").await;
+        let (bob, _) = create_user(&repository.pool, "Bob").await;
+        let mut request = spreadsheet_request(false);
+        request.columns = Some(vec![common::CreateColumnRequest {
+            name: "Stage".to_string(),
+            column_type: common::ColumnType::Text,
+            position: 0,
+            is_required: None,
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+        }]);
+        let sheet = repository.create_spreadsheet(&request, alice, None).await.unwrap().id;
+        let column = repository.get_spreadsheet_columns(sheet).await.unwrap()[0].id;
+        let row = repository
+            .create_row(&mut conn, sheet, &CreateRowRequest { row_data: serde_json::json!({}), position: None }, alice)
+            .await
+            .unwrap()
+            .id;
+        let (alice_socket, bob_socket) = (Uuid::new_v4(), Uuid::new_v4());
+        let soon = Utc::now() + chrono::Duration::seconds(15);
+
+        let lock = repository.lock_cell(sheet, row, column, alice, alice_socket, soon).await.unwrap();
+        assert_eq!((lock.user_id, lock.user_name.as_str()), (alice, "Alice"));
+        // Bob is told Alice has it, and Alice can renew
+        assert_eq!(repository.lock_cell(sheet, row, column, bob, bob_socket, soon).await.unwrap().user_id, alice);
+        assert_eq!(repository.lock_cell(sheet, row, column, alice, alice_socket, soon).await.unwrap().user_id, alice);
+        assert_eq!(repository.get_cell_locks(sheet, Some(row)).await.unwrap().len(), 1);
+
+        // Bob can't release it, but Alice's socket closing does
+        assert!(!repository.unlock_cell(row, column, bob).await.unwrap());
+        assert_eq!(repository.release_cell_locks(alice_socket).await.unwrap(), vec![(sheet, row, column)]);
+        assert!(repository.get_cell_locks(sheet, None).await.unwrap().is_empty());
+
+        // A lapsed lock is taken over
+        let lapsed = Utc::now() - chrono::Duration::seconds(1);
+        repository.lock_cell(sheet, row, column, alice, alice_socket, lapsed).await.unwrap();
+        assert!(repository.get_cell_locks(sheet, None).await.unwrap().is_empty());
+        assert_eq!(repository.lock_cell(sheet, row, column, bob, bob_socket, soon).await.unwrap().user_id, bob);
+
+        // Only the holder can change the locked cell
+        let update = UpdateRowRequest { row_data: Some(serde_json::json!({"Stage": "Won"})), position: None };
+        assert!(matches!(
+            repository.update_row(&mut conn, row, &update, alice).await,
+            Err(ContrivanceError::Conflict { .. })
+        ));
+        repository.update_row(&mut conn, row, &update, bob).await.unwrap();
+        assert!(repository.unlock_cell(row, column, bob).await.unwrap());
+        repository.update_row(&mut conn, row, &update, alice).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_cell_document_edits_are_merged_one_at_a_time() {
+        let repository = test_repository().await;
+        let mut conn = repository.pool.acquire().await.unwrap();
+        let (alice, _) = create_user(&repository.pool, "Alice").await;
+        let mut request = spreadsheet_request(false);
+        request.columns = Some(vec![common::CreateColumnRequest {
+            name: "Requirements".to_string(),
+            column_type: common::ColumnType::Text,
+            position: 0,
+            is_required: None,
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+        }]);
+        let sheet = repository.create_spreadsheet(&request, alice, None).await.unwrap().id;
+        let column = repository.get_spreadsheet_columns(sheet).await.unwrap()[0].id;
+        let key = column.to_string();
+        let row = repository
+            .create_row(&mut conn, sheet, &CreateRowRequest { row_data: serde_json::json!({ key.clone(): "SSO" }), position: None }, alice)
+            .await
+            .unwrap()
+            .id;
+        assert!(repository.get_cell_document_columns(row).await.unwrap().is_empty());
+
+        // Leaving the document alone writes nothing
+        let (_, untouched) = repository
+            .edit_cell_document(&mut conn, row, column, alice, |state, cell| {
+                assert_eq!((state, cell), (None, &serde_json::json!("SSO")));
+                Ok(None)
+            })
+            .await
+            .unwrap();
+        assert!(repository.get_cell_document_columns(row).await.unwrap().is_empty());
+
+        // Each edit sees the one before it, however many race
+        let edits = (0..8).map(|_| {
+            let repository = repository.clone();
+            tokio::spawn(async move {
+                let mut conn = repository.pool.acquire().await.unwrap();
+                repository
+                    .edit_cell_document(&mut conn, row, column, alice, |state, cell| {
+                        let cell = cell.as_str().unwrap();
+                        if let Some(state) = state {
+                            assert_eq!(state, cell.as_bytes());
+                        }
+                        let text = format!("{}+", cell);
+                        Ok(Some((text.clone().into_bytes(), text)))
+                    })
+                    .await
+                    .unwrap()
+            })
+        });
+        for edit in futures::future::join_all(edits).await {
+            edit.unwrap();
+        }
+
+        let row = repository.get_row(row).await.unwrap().unwrap();
+        assert_eq!(row.row_data[&key], serde_json::json!("SSO++++++++"));
+        assert!(row.updated_at > untouched.updated_at);
+        assert_eq!(repository.get_cell_document_columns(row.id).await.unwrap(), vec![column]);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_recurring_todo_series() {
+        let repository = test_repository().await;
+        let mut conn = repository.pool.acquire().await.unwrap();
+        let (alice, _) = create_user(&repository.pool, "Alice").await;
+        let sheet = repository.create_spreadsheet(&spreadsheet_request(false), alice, None).await.unwrap().id;
+        let monday = DateTime::parse_from_rfc3339("2025-11-03T14:00:00Z").unwrap().with_timezone(&Utc);
+        let first = repository
+            .create_todo(
+                &mut conn,
+                &common::CreateTodoRequest {
+                    title: "POC check-in".to_string(),
+                    description: None,
+                    priority: common::TodoPriority::High,
+                    due_date: Some(monday),
+                    supporting_artifact: None,
+                    spreadsheet_id: sheet,
+                    row_id: None,
+                    assigned_to: None,
+                    recurrence: Some("FREQ=WEEKLY;BYDAY=MO".to_string()),
+                    recurrence_timezone: Some("America/New_York".to_string()),
+                    parent_id: None,
+                },
+                alice,
+            )
+            .await
+            .unwrap();
+        assert_eq!(first.series_id, Some(first.id));
+        assert_eq!((first.series_start, first.scheduled_at), (Some(monday), Some(monday)));
+
+        // The next occurrence copies the first, and is only created once
+        let next_monday = monday + chrono::Duration::weeks(1);
+        let second = repository.create_next_occurrence(&mut conn, &first, next_monday).await.unwrap().unwrap();
+        assert_eq!((second.title.as_str(), second.series_id, second.due_date), ("POC check-in", Some(first.id), Some(next_monday)));
+        assert!(repository.create_next_occurrence(&mut conn, &first, next_monday).await.unwrap().is_none());
+        repository.update_todo_completion(&mut conn, first.id, true, alice).await.unwrap();
+
+        // Editing one occurrence leaves the rest alone
+        let mut update = common::UpdateTodoRequest {
+            title: Some("Skip: customer offsite".to_string()),
+            description: None,
+            priority: None,
+            completed: None,
+            due_date: None,
+            supporting_artifact: None,
+            assigned_to: None,
+            recurrence: None,
+            recurrence_timezone: None,
+            parent_id: None,
+            scope: common::TodoEditScope::Occurrence,
+        };
+        let edited = repository.update_todo(&mut conn, second.id, &update, alice).await.unwrap().unwrap();
+        assert_eq!((edited.title.as_str(), edited.priority), ("Skip: customer offsite", common::TodoPriority::High));
+        assert_eq!(repository.get_todo_by_id(first.id, alice).await.unwrap().unwrap().title, "POC check-in");
+
+        // Editing the series changes the open occurrences and the rule on every one
+        update.title = Some("POC sync".to_string());
+        update.recurrence = Some("FREQ=WEEKLY;BYDAY=TU".to_string());
+        let changed = repository.update_todo_series(&mut conn, second.id, first.id, &update, alice).await.unwrap();
+        assert_eq!(changed.iter().map(|t| t.id).collect::<Vec<_>>(), vec![second.id]);
+        assert_eq!(changed[0].title, "POC sync");
+        let first = repository.get_todo_by_id(first.id, alice).await.unwrap().unwrap();
+        assert_eq!((first.title.as_str(), first.recurrence.as_deref()), ("POC check-in", Some("FREQ=WEEKLY;BYDAY=TU")));
+
+        // An empty rule stops it repeating
+        update.recurrence = Some(String::new());
+        let changed = repository.update_todo_series(&mut conn, second.id, first.id, &update, alice).await.unwrap();
+        assert_eq!(changed[0].recurrence, None);
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_subtasks_dependencies_and_checklists() {
+        let repository = test_repository().await;
+        let mut conn = repository.pool.acquire().await.unwrap();
+        let (alice, _) = create_user(&repository.pool, "Alice").await;
+        let sheet = repository.create_spreadsheet(&spreadsheet_request(false), alice, None).await.unwrap().id;
+        let create = |title: &str, parent_id: Option<Uuid>| common::CreateTodoRequest {
+            title: title.to_string(),
+            description: None,
+            priority: common::TodoPriority::Medium,
+            due_date: None,
+            supporting_artifact: None,
+            spreadsheet_id: sheet,
+            row_id: None,
+            assigned_to: None,
+            recurrence: None,
+            recurrence_timezone: None,
+            parent_id,
+        };
+        let plan = repository.create_todo(&mut conn, &create("Mutual action plan", None), alice).await.unwrap();
+        let security = repository.create_todo(&mut conn, &create("Security review", Some(plan.id)), alice).await.unwrap();
+        let legal = repository.create_todo(&mut conn, &create("Legal review", Some(plan.id)), alice).await.unwrap();
+
+        // A parent can't wait on its own subtask's dependents, or become its subtask's subtask
+        let signing = repository.create_todo(&mut conn, &create("Signing", None), alice).await.unwrap();
+        repository.add_todo_dependency(&mut conn, signing.id, plan.id).await.unwrap();
+        let err = repository.add_todo_dependency(&mut conn, security.id, signing.id).await.unwrap_err();
+        assert!(matches!(err, ContrivanceError::Validation { .. }), "{err:?}");
+        let mut reparent = common::UpdateTodoRequest {
+            title: None,
+            description: None,
+            priority: None,
+            completed: None,
+            due_date: None,
+            supporting_artifact: None,
+            assigned_to: None,
+            recurrence: None,
+            recurrence_timezone: None,
+            parent_id: Some(security.id),
+            scope: common::TodoEditScope::Occurrence,
+        };
+        assert!(repository.update_todo(&mut conn, plan.id, &reparent, alice).await.is_err());
+        reparent.parent_id = Some(legal.id);
+        assert!(repository.update_todo(&mut conn, legal.id, &reparent, alice).await.is_err());
+
+        let signing = repository.get_todo_by_id(signing.id, alice).await.unwrap().unwrap();
+        assert_eq!((signing.blocked_by.clone(), signing.blocked), (vec![plan.id], true));
+        assert_eq!(repository.get_todo_stats(sheet, alice).await.unwrap().blocked, Some(1));
+
+        // Completing the last subtask completes the parent, which unblocks the dependent
+        repository.update_todo_completion(&mut conn, security.id, true, alice).await.unwrap();
+        assert!(repository.roll_up_todo_completion(&mut conn, plan.id).await.unwrap().is_empty());
+        repository.update_todo_completion(&mut conn, legal.id, true, alice).await.unwrap();
+        let changed = repository.roll_up_todo_completion(&mut conn, plan.id).await.unwrap();
+        assert_eq!(changed.iter().map(|t| (t.id, t.completed)).collect::<Vec<_>>(), vec![(plan.id, true)]);
+        assert!(!repository.get_dependent_todos(plan.id).await.unwrap()[0].blocked);
+        assert_eq!(repository.get_todo_stats(sheet, alice).await.unwrap().blocked, Some(0));
+
+        // Reopening one reopens the parent
+        repository.update_todo_completion(&mut conn, legal.id, false, alice).await.unwrap();
+        let changed = repository.roll_up_todo_completion(&mut conn, plan.id).await.unwrap();
+        assert_eq!(changed.iter().map(|t| (t.id, t.completed)).collect::<Vec<_>>(), vec![(plan.id, false)]);
+
+        // Checklist items come back in order with the todo
+        let step = |title: &str, position: Option<i32>| common::CreateChecklistItemRequest { title: title.to_string(), position };
+        repository.add_checklist_item(&mut conn, legal.id, &step("Redline MSA", None)).await.unwrap();
+        let todo = repository.add_checklist_item(&mut conn, legal.id, &step("Send DPA", Some(-1))).await.unwrap();
+        assert_eq!(todo.checklist.iter().map(|c| c.title.as_str()).collect::<Vec<_>>(), vec!["Send DPA", "Redline MSA"]);
+        let tick = common::UpdateChecklistItemRequest { title: None, completed: Some(true), position: None };
+        let todo = repository.update_checklist_item(&mut conn, legal.id, todo.checklist[1].id, &tick).await.unwrap().unwrap();
+        assert!(todo.checklist[1].completed);
+        let todo = repository.delete_checklist_item(&mut conn, legal.id, todo.checklist[0].id).await.unwrap().unwrap();
+        assert_eq!(todo.checklist.len(), 1);
+        assert!(repository.delete_checklist_item(&mut conn, legal.id, todo.checklist[0].id).await.unwrap().is_some());
+        assert!(repository.delete_checklist_item(&mut conn, legal.id, todo.checklist[0].id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_update_cell_leaves_other_cells_alone() {
+        let repository = test_repository().await;
+        let mut conn = repository.pool.acquire().await.unwrap();
+        let (alice, _) = create_user(&repository.pool, "Alice").await;
+        let column = |name: &str, column_type| common::CreateColumnRequest {
+            name: name.to_string(),
+            column_type,
+            position: 0,
+            is_required: None,
+            default_value: None,
+            validation_rules: None,
+            display_options: None,
+        };
+        let mut request = spreadsheet_request(false);
+        request.columns = Some(vec![column("Stage", common::ColumnType::Text), column("Amount", common::ColumnType::Currency)]);
+        let sheet = repository.create_spreadsheet(&request, alice, None).await.unwrap().id;
+        let columns = repository.get_spreadsheet_columns(sheet).await.unwrap();
+        let (stage, amount) = (&columns[0], &columns[1]);
+        let row = repository
+            .create_row(&mut conn, sheet, &CreateRowRequest { row_data: serde_json::json!({"Stage": "Discovery"}), position: None }, alice)
+            .await
+            .unwrap();
+
+        // Someone else moves the stage after the row was read
+        let moved = UpdateRowRequest { row_data: Some(serde_json::json!({"Stage": "POC/Pilot"})), position: None };
+        repository.update_row(&mut conn, row.id, &moved, alice).await.unwrap();
+
+        let updated = repository.update_cell(&mut conn, row.id, amount, serde_json::json!(5000), alice).await.unwrap();
+        assert_eq!(stage.cell(&updated.row_data), Some(&serde_json::json!("POC/Pilot")));
+        assert_eq!(amount.cell(&updated.row_data), Some(&serde_json::json!({"amount": 5000.0, "currency": "USD"})));
+        assert!(repository.update_cell(&mut conn, row.id, amount, serde_json::json!("lots"), alice).await.is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Authentication for WebSocket handshakes.
+//!
+//! Browsers can't set headers on a WebSocket upgrade, so besides the usual
+//! `Authorization: Bearer` header a socket accepts its JWT as a
+//! `Sec-WebSocket-Protocol` value following `contrivance.bearer`, as
+//! `?token=`, or a single-use ticket from `POST /ws/ticket` as `?ticket=`.
+//! Tickets keep the JWT out of URLs, which end up in proxy logs.
+//!
+//! Spreadsheet sockets are also handed a resume token, signed for the user and
+//! spreadsheet, which a reconnecting client presents with the last event it
+//! applied to have the ones it missed replayed.
+
+use actix_web::{http::header, HttpMessage, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use common::{auth::SessionService, ApiResponse, Claims, ContrivanceError, ContrivanceResult, JwtService};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{middleware::auth::get_user_from_request, repository::ContrivanceRepository};
+
+/// Subprotocol a client offers alongside its JWT; the server accepts it so
+/// the handshake completes
+pub const SOCKET_PROTOCOL: &str = "contrivance.bearer";
+/// Handshake response header naming the socket's user
+pub const USER_ID_HEADER: header::HeaderName = header::HeaderName::from_static("x-user-id");
+/// How long a ticket can wait before it's used
+const TICKET_TTL_SECONDS: i64 = 30;
+/// How long after it was issued a resume token is honoured
+const RESUME_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SocketAuthQuery {
+    pub token: Option<String>,
+    pub ticket: Option<String>,
+    /// Resume token from the `Session` message of an earlier connection
+    pub resume: Option<String>,
+    /// Last change feed event the client applied before it disconnected
+    pub last_seq: Option<i64>,
+}
+
+/// Who opened a socket, and when their credentials run out
+#[derive(Debug, Clone, Copy)]
+pub struct SocketSession {
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// What a user may do on a spreadsheet socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketAccess {
+    /// Receive changes and presence, but not edit
+    View,
+    Edit,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SocketTicket {
+    pub ticket: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+pub struct SocketAuth {
+    repository: ContrivanceRepository,
+    jwt_service: JwtService,
+    /// Signs resume tokens
+    resume_secret: String,
+}
+
+impl SocketAuth {
+    pub fn new(repository: ContrivanceRepository, jwt_service: JwtService, resume_secret: String) -> Self {
+        Self { repository, jwt_service, resume_secret }
+    }
+
+    /// Issue a ticket for the requesting user's next socket
+    pub async fn create_ticket(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let session_expires_at = req
+            .extensions()
+            .get::<Claims>()
+            .map(token_expiry)
+            .ok_or_else(|| ContrivanceError::unauthorized("Token not found in request"))?;
+
+        let ticket: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(48)
+            .map(char::from)
+            .collect();
+        let expires_at = Utc::now() + Duration::seconds(TICKET_TTL_SECONDS);
+        self.repository
+            .create_socket_ticket(&SessionService::generate_session_hash(&ticket), user.id, expires_at, session_expires_at)
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(SocketTicket { ticket, expires_at })))
+    }
+
+    /// Identify the user opening a socket from whichever credential it carries
+    pub async fn authenticate(&self, req: &HttpRequest, query: &SocketAuthQuery) -> ContrivanceResult<SocketSession> {
+        if let Some(ticket) = &query.ticket {
+            let (user_id, expires_at) = self.repository
+                .redeem_socket_ticket(&SessionService::generate_session_hash(ticket))
+                .await?
+                .ok_or_else(|| ContrivanceError::authentication("Invalid or expired ticket"))?;
+            return Ok(SocketSession { user_id, expires_at });
+        }
+
+        let token = bearer_token(req)
+            .or_else(|| protocol_token(req))
+            .or_else(|| query.token.clone())
+            .ok_or_else(|| ContrivanceError::unauthorized("Missing token"))?;
+        let claims = self.jwt_service.validate_token(&token)?;
+        let user_id = Uuid::parse_str(&claims.sub)
+            .map_err(|_| ContrivanceError::authentication("Invalid user ID in token"))?;
+
+        Ok(SocketSession { user_id, expires_at: token_expiry(&claims) })
+    }
+
+    /// What the user may do on a spreadsheet's socket, or `None` if they can't see it
+    pub async fn access(&self, user_id: Uuid, spreadsheet_id: Uuid) -> ContrivanceResult<Option<SocketAccess>> {
+        if !self.repository.can_user_access_spreadsheet(user_id, spreadsheet_id).await? {
+            return Ok(None);
+        }
+        if self.repository.can_user_edit_spreadsheet(user_id, spreadsheet_id).await? {
+            Ok(Some(SocketAccess::Edit))
+        } else {
+            Ok(Some(SocketAccess::View))
+        }
+    }
+
+    /// A token letting the user's next socket to the spreadsheet pick up
+    /// where this one leaves off
+    pub fn resume_token(&self, user_id: Uuid, spreadsheet_id: Uuid) -> String {
+        sign_resume_token(&self.resume_secret, user_id, spreadsheet_id, Utc::now())
+    }
+
+    /// Whether a resume token was issued for this user and spreadsheet, and
+    /// recently enough to honour
+    pub fn verify_resume_token(&self, token: &str, user_id: Uuid, spreadsheet_id: Uuid) -> bool {
+        verify_resume_token(&self.resume_secret, token, user_id, spreadsheet_id, Utc::now())
+    }
+
+    /// The name shown to other viewers
+    pub async fn user_name(&self, user_id: Uuid) -> ContrivanceResult<String> {
+        let mut names = self.repository.get_user_names(&[user_id]).await?;
+        Ok(names.remove(&user_id).unwrap_or_default())
+    }
+}
+
+fn sign_resume_token(secret: &str, user_id: Uuid, spreadsheet_id: Uuid, now: DateTime<Utc>) -> String {
+    let issued_at = now.timestamp();
+    let signature = resume_mac(secret, user_id, spreadsheet_id, issued_at).finalize().into_bytes();
+    format!("{}.{}", issued_at, hex::encode(signature))
+}
+
+fn verify_resume_token(secret: &str, token: &str, user_id: Uuid, spreadsheet_id: Uuid, now: DateTime<Utc>) -> bool {
+    let Some((issued_at, signature)) = token.split_once('.') else { return false };
+    let (Ok(issued_at), Ok(signature)) = (issued_at.parse::<i64>(), hex::decode(signature)) else { return false };
+    if now.timestamp() - issued_at > RESUME_TOKEN_TTL_HOURS * 3600 {
+        return false;
+    }
+    resume_mac(secret, user_id, spreadsheet_id, issued_at)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn resume_mac(secret: &str, user_id: Uuid, spreadsheet_id: Uuid, issued_at: i64) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(format!("resume.{}.{}.{}", user_id, spreadsheet_id, issued_at).as_bytes());
+    mac
+}
+
+fn token_expiry(claims: &Claims) -> DateTime<Utc> {
+    Utc.timestamp_opt(claims.exp as i64, 0).single().unwrap_or_else(Utc::now)
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+}
+
+/// The JWT offered as the subprotocol after `SOCKET_PROTOCOL`
+fn protocol_token(req: &HttpRequest) -> Option<String> {
+    let offered: Vec<&str> = req
+        .headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    let position = offered.iter().position(|protocol| *protocol == SOCKET_PROTOCOL)?;
+    offered.get(position + 1).map(|token| token.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_token_is_read_from_the_protocol_after_ours() {
+        let req = TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "contrivance.bearer, eyJ.abc.def"))
+            .to_http_request();
+        assert_eq!(protocol_token(&req).as_deref(), Some("eyJ.abc.def"));
+
+        let req = TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "eyJ.abc.def"))
+            .to_http_request();
+        assert_eq!(protocol_token(&req), None);
+
+        let req = TestRequest::default()
+            .insert_header((header::SEC_WEBSOCKET_PROTOCOL, "contrivance.bearer"))
+            .to_http_request();
+        assert_eq!(protocol_token(&req), None);
+    }
+
+    #[test]
+    fn test_resume_tokens_are_bound_to_user_and_spreadsheet() {
+        let (user, sheet, now) = (Uuid::new_v4(), Uuid::new_v4(), Utc::now());
+        let token = sign_resume_token("secret", user, sheet, now);
+        assert!(verify_resume_token("secret", &token, user, sheet, now));
+
+        assert!(!verify_resume_token("secret", &token, Uuid::new_v4(), sheet, now));
+        assert!(!verify_resume_token("secret", &token, user, Uuid::new_v4(), now));
+        assert!(!verify_resume_token("other", &token, user, sheet, now));
+        assert!(!verify_resume_token("secret", "not-a-token", user, sheet, now));
+
+        let later = now + Duration::hours(RESUME_TOKEN_TTL_HOURS) + Duration::seconds(1);
+        assert!(!verify_resume_token("secret", &token, user, sheet, later));
+    }
+}
@@ -0,0 +1,163 @@
+use actix_web::{http::header, web::Bytes, HttpRequest};
+use common::{
+    CollaboratorInfo, ContrivanceResult, RowKeys, Spreadsheet, SpreadsheetColumn, SpreadsheetDetails, SpreadsheetRow,
+    UserResponse,
+};
+use futures::{stream, Stream, StreamExt};
+use serde::Serialize;
+use std::convert::Infallible;
+use tracing::error;
+
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// One line of a streamed spreadsheet. The first line describes the
+/// spreadsheet, then each row follows on its own line, and the last line
+/// is either `end` with the row count or `error` if the stream broke off.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DetailsLine<'a> {
+    Spreadsheet {
+        spreadsheet: &'a Spreadsheet,
+        columns: &'a [SpreadsheetColumn],
+        collaborators: &'a [CollaboratorInfo],
+        owner: &'a UserResponse,
+    },
+    Row { row: &'a SpreadsheetRow },
+    End { rows: u64 },
+    Error { error: String },
+}
+
+impl DetailsLine<'_> {
+    fn to_bytes(&self) -> Bytes {
+        let mut line = serde_json::to_vec(self).unwrap_or_default();
+        line.push(b'\n');
+        Bytes::from(line)
+    }
+}
+
+/// Whether the client asked for spreadsheet details as NDJSON, through the
+/// `Accept` header or `?stream=true`
+pub fn wants_ndjson(req: &HttpRequest, stream: Option<bool>) -> bool {
+    stream.unwrap_or(false)
+        || req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains(NDJSON_CONTENT_TYPE))
+}
+
+/// Encode spreadsheet details followed by its rows as NDJSON, with cells
+/// keyed by `keys`. `details.rows` is ignored; the rows come from `rows` as
+/// they are read.
+pub fn details_ndjson(
+    details: SpreadsheetDetails,
+    rows: impl Stream<Item = ContrivanceResult<SpreadsheetRow>> + Unpin + 'static,
+    keys: RowKeys,
+) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    let header = DetailsLine::Spreadsheet {
+        spreadsheet: &details.spreadsheet,
+        columns: &details.columns,
+        collaborators: &details.collaborators,
+        owner: &details.owner,
+    }
+    .to_bytes();
+
+    let rows = stream::unfold(Some((rows, details.columns, 0u64)), move |state| async move {
+        let (mut rows, columns, count) = state?;
+        match rows.next().await {
+            Some(Ok(row)) => {
+                let line = DetailsLine::Row { row: &row.keyed(&columns, keys) }.to_bytes();
+                Some((line, Some((rows, columns, count + 1))))
+            }
+            Some(Err(e)) => {
+                error!("Streaming rows failed after {} rows: {}", count, e);
+                Some((DetailsLine::Error { error: e.to_string() }.to_bytes(), None))
+            }
+            None => Some((DetailsLine::End { rows: count }.to_bytes(), None)),
+        }
+    });
+
+    stream::once(async move { header }).chain(rows).map(Ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use common::{ContrivanceError, UserRole};
+    use uuid::Uuid;
+
+    fn details() -> SpreadsheetDetails {
+        let owner_id = Uuid::new_v4();
+        SpreadsheetDetails {
+            spreadsheet: Spreadsheet {
+                id: Uuid::new_v4(),
+                name: "Accounts".into(),
+                description: None,
+                owner_id,
+                created_at: None,
+                updated_at: None,
+                is_public: Some(false),
+                settings: None,
+                workspace_id: None,
+                folder_id: None,
+                organization_id: Uuid::new_v4(),
+                team_id: None,
+            },
+            columns: Vec::new(),
+            rows: Vec::new(),
+            collaborators: Vec::new(),
+            owner: UserResponse {
+                id: owner_id,
+                email: "owner@example.com".into(),
+                name: "Owner".into(),
+                role: UserRole::User,
+                created_at: Utc::now(),
+                last_login: None,
+                is_active: true,
+            },
+        }
+    }
+
+    fn row(position: i32) -> SpreadsheetRow {
+        SpreadsheetRow {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::new_v4(),
+            row_data: serde_json::json!({ "Company": format!("Acme {}", position) }),
+            position,
+            created_at: None,
+            updated_at: None,
+            created_by: None,
+            updated_by: None,
+        }
+    }
+
+    async fn lines(rows: Vec<ContrivanceResult<SpreadsheetRow>>) -> Vec<serde_json::Value> {
+        let body: Vec<_> = details_ndjson(details(), stream::iter(rows), RowKeys::Id).collect().await;
+        let text: String = body.into_iter().map(|chunk| String::from_utf8(chunk.unwrap().to_vec()).unwrap()).collect();
+        assert!(text.ends_with('\n'));
+        text.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_details_ndjson_lines() {
+        let lines = lines(vec![Ok(row(1)), Ok(row(2))]).await;
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0]["type"], "spreadsheet");
+        assert_eq!(lines[0]["spreadsheet"]["name"], "Accounts");
+        assert!(lines[0].get("rows").is_none());
+        assert_eq!(lines[1]["type"], "row");
+        assert_eq!(lines[1]["row"]["row_data"]["Company"], "Acme 1");
+        assert_eq!(lines[2]["row"]["position"], 2);
+        assert_eq!(lines[3], serde_json::json!({ "type": "end", "rows": 2 }));
+    }
+
+    #[tokio::test]
+    async fn test_details_ndjson_stops_at_error() {
+        let lines = lines(vec![Ok(row(1)), Err(ContrivanceError::database("connection reset")), Ok(row(2))]).await;
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2]["type"], "error");
+    }
+}
@@ -0,0 +1,186 @@
+//! Collaborative editing for text cells.
+//!
+//! A text cell being edited together is backed by an Automerge document with
+//! the cell's text in a `text` object at its root. A client loads the
+//! document's state, edits its own copy and sends the resulting changes; the
+//! server merges them into the stored document one at a time per row, so
+//! concurrent edits interleave instead of overwriting each other. Plain writes
+//! to the cell (REST or `UpdateRow`) are folded into the document as a diff
+//! against its current text, so both kinds of edit can be mixed. Document
+//! state and changes travel as base64 in JSON.
+
+use automerge::{transaction::Transactable, AutoCommit, ChangeHash, ObjId, ObjType, ReadDoc, Value, ROOT};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{ContrivanceError, ContrivanceResult};
+
+/// Key of the text object at the document root
+const TEXT_KEY: &str = "text";
+
+pub struct TextDocument {
+    doc: AutoCommit,
+    text: ObjId,
+    /// Heads when the document was loaded, to tell what changed since
+    base: Vec<ChangeHash>,
+}
+
+impl TextDocument {
+    /// Start an empty document
+    pub fn new() -> ContrivanceResult<Self> {
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(ROOT, TEXT_KEY, ObjType::Text).map_err(internal)?;
+        Ok(Self { doc, text, base: Vec::new() })
+    }
+
+    /// Load a stored document
+    pub fn load(state: &[u8]) -> ContrivanceResult<Self> {
+        let mut doc = AutoCommit::load(state).map_err(internal)?;
+        let text = text_object(&mut doc).ok_or_else(|| ContrivanceError::internal("Text document has no text"))?;
+        let base = doc.get_heads();
+        Ok(Self { doc, text, base })
+    }
+
+    /// Merge changes made by a client. They must build on a state the server
+    /// has and edit the document's own text object; anything else is refused
+    /// and the document is left as it was.
+    pub fn apply(&mut self, changes: &[u8]) -> ContrivanceResult<()> {
+        let mut merged = self.doc.fork();
+        merged
+            .load_incremental(changes)
+            .map_err(|e| ContrivanceError::validation(format!("Invalid text update: {}", e)))?;
+        if !merged.get_missing_deps(&[]).is_empty() {
+            return Err(ContrivanceError::conflict(
+                "Text update builds on changes the server doesn't have; reopen the cell",
+            ));
+        }
+        let roots = merged.get_all(ROOT, TEXT_KEY).unwrap_or_default();
+        if merged.length(ROOT) != 1 || roots.len() != 1 || roots[0].1 != self.text {
+            return Err(ContrivanceError::validation("Text update must edit the cell's existing text"));
+        }
+
+        self.doc = merged;
+        Ok(())
+    }
+
+    /// Make the text match a value written to the cell some other way, as
+    /// the smallest edit that gets there
+    pub fn replace(&mut self, text: &str) -> ContrivanceResult<()> {
+        self.doc.update_text(&self.text, text).map_err(internal)
+    }
+
+    pub fn text(&self) -> ContrivanceResult<String> {
+        self.doc.text(&self.text).map_err(internal)
+    }
+
+    /// Changes made since the document was started or loaded, for clients
+    /// holding the earlier state
+    pub fn changes(&mut self) -> Vec<u8> {
+        let base = self.base.clone();
+        self.doc.save_after(&base)
+    }
+
+    /// The whole document, compacted
+    pub fn save(&mut self) -> Vec<u8> {
+        self.doc.save()
+    }
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+pub fn decode(encoded: &str) -> ContrivanceResult<Vec<u8>> {
+    STANDARD
+        .decode(encoded)
+        .map_err(|_| ContrivanceError::validation("Text updates must be base64"))
+}
+
+fn text_object(doc: &mut AutoCommit) -> Option<ObjId> {
+    match doc.get(ROOT, TEXT_KEY) {
+        Ok(Some((Value::Object(ObjType::Text), obj))) => Some(obj),
+        _ => None,
+    }
+}
+
+fn internal(error: automerge::AutomergeError) -> ContrivanceError {
+    ContrivanceError::internal(format!("Text document error: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> TextDocument {
+        let mut document = TextDocument::new().unwrap();
+        document.replace(text).unwrap();
+        TextDocument::load(&document.save()).unwrap()
+    }
+
+    /// A client's copy of a document, as it would load it from a snapshot
+    fn client(server: &mut TextDocument) -> AutoCommit {
+        AutoCommit::load(&server.save()).unwrap()
+    }
+
+    fn splice(doc: &mut AutoCommit, at: usize, insert: &str) -> Vec<u8> {
+        let heads = doc.get_heads();
+        let text = text_object(doc).unwrap();
+        doc.splice_text(&text, at, 0, insert).unwrap();
+        doc.save_after(&heads)
+    }
+
+    #[test]
+    fn test_concurrent_edits_merge() {
+        let mut server = document("Needs SSO and audit logs");
+        let mut alice = client(&mut server);
+        let mut bob = client(&mut server);
+
+        let from_alice = splice(&mut alice, 9, " SAML");
+        let from_bob = splice(&mut bob, 24, " by Q3");
+        server.apply(&from_alice).unwrap();
+        server.apply(&from_bob).unwrap();
+        assert_eq!(server.text().unwrap(), "Needs SSO SAML and audit logs by Q3");
+
+        // Peers catch up from the changes, and a change seen twice is a no-op
+        let changes = server.changes();
+        alice.load_incremental(&changes).unwrap();
+        alice.load_incremental(&from_bob).unwrap();
+        assert_eq!(TextDocument::load(&alice.save()).unwrap().text().unwrap(), server.text().unwrap());
+        let mut reloaded = TextDocument::load(&server.save()).unwrap();
+        reloaded.apply(&from_bob).unwrap();
+        assert!(reloaded.changes().is_empty());
+    }
+
+    #[test]
+    fn test_plain_writes_are_folded_in() {
+        let mut server = document("Champion: Dana");
+        let mut alice = client(&mut server);
+
+        server.replace("Champion: Dana (VP Eng)").unwrap();
+        alice.load_incremental(&server.changes()).unwrap();
+        server.apply(&splice(&mut alice, 0, "- ")).unwrap();
+
+        let mut reloaded = TextDocument::load(&server.save()).unwrap();
+        assert_eq!(reloaded.text().unwrap(), "- Champion: Dana (VP Eng)");
+        reloaded.replace("- Champion: Dana (VP Eng)").unwrap();
+        assert!(reloaded.changes().is_empty());
+    }
+
+    #[test]
+    fn test_updates_from_elsewhere_are_refused() {
+        let mut server = document("Stakeholders");
+
+        // A document the client started on its own has its own text object
+        let mut other = client(&mut document("Stakeholders"));
+        assert!(server.apply(&other.save()).is_err());
+
+        // A change whose history the server never saw
+        let mut ahead = client(&mut server);
+        splice(&mut ahead, 0, "Key ");
+        let later = splice(&mut ahead, 0, "The ");
+        assert!(matches!(server.apply(&later), Err(ContrivanceError::Conflict { .. })));
+
+        // Bytes that aren't changes at all apply nothing
+        let _ = server.apply(b"not automerge");
+        assert!(server.changes().is_empty());
+        assert_eq!(server.text().unwrap(), "Stakeholders");
+    }
+}
@@ -1,25 +1,45 @@
 use actix_web::{web, HttpResponse, HttpRequest};
+use tracing::{error, warn};
 use uuid::Uuid;
 use crate::{
+    broadcast::Broadcaster,
+    events::{EventPublisher, Publication},
+    notifications::Notifier,
+    recurrence::Schedule,
     repository::ContrivanceRepository,
-    websocket::ConnectionManager,
     middleware::auth::get_user_from_request,
 };
 use common::{
     ContrivanceResult, ContrivanceError, CreateTodoRequest, UpdateTodoRequest,
-    ApiResponse, Todo, TodoStats,
+    ApiResponse, PaginationParams, Todo, TodoEditScope, TodoNode, TodoStats, WebSocketMessage,
+    AddTodoDependencyRequest, CreateChecklistItemRequest, UpdateChecklistItemRequest,
 };
+use serde::Deserialize;
+use validator::Validate;
+
+/// `?tree=true` returns a row's todos arranged under their parents, unpaginated
+#[derive(Debug, Deserialize)]
+pub struct TreeQuery {
+    #[serde(default)]
+    pub tree: bool,
+}
 
 pub struct TodoHandlers {
     repository: ContrivanceRepository,
-    connection_manager: web::Data<ConnectionManager>,
+    events: EventPublisher,
+    notifier: Notifier,
 }
 
 impl TodoHandlers {
-    pub fn new(repository: ContrivanceRepository, connection_manager: web::Data<ConnectionManager>) -> Self {
+    pub fn new(
+        repository: ContrivanceRepository,
+        broadcaster: Broadcaster,
+        notifier: Notifier,
+    ) -> Self {
         Self {
+            events: EventPublisher::new(repository.clone(), broadcaster),
             repository,
-            connection_manager,
+            notifier,
         }
     }
 
@@ -30,11 +50,39 @@ impl TodoHandlers {
         payload: web::Json<CreateTodoRequest>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
-        
+        if !self.repository.can_user_access_spreadsheet(user.id, payload.spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+        if let Some(assignee) = payload.assigned_to {
+            self.repository.ensure_users_exist(payload.spreadsheet_id, &[assignee]).await?;
+        }
+        if let Some(rule) = payload.recurrence.as_deref().filter(|rule| !rule.trim().is_empty()) {
+            let start = payload.due_date.ok_or_else(|| {
+                ContrivanceError::validation("A recurring todo needs a due_date for its first occurrence")
+            })?;
+            Schedule::new(rule, payload.recurrence_timezone.as_deref().unwrap_or("UTC"), start)?;
+        }
+        if let Some(parent_id) = payload.parent_id {
+            self.ensure_parent(payload.spreadsheet_id, payload.row_id, parent_id).await?;
+        }
+
+        let mut publication = self.events.begin().await?;
         let todo = self.repository
-            .create_todo(&payload, user.id)
+            .create_todo(publication.tx(), &payload, user.id)
             .await?;
 
+        let message = WebSocketMessage::TodoCreated {
+            spreadsheet_id: todo.spreadsheet_id,
+            todo: todo.clone(),
+            created_by: user.id,
+        };
+        publication.record(todo.spreadsheet_id, user.id, message).await?;
+        publication.commit().await?;
+        self.notifier.todo_assigned(&todo, None, user.id).await;
+        self.notifier.mentioned_in_todo(None, &todo, user.id).await;
+        // A new open subtask reopens its parent
+        self.roll_up(todo.parent_id, user.id).await;
+
         Ok(HttpResponse::Created().json(ApiResponse::success(todo)))
     }
 
@@ -42,11 +90,12 @@ impl TodoHandlers {
     pub async fn get_todos(
         &self,
         req: HttpRequest,
+        query: web::Query<PaginationParams>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         
         let todos = self.repository
-            .get_todos_for_user(user.id)
+            .get_todos_for_user(user.id, &query)
             .await?;
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(todos)))
@@ -57,6 +106,7 @@ impl TodoHandlers {
         &self,
         req: HttpRequest,
         path: web::Path<Uuid>,
+        query: web::Query<PaginationParams>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let spreadsheet_id = path.into_inner();
@@ -67,7 +117,7 @@ impl TodoHandlers {
         }
 
         let todos = self.repository
-            .get_todos_by_spreadsheet(spreadsheet_id, user.id)
+            .get_todos_by_spreadsheet(spreadsheet_id, user.id, &query)
             .await?;
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(todos)))
@@ -78,6 +128,8 @@ impl TodoHandlers {
         &self,
         req: HttpRequest,
         path: web::Path<(Uuid, Uuid)>,
+        query: web::Query<PaginationParams>,
+        tree: web::Query<TreeQuery>,
     ) -> Result<HttpResponse, ContrivanceError> {
         let user = get_user_from_request(&req)?;
         let (spreadsheet_id, row_id) = path.into_inner();
@@ -87,8 +139,13 @@ impl TodoHandlers {
             return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
         }
 
+        if tree.tree {
+            let todos = self.repository.get_row_todo_tree(spreadsheet_id, row_id, user.id).await?;
+            return Ok(HttpResponse::Ok().json(ApiResponse::success(TodoNode::tree(todos))));
+        }
+
         let todos = self.repository
-            .get_todos_by_row(spreadsheet_id, row_id, user.id)
+            .get_todos_by_row(spreadsheet_id, row_id, user.id, &query)
             .await?;
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(todos)))
@@ -144,14 +201,76 @@ impl TodoHandlers {
         let user = get_user_from_request(&req)?;
         let todo_id = path.into_inner();
 
-        let todo = self.repository
-            .update_todo(todo_id, &payload, user.id)
-            .await?;
+        let previous = self.repository.get_todo_by_id(todo_id, user.id).await?;
+        if let (Some(previous), Some(assignee)) = (&previous, payload.assigned_to) {
+            self.repository.ensure_users_exist(previous.spreadsheet_id, &[assignee]).await?;
+        }
+        if let (Some(previous), Some(true)) = (&previous, payload.completed) {
+            if !previous.completed {
+                self.ensure_can_complete(previous).await?;
+            }
+        }
 
-        match todo {
-            Some(todo) => Ok(HttpResponse::Ok().json(ApiResponse::success(todo))),
-            None => Err(ContrivanceError::not_found("Todo not found")),
+        let mut publication = self.events.begin().await?;
+        let todos = match payload.scope {
+            TodoEditScope::Occurrence => {
+                if payload.recurrence.is_some() || payload.recurrence_timezone.is_some() {
+                    return Err(ContrivanceError::validation(
+                        "Recurrence belongs to the whole series; update it with \"scope\": \"series\"",
+                    ));
+                }
+                if let (Some(previous), Some(parent_id)) = (&previous, payload.parent_id) {
+                    self.ensure_parent(previous.spreadsheet_id, previous.row_id, parent_id).await?;
+                }
+                self.repository
+                    .update_todo(publication.tx(), todo_id, &payload, user.id)
+                    .await?
+                    .into_iter()
+                    .collect()
+            }
+            TodoEditScope::Series => {
+                if payload.parent_id.is_some() {
+                    return Err(ContrivanceError::validation("Move one occurrence at a time under a parent"));
+                }
+                let previous = previous.as_ref().ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+                let series_id = previous
+                    .series_id
+                    .ok_or_else(|| ContrivanceError::validation("This todo doesn't repeat, so it has no series"))?;
+                validate_series_update(previous, &payload)?;
+                self.repository
+                    .update_todo_series(publication.tx(), todo_id, series_id, &payload, user.id)
+                    .await?
+            }
+        };
+        let todo = todos.first().cloned().ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+
+        let was_completed = previous.as_ref().is_some_and(|p| p.completed);
+        for changed in &todos {
+            // Only the todo the request named can have been completed
+            let completed_now = changed.id == todo_id && changed.completed && !was_completed;
+            record_todo_change(&mut publication, changed, completed_now, user.id).await?;
+        }
+        publication.commit().await?;
+        let previous_assignee = previous.as_ref().and_then(|p| p.assigned_to);
+        self.notifier.todo_assigned(&todo, previous_assignee, user.id).await;
+        self.notifier.mentioned_in_todo(previous.as_ref(), &todo, user.id).await;
+        if previous.as_ref().is_some_and(|p| !p.completed) && todo.completed {
+            self.schedule_next_occurrence(&todo, user.id).await;
+        }
+
+        let previous_parent = previous.as_ref().and_then(|p| p.parent_id);
+        let completion_changed = previous.as_ref().is_some_and(|p| p.completed != todo.completed);
+        if previous_parent != todo.parent_id {
+            self.roll_up(previous_parent, user.id).await;
+        }
+        if completion_changed || previous_parent != todo.parent_id {
+            self.roll_up(todo.parent_id, user.id).await;
         }
+        if completion_changed {
+            self.publish_dependents(todo.id, user.id).await;
+        }
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
     }
 
     /// Delete a todo
@@ -163,15 +282,39 @@ impl TodoHandlers {
         let user = get_user_from_request(&req)?;
         let todo_id = path.into_inner();
 
+        let todo = self.repository
+            .get_todo_by_id(todo_id, user.id)
+            .await?;
+        let dependents = self.repository.get_dependent_todos(todo_id).await?;
+
+        let mut publication = self.events.begin().await?;
         let deleted = self.repository
-            .delete_todo(todo_id, user.id)
+            .delete_todo(publication.tx(), todo_id, user.id)
             .await?;
 
-        if deleted {
-            Ok(HttpResponse::Ok().json(ApiResponse::success("Todo deleted successfully")))
-        } else {
-            Err(ContrivanceError::not_found("Todo not found"))
+        let todo = match (deleted, todo) {
+            (true, Some(todo)) => todo,
+            _ => return Err(ContrivanceError::not_found("Todo not found")),
+        };
+        // Its subtasks go with it
+        let message = WebSocketMessage::TodoDeleted {
+            spreadsheet_id: todo.spreadsheet_id,
+            todo_id,
+            deleted_by: user.id,
+        };
+        publication.record(todo.spreadsheet_id, user.id, message).await?;
+        publication.commit().await?;
+
+        self.roll_up(todo.parent_id, user.id).await;
+        for dependent in dependents {
+            match self.repository.get_spreadsheet_todo(todo.spreadsheet_id, dependent.id).await {
+                Ok(Some(dependent)) => self.publish_todo_updated(&dependent, user.id).await,
+                Ok(None) => {}
+                Err(e) => error!("Failed to reload todo {}: {}", dependent.id, e),
+            }
         }
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success("Todo deleted successfully")))
     }
 
     /// Mark a todo as completed
@@ -183,14 +326,27 @@ impl TodoHandlers {
         let user = get_user_from_request(&req)?;
         let todo_id = path.into_inner();
 
+        let current = self.repository
+            .get_todo_by_id(todo_id, user.id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+        if !current.completed {
+            self.ensure_can_complete(&current).await?;
+        }
+
+        let mut publication = self.events.begin().await?;
         let todo = self.repository
-            .update_todo_completion(todo_id, true, user.id)
-            .await?;
+            .update_todo_completion(publication.tx(), todo_id, true, user.id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+        record_todo_change(&mut publication, &todo, !current.completed, user.id).await?;
+        publication.commit().await?;
 
-        match todo {
-            Some(todo) => Ok(HttpResponse::Ok().json(ApiResponse::success(todo))),
-            None => Err(ContrivanceError::not_found("Todo not found")),
-        }
+        self.schedule_next_occurrence(&todo, user.id).await;
+        self.roll_up(todo.parent_id, user.id).await;
+        self.publish_dependents(todo.id, user.id).await;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
     }
 
     /// Mark a todo as not completed
@@ -202,13 +358,247 @@ impl TodoHandlers {
         let user = get_user_from_request(&req)?;
         let todo_id = path.into_inner();
 
+        let mut publication = self.events.begin().await?;
         let todo = self.repository
-            .update_todo_completion(todo_id, false, user.id)
-            .await?;
+            .update_todo_completion(publication.tx(), todo_id, false, user.id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Todo not found"))?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
 
-        match todo {
-            Some(todo) => Ok(HttpResponse::Ok().json(ApiResponse::success(todo))),
-            None => Err(ContrivanceError::not_found("Todo not found")),
+        self.roll_up(todo.parent_id, user.id).await;
+        self.publish_dependents(todo.id, user.id).await;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
+    }
+
+    /// Publish TodoUpdated for a todo another change affected, such as one
+    /// waiting on a todo that was completed
+    async fn publish_todo_updated(&self, todo: &Todo, user_id: Uuid) {
+        let message = WebSocketMessage::TodoUpdated {
+            spreadsheet_id: todo.spreadsheet_id,
+            todo: todo.clone(),
+            updated_by: user_id,
+        };
+        if let Err(e) = self.events.publish(todo.spreadsheet_id, user_id, message).await {
+            error!("Failed to publish the update to todo {}: {}", todo.id, e);
+        }
+    }
+
+    /// Let clients know the todos waiting on one that was completed, reopened
+    /// or deleted may have become unblocked or blocked
+    async fn publish_dependents(&self, todo_id: Uuid, user_id: Uuid) {
+        match self.repository.get_dependent_todos(todo_id).await {
+            Ok(dependents) => {
+                for dependent in &dependents {
+                    self.publish_todo_updated(dependent, user_id).await;
+                }
+            }
+            Err(e) => error!("Failed to load todos waiting on {}: {}", todo_id, e),
+        }
+    }
+
+    /// Complete or reopen a parent whose subtasks changed, and its parents in turn
+    async fn roll_up(&self, parent_id: Option<Uuid>, user_id: Uuid) {
+        let Some(parent_id) = parent_id else {
+            return;
+        };
+        let rolled_up = async {
+            let mut publication = self.events.begin().await?;
+            let changed = self.repository.roll_up_todo_completion(publication.tx(), parent_id).await?;
+            // Each of these was just completed or reopened
+            for todo in &changed {
+                record_todo_change(&mut publication, todo, todo.completed, user_id).await?;
+            }
+            publication.commit().await?;
+            Ok::<_, ContrivanceError>(changed)
+        };
+        match rolled_up.await {
+            Ok(changed) => {
+                for todo in &changed {
+                    if todo.completed {
+                        self.schedule_next_occurrence(todo, user_id).await;
+                    }
+                    self.publish_dependents(todo.id, user_id).await;
+                }
+            }
+            Err(e) => error!("Failed to roll up completion to todo {}: {}", parent_id, e),
+        }
+    }
+
+    /// Refuse to complete a todo that's waiting on others or has open subtasks
+    async fn ensure_can_complete(&self, todo: &Todo) -> ContrivanceResult<()> {
+        if todo.blocked {
+            return Err(ContrivanceError::conflict("This todo is blocked by todos that are still open"));
+        }
+        let open = self.repository.count_open_subtasks(todo.id).await?;
+        if open > 0 {
+            return Err(ContrivanceError::conflict(format!(
+                "This todo has {} open subtask{}",
+                open,
+                if open == 1 { "" } else { "s" }
+            )));
+        }
+        Ok(())
+    }
+
+    /// A subtask's parent has to be on the same spreadsheet and row
+    async fn ensure_parent(&self, spreadsheet_id: Uuid, row_id: Option<Uuid>, parent_id: Uuid) -> ContrivanceResult<()> {
+        let parent = self.repository
+            .get_spreadsheet_todo(spreadsheet_id, parent_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Parent todo not found on this spreadsheet"))?;
+        if parent.row_id != row_id {
+            return Err(ContrivanceError::validation("A subtask has to be on the same row as its parent"));
+        }
+        Ok(())
+    }
+
+    /// Make a todo wait until another is completed
+    pub async fn add_dependency(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AddTodoDependencyRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let todo_id = path.into_inner();
+        self.updatable_todo(todo_id, user.id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository.add_todo_dependency(publication.tx(), todo_id, payload.blocked_by).await?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
+    }
+
+    /// Stop a todo waiting on another
+    pub async fn remove_dependency(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (todo_id, blocked_by) = path.into_inner();
+        self.updatable_todo(todo_id, user.id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository
+            .remove_todo_dependency(publication.tx(), todo_id, blocked_by)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Dependency not found"))?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
+    }
+
+    /// Add a step to a todo's checklist
+    pub async fn add_checklist_item(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<CreateChecklistItemRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let todo_id = path.into_inner();
+        payload.validate()?;
+        self.updatable_todo(todo_id, user.id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository.add_checklist_item(publication.tx(), todo_id, &payload).await?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(todo)))
+    }
+
+    /// Rename, tick or move a checklist item
+    pub async fn update_checklist_item(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+        payload: web::Json<UpdateChecklistItemRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (todo_id, item_id) = path.into_inner();
+        payload.validate()?;
+        self.updatable_todo(todo_id, user.id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository
+            .update_checklist_item(publication.tx(), todo_id, item_id, &payload)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Checklist item not found"))?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
+    }
+
+    /// Remove a checklist item
+    pub async fn delete_checklist_item(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (todo_id, item_id) = path.into_inner();
+        self.updatable_todo(todo_id, user.id).await?;
+
+        let mut publication = self.events.begin().await?;
+        let todo = self.repository
+            .delete_checklist_item(publication.tx(), todo_id, item_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Checklist item not found"))?;
+        record_todo_change(&mut publication, &todo, false, user.id).await?;
+        publication.commit().await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(todo)))
+    }
+
+    /// A todo the user created or is assigned, which they may change
+    async fn updatable_todo(&self, todo_id: Uuid, user_id: Uuid) -> ContrivanceResult<Todo> {
+        self.repository
+            .get_todo_by_id(todo_id, user_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Todo not found"))
+    }
+
+    /// Create the occurrence after a completed recurring todo, unless the
+    /// series has ended or it already exists
+    async fn schedule_next_occurrence(&self, todo: &Todo, user_id: Uuid) {
+        let (Some(rule), Some(start)) = (todo.recurrence.as_deref(), todo.series_start) else {
+            return;
+        };
+        let timezone = todo.recurrence_timezone.as_deref().unwrap_or("UTC");
+        let after = todo.scheduled_at.unwrap_or(start);
+        let next = match Schedule::new(rule, timezone, start) {
+            Ok(schedule) => schedule.next_after(after),
+            Err(e) => {
+                warn!("Not repeating todo {}: {}", todo.id, e);
+                return;
+            }
+        };
+        let Some(next) = next else {
+            return;
+        };
+
+        let scheduled = async {
+            let mut publication = self.events.begin().await?;
+            if let Some(created) = self.repository.create_next_occurrence(publication.tx(), todo, next).await? {
+                let message = WebSocketMessage::TodoCreated {
+                    spreadsheet_id: created.spreadsheet_id,
+                    todo: created,
+                    created_by: user_id,
+                };
+                publication.record(todo.spreadsheet_id, user_id, message).await?;
+            }
+            publication.commit().await
+        };
+        if let Err(e) = scheduled.await {
+            error!("Failed to create the next occurrence of todo {}: {}", todo.id, e);
         }
     }
 
@@ -217,10 +607,10 @@ impl TodoHandlers {
         &self,
         req: HttpRequest,
     ) -> Result<HttpResponse, ContrivanceError> {
-        let _user = get_user_from_request(&req)?; // Ensure user is authenticated
+        let user = get_user_from_request(&req)?;
 
         let users = self.repository
-            .get_users_for_assignment()
+            .get_users_for_assignment(user.id)
             .await?;
 
         // Return users without password hash for security
@@ -233,4 +623,46 @@ impl TodoHandlers {
 
         Ok(HttpResponse::Ok().json(ApiResponse::success(safe_users)))
     }
-}
\ No newline at end of file
+}
+
+/// Record TodoCompleted for a todo that has just been completed and
+/// TodoUpdated for any other change, e.g. to a todo's checklist or
+/// dependencies that leaves its completion alone
+async fn record_todo_change(
+    publication: &mut Publication,
+    todo: &Todo,
+    completed_now: bool,
+    user_id: Uuid,
+) -> ContrivanceResult<i64> {
+    let message = if completed_now {
+        WebSocketMessage::TodoCompleted {
+            spreadsheet_id: todo.spreadsheet_id,
+            todo: todo.clone(),
+            completed_by: user_id,
+        }
+    } else {
+        WebSocketMessage::TodoUpdated {
+            spreadsheet_id: todo.spreadsheet_id,
+            todo: todo.clone(),
+            updated_by: user_id,
+        }
+    };
+    publication.record(todo.spreadsheet_id, user_id, message).await
+}
+
+/// Check the rule a series update leaves the series with
+fn validate_series_update(todo: &Todo, update: &UpdateTodoRequest) -> ContrivanceResult<()> {
+    let rule = update.recurrence.as_deref().or(todo.recurrence.as_deref()).unwrap_or_default();
+    if rule.trim().is_empty() {
+        return Ok(());
+    }
+    let timezone = update
+        .recurrence_timezone
+        .as_deref()
+        .or(todo.recurrence_timezone.as_deref())
+        .unwrap_or("UTC");
+    let start = update.due_date.or(todo.series_start).ok_or_else(|| {
+        ContrivanceError::validation("A recurring todo needs a due_date for its first occurrence")
+    })?;
+    Schedule::new(rule, timezone, start).map(|_| ())
+}
@@ -0,0 +1,179 @@
+use actix_web::{web, HttpResponse, HttpRequest};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+use crate::{
+    repository::ContrivanceRepository,
+    middleware::auth::get_user_from_request,
+    webhooks::WebhookClient,
+};
+use common::{
+    webhooks::{generate_secret, is_webhook_event_type, WEBHOOK_EVENT_TYPES},
+    ApiResponse, ContrivanceError, CreateWebhookRequest, CreatedWebhookSubscription,
+    UpdateWebhookRequest, WebhookSubscription,
+};
+
+pub struct WebhookHandlers {
+    repository: ContrivanceRepository,
+    /// Checks subscription URLs the way deliveries will be sent
+    client: WebhookClient,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveriesQuery {
+    pub limit: Option<i64>,
+}
+
+impl WebhookHandlers {
+    pub fn new(repository: ContrivanceRepository, client: WebhookClient) -> Self {
+        Self { repository, client }
+    }
+
+    /// Create a webhook subscription. The signing secret is only returned here.
+    pub async fn create_webhook(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<CreateWebhookRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+        validate_event_types(&payload.event_types)?;
+        self.client.validate_url(&payload.url).await?;
+
+        if let Some(spreadsheet_id) = payload.spreadsheet_id {
+            if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+                return Err(ContrivanceError::forbidden("Access denied to spreadsheet"));
+            }
+        }
+
+        let secret = generate_secret();
+        let subscription = self.repository
+            .create_webhook(user.id, &payload, &secret)
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(CreatedWebhookSubscription {
+            subscription,
+            secret,
+        })))
+    }
+
+    /// List the authenticated user's webhook subscriptions
+    pub async fn list_webhooks(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let subscriptions = self.repository.list_webhooks(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(subscriptions)))
+    }
+
+    /// Get a webhook subscription
+    pub async fn get_webhook(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let subscription = self.owned_webhook(path.into_inner(), user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(subscription)))
+    }
+
+    /// Update a webhook subscription's URL, events, description or active flag
+    pub async fn update_webhook(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<UpdateWebhookRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+        if let Some(event_types) = &payload.event_types {
+            validate_event_types(event_types)?;
+        }
+        if let Some(url) = &payload.url {
+            self.client.validate_url(url).await?;
+        }
+
+        let webhook = self.owned_webhook(path.into_inner(), user.id).await?;
+
+        let subscription = self.repository
+            .update_webhook(webhook.id, &payload)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Webhook not found"))?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(subscription)))
+    }
+
+    /// Delete a webhook subscription
+    pub async fn delete_webhook(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let webhook = self.owned_webhook(path.into_inner(), user.id).await?;
+        self.repository.delete_webhook(webhook.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success("Webhook deleted successfully")))
+    }
+
+    /// Get a webhook's delivery log, newest first
+    pub async fn list_deliveries(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        query: web::Query<DeliveriesQuery>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let webhook = self.owned_webhook(path.into_inner(), user.id).await?;
+        let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+        let deliveries = self.repository
+            .list_webhook_deliveries(webhook.id, limit)
+            .await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(deliveries)))
+    }
+
+    /// Queue an earlier delivery to be sent again
+    pub async fn replay_delivery(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (webhook_id, delivery_id) = path.into_inner();
+
+        let webhook = self.owned_webhook(webhook_id, user.id).await?;
+
+        let delivery = self.repository
+            .replay_webhook_delivery(webhook.id, delivery_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Delivery not found"))?;
+
+        Ok(HttpResponse::Accepted().json(ApiResponse::success(delivery)))
+    }
+
+    /// Fetch a webhook, treating other users' webhooks as missing
+    async fn owned_webhook(&self, webhook_id: Uuid, user_id: Uuid) -> Result<WebhookSubscription, ContrivanceError> {
+        self.repository
+            .get_webhook(webhook_id)
+            .await?
+            .filter(|webhook| webhook.owner_id == user_id)
+            .ok_or_else(|| ContrivanceError::not_found("Webhook not found"))
+    }
+}
+
+fn validate_event_types(event_types: &[String]) -> Result<(), ContrivanceError> {
+    match event_types.iter().find(|event_type| !is_webhook_event_type(event_type)) {
+        Some(unknown) => Err(ContrivanceError::validation(format!(
+            "Unknown event type '{}'. Expected one of: {}",
+            unknown,
+            WEBHOOK_EVENT_TYPES.join(", ")
+        ))),
+        None => Ok(()),
+    }
+}
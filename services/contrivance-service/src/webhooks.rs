@@ -0,0 +1,505 @@
+use chrono::Utc;
+use common::webhooks::{sign_payload, DELIVERY_HEADER, EVENT_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use common::{ContrivanceError, ContrivanceResult};
+use futures::{future::join_all, StreamExt};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{config::Config, repository::ContrivanceRepository};
+
+/// How often the worker looks for due deliveries
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// Deliveries sent per poll
+const BATCH_SIZE: i64 = 50;
+/// Longest wait between retries
+const MAX_RETRY_DELAY_SECS: u64 = 6 * 60 * 60;
+/// Receiver response bodies are kept in the log up to this many characters
+const MAX_RESPONSE_BODY_CHARS: usize = 256;
+
+/// A pending delivery joined with its subscription's URL and secret
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DueWebhookDelivery {
+    pub id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub attempt_count: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Result of a single delivery attempt
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryOutcome {
+    pub status_code: Option<u16>,
+    pub response_body: Option<String>,
+    pub error: Option<String>,
+}
+
+impl DeliveryOutcome {
+    /// Any 2xx response counts as delivered
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none() && matches!(self.status_code, Some(200..=299))
+    }
+}
+
+/// Sends queued webhook deliveries and retries failures with exponential backoff
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    repository: ContrivanceRepository,
+    client: WebhookClient,
+    max_attempts: u32,
+    retry_base: Duration,
+    timeout: Duration,
+}
+
+impl WebhookDispatcher {
+    pub fn new(repository: ContrivanceRepository, config: &Config) -> Self {
+        Self {
+            repository,
+            client: WebhookClient::new(config.webhook_allow_private_targets),
+            max_attempts: config.webhook_max_attempts,
+            retry_base: Duration::from_secs(config.webhook_retry_base_secs),
+            timeout: Duration::from_secs(config.webhook_timeout_secs),
+        }
+    }
+
+    /// Run the delivery loop until the process exits
+    pub async fn run(self) {
+        info!("Webhook dispatcher started");
+        loop {
+            match self.dispatch_due().await {
+                // A full batch means more are probably waiting
+                Ok(sent) if sent as i64 == BATCH_SIZE => continue,
+                Ok(_) => {}
+                Err(e) => error!("Webhook dispatch failed: {}", e),
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Send every delivery that is currently due, returning how many were attempted
+    pub async fn dispatch_due(&self) -> common::ContrivanceResult<usize> {
+        // Lease claimed rows for longer than a request can take
+        let lease_seconds = self.timeout.as_secs() as i64 + 30;
+        let due = self.repository
+            .claim_due_webhook_deliveries(BATCH_SIZE, lease_seconds)
+            .await?;
+
+        let count = due.len();
+        join_all(due.into_iter().map(|delivery| self.attempt(delivery))).await;
+
+        Ok(count)
+    }
+
+    async fn attempt(&self, delivery: DueWebhookDelivery) {
+        let outcome = deliver(&self.client, &delivery, self.timeout).await;
+        let attempts = delivery.attempt_count as u32 + 1;
+
+        let retry_at = if outcome.succeeded() || attempts >= self.max_attempts {
+            None
+        } else {
+            let delay = retry_delay(attempts, self.retry_base);
+            Some(Utc::now() + chrono::Duration::seconds(delay.as_secs() as i64))
+        };
+
+        if !outcome.succeeded() {
+            warn!(
+                "Webhook delivery {} to {} failed (attempt {}/{}): {}",
+                delivery.id,
+                delivery.url,
+                attempts,
+                self.max_attempts,
+                outcome.error.clone().unwrap_or_else(|| format!("HTTP {}", outcome.status_code.unwrap_or_default())),
+            );
+        }
+
+        if let Err(e) = self.repository.record_webhook_attempt(delivery.id, &outcome, retry_at).await {
+            error!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+    }
+}
+
+/// HTTP client for user-supplied URLs: webhook deliveries and automation calls.
+///
+/// Redirects are not followed, so a receiver can't bounce a signed payload
+/// somewhere else. Unless private targets are allowed, hosts on private,
+/// loopback or link-local networks are refused, including hostnames that only
+/// resolve to them, so a URL can't be used to reach internal services.
+#[derive(Clone)]
+pub struct WebhookClient {
+    http: reqwest::Client,
+    allow_private_targets: bool,
+}
+
+impl WebhookClient {
+    pub fn new(allow_private_targets: bool) -> Self {
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent("Contrivance-Webhooks/1.0");
+        if !allow_private_targets {
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        Self {
+            http: builder.build().expect("Failed to create webhook HTTP client"),
+            allow_private_targets,
+        }
+    }
+
+    /// Start a POST to `url`. Hostnames are checked as they're resolved, so
+    /// only addresses given literally are checked here.
+    pub fn post(&self, url: &str) -> Result<reqwest::RequestBuilder, String> {
+        let parsed = parse_target(url)?;
+        if !self.allow_private_targets {
+            if let Some(ip) = literal_ip(&parsed) {
+                if is_private_address(ip) {
+                    return Err(format!("{} is not a public address", ip));
+                }
+            }
+        }
+        Ok(self.http.post(parsed))
+    }
+
+    /// Check a URL before it's saved: it has to be http(s), and unless private
+    /// targets are allowed, its host has to resolve to public addresses only
+    pub async fn validate_url(&self, url: &str) -> ContrivanceResult<()> {
+        let parsed = parse_target(url).map_err(ContrivanceError::validation)?;
+        if self.allow_private_targets {
+            return Ok(());
+        }
+
+        let addresses: Vec<IpAddr> = match literal_ip(&parsed) {
+            Some(ip) => vec![ip],
+            None => {
+                let host = parsed.host_str().unwrap_or_default();
+                let port = parsed.port_or_known_default().unwrap_or(443);
+                tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|_| ContrivanceError::validation(format!("Could not resolve {}", host)))?
+                    .map(|address| address.ip())
+                    .collect()
+            }
+        };
+        if addresses.is_empty() || addresses.into_iter().any(is_private_address) {
+            return Err(ContrivanceError::validation(
+                "Webhook URLs must point at a public address, not a private, loopback or link-local one",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_target(url: &str) -> Result<reqwest::Url, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host().is_none() {
+        return Err("URL must be an http(s) URL with a host".to_string());
+    }
+    Ok(parsed)
+}
+
+/// The host of a URL given as an IP address, without the brackets around IPv6
+fn literal_ip(url: &reqwest::Url) -> Option<IpAddr> {
+    let host = url.host_str()?;
+    host.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Whether an address is on a network a webhook mustn't reach: private,
+/// loopback, link-local, shared (carrier-grade NAT), unspecified, multicast
+/// or reserved
+pub fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (b == 18 || b == 19))
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_private_address(IpAddr::V4(mapped));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// Resolves hostnames to their public addresses only, failing when there are none
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let public: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|address| !is_private_address(address.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            let addrs: Addrs = Box::new(public.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// POST a delivery's payload to its subscription URL with signature headers
+pub async fn deliver(client: &WebhookClient, delivery: &DueWebhookDelivery, timeout: Duration) -> DeliveryOutcome {
+    let body = match serde_json::to_vec(&delivery.payload) {
+        Ok(body) => body,
+        Err(e) => {
+            return DeliveryOutcome {
+                error: Some(format!("Failed to serialize payload: {}", e)),
+                ..Default::default()
+            }
+        }
+    };
+    let timestamp = Utc::now().timestamp();
+
+    let request = match client.post(&delivery.url) {
+        Ok(request) => request,
+        Err(error) => {
+            return DeliveryOutcome {
+                error: Some(error),
+                ..Default::default()
+            }
+        }
+    };
+    let result = request
+        .timeout(timeout)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign_payload(&delivery.secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) => {
+            let status_code = response.status().as_u16();
+            let response_body = read_body_start(response).await;
+            DeliveryOutcome {
+                status_code: Some(status_code),
+                response_body,
+                error: None,
+            }
+        }
+        Err(e) => DeliveryOutcome {
+            error: Some(e.to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+/// The first `MAX_RESPONSE_BODY_CHARS` characters of a response, reading no
+/// more of the body than they can take up, however much the receiver sends
+async fn read_body_start(response: reqwest::Response) -> Option<String> {
+    let limit = MAX_RESPONSE_BODY_CHARS * 4;
+    let mut body = Vec::new();
+    let mut chunks = response.bytes_stream();
+    while body.len() < limit {
+        match chunks.next().await {
+            Some(Ok(chunk)) => body.extend_from_slice(&chunk[..chunk.len().min(limit - body.len())]),
+            Some(Err(_)) if body.is_empty() => return None,
+            Some(Err(_)) | None => break,
+        }
+    }
+    Some(String::from_utf8_lossy(&body).chars().take(MAX_RESPONSE_BODY_CHARS).collect())
+}
+
+/// Wait before the next attempt: `base * 2^(attempts - 1)`, capped at six hours
+pub fn retry_delay(attempts: u32, base: Duration) -> Duration {
+    let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+    Duration::from_secs(base.as_secs().saturating_mul(factor).min(MAX_RETRY_DELAY_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use common::webhooks::verify_signature;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Received {
+        requests: Vec<(String, bool, serde_json::Value)>,
+    }
+
+    /// Receiver that checks the signature and fails the first `fail_first` requests
+    async fn receive(
+        req: HttpRequest,
+        body: web::Bytes,
+        state: web::Data<(Mutex<Received>, usize)>,
+    ) -> HttpResponse {
+        let header = |name: &str| {
+            req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or_default().to_string()
+        };
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap_or_default();
+        let valid = verify_signature("whsec_test", timestamp, &body, &header(SIGNATURE_HEADER));
+        let payload = serde_json::from_slice(&body).unwrap_or_default();
+
+        let (received, fail_first) = state.get_ref();
+        let mut received = received.lock().unwrap();
+        received.requests.push((header(EVENT_HEADER), valid, payload));
+
+        if received.requests.len() <= *fail_first {
+            HttpResponse::InternalServerError().body("try again")
+        } else {
+            HttpResponse::Ok().body("ok")
+        }
+    }
+
+    async fn start_receiver(fail_first: usize) -> (String, web::Data<(Mutex<Received>, usize)>) {
+        let state = web::Data::new((Mutex::new(Received::default()), fail_first));
+        let app_state = state.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(app_state.clone())
+                .route("/hook", web::post().to(receive))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        (format!("http://{}/hook", addr), state)
+    }
+
+    fn due_delivery(url: String) -> DueWebhookDelivery {
+        DueWebhookDelivery {
+            id: Uuid::new_v4(),
+            event_type: "row_deleted".to_string(),
+            payload: serde_json::json!({
+                "type": "RowDeleted",
+                "spreadsheet_id": Uuid::new_v4(),
+                "row_id": Uuid::new_v4(),
+                "deleted_by": Uuid::new_v4(),
+                "seq": 7,
+            }),
+            attempt_count: 0,
+            url,
+            secret: "whsec_test".to_string(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_delivers_signed_payload_to_local_receiver() {
+        let (url, state) = start_receiver(0).await;
+        let delivery = due_delivery(url);
+
+        let outcome = deliver(&WebhookClient::new(true), &delivery, Duration::from_secs(5)).await;
+
+        assert!(outcome.succeeded(), "{:?}", outcome);
+        assert_eq!(outcome.status_code, Some(200));
+        assert_eq!(outcome.response_body.as_deref(), Some("ok"));
+
+        let received = state.0.lock().unwrap();
+        assert_eq!(received.requests.len(), 1);
+        let (event, signature_valid, payload) = &received.requests[0];
+        assert_eq!(event, "row_deleted");
+        assert!(signature_valid);
+        assert_eq!(payload, &delivery.payload);
+    }
+
+    #[actix_web::test]
+    async fn test_error_responses_and_unreachable_receivers_fail() {
+        let (url, _state) = start_receiver(1).await;
+        let client = WebhookClient::new(true);
+
+        let outcome = deliver(&client, &due_delivery(url.clone()), Duration::from_secs(5)).await;
+        assert!(!outcome.succeeded());
+        assert_eq!(outcome.status_code, Some(500));
+
+        let outcome = deliver(&client, &due_delivery(url), Duration::from_secs(5)).await;
+        assert!(outcome.succeeded());
+
+        // Nothing listens on port 9 (discard) locally
+        let outcome = deliver(&client, &due_delivery("http://127.0.0.1:9/hook".into()), Duration::from_secs(5)).await;
+        assert!(!outcome.succeeded());
+        assert!(outcome.error.is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_only_the_start_of_a_response_body_is_read() {
+        let server = HttpServer::new(|| {
+            App::new().route(
+                "/hook",
+                web::post().to(|| async {
+                    let chunk = web::Bytes::from_static(&[b'x'; 1024]);
+                    HttpResponse::Ok().streaming(futures::stream::repeat(chunk).map(Ok::<_, actix_web::Error>))
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+
+        // The body never ends, so reading all of it would time out
+        let delivery = due_delivery(format!("http://{}/hook", addr));
+        let outcome = deliver(&WebhookClient::new(true), &delivery, Duration::from_secs(5)).await;
+
+        assert!(outcome.succeeded(), "{:?}", outcome);
+        assert_eq!(outcome.response_body, Some("x".repeat(MAX_RESPONSE_BODY_CHARS)));
+    }
+
+    #[actix_web::test]
+    async fn test_private_targets_are_refused_unless_allowed() {
+        let (url, state) = start_receiver(0).await;
+        let client = WebhookClient::new(false);
+
+        let outcome = deliver(&client, &due_delivery(url.clone()), Duration::from_secs(5)).await;
+        assert!(outcome.error.is_some());
+        let by_name = url.replace("127.0.0.1", "localhost");
+        let outcome = deliver(&client, &due_delivery(by_name.clone()), Duration::from_secs(5)).await;
+        assert!(outcome.error.is_some());
+        assert!(state.0.lock().unwrap().requests.is_empty());
+
+        assert!(client.validate_url(&by_name).await.is_err());
+        assert!(client.validate_url("http://169.254.169.254/latest/meta-data").await.is_err());
+        assert!(client.validate_url("ftp://example.com/hook").await.is_err());
+        assert!(WebhookClient::new(true).validate_url(&by_name).await.is_ok());
+    }
+
+    #[test]
+    fn test_private_addresses() {
+        for private in ["10.1.2.3", "172.16.0.1", "192.168.1.1", "127.0.0.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:10.0.0.1"] {
+            assert!(is_private_address(private.parse().unwrap()), "{}", private);
+        }
+        for public in ["8.8.8.8", "93.184.216.34", "2606:4700::1111"] {
+            assert!(!is_private_address(public.parse().unwrap()), "{}", public);
+        }
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off_exponentially() {
+        let base = Duration::from_secs(30);
+        assert_eq!(retry_delay(1, base), Duration::from_secs(30));
+        assert_eq!(retry_delay(2, base), Duration::from_secs(60));
+        assert_eq!(retry_delay(5, base), Duration::from_secs(480));
+        assert_eq!(retry_delay(40, base), Duration::from_secs(MAX_RETRY_DELAY_SECS));
+    }
+}
@@ -1,83 +1,1010 @@
 use actix::prelude::*;
+use actix_web::web;
 use actix_web_actors::ws;
-use common::WebSocketMessage;
+use chrono::{DateTime, Utc};
+use common::{
+    ContrivanceError, ContrivanceResult, CreateRowRequest, PresenceUser, RowKeys, SpreadsheetRow,
+    UpdateRowRequest, WebSocketMessage,
+};
+use serde::Serialize;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, RwLock};
 use uuid::Uuid;
 use tracing::{info, warn, error};
 
+use crate::{
+    backpressure::{self, Outbox, Queued, RateLimiter, SocketEvent, SocketLimits, SocketMetrics},
+    events::Replay,
+    handlers::ContrivanceHandlers,
+    socket_auth::{SocketAccess, SocketAuth, SocketSession},
+};
+
+/// How often lapsed presence and stopped connections are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+/// Minimum gap between selection changes relayed from one connection
+const SELECTION_THROTTLE: Duration = Duration::from_millis(100);
+/// How often a spreadsheet connection re-checks its user's access
+pub const ACCESS_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Cursor colors, picked per user so everyone sees the same one
+const PRESENCE_COLORS: &[&str] = &[
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4",
+    "#f032e6", "#9a6324", "#469990", "#800000", "#808000", "#000075",
+];
+
+/// The color a user's cursor is drawn in
+pub fn presence_color(user_id: Uuid) -> &'static str {
+    PRESENCE_COLORS[(user_id.as_u128() % PRESENCE_COLORS.len() as u128) as usize]
+}
+
+/// How often connections ping their client, and how long one may go without
+/// hearing back. A connection silent for longer is closed, and a user none of
+/// whose connections are heard from for that long stops being present.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Where a reconnecting spreadsheet client left off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Replay the events after this sequence number
+    After(i64),
+    /// The resume token was expired or not the user's; the client has to reload
+    Rejected,
+}
+
+/// What a WebSocket connection is subscribed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// Changes to one spreadsheet
+    Spreadsheet(Uuid),
+    /// Notifications for one user
+    User(Uuid),
+}
+
+/// A user's presence on a spreadsheet, shared by all their connections to it
+struct Presence {
+    user_name: String,
+    row_id: Option<Uuid>,
+    column_id: Option<Uuid>,
+    connections: usize,
+    last_seen: Instant,
+    /// False once heartbeats stop, until the next one
+    active: bool,
+}
+
+/// Who is viewing each spreadsheet. Methods report whether the change should
+/// be announced; sending is left to the `ConnectionManager`.
+#[derive(Default)]
+struct PresenceRoster {
+    spreadsheets: HashMap<Uuid, HashMap<Uuid, Presence>>,
+}
+
+impl PresenceRoster {
+    /// Count a new connection; true if it made the user present
+    fn connect(&mut self, spreadsheet_id: Uuid, user_id: Uuid, user_name: &str, now: Instant) -> bool {
+        let presence = self.spreadsheets
+            .entry(spreadsheet_id)
+            .or_default()
+            .entry(user_id)
+            .or_insert_with(|| Presence {
+                user_name: user_name.to_string(),
+                row_id: None,
+                column_id: None,
+                connections: 0,
+                last_seen: now,
+                active: false,
+            });
+        presence.connections += 1;
+        presence.last_seen = now;
+        !std::mem::replace(&mut presence.active, true)
+    }
+
+    /// Drop a connection; true if the user was present and it was their last
+    fn disconnect(&mut self, spreadsheet_id: Uuid, user_id: Uuid) -> bool {
+        let Some(users) = self.spreadsheets.get_mut(&spreadsheet_id) else { return false };
+        let Some(presence) = users.get_mut(&user_id) else { return false };
+        presence.connections = presence.connections.saturating_sub(1);
+        if presence.connections > 0 {
+            return false;
+        }
+        let was_active = presence.active;
+        users.remove(&user_id);
+        if users.is_empty() {
+            self.spreadsheets.remove(&spreadsheet_id);
+        }
+        was_active
+    }
+
+    /// Record a heartbeat; true if it brought lapsed presence back
+    fn touch(&mut self, spreadsheet_id: Uuid, user_id: Uuid, now: Instant) -> bool {
+        match self.spreadsheets.get_mut(&spreadsheet_id).and_then(|users| users.get_mut(&user_id)) {
+            Some(presence) => {
+                presence.last_seen = now;
+                !std::mem::replace(&mut presence.active, true)
+            }
+            None => false,
+        }
+    }
+
+    fn select(&mut self, spreadsheet_id: Uuid, user_id: Uuid, row_id: Option<Uuid>, column_id: Option<Uuid>) {
+        if let Some(presence) = self.spreadsheets.get_mut(&spreadsheet_id).and_then(|users| users.get_mut(&user_id)) {
+            presence.row_id = row_id;
+            presence.column_id = column_id;
+        }
+    }
+
+    /// Lapse the presence of users not heard from within `timeout`, returning
+    /// the (spreadsheet, user) pairs that lapsed
+    fn expire(&mut self, now: Instant, timeout: Duration) -> Vec<(Uuid, Uuid)> {
+        let mut expired = Vec::new();
+        for (spreadsheet_id, users) in self.spreadsheets.iter_mut() {
+            for (user_id, presence) in users.iter_mut() {
+                if presence.active && now.duration_since(presence.last_seen) > timeout {
+                    presence.active = false;
+                    presence.row_id = None;
+                    presence.column_id = None;
+                    expired.push((*spreadsheet_id, *user_id));
+                }
+            }
+        }
+        expired
+    }
+
+    fn user(&self, spreadsheet_id: Uuid, user_id: Uuid) -> Option<PresenceUser> {
+        let presence = self.spreadsheets.get(&spreadsheet_id)?.get(&user_id)?;
+        Some(presence_user(user_id, presence))
+    }
+
+    /// Users present on a spreadsheet, other than `except`
+    fn users(&self, spreadsheet_id: Uuid, except: Uuid) -> Vec<PresenceUser> {
+        let mut users: Vec<PresenceUser> = self.spreadsheets
+            .get(&spreadsheet_id)
+            .into_iter()
+            .flatten()
+            .filter(|(user_id, presence)| presence.active && **user_id != except)
+            .map(|(user_id, presence)| presence_user(*user_id, presence))
+            .collect();
+        users.sort_by(|a, b| a.user_name.cmp(&b.user_name).then(a.user_id.cmp(&b.user_id)));
+        users
+    }
+}
+
+fn presence_user(user_id: Uuid, presence: &Presence) -> PresenceUser {
+    PresenceUser {
+        user_id,
+        user_name: presence.user_name.clone(),
+        color: presence_color(user_id).to_string(),
+        row_id: presence.row_id,
+        column_id: presence.column_id,
+    }
+}
+
+/// How a connection is woken to write what's waiting in its outbox
+#[derive(Clone)]
+enum Wake {
+    /// A WebSocket's actor
+    Socket(Addr<WebSocketConnection>),
+    /// An event stream's response body, for as long as the client holds it open
+    Stream(Weak<Notify>),
+}
+
+/// How the manager reaches a connection: its id, how to wake it, and the
+/// outbox that messages for it wait in
+#[derive(Clone)]
+pub struct Outlet {
+    id: Uuid,
+    wake: Wake,
+    outbox: Arc<Mutex<Outbox>>,
+}
+
+impl Outlet {
+    /// Outlet for an event stream, which writes its outbox when `notify` fires
+    pub fn for_stream(id: Uuid, notify: &Arc<Notify>, outbox: Arc<Mutex<Outbox>>) -> Self {
+        Self { id, wake: Wake::Stream(Arc::downgrade(notify)), outbox }
+    }
+
+    fn wake(&self) {
+        match &self.wake {
+            Wake::Socket(addr) => addr.do_send(Flush),
+            Wake::Stream(notify) => {
+                if let Some(notify) = notify.upgrade() {
+                    notify.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Whether the connection is still open
+    fn connected(&self) -> bool {
+        match &self.wake {
+            Wake::Socket(addr) => addr.connected(),
+            Wake::Stream(notify) => notify.strong_count() > 0,
+        }
+    }
+
+    fn outbox(&self) -> std::sync::MutexGuard<'_, Outbox> {
+        self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Close the connection if its outbox has stayed full too long; true if it was
+    fn evict_if_lagging(&self, now: Instant, timeout: Duration) -> bool {
+        let mut outbox = self.outbox();
+        let lagging = outbox.lagging(now, timeout);
+        if lagging {
+            outbox.close();
+        }
+        lagging
+    }
+}
+
+/// A connection to a spreadsheet, and whose it is
+struct Member {
+    user_id: Uuid,
+    outlet: Outlet,
+    /// Whether the connection counts towards its user's presence; event
+    /// streams only listen
+    present: bool,
+}
+
 /// WebSocket connection manager
 pub struct ConnectionManager {
-    // Map of spreadsheet_id -> list of connection actors
-    connections: HashMap<Uuid, Vec<Addr<WebSocketConnection>>>,
+    // Map of spreadsheet_id -> list of connections
+    connections: HashMap<Uuid, Vec<Member>>,
+    // Map of user_id -> list of notification connections
+    user_connections: HashMap<Uuid, Vec<Outlet>>,
+    presence: PresenceRoster,
+    heartbeat: Heartbeat,
+    limits: SocketLimits,
+    metrics: Arc<SocketMetrics>,
 }
 
 impl ConnectionManager {
-    pub fn new() -> Self {
+    pub fn new(heartbeat: Heartbeat) -> Self {
         Self {
             connections: HashMap::new(),
+            user_connections: HashMap::new(),
+            presence: PresenceRoster::default(),
+            heartbeat,
+            limits: SocketLimits::default(),
+            metrics: Arc::new(SocketMetrics::default()),
         }
     }
 
-    /// Add connection to a spreadsheet
-    pub fn add_connection(&mut self, spreadsheet_id: Uuid, addr: Addr<WebSocketConnection>) {
-        let connections = self.connections.entry(spreadsheet_id).or_insert_with(Vec::new);
-        connections.push(addr);
-        info!("Added connection to spreadsheet {}", spreadsheet_id);
+    pub fn with_limits(mut self, limits: SocketLimits) -> Self {
+        self.limits = limits;
+        self
     }
 
-    /// Remove connection from a spreadsheet
-    pub fn remove_connection(&mut self, spreadsheet_id: Uuid, addr: &Addr<WebSocketConnection>) {
-        if let Some(connections) = self.connections.get_mut(&spreadsheet_id) {
-            connections.retain(|conn| !conn.eq(addr));
-            if connections.is_empty() {
-                self.connections.remove(&spreadsheet_id);
-            }
+    /// The heartbeat settings new connections should use
+    pub fn heartbeat(&self) -> Heartbeat {
+        self.heartbeat
+    }
+
+    /// The budgets new connections should use
+    pub fn limits(&self) -> SocketLimits {
+        self.limits
+    }
+
+    pub fn metrics(&self) -> Arc<SocketMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Connections open on this instance, spreadsheet and notification alike
+    pub fn connection_count(&self) -> usize {
+        self.connections.values().map(Vec::len).sum::<usize>()
+            + self.user_connections.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Add a connection to a spreadsheet and mark its user present. Peers are
+    /// told if the user wasn't already, and the connection gets a snapshot of
+    /// everyone else who is.
+    pub fn join(&mut self, spreadsheet_id: Uuid, user_id: Uuid, user_name: &str, outlet: Outlet) {
+        let snapshot = WebSocketMessage::PresenceSnapshot {
+            spreadsheet_id,
+            users: self.presence.users(spreadsheet_id, user_id),
+        };
+        if let Some((json, _)) = serialize(&snapshot) {
+            self.deliver(&outlet, json, None);
         }
+
+        if self.presence.connect(spreadsheet_id, user_id, user_name, Instant::now()) {
+            self.announce_joined(spreadsheet_id, user_id, Some(outlet.id));
+        }
+        self.add_connection(spreadsheet_id, Member { user_id, outlet, present: true });
     }
 
-    /// Broadcast message to all connections of a spreadsheet
-    pub async fn broadcast_to_spreadsheet(&self, spreadsheet_id: Uuid, message: WebSocketMessage) {
-        if let Some(connections) = self.connections.get(&spreadsheet_id) {
-            let message_json = match serde_json::to_string(&message) {
-                Ok(json) => json,
-                Err(e) => {
-                    error!("Failed to serialize WebSocket message: {}", e);
-                    return;
+    /// Add a connection that receives a spreadsheet's messages without
+    /// making its user present
+    pub fn listen(&mut self, spreadsheet_id: Uuid, user_id: Uuid, outlet: Outlet) {
+        self.add_connection(spreadsheet_id, Member { user_id, outlet, present: false });
+    }
+
+    /// Remove a connection from a spreadsheet, telling peers if it was the
+    /// user's last. Connections already reaped are left alone.
+    pub fn leave(&mut self, spreadsheet_id: Uuid, user_id: Uuid, connection_id: Uuid) {
+        if self.remove_connection(spreadsheet_id, connection_id) == Some(true) {
+            self.depart(spreadsheet_id, user_id);
+        }
+    }
+
+    fn depart(&mut self, spreadsheet_id: Uuid, user_id: Uuid) {
+        if self.presence.disconnect(spreadsheet_id, user_id) {
+            self.send_to_spreadsheet(spreadsheet_id, &WebSocketMessage::UserLeft { user_id, spreadsheet_id }, None);
+        }
+    }
+
+    /// Record a heartbeat from one of the user's connections
+    pub fn touch(&mut self, spreadsheet_id: Uuid, user_id: Uuid) {
+        if self.presence.touch(spreadsheet_id, user_id, Instant::now()) {
+            self.announce_joined(spreadsheet_id, user_id, None);
+        }
+    }
+
+    /// Record the user's selected cell and relay it to the other connections
+    pub fn select(
+        &mut self,
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+        row_id: Option<Uuid>,
+        column_id: Option<Uuid>,
+        connection_id: Uuid,
+    ) {
+        self.touch(spreadsheet_id, user_id);
+        self.presence.select(spreadsheet_id, user_id, row_id, column_id);
+        let message = WebSocketMessage::SelectionChanged { spreadsheet_id, user_id, row_id, column_id };
+        self.send_to_spreadsheet(spreadsheet_id, &message, Some(connection_id));
+    }
+
+    /// Lapse the presence of users whose heartbeats have stopped, telling their peers
+    pub fn expire_presence(&mut self) {
+        for (spreadsheet_id, user_id) in self.presence.expire(Instant::now(), self.heartbeat.timeout) {
+            info!("Presence of user {} on spreadsheet {} lapsed", user_id, spreadsheet_id);
+            self.send_to_spreadsheet(spreadsheet_id, &WebSocketMessage::UserLeft { user_id, spreadsheet_id }, None);
+        }
+    }
+
+    /// Drop connections whose actors stopped without leaving, so their
+    /// users' presence is released and nothing more is sent to them
+    pub fn reap(&mut self) {
+        let mut departed = Vec::new();
+        self.connections.retain(|spreadsheet_id, members| {
+            members.retain(|member| {
+                let connected = member.outlet.connected();
+                if !connected && member.present {
+                    departed.push((*spreadsheet_id, member.user_id));
                 }
-            };
+                connected
+            });
+            !members.is_empty()
+        });
+        for (spreadsheet_id, user_id) in departed {
+            warn!("Reaped stopped connection of user {} on spreadsheet {}", user_id, spreadsheet_id);
+            self.depart(spreadsheet_id, user_id);
+        }
 
-            for connection in connections {
-                connection.do_send(SendMessage(message_json.clone()));
+        self.user_connections.retain(|_, connections| {
+            connections.retain(Outlet::connected);
+            !connections.is_empty()
+        });
+    }
+
+    /// Close connections whose outbox has stayed full, as they aren't keeping
+    /// up. Their actors may not be running to close themselves, so they're
+    /// dropped here and told why when they next run.
+    pub fn evict_slow_consumers(&mut self) {
+        let now = Instant::now();
+        let timeout = self.limits.slow_consumer_timeout;
+
+        let mut evicted = Vec::new();
+        self.connections.retain(|spreadsheet_id, members| {
+            members.retain(|member| {
+                let lagging = member.outlet.evict_if_lagging(now, timeout);
+                if lagging {
+                    evicted.push((*spreadsheet_id, member.user_id, member.present));
+                }
+                !lagging
+            });
+            !members.is_empty()
+        });
+        for (spreadsheet_id, user_id, present) in evicted {
+            warn!("Closing slow connection of user {} on spreadsheet {}", user_id, spreadsheet_id);
+            self.metrics.record(SocketEvent::SlowConsumerClosed);
+            if present {
+                self.depart(spreadsheet_id, user_id);
             }
         }
+
+        let metrics = self.metrics.clone();
+        self.user_connections.retain(|user_id, connections| {
+            connections.retain(|outlet| {
+                let lagging = outlet.evict_if_lagging(now, timeout);
+                if lagging {
+                    warn!("Closing slow notification connection of user {}", user_id);
+                    metrics.record(SocketEvent::SlowConsumerClosed);
+                }
+                !lagging
+            });
+            !connections.is_empty()
+        });
+    }
+
+    fn announce_joined(&self, spreadsheet_id: Uuid, user_id: Uuid, except: Option<Uuid>) {
+        if let Some(user) = self.presence.user(spreadsheet_id, user_id) {
+            let message = WebSocketMessage::UserJoined {
+                user_id,
+                user_name: user.user_name,
+                spreadsheet_id,
+                color: user.color,
+            };
+            self.send_to_spreadsheet(spreadsheet_id, &message, except);
+        }
+    }
+
+    /// Send a message to a spreadsheet's connections, optionally skipping one
+    fn send_to_spreadsheet<M: Serialize>(&self, spreadsheet_id: Uuid, message: &M, except: Option<Uuid>) {
+        let Some(connections) = self.connections.get(&spreadsheet_id) else { return };
+        let Some((message_json, row_id)) = serialize(message) else { return };
+
+        for member in connections.iter().filter(|member| except != Some(member.outlet.id)) {
+            self.deliver(&member.outlet, message_json.clone(), row_id);
+        }
+    }
+
+    /// Queue a message for a connection, waking its actor if it was idle
+    fn deliver(&self, outlet: &Outlet, json: String, row_id: Option<Uuid>) {
+        let queued = outlet.outbox().push(json, row_id, Instant::now());
+        match queued {
+            Queued::Wake => outlet.wake(),
+            Queued::Behind => {}
+            Queued::Coalesced => self.metrics.record(SocketEvent::Coalesced),
+            Queued::Dropped => self.metrics.record(SocketEvent::OutboundDropped),
+        }
+    }
+
+    /// Add connection to a spreadsheet
+    fn add_connection(&mut self, spreadsheet_id: Uuid, member: Member) {
+        let connections = self.connections.entry(spreadsheet_id).or_default();
+        connections.push(member);
+        info!("Added connection to spreadsheet {}", spreadsheet_id);
+    }
+
+    /// Remove connection from a spreadsheet, returning whether it counted
+    /// towards its user's presence; `None` if it wasn't there
+    fn remove_connection(&mut self, spreadsheet_id: Uuid, connection_id: Uuid) -> Option<bool> {
+        let connections = self.connections.get_mut(&spreadsheet_id)?;
+        let index = connections.iter().position(|member| member.outlet.id == connection_id)?;
+        let member = connections.remove(index);
+        if connections.is_empty() {
+            self.connections.remove(&spreadsheet_id);
+        }
+        Some(member.present)
+    }
+
+    /// Broadcast message to all connections of a spreadsheet
+    pub async fn broadcast_to_spreadsheet<M: Serialize>(&self, spreadsheet_id: Uuid, message: M) {
+        self.send_to_spreadsheet(spreadsheet_id, &message, None);
+    }
+
+    /// Spreadsheets with at least one connection open on this instance
+    pub fn spreadsheet_ids(&self) -> Vec<Uuid> {
+        self.connections.keys().copied().collect()
     }
 
     /// Get connection count for a spreadsheet
     pub fn get_connection_count(&self, spreadsheet_id: Uuid) -> usize {
         self.connections.get(&spreadsheet_id).map_or(0, |conns| conns.len())
     }
+
+    /// Add a user's notification connection
+    pub fn add_user_connection(&mut self, user_id: Uuid, outlet: Outlet) {
+        self.user_connections.entry(user_id).or_default().push(outlet);
+        info!("Added notification connection for user {}", user_id);
+    }
+
+    /// Remove a user's notification connection
+    pub fn remove_user_connection(&mut self, user_id: Uuid, connection_id: Uuid) {
+        if let Some(connections) = self.user_connections.get_mut(&user_id) {
+            connections.retain(|outlet| outlet.id != connection_id);
+            if connections.is_empty() {
+                self.user_connections.remove(&user_id);
+            }
+        }
+    }
+
+    /// Send a message to every notification connection a user has open
+    pub fn send_to_user<M: Serialize>(&self, user_id: Uuid, message: M) {
+        if let Some(connections) = self.user_connections.get(&user_id) {
+            let Some((message_json, _)) = serialize(&message) else { return };
+            for outlet in connections {
+                self.deliver(outlet, message_json.clone(), None);
+            }
+        }
+    }
+}
+
+/// What a spreadsheet connection's user may do, as of the last check, and
+/// what it needs to apply their edits
+struct Permissions {
+    access: SocketAccess,
+    auth: web::Data<SocketAuth>,
+    handlers: web::Data<ContrivanceHandlers>,
 }
 
 /// WebSocket connection actor
 pub struct WebSocketConnection {
     pub user_id: Uuid,
-    pub spreadsheet_id: Uuid,
+    pub user_name: String,
+    pub channel: Channel,
     pub connection_manager: Arc<RwLock<ConnectionManager>>,
+    /// The connection is closed when the credentials it was opened with expire
+    expires_at: DateTime<Utc>,
+    /// `None` on notification channels
+    permissions: Option<Permissions>,
+    /// When this connection last relayed a selection change
+    last_selection_sent: Option<Instant>,
+    /// Latest selection held back by the throttle, sent when it allows
+    pending_selection: Option<(Option<Uuid>, Option<Uuid>)>,
+    heartbeat: Heartbeat,
+    /// When the client was last heard from
+    last_heard: Instant,
+    /// Handed to the client once it's caught up; spreadsheet channels only
+    resume_token: Option<String>,
+    /// Where the client left off, if it's reconnecting
+    resume: Option<Resume>,
+    /// Identifies the connection's cell locks, so they're released when it closes
+    connection_id: Uuid,
+    /// Whether the connection has locked a cell
+    holds_locks: bool,
+    /// Messages from the manager waiting to be written
+    outbox: Arc<Mutex<Outbox>>,
+    /// Budget for the client's messages
+    limiter: RateLimiter,
+    /// Whether the client has been told it's over its budget since it last wasn't
+    rate_limited: bool,
+    metrics: Arc<SocketMetrics>,
 }
 
 impl WebSocketConnection {
     pub fn new(
-        user_id: Uuid,
+        session: SocketSession,
+        user_name: String,
         spreadsheet_id: Uuid,
+        access: SocketAccess,
         connection_manager: Arc<RwLock<ConnectionManager>>,
+        auth: web::Data<SocketAuth>,
+        handlers: web::Data<ContrivanceHandlers>,
     ) -> Self {
         Self {
-            user_id,
-            spreadsheet_id,
+            user_id: session.user_id,
+            user_name,
+            channel: Channel::Spreadsheet(spreadsheet_id),
+            connection_manager,
+            expires_at: session.expires_at,
+            permissions: Some(Permissions { access, auth, handlers }),
+            last_selection_sent: None,
+            pending_selection: None,
+            heartbeat: Heartbeat::default(),
+            last_heard: Instant::now(),
+            resume_token: None,
+            resume: None,
+            connection_id: Uuid::new_v4(),
+            holds_locks: false,
+            outbox: Arc::new(Mutex::new(Outbox::new(SocketLimits::default().outbox_capacity))),
+            limiter: RateLimiter::new(&SocketLimits::default(), Instant::now()),
+            rate_limited: false,
+            metrics: Arc::new(SocketMetrics::default()),
+        }
+    }
+
+    /// Connection on the user's own notification channel
+    pub fn for_user(session: SocketSession, connection_manager: Arc<RwLock<ConnectionManager>>) -> Self {
+        Self {
+            user_id: session.user_id,
+            user_name: String::new(),
+            channel: Channel::User(session.user_id),
             connection_manager,
+            expires_at: session.expires_at,
+            permissions: None,
+            last_selection_sent: None,
+            pending_selection: None,
+            heartbeat: Heartbeat::default(),
+            last_heard: Instant::now(),
+            resume_token: None,
+            resume: None,
+            connection_id: Uuid::new_v4(),
+            holds_locks: false,
+            outbox: Arc::new(Mutex::new(Outbox::new(SocketLimits::default().outbox_capacity))),
+            limiter: RateLimiter::new(&SocketLimits::default(), Instant::now()),
+            rate_limited: false,
+            metrics: Arc::new(SocketMetrics::default()),
+        }
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    /// Hold the connection to `limits`, counting what they do in `metrics`
+    pub fn with_limits(mut self, limits: SocketLimits, metrics: Arc<SocketMetrics>) -> Self {
+        self.outbox = Arc::new(Mutex::new(Outbox::new(limits.outbox_capacity)));
+        self.limiter = RateLimiter::new(&limits, Instant::now());
+        self.metrics = metrics;
+        self
+    }
+
+    fn outlet(&self, ctx: &ws::WebsocketContext<Self>) -> Outlet {
+        Outlet { id: self.connection_id, wake: Wake::Socket(ctx.address()), outbox: self.outbox.clone() }
+    }
+
+    /// Whether a client message fits the connection's budget. The first one
+    /// over it gets the client an error; the rest are dropped quietly.
+    fn within_budget(&mut self, ctx: &mut ws::WebsocketContext<Self>) -> bool {
+        if self.limiter.allow(Instant::now()) {
+            self.rate_limited = false;
+            return true;
+        }
+        self.metrics.record(SocketEvent::InboundDropped);
+        if !std::mem::replace(&mut self.rate_limited, true) {
+            warn!("Rate limiting socket of user {}", self.user_id);
+            let error = WebSocketMessage::Error {
+                message: "Too many messages; slow down".to_string(),
+                code: Some("RATE_LIMITED".to_string()),
+            };
+            self.send(&error, ctx);
+        }
+        false
+    }
+
+    /// Give the client `resume_token` once it's caught up, replaying what it
+    /// missed first if it's reconnecting
+    pub fn with_resume(mut self, resume_token: String, resume: Option<Resume>) -> Self {
+        self.resume_token = Some(resume_token);
+        self.resume = resume;
+        self
+    }
+
+    /// The client is still there; keeps the user's presence alive
+    fn heartbeat(&self) {
+        if let Channel::Spreadsheet(spreadsheet_id) = self.channel {
+            let user_id = self.user_id;
+            let connection_manager = self.connection_manager.clone();
+            actix::spawn(async move {
+                connection_manager.write().await.touch(spreadsheet_id, user_id);
+            });
+        }
+    }
+
+    /// Relay a selection change, at most once per `SELECTION_THROTTLE`. Changes
+    /// inside the window are coalesced and the latest is sent when it closes.
+    fn select(&mut self, row_id: Option<Uuid>, column_id: Option<Uuid>, ctx: &mut ws::WebsocketContext<Self>) {
+        let now = Instant::now();
+        match self.last_selection_sent {
+            Some(sent) if now.duration_since(sent) < SELECTION_THROTTLE => {
+                if self.pending_selection.replace((row_id, column_id)).is_none() {
+                    ctx.run_later(SELECTION_THROTTLE - now.duration_since(sent), |act, _| {
+                        if let Some((row_id, column_id)) = act.pending_selection.take() {
+                            act.relay_selection(row_id, column_id);
+                        }
+                    });
+                }
+            }
+            _ => self.relay_selection(row_id, column_id),
+        }
+    }
+
+    fn relay_selection(&mut self, row_id: Option<Uuid>, column_id: Option<Uuid>) {
+        let Channel::Spreadsheet(spreadsheet_id) = self.channel else { return };
+        self.last_selection_sent = Some(Instant::now());
+
+        let connection_id = self.connection_id;
+        let user_id = self.user_id;
+        let connection_manager = self.connection_manager.clone();
+        actix::spawn(async move {
+            connection_manager
+                .write()
+                .await
+                .select(spreadsheet_id, user_id, row_id, column_id, connection_id);
+        });
+    }
+
+    /// Apply a row op through the same checks and events as REST, then ack
+    /// or nack it. Ops run one at a time, in the order the client sent them,
+    /// so an edit is never overtaken by a later delete of the same row.
+    fn apply(&mut self, op_id: Uuid, op: RowOp, ctx: &mut ws::WebsocketContext<Self>) {
+        let (Channel::Spreadsheet(spreadsheet_id), Some(permissions)) = (self.channel, &self.permissions) else {
+            let error = ContrivanceError::bad_request("Edits can only be sent on a spreadsheet connection");
+            if let Ok(json) = serde_json::to_string(&op_result(op_id, Err(error))) {
+                ctx.text(json);
+            }
+            return;
+        };
+        if permissions.access == SocketAccess::View {
+            let error = ContrivanceError::forbidden("Edit access denied to this spreadsheet");
+            if let Ok(json) = serde_json::to_string(&op_result(op_id, Err(error))) {
+                ctx.text(json);
+            }
+            return;
+        }
+        let handlers = permissions.handlers.clone();
+        self.heartbeat();
+
+        let user_id = self.user_id;
+        let apply = async move { op.apply(&handlers, user_id, spreadsheet_id).await };
+        ctx.wait(apply.into_actor(self).map(move |result, _, ctx| {
+            if let Err(e) = &result {
+                info!("Rejected op {} from user {}: {}", op_id, user_id, e);
+            }
+            if let Ok(json) = serde_json::to_string(&op_result(op_id, result)) {
+                ctx.text(json);
+            }
+        }));
+    }
+
+    /// Take, renew or release a cell lock. A refused lock is answered with
+    /// the holder's `CellLocked`; granted ones reach the client with everyone
+    /// else's. Runs in order with ops, so a lock taken before an edit is held
+    /// when the edit lands.
+    fn lock(&mut self, row_id: Uuid, column_id: Uuid, locked: bool, ctx: &mut ws::WebsocketContext<Self>) {
+        let (Channel::Spreadsheet(spreadsheet_id), Some(permissions)) = (self.channel, &self.permissions) else { return };
+        if permissions.access == SocketAccess::View {
+            self.send_error(&ContrivanceError::forbidden("Edit access denied to this spreadsheet"), ctx);
+            return;
+        }
+        let handlers = permissions.handlers.clone();
+        let user_id = self.user_id;
+        let connection_id = self.connection_id;
+        self.heartbeat();
+
+        if !locked {
+            let unlock = async move { handlers.unlock_cell(user_id, spreadsheet_id, row_id, column_id).await };
+            ctx.wait(unlock.into_actor(self).map(move |result, act, ctx| {
+                if let Err(e) = result {
+                    act.send_error(&e, ctx);
+                }
+            }));
+            return;
+        }
+
+        self.holds_locks = true;
+        let lock = async move { handlers.lock_cell(user_id, spreadsheet_id, row_id, column_id, connection_id).await };
+        ctx.wait(lock.into_actor(self).map(move |result, act, ctx| match result {
+            Ok(lock) if lock.user_id != user_id => {
+                act.send(&WebSocketMessage::CellLocked { spreadsheet_id, lock }, ctx);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                act.send_error(&e, ctx);
+            }
+        }));
+    }
+
+    /// Send the client a text cell's collaborative document to edit
+    fn open_text(&mut self, row_id: Uuid, column_id: Uuid, ctx: &mut ws::WebsocketContext<Self>) {
+        let (Channel::Spreadsheet(spreadsheet_id), Some(permissions)) = (self.channel, &self.permissions) else { return };
+        let handlers = permissions.handlers.clone();
+        let user_id = self.user_id;
+        self.heartbeat();
+
+        let open = async move { handlers.open_cell_text(user_id, spreadsheet_id, row_id, column_id).await };
+        ctx.wait(open.into_actor(self).map(move |result, act, ctx| match result {
+            Ok(snapshot) => act.send(&snapshot, ctx),
+            Err(e) => act.send_error(&e, ctx),
+        }));
+    }
+
+    /// Re-check the user's access to the spreadsheet, closing the connection
+    /// if it's gone and going read-only if it's down to View
+    fn check_access(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        let (Channel::Spreadsheet(spreadsheet_id), Some(permissions)) = (self.channel, &self.permissions) else { return };
+        let auth = permissions.auth.clone();
+        let user_id = self.user_id;
+
+        let check = async move { auth.access(user_id, spreadsheet_id).await };
+        ctx.spawn(check.into_actor(self).map(move |result, act, ctx| match result {
+            Ok(Some(access)) => {
+                if let Some(permissions) = act.permissions.as_mut() {
+                    permissions.access = access;
+                }
+            }
+            Ok(None) => {
+                info!("Closing socket of user {} on spreadsheet {}: access removed", user_id, spreadsheet_id);
+                act.close("ACCESS_REVOKED", "Access to this spreadsheet was removed", ctx);
+            }
+            // Keep the connection and try again at the next check
+            Err(e) => warn!("Failed to check access of user {} to spreadsheet {}: {}", user_id, spreadsheet_id, e),
+        }));
+    }
+
+    /// Join the spreadsheet, replay anything the client missed and hand it
+    /// its resume token. Messages for the connection wait until this is done,
+    /// so live events always follow the replayed ones; a few may be sent by
+    /// both, and clients skip events they've already applied.
+    fn join(&mut self, spreadsheet_id: Uuid, ctx: &mut ws::WebsocketContext<Self>) {
+        let Some(permissions) = &self.permissions else { return };
+        let handlers = permissions.handlers.clone();
+        let outlet = self.outlet(ctx);
+        let user_id = self.user_id;
+        let user_name = self.user_name.clone();
+        let connection_manager = self.connection_manager.clone();
+        let resume = self.resume.take();
+
+        let join = async move {
+            connection_manager.write().await.join(spreadsheet_id, user_id, &user_name, outlet);
+            let since = match resume {
+                Some(Resume::After(seq)) => Some(seq),
+                _ => None,
+            };
+            let replay = handlers.missed_events(spreadsheet_id, since).await;
+            let locks = handlers.cell_locks(spreadsheet_id).await;
+            (replay, locks)
+        };
+        ctx.wait(join.into_actor(self).map(move |(replay, locks), act, ctx| {
+            match locks {
+                Ok(locks) => {
+                    for lock in locks {
+                        act.send(&WebSocketMessage::CellLocked { spreadsheet_id, lock }, ctx);
+                    }
+                }
+                Err(e) => error!("Failed to load cell locks for spreadsheet {}: {}", spreadsheet_id, e),
+            }
+            let seq = match replay {
+                Ok(Replay::Events(events, seq)) => {
+                    if !events.is_empty() {
+                        info!("Replaying {} events to user {} on spreadsheet {}", events.len(), user_id, spreadsheet_id);
+                    }
+                    for event in events {
+                        ctx.text(event.to_string());
+                    }
+                    if resume == Some(Resume::Rejected) {
+                        act.send(&WebSocketMessage::ResyncRequired { spreadsheet_id, seq }, ctx);
+                    }
+                    seq
+                }
+                Ok(Replay::TooFarBehind(seq)) => {
+                    act.send(&WebSocketMessage::ResyncRequired { spreadsheet_id, seq }, ctx);
+                    seq
+                }
+                Err(e) => {
+                    error!("Failed to load missed events for spreadsheet {}: {}", spreadsheet_id, e);
+                    let error = WebSocketMessage::Error {
+                        message: "Failed to catch up; reload the spreadsheet".to_string(),
+                        code: Some("RESYNC_REQUIRED".to_string()),
+                    };
+                    act.send(&error, ctx);
+                    return;
+                }
+            };
+            if let Some(resume_token) = act.resume_token.clone() {
+                act.send(&WebSocketMessage::Session { spreadsheet_id, resume_token, seq }, ctx);
+            }
+        }));
+    }
+
+    /// Ping the client, or stop if it hasn't been heard from within the timeout
+    fn ping(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.last_heard.elapsed() > self.heartbeat.timeout {
+            info!("Closing socket of user {} on {:?}: no heartbeat for {:?}", self.user_id, self.channel, self.heartbeat.timeout);
+            ctx.stop();
+            return;
+        }
+        ctx.ping(b"");
+    }
+
+    fn send(&self, message: &WebSocketMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        match serde_json::to_string(message) {
+            Ok(json) => ctx.text(json),
+            Err(e) => error!("Failed to serialize {} message: {}", message.event_type(), e),
+        }
+    }
+
+    fn send_error(&self, error: &ContrivanceError, ctx: &mut ws::WebsocketContext<Self>) {
+        let message = WebSocketMessage::Error {
+            message: error.to_string(),
+            code: Some(error.error_code().to_string()),
+        };
+        self.send(&message, ctx);
+    }
+
+    /// Tell the client why, then close the connection
+    fn close(&mut self, code: &str, message: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        let error = WebSocketMessage::Error {
+            message: message.to_string(),
+            code: Some(code.to_string()),
+        };
+        if let Ok(json) = serde_json::to_string(&error) {
+            ctx.text(json);
         }
+        ctx.close(Some(ws::CloseReason {
+            code: ws::CloseCode::Policy,
+            description: Some(message.to_string()),
+        }));
+        ctx.stop();
+    }
+}
+
+/// A row edit sent over a spreadsheet connection
+enum RowOp {
+    Create(CreateRowRequest),
+    Update(Uuid, UpdateRowRequest),
+    Delete(Uuid),
+    /// Automerge changes to a text cell: row, column and the base64 changes
+    EditText(Uuid, Uuid, String),
+}
+
+impl RowOp {
+    /// The op carried by a client message, with its op id
+    fn from_message(message: WebSocketMessage) -> Option<(Uuid, RowOp)> {
+        match message {
+            WebSocketMessage::CreateRow { op_id, row_data, position } => {
+                Some((op_id, RowOp::Create(CreateRowRequest { row_data, position })))
+            }
+            WebSocketMessage::UpdateRow { op_id, row_id, row_data } => {
+                let request = UpdateRowRequest { row_data: Some(row_data), position: None };
+                Some((op_id, RowOp::Update(row_id, request)))
+            }
+            WebSocketMessage::DeleteRow { op_id, row_id } => Some((op_id, RowOp::Delete(row_id))),
+            WebSocketMessage::EditText { op_id, row_id, column_id, changes } => {
+                Some((op_id, RowOp::EditText(row_id, column_id, changes)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply the op, returning the row written (none for deletes) and the
+    /// sequence number of the event it published. Rows come back name-keyed,
+    /// like the row events peers receive.
+    async fn apply(
+        self,
+        handlers: &ContrivanceHandlers,
+        user_id: Uuid,
+        spreadsheet_id: Uuid,
+    ) -> ContrivanceResult<(Option<SpreadsheetRow>, Option<i64>)> {
+        match self {
+            RowOp::Create(request) => handlers
+                .apply_create_row(user_id, spreadsheet_id, &request, RowKeys::Name)
+                .await
+                .map(|(row, seq)| (Some(row), seq)),
+            RowOp::Update(row_id, request) => handlers
+                .apply_update_row(user_id, spreadsheet_id, row_id, &request, RowKeys::Name)
+                .await
+                .map(|(row, seq)| (Some(row), seq)),
+            RowOp::Delete(row_id) => handlers
+                .apply_delete_row(user_id, spreadsheet_id, row_id)
+                .await
+                .map(|seq| (None, seq)),
+            // Peers get `TextEdited` for their editors as well as `RowUpdated`
+            RowOp::EditText(row_id, column_id, changes) => handlers
+                .apply_text_edit(user_id, spreadsheet_id, row_id, column_id, &changes, RowKeys::Name)
+                .await
+                .map(|(row, seq)| (Some(row), seq)),
+        }
+    }
+}
+
+/// The ack or nack for an op
+fn op_result(op_id: Uuid, result: ContrivanceResult<(Option<SpreadsheetRow>, Option<i64>)>) -> WebSocketMessage {
+    match result {
+        Ok((row, seq)) => WebSocketMessage::OpAck { op_id, row, seq },
+        Err(e) => WebSocketMessage::OpNack {
+            op_id,
+            message: e.to_string(),
+            code: e.error_code().to_string(),
+        },
     }
 }
 
@@ -85,73 +1012,146 @@ impl Actor for WebSocketConnection {
     type Context = ws::WebsocketContext<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
-        info!("WebSocket connection started for user {} in spreadsheet {}", 
-              self.user_id, self.spreadsheet_id);
+        info!("WebSocket connection started for user {} on {:?}", 
+              self.user_id, self.channel);
         
         // Add this connection to the manager
-        let addr = ctx.address();
-        let spreadsheet_id = self.spreadsheet_id;
-        let connection_manager = self.connection_manager.clone();
-        
-        actix::spawn(async move {
-            let mut manager = connection_manager.write().await;
-            manager.add_connection(spreadsheet_id, addr);
+        match self.channel {
+            Channel::Spreadsheet(spreadsheet_id) => self.join(spreadsheet_id, ctx),
+            Channel::User(user_id) => {
+                let outlet = self.outlet(ctx);
+                let connection_manager = self.connection_manager.clone();
+                actix::spawn(async move {
+                    connection_manager.write().await.add_user_connection(user_id, outlet);
+                });
+            }
+        }
+
+        // Browsers answer pings on their own, so pongs double as heartbeats
+        ctx.run_interval(self.heartbeat.interval, |act, ctx| act.ping(ctx));
+
+        let remaining = (self.expires_at - Utc::now()).to_std().unwrap_or_default();
+        ctx.run_later(remaining, |act, ctx| {
+            info!("Closing socket of user {}: token expired", act.user_id);
+            act.close("TOKEN_EXPIRED", "Token expired; reconnect with a new one", ctx);
         });
+        if matches!(self.channel, Channel::Spreadsheet(_)) {
+            ctx.run_interval(ACCESS_CHECK_INTERVAL, |act, ctx| act.check_access(ctx));
+        }
     }
 
-    fn stopped(&mut self, ctx: &mut Self::Context) {
-        info!("WebSocket connection stopped for user {} in spreadsheet {}", 
-              self.user_id, self.spreadsheet_id);
+    fn stopped(&mut self, _: &mut Self::Context) {
+        info!("WebSocket connection stopped for user {} on {:?}", 
+              self.user_id, self.channel);
         
         // Remove this connection from the manager
-        let addr = ctx.address();
-        let spreadsheet_id = self.spreadsheet_id;
+        let connection_id = self.connection_id;
+        let channel = self.channel;
+        let user_id = self.user_id;
         let connection_manager = self.connection_manager.clone();
         
         actix::spawn(async move {
             let mut manager = connection_manager.write().await;
-            manager.remove_connection(spreadsheet_id, &addr);
+            match channel {
+                Channel::Spreadsheet(spreadsheet_id) => manager.leave(spreadsheet_id, user_id, connection_id),
+                Channel::User(user_id) => manager.remove_user_connection(user_id, connection_id),
+            }
         });
+
+        if let (true, Some(permissions)) = (self.holds_locks, &self.permissions) {
+            let handlers = permissions.handlers.clone();
+            actix::spawn(async move {
+                if let Err(e) = handlers.release_cell_locks(user_id, connection_id).await {
+                    error!("Failed to release cell locks of user {}: {}", user_id, e);
+                }
+            });
+        }
     }
 }
 
-/// Message to send to WebSocket connection
+/// Wakes a connection to write what's waiting in its outbox
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct SendMessage(pub String);
+struct Flush;
 
-impl Handler<SendMessage> for WebSocketConnection {
+impl Handler<Flush> for WebSocketConnection {
     type Result = ();
 
-    fn handle(&mut self, msg: SendMessage, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+    fn handle(&mut self, _: Flush, ctx: &mut Self::Context) {
+        let (messages, closed, resync) = {
+            let mut outbox = self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            (outbox.drain(), outbox.is_closed(), outbox.needs_resync())
+        };
+        if closed {
+            self.close("SLOW_CONSUMER", "Connection fell too far behind; reconnect to resume", ctx);
+            return;
+        }
+        if resync {
+            self.close("RESYNC_REQUIRED", "Messages were dropped while the connection was behind; reload the spreadsheet", ctx);
+            return;
+        }
+        for json in messages {
+            ctx.text(json);
+        }
+    }
+}
+
+/// A message as JSON, with the row it supersedes updates to if it's a `RowUpdated`
+fn serialize<M: Serialize>(message: &M) -> Option<(String, Option<Uuid>)> {
+    match serde_json::to_value(message) {
+        Ok(value) => Some((value.to_string(), backpressure::coalescing_key(&value))),
+        Err(e) => {
+            error!("Failed to serialize WebSocket message: {}", e);
+            None
+        }
     }
 }
 
 /// Handle WebSocket messages from client
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketConnection {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        // Any frame shows the client is still there
+        if msg.is_ok() {
+            self.last_heard = Instant::now();
+        }
         match msg {
             Ok(ws::Message::Ping(msg)) => {
+                self.heartbeat();
                 ctx.pong(&msg);
             }
             Ok(ws::Message::Pong(_)) => {
-                // Handle pong
+                self.heartbeat();
             }
+            Ok(ws::Message::Text(_) | ws::Message::Binary(_)) if !self.within_budget(ctx) => {}
             Ok(ws::Message::Text(text)) => {
                 // Parse incoming message
                 match serde_json::from_str::<WebSocketMessage>(&text) {
                     Ok(WebSocketMessage::Ping) => {
+                        self.heartbeat();
                         // Respond with pong
                         let pong_msg = WebSocketMessage::Pong;
                         if let Ok(json) = serde_json::to_string(&pong_msg) {
                             ctx.text(json);
                         }
                     }
+                    Ok(WebSocketMessage::Select { row_id, column_id }) => {
+                        self.select(row_id, column_id, ctx);
+                    }
+                    Ok(WebSocketMessage::LockCell { row_id, column_id }) => {
+                        self.lock(row_id, column_id, true, ctx);
+                    }
+                    Ok(WebSocketMessage::UnlockCell { row_id, column_id }) => {
+                        self.lock(row_id, column_id, false, ctx);
+                    }
+                    Ok(WebSocketMessage::OpenText { row_id, column_id }) => {
+                        self.open_text(row_id, column_id, ctx);
+                    }
                     Ok(message) => {
-                        // Handle other message types
-                        info!("Received WebSocket message: {:?}", message);
-                        // Here you would typically process the message and potentially broadcast it
+                        let event_type = message.event_type();
+                        match RowOp::from_message(message) {
+                            Some((op_id, op)) => self.apply(op_id, op, ctx),
+                            None => info!("Ignoring {} message from client", event_type),
+                        }
                     }
                     Err(e) => {
                         warn!("Failed to parse WebSocket message: {}", e);
@@ -181,6 +1181,30 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WebSocketConnecti
     }
 }
 
+/// Lapses the presence of users whose connections stopped answering
+/// heartbeats, reaps connections that stopped without leaving and closes
+/// ones that aren't keeping up
+pub struct ConnectionSweeper {
+    connection_manager: Arc<RwLock<ConnectionManager>>,
+}
+
+impl ConnectionSweeper {
+    pub fn new(connection_manager: Arc<RwLock<ConnectionManager>>) -> Self {
+        Self { connection_manager }
+    }
+
+    /// Sweep every few seconds until the process exits
+    pub async fn run(self) {
+        loop {
+            tokio::time::sleep(SWEEP_INTERVAL).await;
+            let mut manager = self.connection_manager.write().await;
+            manager.reap();
+            manager.evict_slow_consumers();
+            manager.expire_presence();
+        }
+    }
+}
+
 /// Broadcast a message to all connections in a spreadsheet
 pub async fn broadcast_message(
     connection_manager: Arc<RwLock<ConnectionManager>>,
@@ -189,4 +1213,133 @@ pub async fn broadcast_message(
 ) {
     let manager = connection_manager.read().await;
     manager.broadcast_to_spreadsheet(spreadsheet_id, message).await;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence_follows_connections() {
+        let mut roster = PresenceRoster::default();
+        let (sheet, alice, bob) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let now = Instant::now();
+
+        assert!(roster.connect(sheet, alice, "Alice", now));
+        // A second tab doesn't announce her again, and closing one doesn't lose her
+        assert!(!roster.connect(sheet, alice, "Alice", now));
+        assert!(roster.connect(sheet, bob, "Bob", now));
+        assert!(!roster.disconnect(sheet, alice));
+
+        let others = roster.users(sheet, bob);
+        assert_eq!(others.len(), 1);
+        assert_eq!(others[0].user_name, "Alice");
+        assert_eq!(others[0].color, presence_color(alice));
+
+        assert!(roster.disconnect(sheet, alice));
+        assert!(roster.users(sheet, bob).is_empty());
+        assert!(!roster.disconnect(sheet, alice));
+    }
+
+    #[test]
+    fn test_presence_lapses_without_heartbeats() {
+        let mut roster = PresenceRoster::default();
+        let (sheet, alice, bob) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let timeout = Heartbeat::default().timeout;
+        let start = Instant::now();
+        roster.connect(sheet, alice, "Alice", start);
+        roster.connect(sheet, bob, "Bob", start);
+        roster.select(sheet, alice, Some(Uuid::new_v4()), None);
+
+        let later = start + timeout / 2;
+        roster.touch(sheet, bob, later);
+        assert!(roster.expire(later, timeout).is_empty());
+
+        let expired = roster.expire(start + timeout + Duration::from_secs(1), timeout);
+        assert_eq!(expired, vec![(sheet, alice)]);
+        assert!(roster.users(sheet, bob).is_empty());
+
+        // Her next heartbeat brings her back, without the stale selection,
+        // and a lapsed user leaving isn't announced twice
+        assert!(roster.touch(sheet, alice, later + timeout));
+        assert_eq!(roster.users(sheet, bob)[0].row_id, None);
+        roster.expire(later + timeout * 3, timeout);
+        assert!(!roster.disconnect(sheet, alice));
+    }
+
+    #[test]
+    fn test_event_streams_listen_without_being_present() {
+        use futures::FutureExt;
+
+        let mut manager = ConnectionManager::new(Heartbeat::default());
+        let (sheet, alice, bob) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let stream = |manager: &mut ConnectionManager, user_id| {
+            let (id, notify) = (Uuid::new_v4(), Arc::new(Notify::new()));
+            let outbox = Arc::new(Mutex::new(Outbox::new(8)));
+            manager.listen(sheet, user_id, Outlet::for_stream(id, &notify, outbox.clone()));
+            (id, notify, outbox)
+        };
+        let (alice_id, alice_notify, alice_outbox) = stream(&mut manager, alice);
+        let (_, bob_notify, bob_outbox) = stream(&mut manager, bob);
+        assert!(manager.presence.users(sheet, Uuid::nil()).is_empty());
+
+        let message = WebSocketMessage::RowDeleted { spreadsheet_id: sheet, row_id: Uuid::new_v4(), deleted_by: alice };
+        manager.send_to_spreadsheet(sheet, &message, None);
+        assert!(alice_notify.notified().now_or_never().is_some());
+        assert_eq!(alice_outbox.lock().unwrap().drain().len(), 1);
+        assert_eq!(bob_outbox.lock().unwrap().drain().len(), 1);
+
+        // A stream whose response was dropped is reaped, and neither that nor
+        // leaving tells anyone a user left
+        drop(bob_notify);
+        manager.reap();
+        assert_eq!(manager.connection_count(), 1);
+        manager.leave(sheet, alice, alice_id);
+        assert_eq!(manager.connection_count(), 0);
+        assert!(alice_outbox.lock().unwrap().drain().is_empty());
+    }
+
+    #[test]
+    fn test_row_ops_are_read_from_client_messages() {
+        let (op_id, row_id) = (Uuid::new_v4(), Uuid::new_v4());
+        let json = serde_json::json!({
+            "type": "UpdateRow",
+            "op_id": op_id,
+            "row_id": row_id,
+            "row_data": { "Stage": "Won" },
+        });
+        let message: WebSocketMessage = serde_json::from_value(json).unwrap();
+        match RowOp::from_message(message) {
+            Some((id, RowOp::Update(target, request))) => {
+                assert_eq!((id, target), (op_id, row_id));
+                assert_eq!(request.row_data, Some(serde_json::json!({ "Stage": "Won" })));
+                assert_eq!(request.position, None);
+            }
+            _ => panic!("expected an update op"),
+        }
+
+        assert!(RowOp::from_message(WebSocketMessage::Ping).is_none());
+    }
+
+    #[test]
+    fn test_rejected_ops_are_nacked_with_the_error_code() {
+        let op_id = Uuid::new_v4();
+        match op_result(op_id, Err(ContrivanceError::forbidden("Edit access denied to this spreadsheet"))) {
+            WebSocketMessage::OpNack { op_id: id, code, .. } => {
+                assert_eq!(id, op_id);
+                assert_eq!(code, "AUTHORIZATION_ERROR");
+            }
+            other => panic!("expected a nack, got {:?}", other),
+        }
+        assert!(matches!(
+            op_result(op_id, Ok((None, Some(7)))),
+            WebSocketMessage::OpAck { row: None, seq: Some(7), .. }
+        ));
+    }
+
+    #[test]
+    fn test_presence_color_is_stable() {
+        let user = Uuid::new_v4();
+        assert_eq!(presence_color(user), presence_color(user));
+        assert!(PRESENCE_COLORS.contains(&presence_color(user)));
+    }
+}
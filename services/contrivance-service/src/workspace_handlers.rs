@@ -0,0 +1,465 @@
+use actix_web::{web, HttpResponse, HttpRequest};
+use uuid::Uuid;
+use validator::Validate;
+use crate::{
+    repository::{ContrivanceRepository, FavoriteTarget, WorkspaceLocation},
+    middleware::auth::get_user_from_request,
+};
+use common::{
+    AddWorkspaceMemberRequest, ApiResponse, ContrivanceError, CreateFolderRequest,
+    CreateWorkspaceRequest, DefaultPermission, Folder, FolderListing, MoveFolderRequest,
+    MoveSpreadsheetRequest, PaginationParams, UpdateFolderRequest, UpdateWorkspaceRequest,
+    Workspace, WorkspaceRole,
+};
+
+pub struct WorkspaceHandlers {
+    repository: ContrivanceRepository,
+}
+
+impl WorkspaceHandlers {
+    pub fn new(repository: ContrivanceRepository) -> Self {
+        Self { repository }
+    }
+
+    /// List the workspaces the user belongs to
+    pub async fn list_workspaces(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let workspaces = self.repository.list_workspaces(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(workspaces)))
+    }
+
+    /// Create a workspace owned by the user
+    pub async fn create_workspace(
+        &self,
+        req: HttpRequest,
+        payload: web::Json<CreateWorkspaceRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        payload.validate()?;
+        validate_workspace_permission(payload.default_permission)?;
+
+        let workspace = self.repository.create_workspace(&payload, user.id).await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(workspace)))
+    }
+
+    /// Get a workspace
+    pub async fn get_workspace(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+
+        self.member_role(workspace_id, user.id).await?;
+        let workspace = self.workspace(workspace_id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(workspace)))
+    }
+
+    /// Rename a workspace or change its default permission
+    pub async fn update_workspace(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<UpdateWorkspaceRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+        payload.validate()?;
+        validate_workspace_permission(payload.default_permission)?;
+
+        self.require_admin(workspace_id, user.id).await?;
+        let workspace = self.repository.update_workspace(workspace_id, &payload).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(workspace)))
+    }
+
+    /// Delete a workspace; its spreadsheets are kept but no longer filed anywhere
+    pub async fn delete_workspace(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+
+        self.member_role(workspace_id, user.id).await?;
+        if self.workspace(workspace_id).await?.owner_id != user.id {
+            return Err(ContrivanceError::forbidden("Only the workspace owner can delete it"));
+        }
+
+        self.repository.delete_workspace(workspace_id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// List a workspace's members
+    pub async fn list_members(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+
+        self.member_role(workspace_id, user.id).await?;
+        let members = self.repository.list_workspace_members(workspace_id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(members)))
+    }
+
+    /// Add a member by email, or change an existing member's role
+    pub async fn add_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<AddWorkspaceMemberRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+        payload.validate()?;
+
+        self.require_admin(workspace_id, user.id).await?;
+        let workspace = self.workspace(workspace_id).await?;
+        let member_id = self.repository
+            .get_user_id_by_email(workspace.organization_id, &payload.email)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("No user with that email"))?;
+        if member_id == workspace.owner_id {
+            return Err(ContrivanceError::validation("The owner is always a workspace admin"));
+        }
+
+        let member = self.repository
+            .add_workspace_member(workspace_id, member_id, payload.role.unwrap_or(WorkspaceRole::Member))
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(member)))
+    }
+
+    /// Remove a member; members can also remove themselves
+    pub async fn remove_member(
+        &self,
+        req: HttpRequest,
+        path: web::Path<(Uuid, Uuid)>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let (workspace_id, member_id) = path.into_inner();
+
+        if member_id != user.id {
+            self.require_admin(workspace_id, user.id).await?;
+        }
+        if self.workspace(workspace_id).await?.owner_id == member_id {
+            return Err(ContrivanceError::validation("The workspace owner can't be removed"));
+        }
+
+        if !self.repository.remove_workspace_member(workspace_id, member_id).await? {
+            return Err(ContrivanceError::not_found("Member not found"));
+        }
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// List the folders and spreadsheets at the root of a workspace
+    pub async fn get_contents(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        query: web::Query<PaginationParams>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+
+        self.member_role(workspace_id, user.id).await?;
+        let workspace = self.workspace(workspace_id).await?;
+        let listing = self.listing(user.id, workspace, None, &query).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(listing)))
+    }
+
+    /// Create a folder at the workspace root or inside `parent_id`
+    pub async fn create_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<CreateFolderRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let workspace_id = path.into_inner();
+        payload.validate()?;
+
+        let role = self.member_role(workspace_id, user.id).await?;
+        let location = self.repository
+            .resolve_location(Some(workspace_id), payload.parent_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Workspace not found"))?;
+        self.require_edit(user.id, location).await?;
+
+        let default_permission = payload.default_permission.unwrap_or(DefaultPermission::Inherit);
+        if default_permission != DefaultPermission::Inherit && role != WorkspaceRole::Admin {
+            return Err(ContrivanceError::forbidden("Only workspace admins can set folder permissions"));
+        }
+
+        let folder = self.repository
+            .create_folder(location, payload.name.trim(), default_permission, user.id)
+            .await?;
+
+        Ok(HttpResponse::Created().json(ApiResponse::success(folder)))
+    }
+
+    /// List a folder's subfolders and spreadsheets
+    pub async fn get_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        query: web::Query<PaginationParams>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (folder, _) = self.folder_for_member(path.into_inner(), user.id).await?;
+        let workspace = self.workspace(folder.workspace_id).await?;
+        let listing = self.listing(user.id, workspace, Some(folder), &query).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(listing)))
+    }
+
+    /// Rename a folder or change its default permission
+    pub async fn update_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<UpdateFolderRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let mut payload = payload.into_inner();
+        payload.validate()?;
+        if payload.name.is_none() && payload.default_permission.is_none() {
+            return Err(ContrivanceError::validation("At least one field must be provided for update"));
+        }
+
+        let (folder, role) = self.folder_for_member(path.into_inner(), user.id).await?;
+        if payload.default_permission.is_some() && role != WorkspaceRole::Admin {
+            return Err(ContrivanceError::forbidden("Only workspace admins can set folder permissions"));
+        }
+        if payload.name.is_some() {
+            self.require_edit(user.id, parent_location(&folder)).await?;
+        }
+
+        payload.name = payload.name.map(|name| name.trim().to_string());
+        let folder = self.repository.update_folder(folder.id, &payload).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(folder)))
+    }
+
+    /// Move a folder, with everything in it, elsewhere in its workspace
+    pub async fn move_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<MoveFolderRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (folder, _) = self.folder_for_member(path.into_inner(), user.id).await?;
+        let destination = self.repository
+            .resolve_location(Some(folder.workspace_id), payload.parent_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Workspace not found"))?;
+        self.require_edit(user.id, parent_location(&folder)).await?;
+        self.require_edit(user.id, destination).await?;
+
+        let folder = self.repository.move_folder(folder.id, payload.parent_id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(folder)))
+    }
+
+    /// Delete an empty folder
+    pub async fn delete_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (folder, _) = self.folder_for_member(path.into_inner(), user.id).await?;
+        self.require_edit(user.id, parent_location(&folder)).await?;
+
+        self.repository.delete_folder(folder.id).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// File a spreadsheet in a workspace folder, at a workspace root, or take
+    /// it out of workspaces
+    pub async fn move_spreadsheet(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+        payload: web::Json<MoveSpreadsheetRequest>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        if !self.repository.can_user_manage_collaborators(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Only the owner or an admin can move this spreadsheet"));
+        }
+
+        let location = self.repository
+            .resolve_location(payload.workspace_id, payload.folder_id)
+            .await?;
+        if let Some(location) = location {
+            self.require_edit(user.id, location).await?;
+        }
+
+        let spreadsheet = self.repository.move_spreadsheet(spreadsheet_id, location).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(spreadsheet)))
+    }
+
+    /// Get the user's pinned spreadsheets and folders
+    pub async fn get_favorites(&self, req: HttpRequest) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let favorites = self.repository.get_favorites(user.id).await?;
+
+        Ok(HttpResponse::Ok().json(ApiResponse::success(favorites)))
+    }
+
+    /// Pin a spreadsheet
+    pub async fn favorite_spreadsheet(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+        let spreadsheet_id = path.into_inner();
+
+        if !self.repository.can_user_access_spreadsheet(user.id, spreadsheet_id).await? {
+            return Err(ContrivanceError::forbidden("Access denied to this spreadsheet"));
+        }
+
+        self.repository.add_favorite(user.id, FavoriteTarget::Spreadsheet(spreadsheet_id)).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Unpin a spreadsheet
+    pub async fn unfavorite_spreadsheet(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        self.repository.remove_favorite(user.id, FavoriteTarget::Spreadsheet(path.into_inner())).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Pin a folder
+    pub async fn favorite_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        let (folder, _) = self.folder_for_member(path.into_inner(), user.id).await?;
+        self.repository.add_favorite(user.id, FavoriteTarget::Folder(folder.id)).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    /// Unpin a folder
+    pub async fn unfavorite_folder(
+        &self,
+        req: HttpRequest,
+        path: web::Path<Uuid>,
+    ) -> Result<HttpResponse, ContrivanceError> {
+        let user = get_user_from_request(&req)?;
+
+        self.repository.remove_favorite(user.id, FavoriteTarget::Folder(path.into_inner())).await?;
+
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn listing(
+        &self,
+        user_id: Uuid,
+        workspace: Workspace,
+        folder: Option<Folder>,
+        pagination: &PaginationParams,
+    ) -> Result<FolderListing, ContrivanceError> {
+        let location = WorkspaceLocation {
+            workspace_id: workspace.id,
+            folder_id: folder.as_ref().map(|f| f.id),
+        };
+        let path = match &folder {
+            Some(folder) => self.repository.get_folder_path(folder.id).await?,
+            None => Vec::new(),
+        };
+
+        Ok(FolderListing {
+            permission: self.repository.workspace_permission(user_id, location).await?,
+            folders: self.repository.list_folders(user_id, location).await?,
+            spreadsheets: self.repository.list_location_spreadsheets(user_id, location, pagination).await?,
+            workspace,
+            folder,
+            path,
+        })
+    }
+
+    async fn workspace(&self, workspace_id: Uuid) -> Result<Workspace, ContrivanceError> {
+        self.repository
+            .get_workspace(workspace_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Workspace not found"))
+    }
+
+    async fn member_role(&self, workspace_id: Uuid, user_id: Uuid) -> Result<WorkspaceRole, ContrivanceError> {
+        self.repository
+            .get_workspace_role(workspace_id, user_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::forbidden("Access denied to this workspace"))
+    }
+
+    async fn require_admin(&self, workspace_id: Uuid, user_id: Uuid) -> Result<(), ContrivanceError> {
+        match self.member_role(workspace_id, user_id).await? {
+            WorkspaceRole::Admin => Ok(()),
+            WorkspaceRole::Member => Err(ContrivanceError::forbidden("Only workspace admins can do this")),
+        }
+    }
+
+    async fn require_edit(&self, user_id: Uuid, location: WorkspaceLocation) -> Result<(), ContrivanceError> {
+        let permission = self.repository.workspace_permission(user_id, location).await?;
+        if !permission.is_some_and(DefaultPermission::can_edit) {
+            return Err(ContrivanceError::forbidden("Edit access denied to this folder"));
+        }
+        Ok(())
+    }
+
+    async fn folder_for_member(&self, folder_id: Uuid, user_id: Uuid) -> Result<(Folder, WorkspaceRole), ContrivanceError> {
+        let folder = self.repository
+            .get_folder(folder_id)
+            .await?
+            .ok_or_else(|| ContrivanceError::not_found("Folder not found"))?;
+        let role = self.member_role(folder.workspace_id, user_id).await?;
+        Ok((folder, role))
+    }
+}
+
+/// Where a folder itself lives; renaming, moving or deleting it needs edit there
+fn parent_location(folder: &Folder) -> WorkspaceLocation {
+    WorkspaceLocation { workspace_id: folder.workspace_id, folder_id: folder.parent_id }
+}
+
+/// Workspaces sit at the top of the tree, so there is nothing to inherit from
+fn validate_workspace_permission(permission: Option<DefaultPermission>) -> Result<(), ContrivanceError> {
+    if permission == Some(DefaultPermission::Inherit) {
+        return Err(ContrivanceError::validation("A workspace's default permission can't be inherit"));
+    }
+    Ok(())
+}
@@ -10,6 +10,10 @@ pub struct Config {
     pub jwt_secret: String,
     pub rate_limit_requests: usize,
     pub rate_limit_window_seconds: u64,
+    /// Relayed WebSockets one user may hold open at once
+    pub ws_max_connections_per_user: usize,
+    /// Relayed WebSockets whose client is silent for this long are dropped
+    pub ws_client_timeout_seconds: u64,
 }
 
 impl Config {
@@ -39,6 +43,14 @@ impl Config {
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .expect("RATE_LIMIT_WINDOW_SECONDS must be a valid number"),
+            ws_max_connections_per_user: env::var("WS_MAX_CONNECTIONS_PER_USER")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .expect("WS_MAX_CONNECTIONS_PER_USER must be a valid number"),
+            ws_client_timeout_seconds: env::var("WS_CLIENT_TIMEOUT_SECONDS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .expect("WS_CLIENT_TIMEOUT_SECONDS must be a valid number"),
         }
     }
 }
\ No newline at end of file
@@ -2,6 +2,7 @@ mod config;
 mod proxy;
 mod middleware;
 mod grok_proxy;
+mod ws_relay;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpServer, HttpResponse, HttpRequest, middleware::Logger};
@@ -19,8 +20,12 @@ async fn main() -> std::io::Result<()> {
     let config = Config::from_env();
     info!("Starting gateway-service on port {}", config.port);
 
-    // Initialize HTTP client for proxying
-    let http_client = common::HttpUtils::create_client(30);
+    // Initialize HTTP client for proxying. There is no overall timeout:
+    // responses are streamed through and the proxy times out stalls instead.
+    let http_client = reqwest::Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .build()
+        .expect("Failed to create HTTP client");
     
     // Initialize JWT service
     let jwt_service = web::Data::new(common::JwtService::new(
@@ -38,6 +43,14 @@ async fn main() -> std::io::Result<()> {
         config.salesforce_service_url.clone(),
     ));
 
+    // WebSockets are relayed to contrivance-service rather than exposing it
+    let ws_relay = web::Data::new(ws_relay::WebSocketRelay::new(
+        &config.contrivance_service_url,
+        jwt_service.get_ref().clone(),
+        config.ws_max_connections_per_user,
+        std::time::Duration::from_secs(config.ws_client_timeout_seconds),
+    ));
+
     // Start HTTP server
     HttpServer::new(move || {
         let cors = Cors::default()
@@ -53,8 +66,13 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(proxy_service.clone())
             .app_data(jwt_service.clone())
+            .app_data(ws_relay.clone())
             .wrap(cors)
-            .wrap(Logger::default())
+            // The default format logs the query string, where event streams carry their token
+            .wrap(
+                Logger::new(r#"%a "%{request}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("request", |req| format!("{} {}", req.method(), req.path())),
+            )
             .wrap(middleware::rate_limit::RateLimitMiddleware::new())
             // Auth service routes
             .service(
@@ -63,6 +81,8 @@ async fn main() -> std::io::Result<()> {
                     .route("/login", web::post().to(proxy::auth_proxy))
                     .route("/refresh", web::post().to(proxy::auth_proxy))
                     .route("/logout", web::post().to(proxy::auth_proxy))
+                    .route("/password-reset/request", web::post().to(proxy::auth_proxy))
+                    .route("/password-reset/confirm", web::post().to(proxy::auth_proxy))
                     .route("/validate", web::post().to(proxy::auth_proxy))
             )
             // User service routes  
@@ -77,6 +97,9 @@ async fn main() -> std::io::Result<()> {
                     .route("/{id}", web::put().to(proxy::user_proxy))
                     .route("/{id}", web::delete().to(proxy::user_proxy))
             )
+            // Server-Sent Events, streamed through. EventSource can't send headers, so
+            // contrivance service takes the token or ticket the way it does for sockets.
+            .route("/api/spreadsheets/{id}/events", web::get().to(proxy::contrivance_proxy))
             // Spreadsheet service routes
             .service(
                 web::scope("/api/spreadsheets")
@@ -87,12 +110,29 @@ async fn main() -> std::io::Result<()> {
                     .route("/{id}", web::put().to(proxy::contrivance_proxy))
                     .route("/{id}", web::delete().to(proxy::contrivance_proxy))
                     .route("/{id}/columns", web::get().to(proxy::contrivance_proxy))
+                    .route("/{spreadsheet_id}/columns/{column_id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{spreadsheet_id}/columns/{column_id}", web::delete().to(proxy::contrivance_proxy))
                     .route("/{id}/salesforce/columns", web::post().to(proxy::contrivance_proxy))
                     .route("/{id}/rows", web::get().to(proxy::contrivance_proxy))
                     .route("/{id}/rows", web::post().to(proxy::contrivance_proxy))
                     .route("/{spreadsheet_id}/rows/{row_id}", web::put().to(proxy::contrivance_proxy))
                     .route("/{spreadsheet_id}/rows/{row_id}", web::delete().to(proxy::contrivance_proxy))
                     .route("/{id}/collaborators", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/collaborators", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/collaborators/accept", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/move", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/favorite", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/favorite", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/team", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/export", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/summary", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/changes", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations/{rule_id}", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations/{rule_id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations/{rule_id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/automations/{rule_id}/runs", web::get().to(proxy::contrivance_proxy))
                     // Todo routes for spreadsheets
                     .route("/{id}/todos", web::get().to(proxy::contrivance_proxy))
                     .route("/{id}/todos/stats", web::get().to(proxy::contrivance_proxy))
@@ -109,6 +149,101 @@ async fn main() -> std::io::Result<()> {
                     .route("/{id}", web::delete().to(proxy::contrivance_proxy))
                     .route("/{id}/complete", web::put().to(proxy::contrivance_proxy))
                     .route("/{id}/uncomplete", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/dependencies", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/dependencies/{blocked_by_id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/checklist", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/checklist/{item_id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/checklist/{item_id}", web::delete().to(proxy::contrivance_proxy))
+            )
+            // Workspace and folder routes
+            .service(
+                web::scope("/api/workspaces")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/members", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/members", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/members/{user_id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/contents", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/folders", web::post().to(proxy::contrivance_proxy))
+            )
+            .service(
+                web::scope("/api/folders")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("/{id}", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/move", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/favorite", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/favorite", web::delete().to(proxy::contrivance_proxy))
+            )
+            .service(
+                web::scope("/api/favorites")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+            )
+            // Organization and team routes
+            .service(
+                web::scope("/api/organization")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("", web::put().to(proxy::contrivance_proxy))
+                    .route("/members", web::get().to(proxy::contrivance_proxy))
+                    .route("/invitations", web::get().to(proxy::contrivance_proxy))
+                    .route("/invitations", web::post().to(proxy::contrivance_proxy))
+                    .route("/invitations/received", web::get().to(proxy::contrivance_proxy))
+                    .route("/invitations/{id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/invitations/{id}/accept", web::post().to(proxy::contrivance_proxy))
+                    .route("/invitations/{id}/decline", web::post().to(proxy::contrivance_proxy))
+                    .route("/members/{user_id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/members/{user_id}", web::delete().to(proxy::contrivance_proxy))
+            )
+            .service(
+                web::scope("/api/teams")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/members", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/members", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}/members/{user_id}", web::delete().to(proxy::contrivance_proxy))
+            )
+            // Exchange rate routes
+            .service(
+                web::scope("/api/exchange-rates")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("", web::post().to(proxy::contrivance_proxy))
+                    .route("/import", web::post().to(proxy::contrivance_proxy))
+            )
+            // Notification routes
+            .service(
+                web::scope("/api/notifications")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("/read-all", web::post().to(proxy::contrivance_proxy))
+                    .route("/preferences", web::get().to(proxy::contrivance_proxy))
+                    .route("/preferences", web::put().to(proxy::contrivance_proxy))
+                    .route("/digest", web::get().to(proxy::contrivance_proxy))
+                    .route("/digest", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}/read", web::post().to(proxy::contrivance_proxy))
+            )
+            // Webhook routes
+            .service(
+                web::scope("/api/webhooks")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route("", web::get().to(proxy::contrivance_proxy))
+                    .route("", web::post().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::put().to(proxy::contrivance_proxy))
+                    .route("/{id}", web::delete().to(proxy::contrivance_proxy))
+                    .route("/{id}/deliveries", web::get().to(proxy::contrivance_proxy))
+                    .route("/{id}/deliveries/{delivery_id}/replay", web::post().to(proxy::contrivance_proxy))
             )
             // Temporary fix: direct routes to Salesforce service
             .service(
@@ -189,8 +324,15 @@ async fn main() -> std::io::Result<()> {
             )
             // Test route for Salesforce proxy function using contrivance proxy temporarily
             .route("/test-salesforce", web::get().to(proxy::contrivance_proxy))
-            // WebSocket proxy - direct connection to contrivance service
-            .route("/ws/spreadsheet/{id}", web::get().to(proxy::websocket_proxy))
+            // Single-use tickets for opening a WebSocket without putting the JWT in the URL
+            .service(
+                web::resource("/ws/ticket")
+                    .wrap(middleware::auth::auth_middleware())
+                    .route(web::post().to(proxy::contrivance_proxy))
+            )
+            // WebSockets, relayed frame by frame to contrivance service
+            .route("/ws/spreadsheet/{id}", web::get().to(ws_relay::relay))
+            .route("/ws/notifications", web::get().to(ws_relay::relay))
             .route("/health", web::get().to(health_check))
     })
     .bind(format!("0.0.0.0:{}", config.port))?
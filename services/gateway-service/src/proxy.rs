@@ -1,9 +1,16 @@
-use actix_web::{web, HttpRequest, HttpResponse, Result, Error};
+use actix_web::{body::{BodyStream, SizedStream}, web, HttpRequest, HttpResponse, Result, Error};
+use futures_util::stream;
 use reqwest::Client;
 use serde_json::Value;
-use uuid::Uuid;
+use std::time::Duration;
 use common::{ContrivanceError, ApiResponse};
-use tracing::{info, error, warn};
+use tracing::{info, error};
+
+/// How long an upstream service has to start responding
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a response body may stall between chunks. Bodies are streamed
+/// through, so there is no limit on the total transfer time.
+const BODY_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct ProxyService {
     client: Client,
@@ -64,7 +71,8 @@ impl ProxyService {
             url = format!("{}?{}", url, query_string);
         }
 
-        info!("Proxying {} request to: {}", method, url);
+        // Only the path: query strings can carry credentials (`?token=` on event streams)
+        info!("Proxying {} request to: {}{}", method, target_url, path);
 
         let converted_headers = Self::convert_headers(headers);
         let mut request_builder = self.client.request(method, &url);
@@ -83,38 +91,61 @@ impl ProxyService {
         }
 
         // Send request
-        let response = request_builder
-            .send()
+        let response = tokio::time::timeout(RESPONSE_TIMEOUT, request_builder.send())
             .await
+            .map_err(|_| {
+                error!("Proxy request to {}{} timed out", target_url, path);
+                ContrivanceError::internal("Failed to proxy request")
+            })?
             .map_err(|e| {
-                error!("Proxy request failed: {}", e);
+                error!("Proxy request to {}{} failed: {}", target_url, path, e.without_url());
                 ContrivanceError::internal("Failed to proxy request")
             })?;
 
         let status = response.status();
         let headers = response.headers().clone();
-        
-        // Get response body
-        let body = response
-            .bytes()
-            .await
-            .map_err(|e| {
-                error!("Failed to read response body: {}", e);
-                ContrivanceError::internal("Failed to read response")
-            })?;
+        let content_length = response.content_length();
 
         // Build response
         let mut http_response = HttpResponse::build(status);
 
-        // Forward response headers (excluding connection-related ones)
+        // Forward response headers (excluding connection-related ones; the
+        // body below sets its own length)
         for (name, value) in headers.iter() {
             let header_name = name.as_str().to_lowercase();
-            if !["connection", "transfer-encoding"].contains(&header_name.as_str()) {
+            if !["connection", "transfer-encoding", "content-length"].contains(&header_name.as_str()) {
                 http_response.insert_header((name, value));
             }
         }
 
-        Ok(http_response.body(body))
+        // Pass the body through as it arrives rather than buffering it, so
+        // streamed (e.g. NDJSON) responses reach the client incrementally
+        let body = Self::body_stream(response);
+        Ok(match content_length {
+            Some(length) => http_response.body(SizedStream::new(length, body)),
+            None => http_response.body(BodyStream::new(body)),
+        })
+    }
+
+    /// Read an upstream body chunk by chunk, giving up if it stalls
+    fn body_stream(
+        response: reqwest::Response,
+    ) -> impl futures_util::Stream<Item = Result<web::Bytes, std::io::Error>> {
+        stream::unfold(Some(response), |response| async move {
+            let mut response = response?;
+            match tokio::time::timeout(BODY_IDLE_TIMEOUT, response.chunk()).await {
+                Ok(Ok(Some(chunk))) => Some((Ok(chunk), Some(response))),
+                Ok(Ok(None)) => None,
+                Ok(Err(e)) => {
+                    error!("Failed to read response body: {}", e);
+                    Some((Err(std::io::Error::other(e)), None))
+                }
+                Err(_) => {
+                    error!("Upstream response body stalled");
+                    Some((Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "upstream body stalled")), None))
+                }
+            }
+        })
     }
 }
 
@@ -256,20 +287,4 @@ pub async fn salesforce_proxy(
         Ok(response) => Ok(response),
         Err(e) => Ok(HttpResponse::InternalServerError().json(ApiResponse::<()>::error(e.to_string()))),
     }
-}
-
-// WebSocket proxy - redirect to contrivance service
-pub async fn websocket_proxy(
-    req: HttpRequest,
-    path: web::Path<Uuid>,
-    proxy: web::Data<ProxyService>,
-) -> Result<HttpResponse, Error> {
-    let spreadsheet_id = path.into_inner();
-    let redirect_url = format!("{}/ws/spreadsheet/{}", proxy.contrivance_service_url, spreadsheet_id);
-    
-    warn!("WebSocket proxy redirecting to: {} (Note: This should be handled by a reverse proxy in production)", redirect_url);
-    
-    Ok(HttpResponse::BadRequest().json(ApiResponse::<()>::error(
-        "WebSocket connections should be handled by reverse proxy. Use the contrivance service directly for WebSocket connections.".to_string()
-    )))
 }
\ No newline at end of file
@@ -0,0 +1,385 @@
+//! WebSocket relay to contrivance-service.
+//!
+//! The gateway accepts the upgrade, opens its own socket to contrivance-service
+//! and copies frames both ways until either side closes, so clients only ever
+//! talk to the gateway. A JWT (Authorization header, `contrivance.bearer`
+//! subprotocol or `?token=`) is validated here and forwarded upstream as a
+//! bearer token; a `?ticket=` is redeemed by contrivance-service, which names
+//! the user in its handshake response. Either way each user may only hold so
+//! many relayed sockets at once.
+//!
+//! The upstream client answers contrivance-service's pings by itself, so the
+//! relay watches the client side: a client not heard from within the timeout
+//! is dropped, closing the upstream socket with it.
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use common::{ApiResponse, JwtService};
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{
+    self,
+    client::IntoClientRequest,
+    protocol::{frame::coding::CloseCode as UpstreamCloseCode, CloseFrame},
+    Message as UpstreamMessage,
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Subprotocol a client offers alongside its JWT
+const SOCKET_PROTOCOL: &str = "contrivance.bearer";
+/// Upstream handshake header naming the socket's user
+const USER_ID_HEADER: &str = "x-user-id";
+
+/// Open relayed sockets per user
+#[derive(Default)]
+struct ConnectionCounts {
+    counts: Mutex<HashMap<Uuid, usize>>,
+}
+
+impl ConnectionCounts {
+    /// Take one of the user's slots, or `None` if they're all in use
+    fn acquire(self: &Arc<Self>, user_id: Uuid, limit: usize) -> Option<ConnectionSlot> {
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(user_id).or_default();
+        if *count >= limit {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionSlot { counts: self.clone(), user_id })
+    }
+}
+
+/// A user's hold on one relayed socket, given back when dropped
+struct ConnectionSlot {
+    counts: Arc<ConnectionCounts>,
+    user_id: Uuid,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.counts.counts.lock().unwrap();
+        if let Some(count) = counts.get_mut(&self.user_id) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.remove(&self.user_id);
+            }
+        }
+    }
+}
+
+pub struct WebSocketRelay {
+    /// contrivance-service base URL with a ws:// or wss:// scheme
+    upstream_url: String,
+    jwt_service: JwtService,
+    max_connections_per_user: usize,
+    /// Clients silent for this long are disconnected
+    client_timeout: Duration,
+    connections: Arc<ConnectionCounts>,
+}
+
+impl WebSocketRelay {
+    pub fn new(
+        contrivance_service_url: &str,
+        jwt_service: JwtService,
+        max_connections_per_user: usize,
+        client_timeout: Duration,
+    ) -> Self {
+        let upstream_url = match contrivance_service_url.split_once("://") {
+            Some(("https", rest)) => format!("wss://{}", rest),
+            Some((_, rest)) => format!("ws://{}", rest),
+            None => format!("ws://{}", contrivance_service_url),
+        };
+        Self {
+            upstream_url: upstream_url.trim_end_matches('/').to_string(),
+            jwt_service,
+            max_connections_per_user,
+            client_timeout,
+            connections: Arc::default(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct RelayQuery {
+    token: Option<String>,
+    ticket: Option<String>,
+    resume: Option<String>,
+    last_seq: Option<i64>,
+}
+
+impl RelayQuery {
+    /// The parameters passed upstream: everything but the token, which goes as a header
+    fn upstream_params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(ticket) = &self.ticket {
+            params.push(("ticket", ticket.clone()));
+        }
+        if let Some(resume) = &self.resume {
+            params.push(("resume", resume.clone()));
+        }
+        if let Some(last_seq) = self.last_seq {
+            params.push(("last_seq", last_seq.to_string()));
+        }
+        params
+    }
+}
+
+/// Relay a WebSocket to the same path on contrivance-service
+pub async fn relay(
+    req: HttpRequest,
+    payload: web::Payload,
+    query: web::Query<RelayQuery>,
+    relay: web::Data<WebSocketRelay>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let query = query.into_inner();
+    let offered_protocol = offered_protocols(&req).iter().any(|protocol| protocol == SOCKET_PROTOCOL);
+
+    // Check the token before going upstream, and hold the user's slot while we do
+    let token = bearer_token(&req).or_else(|| protocol_token(&req)).or(query.token.clone());
+    let (token, mut slot) = match token {
+        Some(token) => {
+            let user_id = match relay.jwt_service.extract_user_id(&token) {
+                Ok(user_id) => user_id,
+                Err(_) => return Ok(error_response(HttpResponse::Unauthorized(), "Invalid token")),
+            };
+            match relay.connections.acquire(user_id, relay.max_connections_per_user) {
+                Some(slot) => (Some(token), Some(slot)),
+                None => return Ok(too_many_connections()),
+            }
+        }
+        None if query.ticket.is_some() => (None, None),
+        None => return Ok(error_response(HttpResponse::Unauthorized(), "Missing token")),
+    };
+
+    let url = match reqwest::Url::parse(&format!("{}{}", relay.upstream_url, req.path())) {
+        Ok(mut url) => {
+            let params = query.upstream_params();
+            if !params.is_empty() {
+                url.query_pairs_mut().extend_pairs(params);
+            }
+            url.to_string()
+        }
+        Err(e) => {
+            warn!("Invalid upstream WebSocket URL for {}: {}", req.path(), e);
+            return Ok(error_response(HttpResponse::BadGateway(), "Failed to connect to contrivance service"));
+        }
+    };
+    let mut upstream_request = match url.as_str().into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            warn!("Invalid upstream WebSocket URL {}: {}", url, e);
+            return Ok(error_response(HttpResponse::BadGateway(), "Failed to connect to contrivance service"));
+        }
+    };
+    if let Some(token) = &token {
+        if let Ok(value) = format!("Bearer {}", token).parse() {
+            upstream_request.headers_mut().insert(tungstenite::http::header::AUTHORIZATION, value);
+        }
+    }
+
+    let (upstream, upstream_response) = match tokio_tungstenite::connect_async(upstream_request).await {
+        Ok(connected) => connected,
+        // Refusals (bad ticket, no access to the spreadsheet) keep their status
+        Err(tungstenite::Error::Http(response)) => {
+            let status = actix_web::http::StatusCode::from_u16(response.status().as_u16())
+                .unwrap_or(actix_web::http::StatusCode::BAD_GATEWAY);
+            let body = response.into_body().unwrap_or_default();
+            return Ok(HttpResponse::build(status).content_type("application/json").body(body));
+        }
+        Err(e) => {
+            warn!("Failed to open upstream WebSocket {}: {}", url, e);
+            return Ok(error_response(HttpResponse::BadGateway(), "Failed to connect to contrivance service"));
+        }
+    };
+
+    // Ticket holders are only known once contrivance-service has redeemed the ticket
+    if slot.is_none() {
+        let user_id = upstream_response
+            .headers()
+            .get(USER_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Uuid::parse_str(value).ok());
+        let Some(user_id) = user_id else {
+            warn!("Upstream WebSocket handshake for {} did not name its user", url);
+            return Ok(error_response(HttpResponse::BadGateway(), "Failed to connect to contrivance service"));
+        };
+        match relay.connections.acquire(user_id, relay.max_connections_per_user) {
+            Some(acquired) => slot = Some(acquired),
+            None => return Ok(too_many_connections()),
+        }
+    }
+
+    let (mut response, session, client) = actix_ws::handle(&req, payload)?;
+    if offered_protocol {
+        response.headers_mut().insert(
+            header::SEC_WEBSOCKET_PROTOCOL,
+            header::HeaderValue::from_static(SOCKET_PROTOCOL),
+        );
+    }
+
+    let path = req.path().to_string();
+    let client_timeout = relay.client_timeout;
+    actix_web::rt::spawn(async move {
+        pump(session, client, upstream, client_timeout).await;
+        info!("Closed relayed WebSocket {}", path);
+        drop(slot);
+    });
+
+    Ok(response)
+}
+
+type Upstream = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Copy frames between the client and contrivance-service until either closes.
+/// Pings and pongs are passed through so each end's heartbeats reach the other.
+async fn pump(
+    mut session: actix_ws::Session,
+    mut client: actix_ws::MessageStream,
+    upstream: Upstream,
+    client_timeout: Duration,
+) {
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    let idle = tokio::time::sleep(client_timeout);
+    tokio::pin!(idle);
+
+    loop {
+        tokio::select! {
+            message = client.recv() => {
+                idle.as_mut().reset(tokio::time::Instant::now() + client_timeout);
+                let message = match message {
+                    Some(Ok(actix_ws::Message::Text(text))) => UpstreamMessage::Text(text.to_string()),
+                    Some(Ok(actix_ws::Message::Binary(bytes))) => UpstreamMessage::Binary(bytes.to_vec()),
+                    Some(Ok(actix_ws::Message::Ping(bytes))) => UpstreamMessage::Ping(bytes.to_vec()),
+                    Some(Ok(actix_ws::Message::Pong(bytes))) => UpstreamMessage::Pong(bytes.to_vec()),
+                    Some(Ok(actix_ws::Message::Close(reason))) => {
+                        let _ = upstream_tx.send(UpstreamMessage::Close(reason.map(upstream_close))).await;
+                        break;
+                    }
+                    // Continuations aren't sent by browsers; Nop carries nothing
+                    Some(Ok(_)) => continue,
+                    Some(Err(_)) | None => {
+                        let _ = upstream_tx.send(UpstreamMessage::Close(None)).await;
+                        break;
+                    }
+                };
+                if upstream_tx.send(message).await.is_err() {
+                    break;
+                }
+            }
+            message = upstream_rx.next() => {
+                let sent = match message {
+                    Some(Ok(UpstreamMessage::Text(text))) => session.text(text).await,
+                    Some(Ok(UpstreamMessage::Binary(bytes))) => session.binary(bytes).await,
+                    Some(Ok(UpstreamMessage::Ping(bytes))) => session.ping(&bytes).await,
+                    Some(Ok(UpstreamMessage::Pong(bytes))) => session.pong(&bytes).await,
+                    Some(Ok(UpstreamMessage::Close(frame))) => {
+                        let _ = session.close(frame.map(client_close)).await;
+                        return;
+                    }
+                    Some(Ok(UpstreamMessage::Frame(_))) => Ok(()),
+                    Some(Err(_)) | None => break,
+                };
+                if sent.is_err() {
+                    let _ = upstream_tx.send(UpstreamMessage::Close(None)).await;
+                    return;
+                }
+            }
+            _ = &mut idle => {
+                info!("Dropping relayed WebSocket: client silent for {:?}", client_timeout);
+                let _ = upstream_tx.send(UpstreamMessage::Close(None)).await;
+                break;
+            }
+        }
+    }
+
+    let _ = session.close(None).await;
+}
+
+fn upstream_close(reason: actix_ws::CloseReason) -> CloseFrame<'static> {
+    CloseFrame {
+        code: UpstreamCloseCode::from(u16::from(reason.code)),
+        reason: reason.description.unwrap_or_default().into(),
+    }
+}
+
+fn client_close(frame: CloseFrame<'static>) -> actix_ws::CloseReason {
+    actix_ws::CloseReason {
+        code: actix_ws::CloseCode::from(u16::from(frame.code)),
+        description: Some(frame.reason.into_owned()).filter(|reason| !reason.is_empty()),
+    }
+}
+
+fn error_response(mut builder: actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    builder.json(ApiResponse::<()>::error(message.to_string()))
+}
+
+fn too_many_connections() -> HttpResponse {
+    error_response(HttpResponse::TooManyRequests(), "Too many open WebSocket connections")
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::to_string)
+}
+
+fn offered_protocols(req: &HttpRequest) -> Vec<String> {
+    req.headers()
+        .get_all(header::SEC_WEBSOCKET_PROTOCOL)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|protocol| protocol.trim().to_string())
+        .collect()
+}
+
+/// The JWT offered as the subprotocol after `SOCKET_PROTOCOL`
+fn protocol_token(req: &HttpRequest) -> Option<String> {
+    let offered = offered_protocols(req);
+    let position = offered.iter().position(|protocol| protocol == SOCKET_PROTOCOL)?;
+    offered.get(position + 1).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connections_are_limited_per_user() {
+        let counts = Arc::new(ConnectionCounts::default());
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let first = counts.acquire(alice, 2).unwrap();
+        let _second = counts.acquire(alice, 2).unwrap();
+        assert!(counts.acquire(alice, 2).is_none());
+        assert!(counts.acquire(bob, 2).is_some());
+
+        // Closing a socket frees its slot
+        drop(first);
+        assert!(counts.acquire(alice, 2).is_some());
+    }
+
+    #[test]
+    fn test_upstream_url_follows_the_service_scheme() {
+        let jwt = JwtService::new("secret", None, None);
+        assert_eq!(WebSocketRelay::new("http://contrivance:3003/", jwt.clone(), 1, Duration::from_secs(30)).upstream_url, "ws://contrivance:3003");
+        assert_eq!(WebSocketRelay::new("https://contrivance.internal", jwt, 1, Duration::from_secs(30)).upstream_url, "wss://contrivance.internal");
+    }
+
+    #[test]
+    fn test_only_the_token_is_kept_out_of_the_upstream_query() {
+        let query = RelayQuery {
+            token: Some("eyJ.abc.def".to_string()),
+            ticket: None,
+            resume: Some("1700000000.ab12".to_string()),
+            last_seq: Some(42),
+        };
+        assert_eq!(
+            query.upstream_params(),
+            vec![("resume", "1700000000.ab12".to_string()), ("last_seq", "42".to_string())]
+        );
+    }
+}
@@ -61,6 +61,18 @@ pub async fn save_salesforce_connection(
     Ok(id)
 }
 
+/// Whether the user has connected their Salesforce account
+pub async fn has_salesforce_connection(pool: &PgPool, user_id: uuid::Uuid) -> Result<bool> {
+    let connected = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM salesforce_connections WHERE user_id = $1)"
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(connected)
+}
+
 pub async fn update_salesforce_tokens(
     pool: &PgPool,
     connection_id: uuid::Uuid,
@@ -92,4 +104,112 @@ pub async fn update_salesforce_tokens(
     .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+/// Raise a `salesforce_sync_failed` notification in the shared notifications
+/// table, unless the user has turned that kind off. The user sees it the next
+/// time their notification list loads.
+pub async fn record_sync_failure(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    spreadsheet_id: Option<uuid::Uuid>,
+    error: &str,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO notifications (user_id, kind, title, body, spreadsheet_id)
+        SELECT $1, 'salesforce_sync_failed', 'Salesforce sync failed', $2, s.id
+        -- An id that isn't a spreadsheet in the user's organization is dropped
+        -- rather than failing the insert
+        FROM (SELECT 1) AS one
+        LEFT JOIN spreadsheets s ON s.id = $3 AND s.organization_id = user_organization($1)
+        WHERE NOT EXISTS (
+            SELECT 1 FROM notification_preferences
+            WHERE user_id = $1 AND kind = 'salesforce_sync_failed' AND NOT enabled
+        )
+        "#
+    )
+    .bind(user_id)
+    .bind(error)
+    .bind(spreadsheet_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The columns of a spreadsheet in the user's organization, with the
+/// Salesforce field each was created for
+pub async fn get_sheet_columns(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+    spreadsheet_id: uuid::Uuid,
+) -> Result<Vec<crate::models::SheetColumn>> {
+    let columns = sqlx::query_as::<_, crate::models::SheetColumn>(
+        r#"
+        SELECT c.id, c.name, c.display_options->>'salesforce_field' AS salesforce_field
+        FROM spreadsheet_columns c
+        JOIN spreadsheets s ON s.id = c.spreadsheet_id
+        WHERE c.spreadsheet_id = $1 AND s.organization_id = user_organization($2)
+        ORDER BY c.position
+        "#
+    )
+    .bind(spreadsheet_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::testing::{create_user, test_pool};
+    use uuid::Uuid;
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_sheets_and_connections_are_isolated_by_organization() {
+        let pool = test_pool().await;
+        let (alice, _) = create_user(&pool, "Alice").await;
+        let (mallory, _) = create_user(&pool, "Mallory").await;
+        let sheet: Uuid = sqlx::query_scalar(
+            "INSERT INTO spreadsheets (name, owner_id, organization_id) VALUES ('Pipeline', $1, user_organization($1)) RETURNING id"
+        )
+        .bind(alice)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        sqlx::query("INSERT INTO spreadsheet_columns (spreadsheet_id, name, position) VALUES ($1, 'Amount', 0)")
+            .bind(sheet)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        assert_eq!(get_sheet_columns(&pool, alice, sheet).await.unwrap().len(), 1);
+        assert!(get_sheet_columns(&pool, mallory, sheet).await.unwrap().is_empty());
+
+        // A failure notification can't point at another organization's spreadsheet
+        record_sync_failure(&pool, mallory, Some(sheet), "boom").await.unwrap();
+        let linked: Option<Uuid> = sqlx::query_scalar(
+            "SELECT spreadsheet_id FROM notifications WHERE user_id = $1 AND kind = 'salesforce_sync_failed'"
+        )
+        .bind(mallory)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(linked, None);
+
+        let token = crate::models::SalesforceToken {
+            access_token: "token".to_string(),
+            refresh_token: None,
+            instance_url: "https://example.my.salesforce.com".to_string(),
+            token_type: "Bearer".to_string(),
+            expires_in: None,
+            created_at: chrono::Utc::now(),
+        };
+        save_salesforce_connection(&pool, alice, &token).await.unwrap();
+        assert!(has_salesforce_connection(&pool, alice).await.unwrap());
+        assert!(!has_salesforce_connection(&pool, mallory).await.unwrap());
+    }
+}
@@ -2,7 +2,6 @@ use actix_web::{web, HttpRequest, HttpResponse, Result as ActixResult};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::collections::HashMap;
-use sqlx::Row;
 
 use crate::models::*;
 use crate::salesforce::SalesforceClient;
@@ -88,15 +87,10 @@ pub async fn connection_status(
     sf_client: web::Data<SalesforceClient>,
 ) -> Result<HttpResponse, actix_web::Error> {
     println!("Connection status endpoint called with auth bypass");
-    let _claims = extract_user_from_token(&req)?;
+    let claims = extract_user_from_token(&req)?;
     
-    match sqlx::query("SELECT COUNT(*) as count FROM salesforce_connections")
-        .fetch_one(pool.get_ref())
-        .await
-    {
-        Ok(row) => {
-            let count: i64 = row.try_get("count").unwrap_or(0);
-            let connected = count > 0;
+    match database::has_salesforce_connection(&pool, claims.user_id).await {
+        Ok(connected) => {
             Ok(HttpResponse::Ok().json(serde_json::json!({
                 "connected": connected
             })))
@@ -388,48 +382,100 @@ pub async fn sync_opportunities_to_spreadsheet(
                     match sf_client.query_opportunities(&token, None).await {
                         Ok(opps) => opps,
                         Err(retry_e) => {
+                            let error = format!("Failed to fetch opportunities: {}", retry_e);
+                            notify_sync_failure(&pool, claims.user_id, spreadsheet_id, &error).await;
                             return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                                "error": format!("Failed to fetch opportunities: {}", retry_e)
+                                "error": error
                             })));
                         }
                     }
                 }
                 Ok(false) | Err(_) => {
+                    let error = format!("Failed to fetch opportunities: {}", error_msg);
+                    notify_sync_failure(&pool, claims.user_id, spreadsheet_id, &error).await;
                     return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-                        "error": format!("Failed to fetch opportunities: {}", error_msg)
+                        "error": error
                     })));
                 }
             }
         }
     };
 
-    // Transform opportunities into row data
-    let rows: Vec<serde_json::Value> = opportunities.iter().map(|opp| {
-        serde_json::json!({
-            "Opportunity Name": opp.name,
-            "Salesforce ID": &opp.id,
-            "Account": opp.account.as_ref().map(|a| a.name.clone()).unwrap_or_default(),
-            "Type": opp.account.as_ref().and_then(|a| a.account_type.clone()).unwrap_or_default(),
-            "Stage": opp.stage_name,
-            "Amount": opp.amount.unwrap_or(0.0),
-            "Probability": opp.probability.unwrap_or(0.0),
-            "Expected Revenue": opp.expected_revenue.unwrap_or(0.0),
-            "Close Date": opp.close_date,
-            "Owner": opp.owner.as_ref().map(|o| o.name.clone()).unwrap_or_default(),
-            "Last Modified By": opp.last_modified_by.as_ref().map(|u| u.name.clone()).unwrap_or_default(),
-            "Last Modified Date": opp.last_modified_date.clone().unwrap_or_default(),
-            "Technical Win": opp.technical_win.unwrap_or(false),
+    // Cells are stored by column id, so resolve each field to the sheet's
+    // columns: a column tagged with the field wins over one matched by name
+    let columns = match spreadsheet_id.parse::<Uuid>() {
+        Ok(id) => database::get_sheet_columns(&pool, claims.user_id, id).await.unwrap_or_else(|e| {
+            log::error!("Failed to load columns for spreadsheet {}: {}", id, e);
+            Vec::new()
+        }),
+        Err(_) => Vec::new(),
+    };
+    let targets: Vec<(&str, Vec<Uuid>)> = OPPORTUNITY_FIELD_COLUMNS
+        .iter()
+        .map(|(field, names)| {
+            let tagged: Vec<Uuid> = columns
+                .iter()
+                .filter(|c| c.salesforce_field.as_deref() == Some(*field))
+                .map(|c| c.id)
+                .collect();
+            let ids = if tagged.is_empty() {
+                columns.iter().filter(|c| names.contains(&c.name.as_str())).map(|c| c.id).collect()
+            } else {
+                tagged
+            };
+            (*field, ids)
         })
-    }).collect();
+        .collect();
+
+    // Transform opportunities into row data, keyed by column name (legacy)
+    // and by column id
+    let mut opportunity_rows = Vec::with_capacity(opportunities.len());
+    let mut rows = Vec::with_capacity(opportunities.len());
+    for opp in &opportunities {
+        let mut by_name = serde_json::Map::new();
+        let mut by_id = serde_json::Map::new();
+        for ((field, names), (_, ids)) in OPPORTUNITY_FIELD_COLUMNS.iter().zip(&targets) {
+            let value = opportunity_field(opp, field);
+            for id in ids {
+                by_id.insert(id.to_string(), value.clone());
+            }
+            by_name.insert(names[0].to_string(), value);
+        }
+        opportunity_rows.push(serde_json::Value::Object(by_name));
+        rows.push(serde_json::Value::Object(by_id));
+    }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "success": true,
         "spreadsheet_id": spreadsheet_id,
-        "opportunities": rows,
+        "opportunities": opportunity_rows,
+        "rows": rows,
         "count": rows.len()
     })))
 }
 
+/// The cell value for one of the fields in `OPPORTUNITY_FIELD_COLUMNS`
+fn opportunity_field(opp: &SalesforceOpportunity, field: &str) -> serde_json::Value {
+    use serde_json::json;
+
+    match field {
+        "Id" => json!(opp.id),
+        "Name" => json!(opp.name),
+        "Account.Name" => json!(opp.account.as_ref().map(|a| a.name.clone()).unwrap_or_default()),
+        "Account.Type" => json!(opp.account.as_ref().and_then(|a| a.account_type.clone()).unwrap_or_default()),
+        "StageName" => json!(opp.stage_name),
+        "Amount" => json!(opp.amount.unwrap_or(0.0)),
+        "Probability" => json!(opp.probability.unwrap_or(0.0)),
+        "ExpectedRevenue" => json!(opp.expected_revenue.unwrap_or(0.0)),
+        "CloseDate" => json!(opp.close_date),
+        "Owner.Name" => json!(opp.owner.as_ref().map(|o| o.name.clone()).unwrap_or_default()),
+        "LastModifiedBy.Name" => json!(opp.last_modified_by.as_ref().map(|u| u.name.clone()).unwrap_or_default()),
+        "LastModifiedDate" => json!(opp.last_modified_date.clone().unwrap_or_default()),
+        "Technical_Win__c" => json!(opp.technical_win.unwrap_or(false)),
+        _ => serde_json::Value::Null,
+    }
+}
+
 pub async fn import_leads(
     pool: web::Data<sqlx::PgPool>,
     sf_client: web::Data<SalesforceClient>,
@@ -553,6 +599,14 @@ pub async fn sync_pipeline(
     })))
 }
 
+/// Record a failed sync as a notification for the user; failures to do so are only logged
+async fn notify_sync_failure(pool: &sqlx::PgPool, user_id: uuid::Uuid, spreadsheet_id: &str, error: &str) {
+    let spreadsheet_id = uuid::Uuid::parse_str(spreadsheet_id).ok();
+    if let Err(e) = database::record_sync_failure(pool, user_id, spreadsheet_id, error).await {
+        log::error!("Failed to record sync failure notification: {}", e);
+    }
+}
+
 async fn refresh_connection_if_expired(
     pool: &sqlx::PgPool,
     sf_client: &SalesforceClient,
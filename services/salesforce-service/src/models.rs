@@ -141,4 +141,30 @@ pub struct SalesforceConnection {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub expires_at: Option<DateTime<Utc>>,
-}
\ No newline at end of file
+}
+
+/// A spreadsheet column an opportunity sync writes to
+#[derive(Debug, sqlx::FromRow)]
+pub struct SheetColumn {
+    pub id: Uuid,
+    pub name: String,
+    pub salesforce_field: Option<String>,
+}
+
+/// Opportunity fields and the column names they land in when no column is
+/// tagged with the field. The first name is the one used in name-keyed rows.
+pub const OPPORTUNITY_FIELD_COLUMNS: &[(&str, &[&str])] = &[
+    ("Name", &["Opportunity Name"]),
+    ("Id", &["Salesforce ID"]),
+    ("Account.Name", &["Account"]),
+    ("Account.Type", &["Type"]),
+    ("StageName", &["Stage"]),
+    ("Amount", &["Amount", "Deal Value"]),
+    ("Probability", &["Probability"]),
+    ("ExpectedRevenue", &["Expected Revenue"]),
+    ("CloseDate", &["Close Date"]),
+    ("Owner.Name", &["Owner", "Primary Contact"]),
+    ("LastModifiedBy.Name", &["Last Modified By"]),
+    ("LastModifiedDate", &["Last Modified Date"]),
+    ("Technical_Win__c", &["Technical Win"]),
+];
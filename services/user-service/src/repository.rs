@@ -1,11 +1,17 @@
 use common::{
     ContrivanceError, ContrivanceResult, User, UserResponse, UpdateUserRequest,
-    UserRole, PaginationParams, PaginatedResponse,
+    UserRole, PaginationParams, PaginatedResponse, Keyset, SortOrder, OrganizationRole,
 };
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+const USER_FIELDS: &str = "id, email, password_hash, name, role, created_at, updated_at, is_active, last_login";
+
+/// Restricts a users query to members of the organization bound to `$2`
+const IN_ORGANIZATION: &str =
+    "id IN (SELECT user_id FROM organization_members WHERE organization_id = $2)";
+
 #[derive(Clone)]
 pub struct UserRepository {
     pool: PgPool,
@@ -33,54 +39,85 @@ impl UserRepository {
         Ok(user)
     }
 
-    /// List users with pagination
-    pub async fn list_users(&self, pagination: &PaginationParams) -> ContrivanceResult<PaginatedResponse<UserResponse>> {
-        let limit = pagination.limit.unwrap_or(20).min(100) as i64;
-        let offset = ((pagination.page.unwrap_or(1) - 1) * limit as u32) as i64;
+    /// The user's organization and their role in it
+    pub async fn get_membership(&self, user_id: Uuid) -> ContrivanceResult<Option<(Uuid, OrganizationRole)>> {
+        let membership = sqlx::query_as::<_, (Uuid, OrganizationRole)>(
+            "SELECT organization_id, role FROM organization_members WHERE user_id = $1"
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(membership)
+    }
+
+    /// Get a user by ID if they're in the organization
+    pub async fn get_user_in_organization(&self, organization_id: Uuid, user_id: Uuid) -> ContrivanceResult<Option<User>> {
+        let user = sqlx::query_as::<_, User>(&format!(
+            "SELECT {USER_FIELDS} FROM users WHERE id = $1 AND is_active = true AND {IN_ORGANIZATION}"
+        ))
+        .bind(user_id)
+        .bind(organization_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// List an organization's users with pagination, newest first
+    pub async fn list_users(
+        &self,
+        organization_id: Uuid,
+        pagination: &PaginationParams,
+    ) -> ContrivanceResult<PaginatedResponse<UserResponse>> {
+        let keyset = Keyset::<DateTime<Utc>>::from_params(pagination, 20, 100)?;
+        let (op, dir) = keyset.sql(SortOrder::Desc);
         
         // Get total count
-        let total: i64 = sqlx::query_scalar!(
-            "SELECT COUNT(*) FROM users WHERE is_active = true"
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM users u
+            JOIN organization_members m ON m.user_id = u.id
+            WHERE u.is_active = true AND m.organization_id = $1
+            "#
         )
+        .bind(organization_id)
         .fetch_one(&self.pool)
-        .await?
-        .unwrap_or(0);
+        .await?;
 
         // Get users
-        let users = sqlx::query_as!(
-            User,
+        let users = sqlx::query_as::<_, User>(&format!(
             r#"
-            SELECT id, email, password_hash, name, role as "role: UserRole", created_at, updated_at, is_active, last_login
+            SELECT {USER_FIELDS}
             FROM users 
             WHERE is_active = true
-            ORDER BY created_at DESC
-            LIMIT $1 OFFSET $2
+              AND {}
+              AND ($1::timestamptz IS NULL OR (COALESCE(created_at, 'epoch'), id) {op} ($1, $3))
+            ORDER BY COALESCE(created_at, 'epoch') {dir}, id {dir}
+            LIMIT $4 OFFSET $5
             "#,
-            limit,
-            offset
-        )
+            IN_ORGANIZATION
+        ))
+        .bind(keyset.key())
+        .bind(organization_id)
+        .bind(keyset.id())
+        .bind(keyset.fetch_limit())
+        .bind(keyset.offset())
         .fetch_all(&self.pool)
         .await?;
 
-        let user_responses: Vec<UserResponse> = users.into_iter().map(UserResponse::from).collect();
-        
-        let page = pagination.page.unwrap_or(1);
-        let limit = limit as u32;
-        let total_pages = ((total as f64) / (limit as f64)).ceil() as u32;
-
-        Ok(PaginatedResponse {
-            data: user_responses,
-            total: total as u64,
-            page,
-            limit,
-            total_pages,
-            has_next: page < total_pages,
-            has_prev: page > 1,
-        })
+        Ok(keyset
+            .into_response(users, total as u64, |user| (user.created_at.unwrap_or(DateTime::UNIX_EPOCH), user.id))
+            .map(UserResponse::from))
     }
 
-    /// Update user
-    pub async fn update_user(&self, user_id: Uuid, request: &UpdateUserRequest) -> ContrivanceResult<User> {
+    /// Update a user in the organization
+    pub async fn update_user(
+        &self,
+        organization_id: Uuid,
+        user_id: Uuid,
+        request: &UpdateUserRequest,
+    ) -> ContrivanceResult<User> {
         let mut query = String::from("UPDATE users SET updated_at = $1");
         let mut param_count = 1;
         let mut params: Vec<Box<dyn sqlx::Encode<sqlx::Postgres> + Send>> = vec![
@@ -117,20 +154,21 @@ impl UserRepository {
 
         // For simplicity, let's use a direct approach for common update scenarios
         let user = if let Some(name) = &request.name {
-            sqlx::query_as!(
-                User,
+            sqlx::query_as::<_, User>(&format!(
                 r#"
                 UPDATE users 
-                SET name = $1, updated_at = $2
-                WHERE id = $3 AND is_active = true
-                RETURNING id, email, password_hash, name, role as "role: UserRole", created_at, updated_at, is_active, last_login
-                "#,
-                name,
-                Utc::now(),
-                user_id
-            )
-            .fetch_one(&self.pool)
+                SET name = $3, updated_at = $4
+                WHERE id = $1 AND is_active = true AND {IN_ORGANIZATION}
+                RETURNING {USER_FIELDS}
+                "#
+            ))
+            .bind(user_id)
+            .bind(organization_id)
+            .bind(name)
+            .bind(Utc::now())
+            .fetch_optional(&self.pool)
             .await?
+            .ok_or_else(|| ContrivanceError::not_found("User not found"))?
         } else {
             return Err(ContrivanceError::validation("At least one field must be provided for update"));
         };
@@ -138,13 +176,14 @@ impl UserRepository {
         Ok(user)
     }
 
-    /// Soft delete user
-    pub async fn delete_user(&self, user_id: Uuid) -> ContrivanceResult<()> {
-        let result = sqlx::query!(
-            "UPDATE users SET is_active = false, updated_at = $1 WHERE id = $2 AND is_active = true",
-            Utc::now(),
-            user_id
-        )
+    /// Soft delete a user in the organization
+    pub async fn delete_user(&self, organization_id: Uuid, user_id: Uuid) -> ContrivanceResult<()> {
+        let result = sqlx::query(&format!(
+            "UPDATE users SET is_active = false, updated_at = $3 WHERE id = $1 AND is_active = true AND {IN_ORGANIZATION}"
+        ))
+        .bind(user_id)
+        .bind(organization_id)
+        .bind(Utc::now())
         .execute(&self.pool)
         .await?;
 
@@ -155,24 +194,26 @@ impl UserRepository {
         Ok(())
     }
 
-    /// Search users by email or name
-    pub async fn search_users(&self, query: &str, limit: u32) -> ContrivanceResult<Vec<UserResponse>> {
+    /// Search an organization's users by email or name
+    pub async fn search_users(&self, organization_id: Uuid, query: &str, limit: u32) -> ContrivanceResult<Vec<UserResponse>> {
         let search_pattern = format!("%{}%", query.to_lowercase());
         let limit = limit.min(50) as i64;
 
-        let users = sqlx::query_as!(
-            User,
+        let users = sqlx::query_as::<_, User>(&format!(
             r#"
-            SELECT id, email, password_hash, name, role as "role: UserRole", created_at, updated_at, is_active, last_login
+            SELECT {USER_FIELDS}
             FROM users 
             WHERE is_active = true 
+              AND {}
               AND (LOWER(name) LIKE $1 OR LOWER(email) LIKE $1)
             ORDER BY name
-            LIMIT $2
+            LIMIT $3
             "#,
-            search_pattern,
-            limit
-        )
+            IN_ORGANIZATION
+        ))
+        .bind(search_pattern)
+        .bind(organization_id)
+        .bind(limit)
         .fetch_all(&self.pool)
         .await?;
 
@@ -201,4 +242,32 @@ impl UserRepository {
             row.admin_users.unwrap_or(0),
         ))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::testing::{create_user, test_pool};
+
+    #[tokio::test]
+    #[ignore = "needs TEST_DATABASE_URL"]
+    async fn test_users_are_isolated_by_organization() {
+        let repository = UserRepository::new(test_pool().await);
+        let name = format!("Isolated {}", Uuid::new_v4());
+        let (alice, _) = create_user(&repository.pool, &name).await;
+        let (mallory, _) = create_user(&repository.pool, "Mallory").await;
+        let (mallory_org, role) = repository.get_membership(mallory).await.unwrap().unwrap();
+        assert_eq!(role, OrganizationRole::Owner);
+
+        assert!(repository.get_user_in_organization(mallory_org, alice).await.unwrap().is_none());
+        assert!(repository.search_users(mallory_org, &name, 50).await.unwrap().is_empty());
+        let pagination = PaginationParams { page: None, limit: Some(100), sort_by: None, sort_order: None, cursor: None };
+        let listed = repository.list_users(mallory_org, &pagination).await.unwrap();
+        assert_eq!(listed.data.iter().map(|u| u.id).collect::<Vec<_>>(), vec![mallory]);
+
+        let rename = UpdateUserRequest { email: None, name: Some("Renamed".to_string()), role: None, is_active: None };
+        assert!(repository.update_user(mallory_org, alice, &rename).await.is_err());
+        assert!(repository.delete_user(mallory_org, alice).await.is_err());
+        assert!(repository.get_user_by_id(alice).await.unwrap().is_some());
+    }
+}
@@ -1,6 +1,6 @@
 use common::{
-    ContrivanceError, ContrivanceResult, User, UserResponse, UpdateUserRequest,
-    PaginationParams, PaginatedResponse, HealthResponse, ApiResponse,
+    ContrivanceError, ContrivanceResult, UserResponse, UpdateUserRequest,
+    PaginationParams, PaginatedResponse, HealthResponse, ApiResponse, OrganizationRole,
 };
 use crate::repository::UserRepository;
 use uuid::Uuid;
@@ -28,16 +28,15 @@ impl UserService {
 
     /// Get user by ID
     pub async fn get_user(&self, user_id: Uuid, requesting_user_id: Uuid) -> ContrivanceResult<UserResponse> {
-        // Users can view their own profile, admins can view any profile
-        let requesting_user = self.repository.get_user_by_id(requesting_user_id).await?
-            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+        // Users can view their own profile, organization admins can view their members' profiles
+        let (organization_id, role) = self.requester(requesting_user_id).await?;
 
-        if user_id != requesting_user_id && requesting_user.role != common::UserRole::Admin {
+        if user_id != requesting_user_id && !role.is_admin() {
             return Err(ContrivanceError::authorization("Insufficient permissions"));
         }
 
         let user = self.repository
-            .get_user_by_id(user_id)
+            .get_user_in_organization(organization_id, user_id)
             .await?
             .ok_or_else(|| ContrivanceError::not_found("User not found"))?;
 
@@ -50,12 +49,10 @@ impl UserService {
         pagination: PaginationParams,
         requesting_user_id: Uuid,
     ) -> ContrivanceResult<PaginatedResponse<UserResponse>> {
-        // Verify requesting user exists (basic authentication check)
-        let _requesting_user = self.repository.get_user_by_id(requesting_user_id).await?
-            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+        let (organization_id, _) = self.requester(requesting_user_id).await?;
 
-        // All authenticated users can see the user list for todo assignment purposes
-        self.repository.list_users(&pagination).await
+        // Members can see everyone in their organization for todo assignment purposes
+        self.repository.list_users(organization_id, &pagination).await
     }
 
     /// Update user
@@ -69,39 +66,37 @@ impl UserService {
         request.validate()?;
 
         // Check permissions
-        let requesting_user = self.repository.get_user_by_id(requesting_user_id).await?
-            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+        let (organization_id, role) = self.requester(requesting_user_id).await?;
 
-        // Users can update their own profile (except role), admins can update any profile
+        // Users can update their own profile (except role), organization admins can update their members' profiles
         if user_id != requesting_user_id {
-            if requesting_user.role != common::UserRole::Admin {
+            if !role.is_admin() {
                 return Err(ContrivanceError::authorization("Insufficient permissions"));
             }
         } else {
             // Non-admins cannot change their own role
-            if request.role.is_some() && requesting_user.role != common::UserRole::Admin {
+            if request.role.is_some() && !role.is_admin() {
                 return Err(ContrivanceError::authorization("Cannot change own role"));
             }
         }
 
         // Check if user exists
         let _existing_user = self.repository
-            .get_user_by_id(user_id)
+            .get_user_in_organization(organization_id, user_id)
             .await?
             .ok_or_else(|| ContrivanceError::not_found("User not found"))?;
 
         // Update user
-        let updated_user = self.repository.update_user(user_id, &request).await?;
+        let updated_user = self.repository.update_user(organization_id, user_id, &request).await?;
         Ok(UserResponse::from(updated_user))
     }
 
-    /// Delete user (admin only)
+    /// Delete user (organization admins only)
     pub async fn delete_user(&self, user_id: Uuid, requesting_user_id: Uuid) -> ContrivanceResult<()> {
-        // Check if requesting user is admin
-        let requesting_user = self.repository.get_user_by_id(requesting_user_id).await?
-            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+        // Check if requesting user is an organization admin
+        let (organization_id, role) = self.requester(requesting_user_id).await?;
 
-        if requesting_user.role != common::UserRole::Admin {
+        if !role.is_admin() {
             return Err(ContrivanceError::authorization("Admin access required"));
         }
 
@@ -110,7 +105,7 @@ impl UserService {
             return Err(ContrivanceError::validation("Cannot delete own account"));
         }
 
-        self.repository.delete_user(user_id).await
+        self.repository.delete_user(organization_id, user_id).await
     }
 
     /// Search users
@@ -120,15 +115,23 @@ impl UserService {
         limit: u32,
         requesting_user_id: Uuid,
     ) -> ContrivanceResult<Vec<UserResponse>> {
-        // Check if requesting user exists
-        let _requesting_user = self.repository.get_user_by_id(requesting_user_id).await?
-            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+        let (organization_id, _) = self.requester(requesting_user_id).await?;
 
         if query.trim().is_empty() {
             return Err(ContrivanceError::validation("Search query cannot be empty"));
         }
 
-        self.repository.search_users(query, limit).await
+        self.repository.search_users(organization_id, query, limit).await
+    }
+
+    /// The requesting user's organization and role; users only ever see
+    /// their own organization
+    async fn requester(&self, requesting_user_id: Uuid) -> ContrivanceResult<(Uuid, OrganizationRole)> {
+        self.repository.get_user_by_id(requesting_user_id).await?
+            .ok_or_else(|| ContrivanceError::authentication("Requesting user not found"))?;
+
+        self.repository.get_membership(requesting_user_id).await?
+            .ok_or_else(|| ContrivanceError::authentication("Requesting user has no organization"))
     }
 
     /// Validate token with auth service
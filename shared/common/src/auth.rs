@@ -299,9 +299,9 @@ mod tests {
             password_hash: "hash".to_string(),
             name: "Test User".to_string(),
             role: UserRole::User,
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-            is_active: true,
+            created_at: Some(Utc::now()),
+            updated_at: Some(Utc::now()),
+            is_active: Some(true),
             last_login: None,
         };
 
@@ -327,6 +327,8 @@ mod tests {
     fn test_password_strength_validation() {
         assert!(PasswordService::validate_password_strength("weak").is_err());
         assert!(PasswordService::validate_password_strength("StrongPass123!").is_ok());
-        assert!(PasswordService::validate_password_strength("NoNumbers!").is_err());
+        // Three of the four character classes are enough
+        assert!(PasswordService::validate_password_strength("NoNumbers!").is_ok());
+        assert!(PasswordService::validate_password_strength("nonumbers!").is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,554 @@
+//! Cell value validation and export formatting for spreadsheet columns
+//!
+//! Rows store their cells under the column's id, so renaming a column keeps
+//! its cells and two columns may share a label. While clients move over, the
+//! API still accepts and returns cells keyed by column name; see [`RowKeys`].
+
+use chrono::{DateTime, NaiveDate};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use uuid::Uuid;
+
+use crate::currency::{Money, DEFAULT_REPORTING_CURRENCY};
+use crate::errors::{ContrivanceError, ContrivanceResult};
+use crate::models::{ColumnType, SpreadsheetColumn, SpreadsheetRow};
+use crate::utils::is_valid_email;
+
+impl ColumnType {
+    /// Check that a cell value is acceptable for this column type.
+    ///
+    /// `null` and the empty string are always accepted so that cells can be cleared.
+    pub fn validate_value(&self, value: &Value) -> Result<(), String> {
+        if is_blank(value) {
+            return Ok(());
+        }
+
+        match self {
+            ColumnType::Text => match value {
+                Value::String(_) | Value::Number(_) | Value::Bool(_) => Ok(()),
+                _ => Err("expected text".to_string()),
+            },
+            ColumnType::Number => as_number(value)
+                .map(|_| ())
+                .ok_or_else(|| "expected a number".to_string()),
+            ColumnType::Currency => Money::from_cell(value, DEFAULT_REPORTING_CURRENCY)
+                .map(|_| ())
+                .ok_or_else(|| "expected an amount with an ISO currency code".to_string()),
+            ColumnType::Percent => as_percent(value)
+                .map(|_| ())
+                .ok_or_else(|| "expected a percentage".to_string()),
+            ColumnType::Date => match value {
+                Value::String(s) if parse_date(s).is_some() => Ok(()),
+                _ => Err("expected a date (YYYY-MM-DD or RFC 3339)".to_string()),
+            },
+            ColumnType::Boolean | ColumnType::Checkbox => as_bool(value)
+                .map(|_| ())
+                .ok_or_else(|| "expected true or false".to_string()),
+            ColumnType::Select => match value {
+                Value::String(_) => Ok(()),
+                // Legacy multi-value select columns (e.g. Owner) store arrays of strings
+                Value::Array(items) if items.iter().all(Value::is_string) => Ok(()),
+                _ => Err("expected a single option".to_string()),
+            },
+            ColumnType::MultiSelect => match value {
+                Value::Array(items) if items.iter().all(Value::is_string) => Ok(()),
+                _ => Err("expected a list of options".to_string()),
+            },
+            ColumnType::Email => match value {
+                Value::String(s) if is_valid_email(s.trim()) => Ok(()),
+                _ => Err("expected an email address".to_string()),
+            },
+            ColumnType::Url => match value {
+                Value::String(s) if is_valid_url(s.trim()) => Ok(()),
+                _ => Err("expected an http(s) URL".to_string()),
+            },
+            ColumnType::Person => {
+                if person_ids(value).is_some() {
+                    Ok(())
+                } else {
+                    Err("expected a user id or a list of user ids".to_string())
+                }
+            }
+        }
+    }
+
+    /// Render a cell value as plain text for CSV export.
+    ///
+    /// `people` maps user ids to display names for Person columns; unknown ids are
+    /// exported as-is.
+    pub fn export_value(&self, value: &Value, people: &HashMap<Uuid, String>) -> String {
+        if is_blank(value) {
+            return String::new();
+        }
+
+        match self {
+            ColumnType::Boolean | ColumnType::Checkbox => match as_bool(value) {
+                Some(true) => "TRUE".to_string(),
+                Some(false) => "FALSE".to_string(),
+                None => plain_text(value),
+            },
+            ColumnType::Percent => match as_percent(value) {
+                Some(p) => format!("{}%", p),
+                None => plain_text(value),
+            },
+            ColumnType::Number => match as_number(value) {
+                Some(n) => n.to_string(),
+                None => plain_text(value),
+            },
+            ColumnType::Currency => match (value, Money::from_cell(value, DEFAULT_REPORTING_CURRENCY)) {
+                (Value::Object(_), Some(money)) => format!("{} {}", money.amount, money.currency),
+                (_, Some(money)) => money.amount.to_string(),
+                (_, None) => plain_text(value),
+            },
+            ColumnType::Select | ColumnType::MultiSelect => match value {
+                Value::Array(items) => items.iter().map(plain_text).collect::<Vec<_>>().join("; "),
+                _ => plain_text(value),
+            },
+            ColumnType::Person => match person_ids(value) {
+                Some(ids) => ids
+                    .iter()
+                    .map(|id| people.get(id).cloned().unwrap_or_else(|| id.to_string()))
+                    .collect::<Vec<_>>()
+                    .join("; "),
+                None => plain_text(value),
+            },
+            ColumnType::Text | ColumnType::Date | ColumnType::Email | ColumnType::Url => {
+                plain_text(value)
+            }
+        }
+    }
+}
+
+impl SpreadsheetColumn {
+    /// The key this column's cells are stored under in `row_data`
+    pub fn key(&self) -> String {
+        self.id.to_string()
+    }
+
+    /// Get this column's cell from a row's data. Cells still keyed by name,
+    /// e.g. in events recorded before the switch to ids, are found too.
+    pub fn cell<'a>(&self, row_data: &'a Value) -> Option<&'a Value> {
+        row_data.get(self.key()).or_else(|| row_data.get(&self.name))
+    }
+
+    /// Validate a cell against the column type and its `validation_rules`
+    pub fn validate_cell(&self, value: &Value) -> ContrivanceResult<()> {
+        self.column_type
+            .validate_value(value)
+            .map_err(|reason| ContrivanceError::validation(format!("Column '{}': {}", self.name, reason)))?;
+
+        let options = self.options();
+        if options.is_empty() || is_blank(value) {
+            return Ok(());
+        }
+
+        let chosen: Vec<&str> = match value {
+            Value::String(s) => vec![s.as_str()],
+            Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+
+        match chosen.iter().find(|c| !options.iter().any(|o| o == *c)) {
+            Some(unknown) => Err(ContrivanceError::validation(format!(
+                "Column '{}': '{}' is not one of the allowed options",
+                self.name, unknown
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The stored form of a cell. Currency amounts are pinned to a currency as
+    /// `{"amount": ..., "currency": ...}`, so a bare number doesn't change
+    /// denomination when the column's or spreadsheet's currency does later.
+    pub fn normalize_cell(&self, value: Value, reporting_currency: &str) -> Value {
+        if self.column_type != ColumnType::Currency || is_blank(&value) {
+            return value;
+        }
+        let default_currency = self
+            .default_currency()
+            .unwrap_or_else(|| reporting_currency.to_string());
+        match Money::from_cell(&value, &default_currency) {
+            Some(money) => money.to_cell(),
+            None => value,
+        }
+    }
+
+    /// Allowed option values for Select and MultiSelect columns.
+    ///
+    /// Options may be plain strings or `{"value": ..., "label": ...}` objects.
+    pub fn options(&self) -> Vec<String> {
+        if !matches!(self.column_type, ColumnType::Select | ColumnType::MultiSelect) {
+            return Vec::new();
+        }
+
+        self.validation_rules
+            .as_ref()
+            .and_then(|rules| rules.get("options"))
+            .and_then(Value::as_array)
+            .map(|options| {
+                options
+                    .iter()
+                    .filter_map(|o| match o {
+                        Value::String(s) => Some(s.clone()),
+                        Value::Object(obj) => obj.get("value").and_then(Value::as_str).map(str::to_string),
+                        _ => None,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Validate the cells of `row_data` against the spreadsheet's columns.
+///
+/// Only cells that differ from `previous` are checked, so legacy values that
+/// predate a rule don't block unrelated edits. Keys that don't match a column are
+/// left alone. Returns the user ids referenced by Person cells so the caller can
+/// verify them against `users`.
+pub fn validate_row_data(
+    columns: &[SpreadsheetColumn],
+    row_data: &Value,
+    previous: Option<&Value>,
+) -> ContrivanceResult<Vec<Uuid>> {
+    if !row_data.is_object() {
+        return Err(ContrivanceError::validation("row_data must be a JSON object"));
+    }
+
+    let mut people = Vec::new();
+    for column in columns {
+        let value = match column.cell(row_data) {
+            Some(v) => v,
+            None => continue,
+        };
+        if previous.and_then(|p| column.cell(p)) == Some(value) {
+            continue;
+        }
+
+        column.validate_cell(value)?;
+
+        if column.column_type == ColumnType::Person && !is_blank(value) {
+            people.extend(person_ids(value).unwrap_or_default());
+        }
+    }
+
+    people.sort();
+    people.dedup();
+    Ok(people)
+}
+
+/// Rewrite the cells of `row_data` into their stored form; see
+/// [`SpreadsheetColumn::normalize_cell`]
+pub fn normalize_row_data(columns: &[SpreadsheetColumn], row_data: &mut Value, reporting_currency: &str) {
+    let Some(cells) = row_data.as_object_mut() else {
+        return;
+    };
+    for column in columns {
+        if let Some(cell) = cells.get_mut(&column.key()) {
+            *cell = column.normalize_cell(cell.take(), reporting_currency);
+        }
+    }
+}
+
+/// How cells are keyed in `row_data` in API requests and responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowKeys {
+    /// Column display names, as before cells were stored by id
+    #[default]
+    Name,
+    /// Column ids, the stored form
+    Id,
+}
+
+/// Rewrite incoming `row_data` into the stored form, keyed by column id.
+///
+/// Keys may be column ids or column names. A name shared by more than one
+/// column is rejected since we can't tell which is meant, and an id wins over
+/// a name for the same column. Keys matching neither are kept as they are.
+pub fn cells_by_id(columns: &[SpreadsheetColumn], row_data: &Value) -> ContrivanceResult<Value> {
+    let cells = row_data
+        .as_object()
+        .ok_or_else(|| ContrivanceError::validation("row_data must be a JSON object"))?;
+    let ids: HashMap<String, &SpreadsheetColumn> = columns.iter().map(|c| (c.key(), c)).collect();
+
+    let mut keyed = Map::new();
+    let mut by_id = Vec::new();
+    for (key, value) in cells {
+        if ids.contains_key(key) {
+            by_id.push((key.clone(), value.clone()));
+            continue;
+        }
+
+        let mut named = columns.iter().filter(|c| c.name == *key);
+        let key = match (named.next(), named.next()) {
+            (Some(_), Some(_)) => {
+                return Err(ContrivanceError::validation(format!(
+                    "Column name '{}' is shared by several columns; key the cell by column id",
+                    key
+                )))
+            }
+            (Some(column), None) => column.key(),
+            (None, _) => key.clone(),
+        };
+        keyed.insert(key, value.clone());
+    }
+    keyed.extend(by_id);
+
+    Ok(Value::Object(keyed))
+}
+
+/// Present stored `row_data` keyed by column name. Keys that aren't column ids
+/// are kept as they are; if two columns share a name the later one wins.
+pub fn cells_by_name(columns: &[SpreadsheetColumn], row_data: &Value) -> Value {
+    let Some(cells) = row_data.as_object() else {
+        return row_data.clone();
+    };
+    let names: HashMap<String, &str> = columns.iter().map(|c| (c.key(), c.name.as_str())).collect();
+
+    let keyed = cells
+        .iter()
+        .map(|(key, value)| {
+            let key = names.get(key).map(|name| name.to_string()).unwrap_or_else(|| key.clone());
+            (key, value.clone())
+        })
+        .collect();
+
+    Value::Object(keyed)
+}
+
+impl SpreadsheetRow {
+    /// This row with its cells keyed for an API response
+    pub fn keyed(mut self, columns: &[SpreadsheetColumn], keys: RowKeys) -> Self {
+        if keys == RowKeys::Name {
+            self.row_data = cells_by_name(columns, &self.row_data);
+        }
+        self
+    }
+}
+
+/// Extract the user ids stored in a Person cell (a single id or a list of ids)
+pub fn person_ids(value: &Value) -> Option<Vec<Uuid>> {
+    match value {
+        Value::String(s) => Uuid::parse_str(s.trim()).ok().map(|id| vec![id]),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().and_then(|s| Uuid::parse_str(s.trim()).ok()))
+            .collect(),
+        _ => None,
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        _ => false,
+    }
+}
+
+fn plain_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().replace(',', "").parse().ok(),
+        _ => None,
+    }
+}
+
+fn as_percent(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(s) => as_number(&Value::String(s.trim().trim_end_matches('%').to_string())),
+        other => as_number(other),
+    }
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "1" => Some(true),
+            "false" | "no" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parse a Date cell, either `YYYY-MM-DD` or RFC 3339
+pub fn parse_date(s: &str) -> Option<NaiveDate> {
+    let s = s.trim();
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(s).ok().map(|dt| dt.date_naive()))
+}
+
+/// Check for an absolute http(s) URL
+pub fn is_valid_url(s: &str) -> bool {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    URL_REGEX
+        .get_or_init(|| Regex::new(r"^https?://[^\s/?#]+(?:[/?#]\S*)?$").unwrap())
+        .is_match(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, column_type: ColumnType, validation_rules: Option<Value>) -> SpreadsheetColumn {
+        SpreadsheetColumn {
+            id: Uuid::new_v4(),
+            spreadsheet_id: Uuid::new_v4(),
+            name: name.to_string(),
+            column_type,
+            position: 0,
+            is_required: Some(false),
+            default_value: None,
+            validation_rules,
+            display_options: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn test_column_type_serialization() {
+        assert_eq!(serde_json::to_value(ColumnType::MultiSelect).unwrap(), json!("multi_select"));
+        assert_eq!(serde_json::to_value(ColumnType::Url).unwrap(), json!("url"));
+        let parsed: ColumnType = serde_json::from_value(json!("checkbox")).unwrap();
+        assert_eq!(parsed, ColumnType::Checkbox);
+        let parsed: ColumnType = serde_json::from_value(json!("person")).unwrap();
+        assert_eq!(parsed, ColumnType::Person);
+    }
+
+    #[test]
+    fn test_validate_values() {
+        assert!(ColumnType::Email.validate_value(&json!("se@example.com")).is_ok());
+        assert!(ColumnType::Email.validate_value(&json!("not-an-email")).is_err());
+        assert!(ColumnType::Url.validate_value(&json!("https://example.com/deal")).is_ok());
+        assert!(ColumnType::Url.validate_value(&json!("example")).is_err());
+        assert!(ColumnType::Percent.validate_value(&json!("45%")).is_ok());
+        assert!(ColumnType::Percent.validate_value(&json!("lots")).is_err());
+        assert!(ColumnType::Checkbox.validate_value(&json!(true)).is_ok());
+        assert!(ColumnType::Checkbox.validate_value(&json!("maybe")).is_err());
+        assert!(ColumnType::MultiSelect.validate_value(&json!(["a", "b"])).is_ok());
+        assert!(ColumnType::MultiSelect.validate_value(&json!("a")).is_err());
+        assert!(ColumnType::Date.validate_value(&json!("2025-10-20")).is_ok());
+        assert!(ColumnType::Date.validate_value(&json!("next week")).is_err());
+        assert!(ColumnType::Person.validate_value(&json!(Uuid::new_v4().to_string())).is_ok());
+        assert!(ColumnType::Person.validate_value(&json!("jane")).is_err());
+        assert!(ColumnType::Number.validate_value(&Value::Null).is_ok());
+        assert!(ColumnType::Number.validate_value(&json!("")).is_ok());
+        assert!(ColumnType::Currency.validate_value(&json!({"amount": 10, "currency": "GBP"})).is_ok());
+        assert!(ColumnType::Currency.validate_value(&json!({"amount": 10, "currency": "pounds"})).is_err());
+    }
+
+    #[test]
+    fn test_select_options() {
+        let stage = column(
+            "Stage",
+            ColumnType::MultiSelect,
+            Some(json!({"options": ["Discovery", {"value": "POC/Pilot", "label": "POC"}]})),
+        );
+        assert!(stage.validate_cell(&json!(["Discovery", "POC/Pilot"])).is_ok());
+        assert!(stage.validate_cell(&json!(["Closed"])).is_err());
+    }
+
+    #[test]
+    fn test_validate_row_data_only_checks_changed_cells() {
+        let owner = Uuid::new_v4();
+        let columns = vec![
+            column("Website", ColumnType::Url, None),
+            column("Owner", ColumnType::Person, None),
+        ];
+        let previous = json!({"Website": "legacy value"});
+        let row = json!({"Website": "legacy value", "Owner": owner.to_string()});
+
+        let people = validate_row_data(&columns, &row, Some(&previous)).unwrap();
+        assert_eq!(people, vec![owner]);
+        assert!(validate_row_data(&columns, &row, None).is_err());
+    }
+
+    #[test]
+    fn test_currency_cells_are_stored_with_their_currency() {
+        let amount = column("Amount", ColumnType::Currency, None);
+        let mut arr = column("ARR", ColumnType::Currency, None);
+        arr.display_options = Some(json!({"currency": "EUR"}));
+        let stage = column("Stage", ColumnType::Text, None);
+        let mut row = json!({
+            amount.key(): 1000,
+            arr.key(): "2,500",
+            stage.key(): "Discovery",
+        });
+
+        normalize_row_data(&[amount.clone(), arr.clone(), stage.clone()], &mut row, "GBP");
+        assert_eq!(row[amount.key()], json!({"amount": 1000.0, "currency": "GBP"}));
+        assert_eq!(row[arr.key()], json!({"amount": 2500.0, "currency": "EUR"}));
+        assert_eq!(row[stage.key()], json!("Discovery"));
+        assert_eq!(amount.normalize_cell(json!(""), "GBP"), json!(""));
+    }
+
+    #[test]
+    fn test_cells_keyed_by_id_and_name() {
+        let stage = column("Stage", ColumnType::Text, None);
+        let amount = column("Amount", ColumnType::Number, None);
+        let columns = vec![stage.clone(), amount.clone()];
+
+        // Names and ids are both accepted on the way in; unknown keys are kept
+        let stored = cells_by_id(&columns, &json!({"Stage": "POC", amount.key(): 10, "Legacy": "x"})).unwrap();
+        assert_eq!(stored, json!({stage.key(): "POC", amount.key(): 10, "Legacy": "x"}));
+        assert_eq!(stage.cell(&stored), Some(&json!("POC")));
+
+        // The id wins over the name for the same column
+        let stored = cells_by_id(&columns, &json!({"Stage": "old", stage.key(): "new"})).unwrap();
+        assert_eq!(stored, json!({stage.key(): "new"}));
+
+        // Renaming a column keeps its cells
+        let mut renamed = stage.clone();
+        renamed.name = "SE Stage".to_string();
+        assert_eq!(
+            cells_by_name(&[renamed, amount.clone()], &json!({stage.key(): "POC", amount.key(): 10})),
+            json!({"SE Stage": "POC", "Amount": 10})
+        );
+
+        assert!(cells_by_id(&columns, &json!(["not", "an", "object"])).is_err());
+    }
+
+    #[test]
+    fn test_cells_with_shared_column_names() {
+        let first = column("Notes", ColumnType::Text, None);
+        let second = column("Notes", ColumnType::Text, None);
+        let columns = vec![first.clone(), second.clone()];
+
+        let stored = cells_by_id(&columns, &json!({first.key(): "a", second.key(): "b"})).unwrap();
+        assert_eq!(first.cell(&stored), Some(&json!("a")));
+        assert_eq!(second.cell(&stored), Some(&json!("b")));
+
+        assert!(cells_by_id(&columns, &json!({"Notes": "which one?"})).is_err());
+    }
+
+    #[test]
+    fn test_export_values() {
+        let owner = Uuid::new_v4();
+        let people = HashMap::from([(owner, "Jane Smith".to_string())]);
+        assert_eq!(ColumnType::Checkbox.export_value(&json!(true), &people), "TRUE");
+        assert_eq!(ColumnType::Percent.export_value(&json!(45), &people), "45%");
+        assert_eq!(ColumnType::MultiSelect.export_value(&json!(["a", "b"]), &people), "a; b");
+        assert_eq!(ColumnType::Person.export_value(&json!(owner.to_string()), &people), "Jane Smith");
+        assert_eq!(ColumnType::Text.export_value(&Value::Null, &people), "");
+        assert_eq!(
+            ColumnType::Currency.export_value(&json!({"amount": 1500, "currency": "EUR"}), &people),
+            "1500 EUR"
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! Currency amounts and exchange-rate conversion for Currency columns
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::models::{ExchangeRate, NewExchangeRate, Spreadsheet, SpreadsheetColumn};
+
+/// Reporting currency used when a spreadsheet doesn't set one
+pub const DEFAULT_REPORTING_CURRENCY: &str = "USD";
+
+/// An amount in a specific ISO 4217 currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: impl Into<String>) -> Self {
+        Self {
+            amount,
+            currency: currency.into(),
+        }
+    }
+
+    /// Read a Currency cell.
+    ///
+    /// Cells are stored as `{"amount": 1000, "currency": "EUR"}`. Bare numbers and
+    /// numeric strings from before multi-currency support are taken to be in
+    /// `default_currency`; strings like `"1,000 EUR"` or `"€1000"` are also accepted.
+    pub fn from_cell(value: &Value, default_currency: &str) -> Option<Money> {
+        match value {
+            Value::Number(n) => n.as_f64().map(|amount| Money::new(amount, default_currency)),
+            Value::String(s) => parse_amount_string(s, default_currency),
+            Value::Object(obj) => {
+                let amount = match obj.get("amount")? {
+                    Value::Number(n) => n.as_f64()?,
+                    Value::String(s) => s.trim().replace(',', "").parse().ok()?,
+                    _ => return None,
+                };
+                let currency = match obj.get("currency") {
+                    Some(Value::String(code)) => normalize_currency_code(code)?,
+                    None | Some(Value::Null) => default_currency.to_string(),
+                    _ => return None,
+                };
+                Some(Money::new(amount, currency))
+            }
+            _ => None,
+        }
+    }
+
+    /// The cell representation of this amount
+    pub fn to_cell(&self) -> Value {
+        serde_json::json!({ "amount": self.amount, "currency": self.currency })
+    }
+}
+
+/// Check for a three-letter ISO 4217 code, e.g. "USD"
+pub fn is_currency_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
+/// Upper-case and validate a currency code
+pub fn normalize_currency_code(code: &str) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    is_currency_code(&code).then_some(code)
+}
+
+fn parse_amount_string(s: &str, default_currency: &str) -> Option<Money> {
+    let s = s.trim();
+    let symbols = [('$', "USD"), ('€', "EUR"), ('£', "GBP")];
+
+    for (symbol, code) in symbols {
+        if let Some(rest) = s.strip_prefix(symbol) {
+            return rest.trim().replace(',', "").parse().ok().map(|amount| Money::new(amount, code));
+        }
+    }
+
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    match parts.as_slice() {
+        [amount] => amount.replace(',', "").parse().ok().map(|a| Money::new(a, default_currency)),
+        [amount, code] if normalize_currency_code(code).is_some() => amount
+            .replace(',', "")
+            .parse()
+            .ok()
+            .map(|a| Money::new(a, normalize_currency_code(code).unwrap())),
+        [code, amount] if normalize_currency_code(code).is_some() => amount
+            .replace(',', "")
+            .parse()
+            .ok()
+            .map(|a| Money::new(a, normalize_currency_code(code).unwrap())),
+        _ => None,
+    }
+}
+
+impl Spreadsheet {
+    /// Currency that aggregations, forecasts and exports are reported in
+    /// (`settings.reporting_currency`, defaulting to USD)
+    pub fn reporting_currency(&self) -> String {
+        self.settings
+            .as_ref()
+            .and_then(|s| s.get("reporting_currency"))
+            .and_then(Value::as_str)
+            .and_then(normalize_currency_code)
+            .unwrap_or_else(|| DEFAULT_REPORTING_CURRENCY.to_string())
+    }
+}
+
+impl SpreadsheetColumn {
+    /// Currency assumed for bare numbers in this column (`display_options.currency`)
+    pub fn default_currency(&self) -> Option<String> {
+        self.display_options
+            .as_ref()
+            .and_then(|o| o.get("currency"))
+            .and_then(Value::as_str)
+            .and_then(normalize_currency_code)
+    }
+}
+
+/// An amount converted into another currency, with the rate that was applied
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conversion {
+    pub amount: f64,
+    pub currency: String,
+    pub rate: f64,
+    /// Date of the oldest rate used; `None` when no conversion was needed
+    pub rate_date: Option<NaiveDate>,
+}
+
+/// Converts amounts using the exchange-rate table.
+///
+/// A rate of `r` for base `EUR` and quote `USD` means 1 EUR = r USD. Inverse rates
+/// are derived automatically, and pairs without a direct rate are crossed through
+/// a common currency.
+#[derive(Debug, Clone, Default)]
+pub struct CurrencyConverter {
+    rates: HashMap<(String, String), Vec<(NaiveDate, f64)>>,
+}
+
+impl CurrencyConverter {
+    pub fn new(rates: &[ExchangeRate]) -> Self {
+        let mut converter = Self::default();
+        for rate in rates {
+            converter.add_rate(&rate.base_currency, &rate.quote_currency, rate.rate, rate.rate_date);
+        }
+        converter
+    }
+
+    pub fn add_rate(&mut self, base: &str, quote: &str, rate: f64, rate_date: NaiveDate) {
+        if rate <= 0.0 || base == quote {
+            return;
+        }
+        let entries = self.rates.entry((base.to_string(), quote.to_string())).or_default();
+        entries.retain(|(date, _)| *date != rate_date);
+        entries.push((rate_date, rate));
+        entries.sort_by_key(|(date, _)| *date);
+    }
+
+    /// Latest direct or inverse rate on or before `as_of`
+    fn pair_rate(&self, from: &str, to: &str, as_of: NaiveDate) -> Option<(f64, NaiveDate)> {
+        let latest = |entries: &Vec<(NaiveDate, f64)>| {
+            entries.iter().rev().find(|(date, _)| *date <= as_of).copied()
+        };
+
+        let direct = self.rates.get(&(from.to_string(), to.to_string())).and_then(latest);
+        let inverse = self
+            .rates
+            .get(&(to.to_string(), from.to_string()))
+            .and_then(latest)
+            .map(|(date, rate)| (date, 1.0 / rate));
+
+        // Prefer whichever quote is more recent
+        match (direct, inverse) {
+            (Some(d), Some(i)) => Some(if i.0 > d.0 { i } else { d }),
+            (d, i) => d.or(i),
+        }
+        .map(|(date, rate)| (rate, date))
+    }
+
+    /// Rate to convert `from` into `to` as of a date, with the date of the oldest
+    /// quote involved
+    pub fn rate(&self, from: &str, to: &str, as_of: NaiveDate) -> Option<(f64, NaiveDate)> {
+        if let Some(found) = self.pair_rate(from, to, as_of) {
+            return Some(found);
+        }
+
+        let currencies: BTreeSet<&String> = self.rates.keys().flat_map(|(a, b)| [a, b]).collect();
+        currencies
+            .into_iter()
+            .filter(|pivot| pivot.as_str() != from && pivot.as_str() != to)
+            .filter_map(|pivot| {
+                let (first, first_date) = self.pair_rate(from, pivot, as_of)?;
+                let (second, second_date) = self.pair_rate(pivot, to, as_of)?;
+                Some((first * second, first_date.min(second_date)))
+            })
+            .max_by_key(|(_, date)| *date)
+    }
+
+    /// Convert an amount into `to`; `None` when no rate is known
+    pub fn convert(&self, money: &Money, to: &str, as_of: NaiveDate) -> Option<Conversion> {
+        if money.currency == to {
+            return Some(Conversion {
+                amount: money.amount,
+                currency: to.to_string(),
+                rate: 1.0,
+                rate_date: None,
+            });
+        }
+
+        self.rate(&money.currency, to, as_of).map(|(rate, rate_date)| Conversion {
+            amount: money.amount * rate,
+            currency: to.to_string(),
+            rate,
+            rate_date: Some(rate_date),
+        })
+    }
+}
+
+/// Parse exchange rates from CSV with the columns
+/// `rate_date,base_currency,quote_currency,rate[,source]`. A header line is optional.
+pub fn parse_rates_csv(text: &str) -> Result<Vec<NewExchangeRate>, String> {
+    let mut rates = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if index == 0 && fields.first().map(|f| f.eq_ignore_ascii_case("rate_date")).unwrap_or(false) {
+            continue;
+        }
+        if fields.len() < 4 {
+            return Err(format!("line {}: expected rate_date,base_currency,quote_currency,rate", index + 1));
+        }
+
+        let rate_date = NaiveDate::parse_from_str(fields[0], "%Y-%m-%d")
+            .map_err(|_| format!("line {}: invalid date '{}'", index + 1, fields[0]))?;
+        let rate: f64 = fields[3]
+            .parse()
+            .map_err(|_| format!("line {}: invalid rate '{}'", index + 1, fields[3]))?;
+
+        let rate = NewExchangeRate {
+            base_currency: fields[1].to_string(),
+            quote_currency: fields[2].to_string(),
+            rate,
+            rate_date,
+            source: fields.get(4).filter(|s| !s.is_empty()).map(|s| s.to_string()),
+        };
+        rate.check().map_err(|e| format!("line {}: {}", index + 1, e))?;
+        rates.push(rate);
+    }
+
+    Ok(rates)
+}
+
+impl NewExchangeRate {
+    /// Validate currency codes and the rate
+    pub fn check(&self) -> Result<(), String> {
+        if !is_currency_code(&self.base_currency) {
+            return Err(format!("invalid currency code '{}'", self.base_currency));
+        }
+        if !is_currency_code(&self.quote_currency) {
+            return Err(format!("invalid currency code '{}'", self.quote_currency));
+        }
+        if self.base_currency == self.quote_currency {
+            return Err("base and quote currency must differ".to_string());
+        }
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err("rate must be a positive number".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_money_from_cell() {
+        assert_eq!(Money::from_cell(&json!(1000), "USD"), Some(Money::new(1000.0, "USD")));
+        assert_eq!(
+            Money::from_cell(&json!({"amount": 250, "currency": "eur"}), "USD"),
+            Some(Money::new(250.0, "EUR"))
+        );
+        assert_eq!(Money::from_cell(&json!("£1,200"), "USD"), Some(Money::new(1200.0, "GBP")));
+        assert_eq!(Money::from_cell(&json!("99.5 EUR"), "USD"), Some(Money::new(99.5, "EUR")));
+        assert_eq!(Money::from_cell(&json!({"amount": 1, "currency": "EURO"}), "USD"), None);
+        assert_eq!(Money::from_cell(&json!("lots"), "USD"), None);
+    }
+
+    #[test]
+    fn test_conversion_uses_latest_rate_before_date() {
+        let mut converter = CurrencyConverter::default();
+        converter.add_rate("EUR", "USD", 1.10, date("2025-10-01"));
+        converter.add_rate("EUR", "USD", 1.20, date("2025-10-15"));
+
+        let converted = converter.convert(&Money::new(100.0, "EUR"), "USD", date("2025-10-10")).unwrap();
+        assert!((converted.amount - 110.0).abs() < 1e-9);
+        assert_eq!(converted.rate_date, Some(date("2025-10-01")));
+
+        let converted = converter.convert(&Money::new(120.0, "USD"), "EUR", date("2025-10-20")).unwrap();
+        assert!((converted.amount - 100.0).abs() < 1e-9);
+        assert_eq!(converted.rate_date, Some(date("2025-10-15")));
+
+        assert!(converter.convert(&Money::new(1.0, "EUR"), "USD", date("2025-09-01")).is_none());
+    }
+
+    #[test]
+    fn test_cross_rate_through_pivot() {
+        let mut converter = CurrencyConverter::default();
+        converter.add_rate("EUR", "USD", 1.25, date("2025-10-01"));
+        converter.add_rate("GBP", "USD", 1.50, date("2025-10-02"));
+
+        let converted = converter.convert(&Money::new(100.0, "GBP"), "EUR", date("2025-10-20")).unwrap();
+        assert!((converted.amount - 120.0).abs() < 1e-9);
+        assert_eq!(converted.rate_date, Some(date("2025-10-01")));
+    }
+
+    #[test]
+    fn test_parse_rates_csv() {
+        let csv = "rate_date,base_currency,quote_currency,rate,source\n2025-10-20,EUR,USD,1.08,ecb\n2025-10-20,GBP,USD,1.27\n";
+        let rates = parse_rates_csv(csv).unwrap();
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].source.as_deref(), Some("ecb"));
+        assert!(parse_rates_csv("2025-10-20,EUR,USD,-1").is_err());
+        assert!(parse_rates_csv("2025-10-20,EUR,USD").is_err());
+    }
+}
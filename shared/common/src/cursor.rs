@@ -0,0 +1,246 @@
+//! Opaque cursors for keyset pagination.
+//!
+//! A cursor records the sort key and id of the row a page starts after (or
+//! before, going backwards). Clients treat it as an opaque string and pass
+//! it back as `?cursor=`; page numbers keep working for clients that don't.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{ContrivanceError, ContrivanceResult, PaginatedResponse, PaginationParams};
+
+/// Which way a cursor pages from its row
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CursorDirection {
+    #[serde(rename = "n")]
+    Next,
+    #[serde(rename = "p")]
+    Prev,
+}
+
+/// A position in a list ordered by `(key, id)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor<K> {
+    #[serde(rename = "k")]
+    pub key: K,
+    #[serde(rename = "i")]
+    pub id: Uuid,
+    #[serde(rename = "d")]
+    pub direction: CursorDirection,
+}
+
+impl<K: Serialize + DeserializeOwned> Cursor<K> {
+    pub fn new(key: K, id: Uuid, direction: CursorDirection) -> Self {
+        Self { key, id, direction }
+    }
+
+    pub fn encode(&self) -> String {
+        // Serializing a key and a uuid can't fail
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(value: &str) -> ContrivanceResult<Self> {
+        URL_SAFE_NO_PAD
+            .decode(value)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ContrivanceError::bad_request("Invalid cursor"))
+    }
+}
+
+/// The order a list is normally shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// One page request against a list ordered by `(key, id)`, from either a
+/// cursor or a page number
+#[derive(Debug, Clone)]
+pub struct Keyset<K> {
+    pub page: u32,
+    pub limit: u32,
+    pub cursor: Option<Cursor<K>>,
+}
+
+impl<K: Clone + Serialize + DeserializeOwned> Keyset<K> {
+    pub fn from_params(params: &PaginationParams, default_limit: u32, max_limit: u32) -> ContrivanceResult<Self> {
+        let cursor = params
+            .cursor
+            .as_deref()
+            .filter(|cursor| !cursor.is_empty())
+            .map(Cursor::decode)
+            .transpose()?;
+
+        Ok(Self {
+            page: params.page.unwrap_or(1).max(1),
+            limit: params.limit.unwrap_or(default_limit).clamp(1, max_limit),
+            cursor,
+        })
+    }
+
+    /// Rows to fetch; the one extra tells us whether there is another page
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit as i64 + 1
+    }
+
+    /// Page-number requests skip whole pages; cursor requests start at the cursor
+    pub fn offset(&self) -> i64 {
+        match self.cursor {
+            Some(_) => 0,
+            None => (self.page as i64 - 1) * self.limit as i64,
+        }
+    }
+
+    /// The key to bind for the `(key, id)` comparison, `NULL` on the first page
+    pub fn key(&self) -> Option<K> {
+        self.cursor.as_ref().map(|cursor| cursor.key.clone())
+    }
+
+    pub fn id(&self) -> Option<Uuid> {
+        self.cursor.as_ref().map(|cursor| cursor.id)
+    }
+
+    fn backwards(&self) -> bool {
+        matches!(&self.cursor, Some(cursor) if cursor.direction == CursorDirection::Prev)
+    }
+
+    /// The comparison operator and ORDER BY direction for a list shown in
+    /// `order`. Paging backwards reads the list in reverse from the cursor.
+    pub fn sql(&self, order: SortOrder) -> (&'static str, &'static str) {
+        match (order, self.backwards()) {
+            (SortOrder::Asc, false) | (SortOrder::Desc, true) => (">", "ASC"),
+            (SortOrder::Desc, false) | (SortOrder::Asc, true) => ("<", "DESC"),
+        }
+    }
+
+    /// Build the response from up to `fetch_limit()` rows, with cursors for
+    /// the pages either side
+    pub fn into_response<T>(
+        self,
+        mut items: Vec<T>,
+        total: u64,
+        key: impl Fn(&T) -> (K, Uuid),
+    ) -> PaginatedResponse<T> {
+        let more = items.len() > self.limit as usize;
+        items.truncate(self.limit as usize);
+
+        let (has_next, has_prev) = match &self.cursor {
+            None => (more, self.page > 1),
+            Some(cursor) if cursor.direction == CursorDirection::Prev => {
+                items.reverse();
+                (true, more)
+            }
+            Some(_) => (more, true),
+        };
+
+        let cursor_at = |item: Option<&T>, direction| {
+            item.map(|item| {
+                let (key, id) = key(item);
+                Cursor::new(key, id, direction).encode()
+            })
+        };
+        let next_cursor = if has_next { cursor_at(items.last(), CursorDirection::Next) } else { None };
+        let prev_cursor = if has_prev { cursor_at(items.first(), CursorDirection::Prev) } else { None };
+
+        PaginatedResponse {
+            total,
+            page: self.page,
+            limit: self.limit,
+            total_pages: ((total as f64) / (self.limit as f64)).ceil() as u32,
+            has_next,
+            has_prev,
+            next_cursor,
+            prev_cursor,
+            data: items,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(cursor: Option<String>, limit: u32) -> PaginationParams {
+        PaginationParams { limit: Some(limit), cursor, ..Default::default() }
+    }
+
+    /// Page through `list` (sorted by the first element) the way the SQL would
+    fn fetch(list: &[(i32, Uuid)], keyset: &Keyset<i32>) -> Vec<(i32, Uuid)> {
+        let (op, dir) = keyset.sql(SortOrder::Asc);
+        let mut rows: Vec<_> = list
+            .iter()
+            .copied()
+            .filter(|row| match &keyset.cursor {
+                None => true,
+                Some(c) if op == ">" => (row.0, row.1) > (c.key, c.id),
+                Some(c) => (row.0, row.1) < (c.key, c.id),
+            })
+            .collect();
+        if dir == "DESC" {
+            rows.reverse();
+        }
+        rows.into_iter()
+            .skip(keyset.offset() as usize)
+            .take(keyset.fetch_limit() as usize)
+            .collect()
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor::new(42, Uuid::new_v4(), CursorDirection::Prev);
+        assert_eq!(Cursor::<i32>::decode(&cursor.encode()).unwrap(), cursor);
+
+        assert!(Cursor::<i32>::decode("not a cursor").is_err());
+        assert!(Cursor::<chrono::DateTime<chrono::Utc>>::decode(&cursor.encode()).is_err());
+    }
+
+    #[test]
+    fn test_keyset_pages_forwards_and_backwards() {
+        // Duplicate keys are ordered by id
+        let mut list: Vec<_> = (0..7).map(|i| (i / 2, Uuid::new_v4())).collect();
+        list.sort();
+
+        let first = Keyset::from_params(&params(None, 3), 20, 100).unwrap();
+        let page1 = first.clone().into_response(fetch(&list, &first), 7, |row| *row);
+        assert_eq!(page1.data, list[0..3]);
+        assert!(page1.has_next && !page1.has_prev && page1.prev_cursor.is_none());
+
+        let second = Keyset::from_params(&params(page1.next_cursor, 3), 20, 100).unwrap();
+        let page2 = second.clone().into_response(fetch(&list, &second), 7, |row| *row);
+        assert_eq!(page2.data, list[3..6]);
+        assert!(page2.has_next && page2.has_prev);
+
+        let third = Keyset::from_params(&params(page2.next_cursor, 3), 20, 100).unwrap();
+        let page3 = third.clone().into_response(fetch(&list, &third), 7, |row| *row);
+        assert_eq!(page3.data, list[6..7]);
+        assert!(!page3.has_next && page3.next_cursor.is_none());
+
+        // Back from the last page lands on the same middle page
+        let back = Keyset::from_params(&params(page3.prev_cursor, 3), 20, 100).unwrap();
+        let page2_again = back.clone().into_response(fetch(&list, &back), 7, |row| *row);
+        assert_eq!(page2_again.data, list[3..6]);
+        assert!(page2_again.has_next && page2_again.has_prev);
+
+        let back = Keyset::from_params(&params(page2_again.prev_cursor, 3), 20, 100).unwrap();
+        let page1_again = back.clone().into_response(fetch(&list, &back), 7, |row| *row);
+        assert_eq!(page1_again.data, list[0..3]);
+        assert!(!page1_again.has_prev && page1_again.prev_cursor.is_none());
+    }
+
+    #[test]
+    fn test_keyset_page_numbers_still_work() {
+        let list: Vec<_> = (0..5).map(|i| (i, Uuid::new_v4())).collect();
+        let keyset = Keyset::from_params(&PaginationParams { page: Some(2), ..params(None, 2) }, 20, 100).unwrap();
+        assert_eq!(keyset.offset(), 2);
+
+        let page = keyset.clone().into_response(fetch(&list, &keyset), 5, |row| *row);
+        assert_eq!(page.data, list[2..4]);
+        assert_eq!(page.total_pages, 3);
+        assert!(page.has_next && page.has_prev);
+        assert!(page.next_cursor.is_some() && page.prev_cursor.is_some());
+    }
+}
@@ -4,10 +4,20 @@ pub mod auth;
 pub mod database;
 pub mod utils;
 pub mod jwt;
+pub mod cells;
+pub mod currency;
+pub mod webhooks;
+pub mod mailer;
+pub mod cursor;
+#[cfg(feature = "test-support")]
+pub mod testing;
 
 pub use models::*;
 pub use errors::*;
 pub use database::*;
 pub use utils::*;
+pub use cells::{cells_by_id, cells_by_name, normalize_row_data, person_ids, validate_row_data, RowKeys};
+pub use cursor::{Cursor, CursorDirection, Keyset, SortOrder};
+pub use currency::{Conversion, CurrencyConverter, Money};
 // Use JWT module items directly instead of auth module to avoid conflicts
 pub use jwt::{JwtService, Claims};
\ No newline at end of file
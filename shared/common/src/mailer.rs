@@ -0,0 +1,750 @@
+//! Outbound email: templates, SMTP delivery and a persistent outbox.
+//!
+//! Services never send mail inline. They render an [`EmailTemplate`] into the
+//! `email_outbox` table with [`EmailOutbox::enqueue`], and contrivance-service's
+//! outbox worker sends due rows through [`Mailer`], retrying failures with
+//! backoff. For local development point `SMTP_HOST`/`SMTP_PORT` at MailHog
+//! (`localhost:1025`, UI on `:8025`) with `SMTP_TLS=none`.
+
+use chrono::{DateTime, Utc};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{ContrivanceError, ContrivanceResult, EnvUtils};
+
+/// How the SMTP connection is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain text, for local catchers like MailHog
+    None,
+    /// Upgrade with STARTTLS, usually port 587
+    StartTls,
+    /// Implicit TLS, usually port 465
+    Tls,
+}
+
+impl SmtpTls {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "off" | "false" => Some(SmtpTls::None),
+            "starttls" => Some(SmtpTls::StartTls),
+            "tls" | "ssl" => Some(SmtpTls::Tls),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MailerConfig {
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_tls: SmtpTls,
+    /// Sender, e.g. `Contrivance <no-reply@example.com>`
+    pub from: String,
+    /// Frontend base URL used for links in emails
+    pub app_url: String,
+    pub timeout_secs: u64,
+}
+
+impl MailerConfig {
+    /// Read `SMTP_*`, `MAIL_FROM` and `APP_URL`. Defaults target MailHog.
+    pub fn from_env() -> Self {
+        let optional = |key: &str| Some(EnvUtils::get_var(key, "")).filter(|value| !value.is_empty());
+
+        Self {
+            smtp_host: EnvUtils::get_var("SMTP_HOST", "localhost"),
+            smtp_port: EnvUtils::get_var_as_int("SMTP_PORT", 1025) as u16,
+            smtp_username: optional("SMTP_USERNAME"),
+            smtp_password: optional("SMTP_PASSWORD"),
+            smtp_tls: SmtpTls::parse(&EnvUtils::get_var("SMTP_TLS", "none")).unwrap_or(SmtpTls::None),
+            from: EnvUtils::get_var("MAIL_FROM", "Contrivance <no-reply@contrivance.local>"),
+            app_url: EnvUtils::get_var("APP_URL", "http://localhost:3000")
+                .trim_end_matches('/')
+                .to_string(),
+            timeout_secs: EnvUtils::get_var_as_int("SMTP_TIMEOUT_SECONDS", 30).max(1) as u64,
+        }
+    }
+}
+
+/// A template rendered to a subject and both bodies
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// Emails the application sends
+#[derive(Debug, Clone, Serialize)]
+pub enum EmailTemplate {
+    CollaboratorInvite {
+        inviter: String,
+        spreadsheet: String,
+        permission: String,
+        accept_url: String,
+    },
+    PasswordReset {
+        reset_url: String,
+        expires_minutes: i64,
+    },
+    OverdueReminder {
+        todo_title: String,
+        spreadsheet: String,
+        due_date: DateTime<Utc>,
+        todo_url: String,
+    },
+    WeeklyDigest(PipelineDigest),
+}
+
+/// Contents of the weekly pipeline digest
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineDigest {
+    pub recipient: String,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub spreadsheets: Vec<DigestSpreadsheet>,
+    pub overdue_todos: Vec<DigestTodo>,
+    pub upcoming_todos: Vec<DigestTodo>,
+    /// Where the recipient can change their schedule
+    pub settings_url: String,
+}
+
+impl PipelineDigest {
+    /// Nothing changed and nothing is due; not worth an email
+    pub fn is_empty(&self) -> bool {
+        self.overdue_todos.is_empty()
+            && self.upcoming_todos.is_empty()
+            && self.spreadsheets.iter().all(|s| s.rows_created == 0 && s.rows_updated == 0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DigestSpreadsheet {
+    pub name: String,
+    pub url: String,
+    pub row_count: i64,
+    pub rows_created: i64,
+    pub rows_updated: i64,
+    pub open_todos: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DigestTodo {
+    pub title: String,
+    pub spreadsheet: String,
+    pub due_date: Option<DateTime<Utc>>,
+    pub url: String,
+}
+
+const LAYOUT_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>{{subject}}</title></head>
+<body style="margin:0;padding:24px;background:#f5f6f8;font-family:Helvetica,Arial,sans-serif;color:#1f2937;">
+<div style="max-width:560px;margin:0 auto;background:#ffffff;border-radius:8px;padding:32px;">
+{{{content}}}
+</div>
+<p style="max-width:560px;margin:16px auto 0;font-size:12px;color:#6b7280;">Sent by Contrivance.</p>
+</body>
+</html>
+"#;
+
+const INVITE_HTML: &str = r#"<h1 style="font-size:20px;">You've been invited to {{spreadsheet}}</h1>
+<p>{{inviter}} invited you to collaborate on <strong>{{spreadsheet}}</strong> with {{permission}} access.</p>
+<p><a href="{{accept_url}}" style="display:inline-block;padding:10px 18px;background:#2563eb;color:#ffffff;border-radius:6px;text-decoration:none;">Accept invitation</a></p>
+"#;
+
+const INVITE_TEXT: &str = "{{inviter}} invited you to collaborate on {{spreadsheet}} with {{permission}} access.
+
+Accept the invitation: {{accept_url}}
+";
+
+const PASSWORD_RESET_HTML: &str = r#"<h1 style="font-size:20px;">Reset your password</h1>
+<p>We received a request to reset your Contrivance password. The link below works once and expires in {{expires_minutes}} minutes.</p>
+<p><a href="{{reset_url}}" style="display:inline-block;padding:10px 18px;background:#2563eb;color:#ffffff;border-radius:6px;text-decoration:none;">Choose a new password</a></p>
+<p style="font-size:13px;color:#6b7280;">If you didn't ask for this you can ignore this email; your password hasn't changed.</p>
+"#;
+
+const PASSWORD_RESET_TEXT: &str = "We received a request to reset your Contrivance password.
+
+Choose a new password: {{reset_url}}
+
+The link works once and expires in {{expires_minutes}} minutes. If you didn't ask for this you can ignore this email; your password hasn't changed.
+";
+
+const OVERDUE_HTML: &str = r#"<h1 style="font-size:20px;">A todo is overdue</h1>
+<p><strong>{{todo_title}}</strong> in {{spreadsheet}} was due {{due_date}}.</p>
+<p><a href="{{todo_url}}" style="display:inline-block;padding:10px 18px;background:#2563eb;color:#ffffff;border-radius:6px;text-decoration:none;">Open spreadsheet</a></p>
+"#;
+
+const OVERDUE_TEXT: &str = "{{todo_title}} in {{spreadsheet}} was due {{due_date}}.
+
+Open spreadsheet: {{todo_url}}
+";
+
+const DIGEST_HTML: &str = r#"<h1 style="font-size:20px;">Your pipeline this week</h1>
+<p>Hi {{recipient}}, here's what happened between {{period_start}} and {{period_end}}.</p>
+{{{overdue}}}{{{upcoming}}}{{{spreadsheets}}}<p style="font-size:13px;color:#6b7280;"><a href="{{settings_url}}">Change when you get this email</a></p>
+"#;
+
+const DIGEST_TEXT: &str = "Hi {{recipient}}, here's what happened between {{period_start}} and {{period_end}}.
+{{{overdue}}}{{{upcoming}}}{{{spreadsheets}}}
+Change when you get this email: {{settings_url}}
+";
+
+impl EmailTemplate {
+    /// Stable name stored with each outbox row
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::CollaboratorInvite { .. } => "collaborator_invite",
+            EmailTemplate::PasswordReset { .. } => "password_reset",
+            EmailTemplate::OverdueReminder { .. } => "overdue_reminder",
+            EmailTemplate::WeeklyDigest(_) => "weekly_digest",
+        }
+    }
+
+    pub fn render(&self) -> RenderedEmail {
+        match self {
+            EmailTemplate::CollaboratorInvite { inviter, spreadsheet, permission, accept_url } => {
+                let vars = [
+                    ("inviter", inviter.as_str()),
+                    ("spreadsheet", spreadsheet.as_str()),
+                    ("permission", permission.as_str()),
+                    ("accept_url", accept_url.as_str()),
+                ];
+                page(
+                    format!("{} invited you to {}", inviter, spreadsheet),
+                    render_template(INVITE_HTML, &vars, true),
+                    render_template(INVITE_TEXT, &vars, false),
+                )
+            }
+            EmailTemplate::PasswordReset { reset_url, expires_minutes } => {
+                let expires = expires_minutes.to_string();
+                let vars = [("reset_url", reset_url.as_str()), ("expires_minutes", expires.as_str())];
+                page(
+                    "Reset your Contrivance password".to_string(),
+                    render_template(PASSWORD_RESET_HTML, &vars, true),
+                    render_template(PASSWORD_RESET_TEXT, &vars, false),
+                )
+            }
+            EmailTemplate::OverdueReminder { todo_title, spreadsheet, due_date, todo_url } => {
+                let due = format_date(due_date);
+                let vars = [
+                    ("todo_title", todo_title.as_str()),
+                    ("spreadsheet", spreadsheet.as_str()),
+                    ("due_date", due.as_str()),
+                    ("todo_url", todo_url.as_str()),
+                ];
+                page(
+                    format!("Overdue: {}", todo_title),
+                    render_template(OVERDUE_HTML, &vars, true),
+                    render_template(OVERDUE_TEXT, &vars, false),
+                )
+            }
+            EmailTemplate::WeeklyDigest(digest) => render_digest(digest),
+        }
+    }
+}
+
+fn page(subject: String, content_html: String, text: String) -> RenderedEmail {
+    let html = render_template(
+        LAYOUT_HTML,
+        &[("subject", subject.as_str()), ("content", content_html.as_str())],
+        true,
+    );
+    RenderedEmail { subject, html, text }
+}
+
+fn render_digest(digest: &PipelineDigest) -> RenderedEmail {
+    let todo_section = |heading: &str, todos: &[DigestTodo], html: bool| -> String {
+        if todos.is_empty() {
+            return String::new();
+        }
+        let items: Vec<String> = todos
+            .iter()
+            .map(|todo| {
+                let due = todo.due_date.as_ref().map(format_date).unwrap_or_else(|| "no due date".to_string());
+                let vars = [
+                    ("title", todo.title.as_str()),
+                    ("spreadsheet", todo.spreadsheet.as_str()),
+                    ("due", due.as_str()),
+                    ("url", todo.url.as_str()),
+                ];
+                if html {
+                    render_template(r#"<li><a href="{{url}}">{{title}}</a> &middot; {{spreadsheet}} &middot; {{due}}</li>"#, &vars, true)
+                } else {
+                    render_template("- {{title}} ({{spreadsheet}}, {{due}}) {{url}}", &vars, false)
+                }
+            })
+            .collect();
+        section(heading, &items, html)
+    };
+
+    let spreadsheet_section = |html: bool| -> String {
+        let items: Vec<String> = digest
+            .spreadsheets
+            .iter()
+            .map(|s| {
+                let counts = format!(
+                    "{} rows, {} added, {} updated, {} open todos",
+                    s.row_count, s.rows_created, s.rows_updated, s.open_todos
+                );
+                let vars = [("name", s.name.as_str()), ("counts", counts.as_str()), ("url", s.url.as_str())];
+                if html {
+                    render_template(r#"<li><a href="{{url}}">{{name}}</a>: {{counts}}</li>"#, &vars, true)
+                } else {
+                    render_template("- {{name}}: {{counts}} {{url}}", &vars, false)
+                }
+            })
+            .collect();
+        section("Spreadsheets", &items, html)
+    };
+
+    let period_start = format_date(&digest.period_start);
+    let period_end = format_date(&digest.period_end);
+    let render = |template: &str, html: bool| {
+        let overdue = todo_section("Overdue todos", &digest.overdue_todos, html);
+        let upcoming = todo_section("Due in the next week", &digest.upcoming_todos, html);
+        let spreadsheets = spreadsheet_section(html);
+        render_template(
+            template,
+            &[
+                ("recipient", digest.recipient.as_str()),
+                ("period_start", period_start.as_str()),
+                ("period_end", period_end.as_str()),
+                ("settings_url", digest.settings_url.as_str()),
+                ("overdue", overdue.as_str()),
+                ("upcoming", upcoming.as_str()),
+                ("spreadsheets", spreadsheets.as_str()),
+            ],
+            html,
+        )
+    };
+
+    let subject = match digest.overdue_todos.len() {
+        0 => "Your weekly pipeline digest".to_string(),
+        1 => "Your weekly pipeline digest: 1 overdue todo".to_string(),
+        n => format!("Your weekly pipeline digest: {} overdue todos", n),
+    };
+    page(subject, render(DIGEST_HTML, true), render(DIGEST_TEXT, false))
+}
+
+/// A titled list, or nothing if there are no items
+fn section(heading: &str, items: &[String], html: bool) -> String {
+    if items.is_empty() {
+        return String::new();
+    }
+    if html {
+        format!(
+            "<h2 style=\"font-size:16px;margin-top:24px;\">{}</h2>\n<ul>\n{}\n</ul>\n",
+            escape_html(heading),
+            items.join("\n")
+        )
+    } else {
+        format!("\n{}\n{}\n", heading, items.join("\n"))
+    }
+}
+
+fn format_date(date: &DateTime<Utc>) -> String {
+    date.format("%a %-d %b %Y").to_string()
+}
+
+/// Substitute `{{name}}` placeholders, HTML-escaping values when `escape_values`
+/// is set. `{{{name}}}` inserts the value as-is, for fragments that are already
+/// rendered. Unknown placeholders are left in place so they show up in review.
+pub fn render_template(template: &str, vars: &[(&str, &str)], escape_values: bool) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start..];
+        let (raw, open, close) = if after.starts_with("{{{") { (true, 3, "}}}") } else { (false, 2, "}}") };
+
+        let Some(end) = after[open..].find(close) else {
+            rest = after;
+            break;
+        };
+        let key = after[open..open + end].trim();
+        let placeholder_len = open + end + close.len();
+
+        match vars.iter().find(|(name, _)| *name == key) {
+            Some((_, value)) if escape_values && !raw => out.push_str(&escape_html(value)),
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(&after[..placeholder_len]),
+        }
+        rest = &after[placeholder_len..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Sends email over SMTP
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    pub fn new(config: &MailerConfig) -> ContrivanceResult<Self> {
+        let smtp_error = |e: lettre::transport::smtp::Error| ContrivanceError::configuration(format!("Invalid SMTP settings: {}", e));
+
+        let builder = match config.smtp_tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host),
+            SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.smtp_host).map_err(smtp_error)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host).map_err(smtp_error)?,
+        };
+        let builder = builder
+            .port(config.smtp_port)
+            .timeout(Some(Duration::from_secs(config.timeout_secs)));
+        let builder = match (&config.smtp_username, &config.smtp_password) {
+            (Some(username), password) => builder.credentials(Credentials::new(
+                username.clone(),
+                password.clone().unwrap_or_default(),
+            )),
+            _ => builder,
+        };
+
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| ContrivanceError::configuration(format!("Invalid MAIL_FROM {:?}: {}", config.from, e)))?;
+
+        Ok(Self { transport: builder.build(), from })
+    }
+
+    /// Send a multipart (text and HTML) email to one recipient
+    pub async fn send(&self, to: &str, email: &RenderedEmail) -> ContrivanceResult<()> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| ContrivanceError::validation(format!("Invalid recipient {:?}: {}", to, e)))?;
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&email.subject)
+            .multipart(MultiPart::alternative_plain_html(email.text.clone(), email.html.clone()))
+            .map_err(|e| ContrivanceError::internal(format!("Failed to build email: {}", e)))?;
+
+        self.transport
+            .send(message)
+            .await
+            .map_err(|e| ContrivanceError::external_service("smtp", e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+/// A queued email claimed for sending
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OutboxEmail {
+    pub id: Uuid,
+    pub to_address: String,
+    pub template: String,
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+    pub attempt_count: i32,
+}
+
+impl OutboxEmail {
+    pub fn rendered(&self) -> RenderedEmail {
+        RenderedEmail {
+            subject: self.subject.clone(),
+            html: self.html_body.clone(),
+            text: self.text_body.clone(),
+        }
+    }
+}
+
+/// The `email_outbox` table
+#[derive(Clone)]
+pub struct EmailOutbox {
+    pool: PgPool,
+}
+
+impl EmailOutbox {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Render and queue an email. Returns `None` if an email with the same
+    /// dedupe key was already queued.
+    pub async fn enqueue(
+        &self,
+        user_id: Option<Uuid>,
+        to_address: &str,
+        template: &EmailTemplate,
+        dedupe_key: Option<&str>,
+    ) -> ContrivanceResult<Option<Uuid>> {
+        let email = template.render();
+
+        let id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO email_outbox (user_id, to_address, template, subject, html_body, text_body, dedupe_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (dedupe_key) WHERE dedupe_key IS NOT NULL DO NOTHING
+            RETURNING id
+            "#
+        )
+        .bind(user_id)
+        .bind(to_address)
+        .bind(template.name())
+        .bind(&email.subject)
+        .bind(&email.html)
+        .bind(&email.text)
+        .bind(dedupe_key)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Claim pending emails that are due, pushing their next attempt out by
+    /// `lease_seconds` so another instance won't send them at the same time
+    pub async fn claim_due(&self, limit: i64, lease_seconds: i64) -> ContrivanceResult<Vec<OutboxEmail>> {
+        let emails = sqlx::query_as::<_, OutboxEmail>(
+            r#"
+            WITH due AS (
+                SELECT id
+                FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= NOW()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            UPDATE email_outbox e
+            SET next_attempt_at = NOW() + make_interval(secs => $2)
+            FROM due
+            WHERE e.id = due.id
+            RETURNING e.id, e.to_address, e.template, e.subject, e.html_body, e.text_body, e.attempt_count
+            "#
+        )
+        .bind(limit)
+        .bind(lease_seconds as f64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(emails)
+    }
+
+    /// Record a send attempt. `error` is `None` on success; `retry_at` is when
+    /// to try again after a failure, and `None` gives up. Once sent or given
+    /// up on, the body is cleared, as it can hold a reset link or other secret.
+    pub async fn record_attempt(
+        &self,
+        id: Uuid,
+        error: Option<&str>,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> ContrivanceResult<()> {
+        let status = match (error, retry_at) {
+            (None, _) => "sent",
+            (Some(_), Some(_)) => "pending",
+            (Some(_), None) => "failed",
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE email_outbox
+            SET status = $2,
+                attempt_count = attempt_count + 1,
+                last_attempt_at = NOW(),
+                next_attempt_at = $3,
+                last_error = $4,
+                sent_at = CASE WHEN $2 = 'sent' THEN NOW() ELSE sent_at END,
+                html_body = CASE WHEN $2 = 'pending' THEN html_body ELSE '' END,
+                text_body = CASE WHEN $2 = 'pending' THEN text_body ELSE '' END
+            WHERE id = $1
+            "#
+        )
+        .bind(id)
+        .bind(status)
+        .bind(retry_at)
+        .bind(error)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_render_template_escapes_values() {
+        let vars = [("name", "<b>Tom & \"Jerry\"</b>"), ("fragment", "<li>x</li>")];
+
+        assert_eq!(
+            render_template("Hi {{name}} {{{fragment}}}", &vars, true),
+            "Hi &lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt; <li>x</li>"
+        );
+        assert_eq!(
+            render_template("Hi {{ name }}", &vars, false),
+            "Hi <b>Tom & \"Jerry\"</b>"
+        );
+        assert_eq!(render_template("{{missing}} {{unclosed", &vars, true), "{{missing}} {{unclosed");
+    }
+
+    #[test]
+    fn test_invite_renders_both_bodies() {
+        let email = EmailTemplate::CollaboratorInvite {
+            inviter: "Ann <ann@example.com>".to_string(),
+            spreadsheet: "Q3 Pipeline".to_string(),
+            permission: "edit".to_string(),
+            accept_url: "http://localhost:3000/spreadsheet/1?invite=accept".to_string(),
+        }
+        .render();
+
+        assert_eq!(email.subject, "Ann <ann@example.com> invited you to Q3 Pipeline");
+        assert!(email.html.contains("<title>Ann &lt;ann@example.com&gt; invited you to Q3 Pipeline</title>"));
+        assert!(email.html.contains("Ann &lt;ann@example.com&gt; invited you"));
+        assert!(email.text.contains("Ann <ann@example.com> invited you"));
+        assert!(email.text.contains("Accept the invitation: http://localhost:3000/spreadsheet/1?invite=accept"));
+        assert!(!email.text.contains("{{"));
+        assert!(!email.html.contains("{{"));
+    }
+
+    #[test]
+    fn test_digest_lists_sections_with_content() {
+        let now = Utc::now();
+        let digest = PipelineDigest {
+            recipient: "Ann".to_string(),
+            period_start: now - chrono::Duration::days(7),
+            period_end: now,
+            spreadsheets: vec![DigestSpreadsheet {
+                name: "Deals".to_string(),
+                url: "http://app/spreadsheet/1".to_string(),
+                row_count: 12,
+                rows_created: 2,
+                rows_updated: 5,
+                open_todos: 3,
+            }],
+            overdue_todos: vec![DigestTodo {
+                title: "Call <Acme>".to_string(),
+                spreadsheet: "Deals".to_string(),
+                due_date: Some(now - chrono::Duration::days(1)),
+                url: "http://app/spreadsheet/1".to_string(),
+            }],
+            upcoming_todos: vec![],
+            settings_url: "http://app/settings".to_string(),
+        };
+        assert!(!digest.is_empty());
+
+        let email = EmailTemplate::WeeklyDigest(digest).render();
+        assert_eq!(email.subject, "Your weekly pipeline digest: 1 overdue todo");
+        assert!(email.html.contains("Call &lt;Acme&gt;"));
+        assert!(email.html.contains("Deals</a>: 12 rows, 2 added, 5 updated, 3 open todos"));
+        assert!(!email.html.contains("Due in the next week"));
+        assert!(email.text.contains("- Call <Acme> (Deals, "));
+    }
+
+    /// Just enough of an SMTP server to accept one message, like MailHog does
+    async fn smtp_catcher() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+
+            write.write_all(b"220 catcher ESMTP\r\n").await.unwrap();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        write.write_all(b"250 OK queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    b"250-catcher\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                write.write_all(reply).await.unwrap();
+            }
+            data
+        });
+
+        (port, handle)
+    }
+
+    #[tokio::test]
+    async fn test_mailer_sends_multipart_message() {
+        let (port, received) = smtp_catcher().await;
+        let mailer = Mailer::new(&MailerConfig {
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: port,
+            smtp_username: None,
+            smtp_password: None,
+            smtp_tls: SmtpTls::None,
+            from: "Contrivance <no-reply@contrivance.local>".to_string(),
+            app_url: "http://localhost:3000".to_string(),
+            timeout_secs: 5,
+        })
+        .unwrap();
+
+        let email = EmailTemplate::PasswordReset {
+            reset_url: "http://localhost:3000/reset-password?token=abc".to_string(),
+            expires_minutes: 60,
+        }
+        .render();
+        mailer.send("ann@example.com", &email).await.unwrap();
+        drop(mailer);
+
+        let data = received.await.unwrap();
+        assert!(data.contains("Subject: Reset your Contrivance password"));
+        assert!(data.contains("To: ann@example.com"));
+        assert!(data.contains("multipart/alternative"));
+        assert!(data.contains("text/plain"));
+        assert!(data.contains("text/html"));
+    }
+
+    #[test]
+    fn test_smtp_tls_parse() {
+        assert_eq!(SmtpTls::parse("STARTTLS"), Some(SmtpTls::StartTls));
+        assert_eq!(SmtpTls::parse("none"), Some(SmtpTls::None));
+        assert_eq!(SmtpTls::parse("ssl"), Some(SmtpTls::Tls));
+        assert_eq!(SmtpTls::parse("maybe"), None);
+    }
+}
@@ -1,5 +1,6 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use sqlx::FromRow;
 use uuid::Uuid;
 use validator::Validate;
@@ -117,9 +118,18 @@ pub enum ColumnType {
     Text,
     Number,
     Date,
-    Boolean,   
+    Boolean,
     Select,
+    #[serde(rename = "multi_select")]
+    #[sqlx(rename = "multi_select")]
+    MultiSelect,
     Currency,
+    Email,
+    Url,
+    Percent,
+    /// Reference to a user by id; values are checked against `users` on write
+    Person,
+    Checkbox,
 }
 
 impl Default for ColumnType {
@@ -157,6 +167,21 @@ pub struct CreateColumnRequest {
     pub display_options: Option<serde_json::Value>,
 }
 
+/// Spreadsheet column update request
+///
+/// The column type can't be changed in place. `display_options` is merged
+/// into the existing options rather than replacing them.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateColumnRequest {
+    #[validate(length(min = 1))]
+    pub name: Option<String>,
+    pub position: Option<i32>,
+    pub is_required: Option<bool>,
+    pub default_value: Option<String>,
+    pub validation_rules: Option<serde_json::Value>,
+    pub display_options: Option<serde_json::Value>,
+}
+
 /// Spreadsheet model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Spreadsheet {
@@ -168,6 +193,11 @@ pub struct Spreadsheet {
     pub updated_at: Option<DateTime<Utc>>,
     pub is_public: Option<bool>,
     pub settings: Option<serde_json::Value>,
+    pub workspace_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+    pub organization_id: Uuid,
+    /// Team that owns the spreadsheet, whose members get access to it
+    pub team_id: Option<Uuid>,
 }
 
 /// Spreadsheet creation request
@@ -179,6 +209,11 @@ pub struct CreateSpreadsheetRequest {
     pub is_public: Option<bool>,
     pub settings: Option<serde_json::Value>,
     pub columns: Option<Vec<CreateColumnRequest>>,
+    /// Workspace to file the spreadsheet in; implied by `folder_id` when that is set
+    pub workspace_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+    /// Team in the creator's organization to own the spreadsheet
+    pub team_id: Option<Uuid>,
 }
 
 /// Spreadsheet update request
@@ -190,6 +225,29 @@ pub struct UpdateSpreadsheetRequest {
     pub settings: Option<serde_json::Value>,
 }
 
+/// Exchange rate: 1 unit of `base_currency` is worth `rate` units of `quote_currency`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExchangeRate {
+    pub id: Uuid,
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: f64,
+    pub rate_date: NaiveDate,
+    pub source: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Exchange rate creation/update request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewExchangeRate {
+    pub base_currency: String,
+    pub quote_currency: String,
+    pub rate: f64,
+    pub rate_date: NaiveDate,
+    pub source: Option<String>,
+}
+
 /// Spreadsheet row model
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct SpreadsheetRow {
@@ -220,6 +278,7 @@ pub struct UpdateRowRequest {
 /// Permission level enumeration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, sqlx::Type)]
 #[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum PermissionLevel {
     View,
     Edit,
@@ -287,12 +346,120 @@ pub enum WebSocketMessage {
         user_id: Uuid,
         user_name: String,
         spreadsheet_id: Uuid,
+        /// Color to draw the user's cursor in
+        color: String,
     },
-    /// User left the spreadsheet
+    /// User left the spreadsheet, or stopped sending heartbeats
     UserLeft {
         user_id: Uuid,
         spreadsheet_id: Uuid,
     },
+    /// Everyone else viewing the spreadsheet, sent to a connection when it joins
+    PresenceSnapshot {
+        spreadsheet_id: Uuid,
+        users: Vec<PresenceUser>,
+    },
+    /// Sent by a client when its selected cell changes; `None`s clear the selection
+    Select {
+        row_id: Option<Uuid>,
+        column_id: Option<Uuid>,
+    },
+    /// A peer's selected cell changed
+    SelectionChanged {
+        spreadsheet_id: Uuid,
+        user_id: Uuid,
+        row_id: Option<Uuid>,
+        column_id: Option<Uuid>,
+    },
+    /// Sent by a client opening a cell's editor. Send it again before the
+    /// lock expires to keep it while the editor stays open.
+    LockCell {
+        row_id: Uuid,
+        column_id: Uuid,
+    },
+    /// Sent by a client closing a cell's editor
+    UnlockCell {
+        row_id: Uuid,
+        column_id: Uuid,
+    },
+    /// A cell was locked or its lock renewed. Also sent to a client whose
+    /// `LockCell` was refused, naming who holds the lock.
+    CellLocked {
+        spreadsheet_id: Uuid,
+        lock: CellLock,
+    },
+    /// A cell's lock was released. Locks that simply lapse aren't announced;
+    /// clients drop them at `expires_at`.
+    CellUnlocked {
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        user_id: Uuid,
+    },
+    /// Sent by a client opening a text cell for collaborative editing; answered
+    /// with a `TextSnapshot`
+    OpenText {
+        row_id: Uuid,
+        column_id: Uuid,
+    },
+    /// A text cell's collaborative document: its Automerge state, base64
+    /// encoded, and the text it holds
+    TextSnapshot {
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        state: String,
+        text: String,
+    },
+    /// Sent by a client with base64 Automerge changes to an opened text cell.
+    /// Answered with an `OpAck` or `OpNack` like the row ops.
+    EditText {
+        op_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        changes: String,
+    },
+    /// Changes merged into a text cell's document, by a collaborative edit or
+    /// a plain write to the cell, with the cell's text afterwards
+    TextEdited {
+        spreadsheet_id: Uuid,
+        row_id: Uuid,
+        column_id: Uuid,
+        changes: String,
+        text: String,
+        edited_by: Uuid,
+    },
+    /// Sent by a client to add a row, applied as `POST .../rows` would be.
+    /// `op_id` is chosen by the client and echoed in the `OpAck` or `OpNack`.
+    CreateRow {
+        op_id: Uuid,
+        row_data: serde_json::Value,
+        position: Option<i32>,
+    },
+    /// Sent by a client to change cells in a row; cells not named are kept
+    UpdateRow {
+        op_id: Uuid,
+        row_id: Uuid,
+        row_data: serde_json::Value,
+    },
+    /// Sent by a client to delete a row
+    DeleteRow {
+        op_id: Uuid,
+        row_id: Uuid,
+    },
+    /// A client's op was applied. `row` is the row as written, absent for
+    /// deletes, and `seq` is the change feed event the op produced.
+    OpAck {
+        op_id: Uuid,
+        row: Option<SpreadsheetRow>,
+        seq: Option<i64>,
+    },
+    /// A client's op was rejected and nothing was written
+    OpNack {
+        op_id: Uuid,
+        message: String,
+        code: String,
+    },
     /// Row was updated
     RowUpdated {
         spreadsheet_id: Uuid,
@@ -339,6 +506,64 @@ pub enum WebSocketMessage {
         spreadsheet_id: Uuid,
         deleted_by: Uuid,
     },
+    /// Todo was created
+    TodoCreated {
+        spreadsheet_id: Uuid,
+        todo: Todo,
+        created_by: Uuid,
+    },
+    /// Todo was updated
+    TodoUpdated {
+        spreadsheet_id: Uuid,
+        todo: Todo,
+        updated_by: Uuid,
+    },
+    /// Todo was marked as completed
+    TodoCompleted {
+        spreadsheet_id: Uuid,
+        todo: Todo,
+        completed_by: Uuid,
+    },
+    /// Todo was deleted
+    TodoDeleted {
+        spreadsheet_id: Uuid,
+        todo_id: Uuid,
+        deleted_by: Uuid,
+    },
+    /// Discovery session was marked as completed
+    DiscoverySessionCompleted {
+        session_id: Uuid,
+        account_id: String,
+        account_name: String,
+        user_id: Uuid,
+        completed_at: Option<DateTime<Utc>>,
+    },
+    /// Notification raised by an automation rule
+    AutomationNotification {
+        spreadsheet_id: Uuid,
+        rule_id: Uuid,
+        row_id: Option<Uuid>,
+        recipients: Vec<Uuid>,
+        message: String,
+    },
+    /// Notification for the receiving user, sent on their `/ws/notifications` channel
+    NotificationCreated {
+        notification: Notification,
+    },
+    /// Sent on a spreadsheet connection once it's caught up, after any
+    /// missed events were replayed. `seq` is the last change feed event the
+    /// client has been sent; reconnect with `?resume=<resume_token>&last_seq=`
+    /// the last one it applied to get the rest.
+    Session {
+        spreadsheet_id: Uuid,
+        resume_token: String,
+        seq: i64,
+    },
+    /// Too many events were missed to replay; reload the spreadsheet
+    ResyncRequired {
+        spreadsheet_id: Uuid,
+        seq: i64,
+    },
     /// Error message
     Error {
         message: String,
@@ -350,6 +575,182 @@ pub enum WebSocketMessage {
     Pong,
 }
 
+impl WebSocketMessage {
+    /// Event type name used in the change feed, e.g. `row_updated`
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            WebSocketMessage::UserJoined { .. } => "user_joined",
+            WebSocketMessage::UserLeft { .. } => "user_left",
+            WebSocketMessage::PresenceSnapshot { .. } => "presence_snapshot",
+            WebSocketMessage::Select { .. } => "select",
+            WebSocketMessage::SelectionChanged { .. } => "selection_changed",
+            WebSocketMessage::LockCell { .. } => "lock_cell",
+            WebSocketMessage::UnlockCell { .. } => "unlock_cell",
+            WebSocketMessage::CellLocked { .. } => "cell_locked",
+            WebSocketMessage::CellUnlocked { .. } => "cell_unlocked",
+            WebSocketMessage::OpenText { .. } => "open_text",
+            WebSocketMessage::TextSnapshot { .. } => "text_snapshot",
+            WebSocketMessage::EditText { .. } => "edit_text",
+            WebSocketMessage::TextEdited { .. } => "text_edited",
+            WebSocketMessage::CreateRow { .. } => "create_row",
+            WebSocketMessage::UpdateRow { .. } => "update_row",
+            WebSocketMessage::DeleteRow { .. } => "delete_row",
+            WebSocketMessage::OpAck { .. } => "op_ack",
+            WebSocketMessage::OpNack { .. } => "op_nack",
+            WebSocketMessage::RowUpdated { .. } => "row_updated",
+            WebSocketMessage::RowCreated { .. } => "row_created",
+            WebSocketMessage::RowDeleted { .. } => "row_deleted",
+            WebSocketMessage::ColumnUpdated { .. } => "column_updated",
+            WebSocketMessage::ColumnCreated { .. } => "column_created",
+            WebSocketMessage::ColumnDeleted { .. } => "column_deleted",
+            WebSocketMessage::SpreadsheetUpdated { .. } => "spreadsheet_updated",
+            WebSocketMessage::SpreadsheetDeleted { .. } => "spreadsheet_deleted",
+            WebSocketMessage::TodoCreated { .. } => "todo_created",
+            WebSocketMessage::TodoUpdated { .. } => "todo_updated",
+            WebSocketMessage::TodoCompleted { .. } => "todo_completed",
+            WebSocketMessage::TodoDeleted { .. } => "todo_deleted",
+            WebSocketMessage::DiscoverySessionCompleted { .. } => "discovery_session_completed",
+            WebSocketMessage::AutomationNotification { .. } => "automation_notification",
+            WebSocketMessage::NotificationCreated { .. } => "notification_created",
+            WebSocketMessage::Session { .. } => "session",
+            WebSocketMessage::ResyncRequired { .. } => "resync_required",
+            WebSocketMessage::Error { .. } => "error",
+            WebSocketMessage::Ping => "ping",
+            WebSocketMessage::Pong => "pong",
+        }
+    }
+}
+
+/// A user viewing a spreadsheet, and the cell they have selected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PresenceUser {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub color: String,
+    pub row_id: Option<Uuid>,
+    pub column_id: Option<Uuid>,
+}
+
+/// An advisory lock on a cell open in someone's editor
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, FromRow)]
+pub struct CellLock {
+    pub row_id: Uuid,
+    pub column_id: Uuid,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A WebSocket message stamped with its sequence number in the spreadsheet's
+/// change feed. Serializes as the message JSON with an extra `seq` field.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequencedMessage {
+    pub seq: i64,
+    #[serde(flatten)]
+    pub message: WebSocketMessage,
+}
+
+/// Entry in a spreadsheet's change feed
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SpreadsheetEvent {
+    pub id: i64,
+    pub spreadsheet_id: Uuid,
+    pub seq: i64,
+    pub event_type: String,
+    /// The `WebSocketMessage` JSON that was broadcast for this event
+    pub payload: serde_json::Value,
+    pub actor_id: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Response for `GET /spreadsheets/{id}/changes`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeFeed {
+    pub spreadsheet_id: Uuid,
+    pub since: i64,
+    /// Highest sequence number recorded for the spreadsheet
+    pub latest_seq: i64,
+    pub has_more: bool,
+    pub events: Vec<SpreadsheetEvent>,
+}
+
+/// Webhook subscription, scoped to one spreadsheet or to everything its owner can see
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    /// `None` for per-user subscriptions
+    pub spreadsheet_id: Option<Uuid>,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub event_types: Vec<String>,
+    pub description: Option<String>,
+    pub is_active: bool,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Returned once on creation, the only time the signing secret is shown
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreatedWebhookSubscription {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    pub secret: String,
+}
+
+/// Create webhook subscription request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateWebhookRequest {
+    #[validate(url(message = "A valid URL is required"))]
+    pub url: String,
+    pub spreadsheet_id: Option<Uuid>,
+    #[validate(length(min = 1, message = "At least one event type is required"))]
+    pub event_types: Vec<String>,
+    pub description: Option<String>,
+}
+
+/// Update webhook subscription request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateWebhookRequest {
+    #[validate(url(message = "A valid URL is required"))]
+    pub url: Option<String>,
+    #[validate(length(min = 1, message = "At least one event type is required"))]
+    pub event_types: Option<Vec<String>>,
+    pub description: Option<String>,
+    pub is_active: Option<bool>,
+}
+
+/// Webhook delivery status
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// One event sent to one subscription, with the outcome of its latest attempt
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempt_count: i32,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub response_body: Option<String>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// Set when this delivery is a manual replay of an earlier one
+    pub replay_of: Option<Uuid>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
 /// API response wrapper
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
@@ -395,6 +796,8 @@ pub struct PaginationParams {
     pub limit: Option<u32>,
     pub sort_by: Option<String>,
     pub sort_order: Option<String>, // "asc" or "desc"
+    /// Opaque keyset cursor from a previous page's `next_cursor`/`prev_cursor`
+    pub cursor: Option<String>,
 }
 
 impl Default for PaginationParams {
@@ -404,6 +807,7 @@ impl Default for PaginationParams {
             limit: Some(20),
             sort_by: None,
             sort_order: Some("asc".to_string()),
+            cursor: None,
         }
     }
 }
@@ -418,6 +822,27 @@ pub struct PaginatedResponse<T> {
     pub total_pages: u32,
     pub has_next: bool,
     pub has_prev: bool,
+    #[serde(default)]
+    pub next_cursor: Option<String>,
+    #[serde(default)]
+    pub prev_cursor: Option<String>,
+}
+
+impl<T> PaginatedResponse<T> {
+    /// Convert the items, keeping the paging details
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> PaginatedResponse<U> {
+        PaginatedResponse {
+            data: self.data.into_iter().map(f).collect(),
+            total: self.total,
+            page: self.page,
+            limit: self.limit,
+            total_pages: self.total_pages,
+            has_next: self.has_next,
+            has_prev: self.has_prev,
+            next_cursor: self.next_cursor,
+            prev_cursor: self.prev_cursor,
+        }
+    }
 }
 
 /// Health check response
@@ -470,6 +895,37 @@ pub struct Todo {
     pub row_id: Option<Uuid>,
     pub user_id: Uuid,
     pub assigned_to: Option<Uuid>,
+    /// Shared by every occurrence of a recurring todo
+    pub series_id: Option<Uuid>,
+    /// RFC 5545 `RRULE` of the series, e.g. `FREQ=WEEKLY;BYDAY=MO`
+    pub recurrence: Option<String>,
+    /// IANA time zone the rule is read in
+    pub recurrence_timezone: Option<String>,
+    /// Due date of the series' first occurrence, the rule's `DTSTART`
+    pub series_start: Option<DateTime<Utc>>,
+    /// When the rule scheduled this occurrence; `due_date` may since have moved
+    pub scheduled_at: Option<DateTime<Utc>>,
+    /// The todo this is a subtask of
+    pub parent_id: Option<Uuid>,
+    /// Todos that have to be completed before this one can be
+    #[serde(default)]
+    pub blocked_by: Vec<Uuid>,
+    /// Whether any of `blocked_by` is still open
+    #[serde(default)]
+    pub blocked: bool,
+    /// Steps within the todo, in order
+    #[serde(default)]
+    #[sqlx(json)]
+    pub checklist: Vec<TodoChecklistItem>,
+}
+
+/// A step within a todo
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoChecklistItem {
+    pub id: Uuid,
+    pub title: String,
+    pub completed: bool,
+    pub position: i32,
 }
 
 /// Create todo request
@@ -484,6 +940,26 @@ pub struct CreateTodoRequest {
     pub spreadsheet_id: Uuid,
     pub row_id: Option<Uuid>,
     pub assigned_to: Option<Uuid>,
+    /// Repeat the todo by this RFC 5545 rule, starting from `due_date`
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    /// IANA time zone the rule is read in; UTC if not given
+    #[serde(default)]
+    pub recurrence_timezone: Option<String>,
+    /// Make this a subtask of another todo on the same spreadsheet and row
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+}
+
+/// Which occurrences of a recurring todo an update applies to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoEditScope {
+    /// Only the todo being updated
+    #[default]
+    Occurrence,
+    /// The todo, the series' other open occurrences and those still to come
+    Series,
 }
 
 /// Update todo request
@@ -496,6 +972,90 @@ pub struct UpdateTodoRequest {
     pub due_date: Option<DateTime<Utc>>,
     pub supporting_artifact: Option<String>,
     pub assigned_to: Option<Uuid>,
+    /// New rule for the series; an empty string stops it repeating
+    #[serde(default)]
+    pub recurrence: Option<String>,
+    #[serde(default)]
+    pub recurrence_timezone: Option<String>,
+    #[serde(default)]
+    pub scope: TodoEditScope,
+    /// Move the todo under another todo on the same spreadsheet and row
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+}
+
+/// Add checklist item request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateChecklistItemRequest {
+    #[validate(length(min = 1, max = 500, message = "Title must be 1 to 500 characters"))]
+    pub title: String,
+    /// Defaults to after the last item
+    pub position: Option<i32>,
+}
+
+/// Update checklist item request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateChecklistItemRequest {
+    #[validate(length(min = 1, max = 500, message = "Title must be 1 to 500 characters"))]
+    pub title: Option<String>,
+    pub completed: Option<bool>,
+    pub position: Option<i32>,
+}
+
+/// Add dependency request: the todo waits until `blocked_by` is completed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddTodoDependencyRequest {
+    pub blocked_by: Uuid,
+}
+
+/// Completed steps of a todo out of all of them: its checklist items, and
+/// its subtasks and theirs at every depth
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoProgress {
+    pub completed: u32,
+    pub total: u32,
+}
+
+/// A todo with its subtasks, as returned for a row with `?tree=true`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TodoNode {
+    #[serde(flatten)]
+    pub todo: Todo,
+    pub subtasks: Vec<TodoNode>,
+    pub progress: TodoProgress,
+}
+
+impl TodoNode {
+    /// Arrange todos under their parents, keeping their order. A todo whose
+    /// parent isn't among them is a root.
+    pub fn tree(todos: Vec<Todo>) -> Vec<TodoNode> {
+        let ids: HashSet<Uuid> = todos.iter().map(|todo| todo.id).collect();
+        let mut children: HashMap<Option<Uuid>, Vec<Todo>> = HashMap::new();
+        for todo in todos {
+            let parent = todo.parent_id.filter(|parent| ids.contains(parent));
+            children.entry(parent).or_default().push(todo);
+        }
+        Self::build(None, &mut children)
+    }
+
+    fn build(parent: Option<Uuid>, children: &mut HashMap<Option<Uuid>, Vec<Todo>>) -> Vec<TodoNode> {
+        let todos = children.remove(&parent).unwrap_or_default();
+        todos
+            .into_iter()
+            .map(|todo| {
+                let subtasks = Self::build(Some(todo.id), children);
+                let mut progress = TodoProgress {
+                    completed: todo.checklist.iter().filter(|item| item.completed).count() as u32,
+                    total: todo.checklist.len() as u32,
+                };
+                for subtask in &subtasks {
+                    progress.completed += subtask.progress.completed + u32::from(subtask.todo.completed);
+                    progress.total += subtask.progress.total + 1;
+                }
+                TodoNode { todo, subtasks, progress }
+            })
+            .collect()
+    }
 }
 
 /// Todo statistics
@@ -507,6 +1067,8 @@ pub struct TodoStats {
     pub high_priority: Option<i64>,
     pub medium_priority: Option<i64>,
     pub low_priority: Option<i64>,
+    /// Open todos waiting on another open todo; also counted in `pending`
+    pub blocked: Option<i64>,
 }
 
 impl TodoStats {
@@ -518,6 +1080,685 @@ impl TodoStats {
             high_priority: Some(0),
             medium_priority: Some(0),
             low_priority: Some(0),
+            blocked: Some(0),
+        }
+    }
+}
+
+/// What makes an automation rule fire
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationTrigger {
+    /// A cell changes; with `to`, only when it changes to that value
+    CellChanged {
+        column: String,
+        #[serde(default)]
+        to: Option<serde_json::Value>,
+    },
+    /// A row is created
+    RowCreated,
+    /// A date cell is within `days_before` days; fires once per row and date
+    CloseDateApproaching {
+        #[serde(default = "default_close_date_column")]
+        column: String,
+        days_before: i64,
+    },
+    /// A todo on the spreadsheet passes its due date; fires once per todo
+    TodoOverdue,
+}
+
+fn default_close_date_column() -> String {
+    "Close Date".to_string()
+}
+
+fn default_owner_column() -> String {
+    "Owner".to_string()
+}
+
+fn default_salesforce_id_column() -> String {
+    "Salesforce ID".to_string()
+}
+
+/// What an automation rule does when it fires.
+///
+/// Text fields may reference cells of the triggering row as `{{Column Name}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomationAction {
+    /// Create todos on the row, from a built-in template set and/or inline templates
+    CreateTodos {
+        #[serde(default)]
+        template: Option<String>,
+        #[serde(default)]
+        todos: Vec<TodoTemplate>,
+    },
+    /// Set a cell on the row
+    SetCell {
+        column: String,
+        value: serde_json::Value,
+    },
+    /// Set the row's Person owner column
+    AssignOwner {
+        #[serde(default = "default_owner_column")]
+        column: String,
+        user_id: Uuid,
+    },
+    /// Notify users, given directly or read from a Person column
+    Notify {
+        message: String,
+        #[serde(default)]
+        user_ids: Vec<Uuid>,
+        #[serde(default)]
+        people_column: Option<String>,
+    },
+    /// POST the rule, trigger and row to a URL, signed like webhook deliveries when a secret is set
+    CallWebhook {
+        url: String,
+        #[serde(default)]
+        secret: Option<String>,
+    },
+    /// Write a cell's value to a field of the row's Salesforce opportunity
+    PushToSalesforce {
+        column: String,
+        field: String,
+        #[serde(default = "default_salesforce_id_column")]
+        id_column: String,
+    },
+}
+
+/// Todo created by a `create_todos` action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TodoTemplate {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub priority: TodoPriority,
+    /// Due date relative to when the rule fires
+    #[serde(default)]
+    pub due_in_days: Option<i64>,
+    #[serde(default)]
+    pub assigned_to: Option<Uuid>,
+    /// Assign to the first person in this Person column instead, e.g. "Owner"
+    #[serde(default)]
+    pub assign_from_column: Option<String>,
+}
+
+/// Automation rule configured on a spreadsheet
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AutomationRule {
+    pub id: Uuid,
+    pub spreadsheet_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    #[sqlx(json)]
+    pub trigger: AutomationTrigger,
+    #[sqlx(json)]
+    pub actions: Vec<AutomationAction>,
+    pub is_active: bool,
+    /// Actions run on behalf of this user: whoever created or last edited the rule
+    pub created_by: Uuid,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// Shown in place of a `call_webhook` secret. Sending it back in an update
+/// keeps the secret already set for that URL.
+pub const REDACTED_SECRET: &str = "********";
+
+impl AutomationRule {
+    /// The rule as it's shown to users, with webhook secrets hidden
+    pub fn redacted(mut self) -> Self {
+        for action in &mut self.actions {
+            if let AutomationAction::CallWebhook { secret: Some(secret), .. } = action {
+                *secret = REDACTED_SECRET.to_string();
+            }
+        }
+        self
+    }
+}
+
+/// Create automation rule request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateAutomationRuleRequest {
+    #[validate(length(min = 1, max = 255, message = "Name is required"))]
+    pub name: String,
+    pub description: Option<String>,
+    pub trigger: AutomationTrigger,
+    #[validate(length(min = 1, message = "At least one action is required"))]
+    pub actions: Vec<AutomationAction>,
+    pub is_active: Option<bool>,
+}
+
+/// Update automation rule request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateAutomationRuleRequest {
+    #[validate(length(min = 1, max = 255, message = "Name is required"))]
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub trigger: Option<AutomationTrigger>,
+    #[validate(length(min = 1, message = "At least one action is required"))]
+    pub actions: Option<Vec<AutomationAction>>,
+    pub is_active: Option<bool>,
+}
+
+/// One firing of an automation rule and the outcome of each action
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AutomationRun {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub row_id: Option<Uuid>,
+    pub todo_id: Option<Uuid>,
+    /// Set for scheduled triggers so they fire only once per row or todo
+    pub trigger_key: Option<String>,
+    /// "running" until its actions finish, then "succeeded" or "failed" if any action failed
+    pub status: String,
+    pub results: serde_json::Value,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Kind of in-app notification, also the key for per-user preferences
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    TodoAssigned,
+    TodoDueSoon,
+    TodoOverdue,
+    Mention,
+    CollaboratorInvite,
+    SalesforceSyncFailed,
+    Automation,
+}
+
+impl NotificationKind {
+    pub const ALL: [NotificationKind; 7] = [
+        NotificationKind::TodoAssigned,
+        NotificationKind::TodoDueSoon,
+        NotificationKind::TodoOverdue,
+        NotificationKind::Mention,
+        NotificationKind::CollaboratorInvite,
+        NotificationKind::SalesforceSyncFailed,
+        NotificationKind::Automation,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationKind::TodoAssigned => "todo_assigned",
+            NotificationKind::TodoDueSoon => "todo_due_soon",
+            NotificationKind::TodoOverdue => "todo_overdue",
+            NotificationKind::Mention => "mention",
+            NotificationKind::CollaboratorInvite => "collaborator_invite",
+            NotificationKind::SalesforceSyncFailed => "salesforce_sync_failed",
+            NotificationKind::Automation => "automation",
         }
     }
-}
\ No newline at end of file
+}
+
+/// In-app notification for one user
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: NotificationKind,
+    pub title: String,
+    pub body: Option<String>,
+    pub spreadsheet_id: Option<Uuid>,
+    pub row_id: Option<Uuid>,
+    pub todo_id: Option<Uuid>,
+    /// User whose action raised the notification, if any
+    pub actor_id: Option<Uuid>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Response for `GET /notifications`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationList {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+}
+
+/// Whether a user receives one kind of notification
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationPreference {
+    pub kind: NotificationKind,
+    pub enabled: bool,
+}
+
+/// Update notification preferences request; kinds not listed are left unchanged
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateNotificationPreferencesRequest {
+    #[validate(length(min = 1, message = "At least one preference is required"))]
+    pub preferences: Vec<NotificationPreference>,
+}
+
+/// Request a password reset link by email
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct PasswordResetRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+/// Set a new password using the token from a reset email
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ConfirmPasswordResetRequest {
+    #[validate(length(min = 1))]
+    pub token: String,
+    #[validate(length(min = 8))]
+    pub new_password: String,
+}
+
+/// When a user receives the weekly pipeline digest email
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DigestSchedule {
+    pub enabled: bool,
+    /// 0 = Monday through 6 = Sunday
+    pub day_of_week: i16,
+    /// Hour of the day, 0-23, in `timezone`
+    pub hour: i16,
+    /// IANA time zone name, e.g. `Europe/London`
+    pub timezone: String,
+    pub next_send_at: Option<DateTime<Utc>>,
+    pub last_sent_at: Option<DateTime<Utc>>,
+}
+
+/// Update digest schedule request; fields left out are unchanged
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateDigestScheduleRequest {
+    pub enabled: Option<bool>,
+    #[validate(range(min = 0, max = 6))]
+    pub day_of_week: Option<i16>,
+    #[validate(range(min = 0, max = 23))]
+    pub hour: Option<i16>,
+    #[validate(length(min = 1, max = 64))]
+    pub timezone: Option<String>,
+}
+
+/// Access a workspace or folder grants its members on the spreadsheets inside
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DefaultPermission {
+    /// Use the parent folder's (or the workspace's) permission; folders only
+    Inherit,
+    None,
+    View,
+    Edit,
+    Admin,
+}
+
+impl DefaultPermission {
+    /// Whether this lets members add, edit and move things
+    pub fn can_edit(self) -> bool {
+        matches!(self, DefaultPermission::Edit | DefaultPermission::Admin)
+    }
+}
+
+/// Role of a workspace member
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WorkspaceRole {
+    /// Full control of the workspace and everything in it
+    Admin,
+    /// Access according to folder and workspace default permissions
+    Member,
+}
+
+/// A workspace grouping folders and spreadsheets
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Workspace {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: Uuid,
+    pub organization_id: Uuid,
+    /// Permission members get on spreadsheets where no folder sets one; never `inherit`
+    pub default_permission: DefaultPermission,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create workspace request; the creator becomes its owner and first admin
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateWorkspaceRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    pub default_permission: Option<DefaultPermission>,
+}
+
+/// Update workspace request; fields left out are unchanged
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateWorkspaceRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+    pub default_permission: Option<DefaultPermission>,
+}
+
+/// A workspace member with user details
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct WorkspaceMember {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: WorkspaceRole,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Add (or change the role of) a workspace member
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AddWorkspaceMemberRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: Option<WorkspaceRole>,
+}
+
+/// A folder in a workspace; `parent_id` is `None` at the workspace root
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Folder {
+    pub id: Uuid,
+    pub workspace_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub default_permission: DefaultPermission,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A folder with the number of subfolders and spreadsheets directly inside it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FolderSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub folder: Folder,
+    pub folder_count: i64,
+    pub spreadsheet_count: i64,
+}
+
+/// Create folder request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateFolderRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    /// Parent folder; the workspace root when left out
+    pub parent_id: Option<Uuid>,
+    pub default_permission: Option<DefaultPermission>,
+}
+
+/// Rename a folder or change its default permission
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateFolderRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+    pub default_permission: Option<DefaultPermission>,
+}
+
+/// Move a folder under another folder in the same workspace, or to the root when `parent_id` is null
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveFolderRequest {
+    pub parent_id: Option<Uuid>,
+}
+
+/// Move a spreadsheet into a workspace folder. A null `folder_id` files it at
+/// the workspace root; a null `workspace_id` takes it out of workspaces.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveSpreadsheetRequest {
+    pub workspace_id: Option<Uuid>,
+    pub folder_id: Option<Uuid>,
+}
+
+/// Contents of a workspace root or folder
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderListing {
+    pub workspace: Workspace,
+    /// The folder listed, `None` for the workspace root
+    pub folder: Option<Folder>,
+    /// Folders from the root down to and including `folder`
+    pub path: Vec<Folder>,
+    /// What the requesting user can do with spreadsheets here, `None` for nothing
+    pub permission: Option<DefaultPermission>,
+    pub folders: Vec<FolderSummary>,
+    pub spreadsheets: PaginatedResponse<Spreadsheet>,
+}
+
+/// A user's pinned spreadsheets and folders
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Favorites {
+    pub spreadsheets: Vec<Spreadsheet>,
+    pub folders: Vec<Folder>,
+}
+
+/// Role of a user in their organization
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrganizationRole {
+    /// Created the organization; can't be removed or demoted
+    Owner,
+    /// Manages members and teams, and gets admin on every spreadsheet in the organization
+    Admin,
+    Member,
+}
+
+impl OrganizationRole {
+    /// Whether this role administers the organization
+    pub fn is_admin(self) -> bool {
+        matches!(self, OrganizationRole::Owner | OrganizationRole::Admin)
+    }
+}
+
+/// An organization; the boundary no data is shared across
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A user's organization and their role in it
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrganizationMembership {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub organization: Organization,
+    pub role: OrganizationRole,
+}
+
+/// Rename the organization
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateOrganizationRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+}
+
+/// An organization member with user details
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrganizationMember {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: OrganizationRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// Invite someone to the organization by email. They join only by
+/// accepting, whether or not they have an account yet.
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct InviteOrganizationMemberRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: Option<OrganizationRole>,
+}
+
+/// A pending invitation to join an organization
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OrganizationInvitation {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub organization_name: String,
+    pub email: String,
+    pub role: OrganizationRole,
+    pub invited_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Accept an invitation. Spreadsheets, workspaces and discovery sessions in
+/// the accepting user's current organization only come along if they say so.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AcceptOrganizationInvitationRequest {
+    #[serde(default)]
+    pub bring_data: bool,
+}
+
+/// Change a member's role
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateOrganizationMemberRequest {
+    pub role: OrganizationRole,
+}
+
+/// Role of a team member
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum TeamRole {
+    /// Manages the team's members and gets admin on its spreadsheets
+    Lead,
+    /// Gets edit on the team's spreadsheets
+    Member,
+}
+
+/// A team within an organization
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Team {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Create team request
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateTeamRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Update team request; fields left out are unchanged
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateTeamRequest {
+    #[validate(length(min = 1, max = 255))]
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+/// A team member with user details
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct TeamMember {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: TeamRole,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Add (or change the role of) a team member from the same organization
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AddTeamMemberRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: Option<TeamRole>,
+}
+
+/// Hand a spreadsheet to a team, or take it away from its team when `team_id` is null
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssignTeamRequest {
+    pub team_id: Option<Uuid>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequenced_message_round_trip() {
+        let row_id = Uuid::new_v4();
+        let message = SequencedMessage {
+            seq: 42,
+            message: WebSocketMessage::RowDeleted {
+                spreadsheet_id: Uuid::new_v4(),
+                row_id,
+                deleted_by: Uuid::new_v4(),
+            },
+        };
+
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(json["seq"], 42);
+        assert_eq!(json["type"], "RowDeleted");
+
+        let parsed: SequencedMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.seq, 42);
+        assert!(matches!(parsed.message, WebSocketMessage::RowDeleted { row_id: id, .. } if id == row_id));
+    }
+
+    #[test]
+    fn test_default_permission_serde() {
+        assert!(DefaultPermission::Admin.can_edit());
+        assert!(!DefaultPermission::View.can_edit());
+        assert_eq!(serde_json::to_value(DefaultPermission::Inherit).unwrap(), "inherit");
+        let parsed: UpdateFolderRequest =
+            serde_json::from_str(r#"{"default_permission": "edit"}"#).unwrap();
+        assert_eq!(parsed.default_permission, Some(DefaultPermission::Edit));
+        assert!(parsed.name.is_none());
+    }
+
+    #[test]
+    fn test_organization_roles() {
+        assert!(OrganizationRole::Owner.is_admin());
+        assert!(OrganizationRole::Admin.is_admin());
+        assert!(!OrganizationRole::Member.is_admin());
+
+        let parsed: InviteOrganizationMemberRequest =
+            serde_json::from_str(r#"{"email": "rep@example.com", "role": "admin"}"#).unwrap();
+        assert_eq!(parsed.role, Some(OrganizationRole::Admin));
+        let accept: AcceptOrganizationInvitationRequest = serde_json::from_str("{}").unwrap();
+        assert!(!accept.bring_data);
+        assert_eq!(serde_json::to_value(TeamRole::Lead).unwrap(), "lead");
+        assert!(serde_json::from_str::<UpdateOrganizationMemberRequest>(r#"{"role": "superuser"}"#).is_err());
+    }
+
+    #[test]
+    fn test_todo_tree_rolls_up_progress() {
+        let todo = |id: Uuid, parent_id: Option<Uuid>, completed: bool, checklist: &[bool]| -> Todo {
+            let checklist: Vec<_> = checklist
+                .iter()
+                .map(|done| serde_json::json!({ "id": Uuid::new_v4(), "title": "Step", "completed": done, "position": 0 }))
+                .collect();
+            serde_json::from_value(serde_json::json!({
+                "id": id, "title": "Todo", "priority": "medium", "completed": completed,
+                "spreadsheet_id": Uuid::nil(), "user_id": Uuid::nil(), "parent_id": parent_id, "checklist": checklist,
+            }))
+            .unwrap()
+        };
+        let (plan, security, architecture, orphan) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let tree = TodoNode::tree(vec![
+            todo(plan, None, false, &[true]),
+            todo(security, Some(plan), false, &[true, false]),
+            todo(architecture, Some(security), true, &[]),
+            // Its parent isn't visible, so it's a root
+            todo(orphan, Some(Uuid::new_v4()), false, &[]),
+        ]);
+
+        assert_eq!(tree.iter().map(|node| node.todo.id).collect::<Vec<_>>(), vec![plan, orphan]);
+        let security_node = &tree[0].subtasks[0];
+        assert_eq!(security_node.subtasks[0].todo.id, architecture);
+        assert_eq!(security_node.progress, TodoProgress { completed: 2, total: 3 });
+        // Its own checklist item, plus security, its two steps and architecture
+        assert_eq!(tree[0].progress, TodoProgress { completed: 3, total: 5 });
+        assert_eq!(serde_json::to_value(&tree[0]).unwrap()["subtasks"][0]["id"], serde_json::json!(security));
+    }
+}
@@ -0,0 +1,26 @@
+//! Fixtures for tests that run against a database.
+//!
+//! Such tests are `#[ignore]`d, so a plain `cargo test` doesn't pass them
+//! without running them. Point `TEST_DATABASE_URL` at a migrated database and
+//! run them with `cargo test -- --ignored`.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// The database at `TEST_DATABASE_URL`
+pub async fn test_pool() -> PgPool {
+    let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must point at a migrated database");
+    PgPool::connect(&url).await.expect("connect to TEST_DATABASE_URL")
+}
+
+/// A new user, in an organization of their own; returns their id and email
+pub async fn create_user(pool: &PgPool, name: &str) -> (Uuid, String) {
+    let email = format!("{}-{}@example.com", name.to_lowercase(), Uuid::new_v4());
+    let id = sqlx::query_scalar("INSERT INTO users (email, password_hash, name) VALUES ($1, 'x', $2) RETURNING id")
+        .bind(&email)
+        .bind(name)
+        .fetch_one(pool)
+        .await
+        .expect("create test user");
+    (id, email)
+}
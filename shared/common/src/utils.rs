@@ -1,6 +1,8 @@
 use chrono::{DateTime, Utc};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::OnceLock;
 use uuid::Uuid;
 
 /// Utility functions for common operations
@@ -23,9 +25,10 @@ pub fn format_timestamp(timestamp: DateTime<Utc>, format: Option<&str>) -> Strin
 
 /// Validate email format
 pub fn is_valid_email(email: &str) -> bool {
-    use regex::Regex;
-    let email_regex = Regex::new(r"^[^\s@]+@[^\s@]+\.[^\s@]+$").unwrap();
-    email_regex.is_match(email)
+    static EMAIL_REGEX: OnceLock<Regex> = OnceLock::new();
+    EMAIL_REGEX
+        .get_or_init(|| Regex::new(r"^[^\s@]+@[^\s@]+\.[^\s@]+$").unwrap())
+        .is_match(email)
 }
 
 /// Sanitize string input (remove potentially harmful characters)
@@ -38,10 +41,11 @@ pub fn sanitize_string(input: &str) -> String {
 
 /// Truncate string to specified length with ellipsis
 pub fn truncate_string(input: &str, max_length: usize) -> String {
-    if input.len() <= max_length {
+    if input.chars().count() <= max_length {
         input.to_string()
     } else {
-        format!("{}...", &input[..max_length.saturating_sub(3)])
+        let kept: String = input.chars().take(max_length.saturating_sub(3)).collect();
+        format!("{}...", kept)
     }
 }
 
@@ -415,7 +419,8 @@ mod tests {
     fn test_string_utils() {
         assert_eq!(snake_to_title_case("hello_world"), "Hello World");
         assert_eq!(title_to_snake_case("Hello World"), "hello_world");
-        assert_eq!(truncate_string("Hello, World!", 10), "Hello, W...");
+        assert_eq!(truncate_string("Hello, World!", 10), "Hello, ...");
+        assert_eq!(truncate_string("Grüße aus Köln", 8), "Grüße...");
         assert_eq!(truncate_string("Short", 10), "Short");
     }
 